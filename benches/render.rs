@@ -0,0 +1,78 @@
+// --- レンダリングとディレクトリ一覧のベンチマーク ---
+//
+// `cargo bench --features bench` で実行する。入力は毎回同じ内容を生成するので、
+// 変更の前後で結果を比べられる（`--save-baseline` と `--baseline` で前回の結果と比べる）。
+
+use std::{env, fs, hint::black_box, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use peek::{
+    listing,
+    render::{render_markdown_document, RenderOptions},
+    theme::GITHUB_DARK_THEME,
+};
+
+/// 見出し・段落・リスト・表・コードブロック・引用を含む1節を `sections` 回繰り返した文書
+fn document(sections: usize) -> String {
+    let mut markdown = String::new();
+    for i in 0..sections {
+        markdown.push_str(&format!(
+            "## 節 {i}\n\n\
+             本文の段落です。*強調* と **太字** と `code` と [リンク](https://example.com/{i}) を含みます。<br>改行の後の行。\n\n\
+             - 項目 {i}\n  - 入れ子\n    1. 深い項目\n- 次の項目\n\n\
+             | 列A | 列B | 列C |\n| :-- | :-: | --: |\n| {i} | 値 | 1.0 |\n| x | y | z |\n\n\
+             ```rust\nfn f{i}() -> usize {{\n    {i}\n}}\n```\n\n\
+             > 引用 {i}\n> > 入れ子の引用\n\n"
+        ));
+    }
+    markdown
+}
+
+fn render(c: &mut Criterion) {
+    let options = RenderOptions::default();
+    let mut group = c.benchmark_group("render");
+    for (name, sections) in [("small", 5), ("medium", 200), ("huge", 5000)] {
+        let markdown = document(sections);
+        group.throughput(Throughput::Bytes(markdown.len() as u64));
+        // 巨大な文書は1回に時間がかかるので、サンプル数を減らす
+        group.sample_size(if sections > 1000 { 10 } else { 100 });
+        group.bench_function(name, |b| {
+            b.iter(|| render_markdown_document(black_box(&markdown), &GITHUB_DARK_THEME, &|_| None, &options))
+        });
+    }
+    group.finish();
+}
+
+fn listing(c: &mut Criterion) {
+    let dir = listing_fixture(2000);
+    let mut group = c.benchmark_group("listing");
+    for (name, hide_gitignored) in [("read_dir", false), ("gitignore", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut entries: Vec<_> = listing::read_entries(&dir, hide_gitignored).unwrap().collect();
+                listing::sort_entries(&mut entries);
+                entries
+            })
+        });
+    }
+    group.finish();
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// `files` 個のファイルと1割のディレクトリを持つ一時ディレクトリを作る
+fn listing_fixture(files: usize) -> PathBuf {
+    let dir = env::temp_dir().join(format!("peek-bench-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for i in 0..files {
+        if i % 10 == 0 {
+            fs::create_dir(dir.join(format!("dir-{i:05}"))).unwrap();
+        } else {
+            fs::write(dir.join(format!("note-{i:05}.md")), "# note\n").unwrap();
+        }
+    }
+    dir
+}
+
+criterion_group!(benches, render, listing);
+criterion_main!(benches);
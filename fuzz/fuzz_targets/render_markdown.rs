@@ -0,0 +1,17 @@
+// 任意のUTF-8文字列をレンダラーに渡し、パニックしないことを確かめる。
+// 閉じていないタグ・巨大な表・深い入れ子などを想定している。
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use peek::{
+    render::{render_markdown, render_markdown_document, RenderOptions},
+    theme::GITHUB_DARK_THEME,
+};
+
+fuzz_target!(|markdown: &str| {
+    let options = RenderOptions::default();
+    let _ = render_markdown_document(markdown, &GITHUB_DARK_THEME, &|_| None, &options);
+    // 画像の近似表示がある場合の経路
+    let _ = render_markdown(markdown, &GITHUB_DARK_THEME, &|_| Some(Vec::new()), &options);
+});
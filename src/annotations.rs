@@ -0,0 +1,187 @@
+// --- 文書へのコメント ---
+//
+// 元のファイルには手を加えず、隣の `doc.md.comments.toml` にコメントを書いておく。
+// 1つのコメントは次のような `[[comment]]` の表で、`heading`（見出しのアンカー）か `line`（元のMarkdownの行番号）で場所を示す。
+//
+//     [[comment]]
+//     heading = "install"
+//     text = "手順が古い"
+//     date = "2024-05-01"
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, Value};
+
+/// コメントを付ける場所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// 見出しのアンカー名
+    Heading(String),
+    /// 元のMarkdownの行番号（1始まり）
+    Line(usize),
+}
+
+/// 1つのコメント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub target: Target,
+    pub text: String,
+    /// 書いた日付。無くてもよい
+    pub date: Option<String>,
+}
+
+/// `path` のコメントを書いておくファイル
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".comments.toml");
+    PathBuf::from(name)
+}
+
+/// `path` の文書へのコメントを読み込む。コメントのファイルが無ければ空
+pub fn load(path: &Path) -> io::Result<Vec<Annotation>> {
+    let sidecar = sidecar_path(path);
+    match fs::read_to_string(&sidecar) {
+        Ok(text) => parse(&text, &sidecar.display().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// コメントを1つ書き足す。ファイルが無ければ作る
+pub fn append(path: &Path, annotation: &Annotation) -> io::Result<()> {
+    let sidecar = sidecar_path(path);
+    let mut text = match fs::read_to_string(&sidecar) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if !text.is_empty() {
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push('\n');
+    }
+    text.push_str(&format(annotation));
+    fs::write(&sidecar, text)
+}
+
+/// `[[comment]]` の表を順に読む。`source` はエラー表示用のファイル名
+pub fn parse(text: &str, source: &str) -> io::Result<Vec<Annotation>> {
+    let mut annotations = Vec::new();
+    // 読んでいる表の行番号と、そこまでのキーと値
+    let mut current: Option<(usize, Vec<(String, Value)>)> = None;
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = config::strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |what: &str| config::invalid(format!("{} {}行目: {}", source, index + 1, what));
+        if line == "[[comment]]" {
+            if let Some((start, entries)) = current.take() {
+                annotations.push(annotation(&entries, source, start)?);
+            }
+            current = Some((index + 1, Vec::new()));
+            continue;
+        }
+        let Some((_, entries)) = &mut current else {
+            return Err(error("`[[comment]]` の外に値があります"));
+        };
+        let (key, value) = line.split_once('=').ok_or_else(|| error("`key = value` の形式ではありません"))?;
+        let value = config::parse_value(value.trim()).ok_or_else(|| error("値を解釈できません"))?;
+        entries.push((key.trim().trim_matches('"').to_string(), value));
+    }
+    if let Some((start, entries)) = current {
+        annotations.push(annotation(&entries, source, start)?);
+    }
+    Ok(annotations)
+}
+
+/// 1つの表のキーと値からコメントを作る。`start` は表の見出しの行番号
+fn annotation(entries: &[(String, Value)], source: &str, start: usize) -> io::Result<Annotation> {
+    let error = |what: &str| config::invalid(format!("{} {}行目のコメント: {}", source, start, what));
+    let (mut target, mut text, mut date) = (None, None, None);
+    for (key, value) in entries {
+        match (key.as_str(), value) {
+            ("heading", Value::Str(anchor)) => target = Some(Target::Heading(anchor.trim_start_matches('#').to_string())),
+            ("line", Value::Int(line)) if *line > 0 => target = Some(Target::Line(*line as usize)),
+            ("text", Value::Str(value)) => text = Some(value.clone()),
+            ("date", Value::Str(value)) => date = Some(value.clone()),
+            ("heading" | "text" | "date", _) => return Err(error(&format!("{}には文字列を指定してください", key))),
+            ("line", _) => return Err(error("lineには1以上の行番号を指定してください")),
+            _ => return Err(error(&format!("不明なキーです: {}", key))),
+        }
+    }
+    let target = target.ok_or_else(|| error("heading か line を指定してください"))?;
+    let text = text.ok_or_else(|| error("text を指定してください"))?;
+    Ok(Annotation { target, text, date })
+}
+
+/// コメントを `[[comment]]` の表として書く
+pub fn format(annotation: &Annotation) -> String {
+    let mut table = String::from("[[comment]]\n");
+    match &annotation.target {
+        Target::Heading(anchor) => table.push_str(&format!("heading = {}\n", quote(anchor))),
+        Target::Line(line) => table.push_str(&format!("line = {}\n", line)),
+    }
+    table.push_str(&format!("text = {}\n", quote(&annotation.text)));
+    if let Some(date) = &annotation.date {
+        table.push_str(&format!("date = {}\n", quote(date)));
+    }
+    table
+}
+
+/// 設定ファイルと同じ書き方の文字列にする
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// レビューの報告に引用する元のMarkdownの最大の行数
+const CONTEXT_LINES: usize = 4;
+
+/// コメントをまとめたMarkdownの報告（PRに貼るためのもの）。
+/// `entries` はコメントと元のMarkdownでの行番号（場所が見つからなければNone）で、この順に並べる
+pub fn report(title: &str, source: &str, entries: &[(Option<usize>, &Annotation)]) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut report = format!("# Review notes: {}\n", title);
+    for (line, annotation) in entries {
+        let place = match (line, &annotation.target) {
+            (Some(line), Target::Heading(anchor)) => format!("Line {} (`#{}`)", line, anchor),
+            (Some(line), Target::Line(_)) => format!("Line {}", line),
+            (None, Target::Heading(anchor)) => format!("`#{}` (not found)", anchor),
+            (None, Target::Line(line)) => format!("Line {} (not found)", line),
+        };
+        report.push_str(&format!("\n## {}\n\n", place));
+        // 行から空行までのまとまりを引用する
+        let context: Vec<&str> = line
+            .map(|line| lines.iter().skip(line - 1).take_while(|text| !text.trim().is_empty()).take(CONTEXT_LINES).copied().collect())
+            .unwrap_or_default();
+        if !context.is_empty() {
+            for text in context {
+                report.push_str(&format!("> {}\n", text).replace("> \n", ">\n"));
+            }
+            report.push('\n');
+        }
+        report.push_str(annotation.text.trim_end());
+        report.push('\n');
+        if let Some(date) = &annotation.date {
+            report.push_str(&format!("\n_{}_\n", date));
+        }
+    }
+    report
+}
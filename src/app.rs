@@ -0,0 +1,4482 @@
+// --- TUIのアプリケーション（画面の状態・イベントの処理・描画） ---
+//
+// `App::update` がイベントを受けて状態を遷移させ、端末や別スレッドを使う処理を `Effect` として返す。
+// `main` は引数を読んでから `run` を呼ぶだけで、状態遷移はテストからも直接呼び出せる。
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+// pulldown_cmarkからhtmlモジュールをインポート
+use pulldown_cmark::{html, Parser as MarkdownParser};
+use ratatui::{
+    prelude::*,
+    symbols::border,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Padding, Paragraph, Wrap},
+};
+
+use crate::{
+    annotations, archive, audit, bulk, changelog, check, compressed, config, daemon, diff, error_report, export, filter, git, graph, image_art, include, input, journal, keymap, links, listing, logging, metadata, org, pager, readability,
+    platform, render, script, search, settings, shell_words, spell, structure, tags, templates, theme, translation, vault, workspace,
+};
+use config::Config;
+use error_report::ErrorReport;
+use graph::{Column, Graph, GraphBrowser};
+use links::LinkTarget;
+use listing::{sort_entries, Entry};
+use input::InputReader;
+use keymap::{Action, Key, Keymap, Lookup, Mode};
+use pager::StdinEvent;
+use render::{
+    clip_code_lines, estimated_size, render_markdown_document, wrap_quote_lines, wrapped_rows, Decorations, FootnoteRef, ImageArt, LinkSpan, RenderOptions, RenderedMarkdown,
+};
+use settings::Settings;
+use tags::TagBrowser;
+use theme::ColorScheme;
+use vault::Vault;
+
+/// 入力が無いときに、アニメーション以外の定期的な確認（ディレクトリの変更など）をする間隔
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+/// ページャーモードで標準入力を再描画する最短間隔
+const STDIN_RENDER_INTERVAL: Duration = Duration::from_millis(200);
+/// `low_bandwidth` のときの、標準入力をまとめて描き直す間隔と、イベントが無いときに描き直す間隔
+const LOW_BANDWIDTH_INTERVAL: Duration = Duration::from_secs(1);
+/// 再読み込みで変わった部分を強調する時間。最後の3分の1は下線だけにして薄くする
+const CHANGE_HIGHLIGHT_DURATION: Duration = Duration::from_secs(4);
+/// エクスプローラーでディレクトリ（`watch` ならプレビュー中のファイル）の変更を確認する間隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// スムーススクロールにかける時間
+const SMOOTH_SCROLL_DURATION: Duration = Duration::from_millis(150);
+/// アニメーション中の描画間隔
+const ANIMATION_FRAME: Duration = Duration::from_millis(16);
+/// エクスプローラーの戻る・進む履歴に残すディレクトリの数
+const HISTORY_LIMIT: usize = 100;
+
+/// エクスプローラーでの表示を切り替えるためのファイル種別
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Directory,
+    Markdown,
+    Image,
+    Code,
+    Archive,
+    Other,
+}
+
+impl FileKind {
+    fn of(entry: &Entry) -> Self {
+        if entry.is_dir {
+            return Self::Directory;
+        }
+        let extension = entry
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "md" | "markdown" | "org" => Self::Markdown,
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "bmp" | "ico" => Self::Image,
+            "rs" | "py" | "js" | "ts" | "go" | "c" | "h" | "cpp" | "java" | "rb" | "sh" | "toml" | "json"
+            | "yaml" | "yml" | "html" | "css" => Self::Code,
+            "zip" | "tar" | "gz" | "tgz" | "xz" | "bz2" | "7z" | "zst" => Self::Archive,
+            _ => Self::Other,
+        }
+    }
+
+    fn color(self, theme: &ColorScheme) -> Color {
+        match self {
+            Self::Directory => theme.link,
+            Self::Markdown => theme.file_markdown,
+            Self::Image => theme.file_image,
+            Self::Code => theme.file_code,
+            Self::Archive => theme.file_archive,
+            Self::Other => theme.fg,
+        }
+    }
+
+    /// Nerd Fontのアイコン
+    fn icon(self) -> &'static str {
+        match self {
+            Self::Directory => "\u{f07b}",
+            Self::Markdown => "\u{f48a}",
+            Self::Image => "\u{f1c5}",
+            Self::Code => "\u{f121}",
+            Self::Archive => "\u{f410}",
+            Self::Other => "\u{f15b}",
+        }
+    }
+}
+
+
+// --- アプリケーションの状態管理 ---
+
+/// 重ねて表示する画面。キー操作は最前面の画面だけが受け取り、描画は奥から順に行う
+pub enum Screen {
+    /// エクスプローラー（ペインの状態は `App` が持つ）
+    Explorer,
+    Preview(Box<PreviewState>),
+    /// `:log` で開いたログの表示
+    Log(LogView),
+    /// `:graph` で開いた文書のリンクのグラフ
+    Graph(GraphBrowser),
+    /// 操作に失敗したときに前面に表示するエラー画面。何かキーを押すと閉じる
+    Error(ErrorReport),
+}
+
+impl Screen {
+    /// 下の画面の上に重ねて表示するものかどうか（そうでなければ画面全体を覆う）
+    fn is_overlay(&self) -> bool {
+        matches!(self, Self::Log(_) | Self::Graph(_) | Self::Error(_))
+    }
+}
+
+/// 別スレッドで起きたことをUIスレッドに伝えるイベント。UIスレッドはこれを1つのチャネルで待つ
+pub enum AppEvent {
+    /// 入力が無くても一定間隔で送る確認の合図（チャネルは通らない）
+    Tick,
+    /// 端末からの入力（キー・リサイズなど）
+    Terminal(io::Result<Event>),
+    /// ページャーモードで読み込んだ標準入力
+    Stdin(StdinEvent),
+    /// 別スレッドで読み込んだディレクトリのエントリ。`loader` で読み込みを見分ける
+    Entries { loader: u64, batch: io::Result<Vec<Entry>>, finished: bool },
+    /// 別スレッドで開いたプレビュー。`request` で最新の要求かを見分ける
+    Preview { request: u64, anchor: Option<String>, result: Result<Box<PreviewState>, ErrorReport> },
+    /// 別スレッドでレンダリングした標準入力と、フィルターの失敗
+    StdinRendered { markdown: String, result: Result<(Box<RenderedMarkdown>, usize), ErrorReport>, message: Option<String> },
+    /// 別スレッドで終えた時間のかかる処理の結果を、アプリの状態に反映する関数
+    Background(AppUpdate),
+}
+
+/// 別スレッドで終えた処理の結果を、UIスレッドでアプリの状態に反映する関数
+pub type AppUpdate = Box<dyn FnOnce(&mut App) + Send>;
+
+/// 別スレッドで終えた処理の結果を、要求したプレビューに反映する関数
+type PreviewUpdate = Box<dyn FnOnce(&mut PreviewState) + Send>;
+
+/// プレビューが予約した、別スレッドで行う処理
+type PreviewWork = Box<dyn FnOnce() -> PreviewUpdate + Send>;
+
+impl From<io::Result<Event>> for AppEvent {
+    fn from(event: io::Result<Event>) -> Self {
+        Self::Terminal(event)
+    }
+}
+
+impl From<StdinEvent> for AppEvent {
+    fn from(event: StdinEvent) -> Self {
+        Self::Stdin(event)
+    }
+}
+
+/// 別スレッドの処理に振る番号。古い処理の結果を捨てるのに使う
+fn next_task_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 別スレッドで読み込み中のディレクトリ
+struct DirectoryLoader {
+    id: u64,
+    /// 破棄されたら読み込みを打ち切る
+    cancelled: Arc<AtomicBool>,
+    /// true なら届いた分から一覧に表示する。false なら読み込み完了後にまとめて差し替える
+    streaming: bool,
+    pending: Vec<Entry>,
+    /// 読み込み完了後に選択し直すエントリと、見つからなかった場合の位置。
+    /// 逐次表示ではエントリが届いた時点で選択する
+    reselect: Option<(PathBuf, usize)>,
+    started: Instant,
+}
+
+impl DirectoryLoader {
+    /// 一度に送るエントリ数
+    const BATCH_SIZE: usize = 256;
+
+    fn spawn(
+        path: PathBuf,
+        hide_gitignored: bool,
+        streaming: bool,
+        reselect: Option<(PathBuf, usize)>,
+        events: &Sender<AppEvent>,
+    ) -> Self {
+        let id = next_task_id();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let events = events.clone();
+        let stop = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            let send = |batch, finished| {
+                !stop.load(Ordering::Relaxed) && events.send(AppEvent::Entries { loader: id, batch, finished }).is_ok()
+            };
+            let entries = match listing::read_entries(&path, hide_gitignored) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    send(Err(e), true);
+                    return;
+                }
+            };
+            let mut batch = Vec::with_capacity(Self::BATCH_SIZE);
+            for entry in entries {
+                batch.push(entry);
+                if batch.len() >= Self::BATCH_SIZE && !send(Ok(std::mem::take(&mut batch)), false) {
+                    return;
+                }
+            }
+            send(Ok(batch), true);
+        });
+        Self {
+            id,
+            cancelled,
+            streaming,
+            pending: Vec::new(),
+            reselect,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for DirectoryLoader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct ExplorerState {
+    pub current_path: PathBuf,
+    /// `-` で戻る、直前にいたディレクトリ
+    previous_path: Option<PathBuf>,
+    /// `H` / `L` でたどる移動の履歴。末尾ほど新しい
+    back_history: Vec<PathBuf>,
+    forward_history: Vec<PathBuf>,
+    entries: Vec<Entry>,
+    list_state: ListState,
+    pub error_message: Option<String>,
+    /// 一括書き出しの結果など、エラーではない操作の結果
+    pub message: Option<String>,
+    command_input: String,
+    in_command_mode: bool,
+    /// `Space` で印を付けたエントリ（一括操作の対象）
+    marked: BTreeSet<PathBuf>,
+    /// `:delete` で確認中の削除対象
+    pending_delete: Option<Vec<PathBuf>>,
+    /// .gitignore で無視されるエントリを隠すかどうか
+    hide_gitignored: bool,
+    /// エントリの前にNerd Fontのアイコンを表示するかどうか
+    show_icons: bool,
+    /// 一覧の表示開始位置と、直近の描画で表示できた行数。
+    /// エントリが多いディレクトリでも、見えている範囲だけを描画するために使う
+    list_offset: usize,
+    visible_rows: usize,
+    /// 現在のディレクトリを含むgitリポジトリのルート
+    workspace_root: Option<PathBuf>,
+    /// `:tags` で開いたタグの一覧
+    tag_browser: Option<TagBrowser>,
+    /// 読み込み中のディレクトリ。None なら読み込み済み
+    loader: Option<DirectoryLoader>,
+    /// 別スレッドの読み込みの結果を送るチャネル
+    events: Sender<AppEvent>,
+    /// 一覧の読み込みに失敗したときのエラー画面（呼び出し側で表示する）
+    error_report: Option<ErrorReport>,
+    /// 外部での変更を検知するための、読み込み時点のディレクトリの更新日時
+    dir_modified: Option<SystemTime>,
+    last_watch_check: Instant,
+}
+
+impl ExplorerState {
+    fn new(config: &Config, start_dir: PathBuf, events: &Sender<AppEvent>) -> io::Result<Self> {
+        let mut state = Self {
+            current_path: start_dir,
+            previous_path: None,
+            back_history: Vec::new(),
+            forward_history: Vec::new(),
+            hide_gitignored: config.hide_gitignored,
+            show_icons: config.icons,
+            entries: Vec::new(),
+            list_state: ListState::default(),
+            error_message: None,
+            message: None,
+            command_input: String::new(),
+            in_command_mode: false,
+            marked: BTreeSet::new(),
+            pending_delete: None,
+            list_offset: 0,
+            visible_rows: 0,
+            workspace_root: None,
+            tag_browser: None,
+            loader: None,
+            events: events.clone(),
+            error_report: None,
+            dir_modified: None,
+            last_watch_check: Instant::now(),
+        };
+        state.load_entries(None);
+        Ok(state)
+    }
+
+    /// ディレクトリ読み込み時にカーソル位置をリセットし、`reselect` のエントリが届いたらそれを選択する。
+    /// 読み込みは別スレッドで行い、結果は `receive_entries` で受け取る
+    fn load_entries(&mut self, reselect: Option<PathBuf>) {
+        self.entries.clear();
+        self.marked.clear();
+        self.list_state.select(None);
+        self.list_offset = 0;
+        self.workspace_root = workspace::find_root(&self.current_path);
+        self.dir_modified = modified_time(&self.current_path);
+        self.loader = Some(DirectoryLoader::spawn(
+            self.current_path.clone(),
+            self.hide_gitignored,
+            true,
+            reselect.map(|path| (path, 0)),
+            &self.events,
+        ));
+    }
+
+    /// 選択中のエントリを名前で覚えておき、読み込み直した後も同じエントリを選択する
+    fn reload_entries(&mut self) {
+        let reselect = self
+            .list_state
+            .selected()
+            .and_then(|i| self.entries.get(i).map(|entry| (entry.path.clone(), i)));
+        self.reload_with(reselect);
+    }
+
+    /// 一覧を読み込み直し、`path` のエントリを選択する
+    fn reload_selecting(&mut self, path: PathBuf) {
+        let index = self.list_state.selected().unwrap_or(0);
+        self.reload_with(Some((path, index)));
+    }
+
+    fn reload_with(&mut self, reselect: Option<(PathBuf, usize)>) {
+        self.dir_modified = modified_time(&self.current_path);
+        self.loader = Some(DirectoryLoader::spawn(
+            self.current_path.clone(),
+            self.hide_gitignored,
+            false,
+            reselect,
+            &self.events,
+        ));
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    /// 読み込み中のディレクトリの番号
+    fn loader_id(&self) -> Option<u64> {
+        self.loader.as_ref().map(|loader| loader.id)
+    }
+
+    /// 読み込み中のディレクトリから届いたエントリを一覧に反映する
+    fn receive_entries(&mut self, batch: io::Result<Vec<Entry>>, finished: bool) {
+        if self.loader.is_none() {
+            return;
+        }
+        let received = match batch {
+            Ok(batch) => batch,
+            Err(e) => {
+                self.error_report = Some(ErrorReport::new(
+                    format!("ディレクトリを読み込めません: {}", self.current_path.display()),
+                    &e,
+                ));
+                Vec::new()
+            }
+        };
+        let Some(loader) = &mut self.loader else {
+            return;
+        };
+
+        if loader.streaming {
+            if !received.is_empty() {
+                let selected_path = match &loader.reselect {
+                    Some((path, _)) if received.iter().any(|entry| &entry.path == path) => Some(path.clone()),
+                    _ => self.list_state.selected().and_then(|i| self.entries.get(i).map(|entry| entry.path.clone())),
+                };
+                self.entries.extend(received);
+                sort_entries(&mut self.entries);
+                // 並べ替えで選択中のエントリが動かないよう、名前で選択し直す
+                let index = selected_path
+                    .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+                    .unwrap_or(0);
+                self.list_state.select(Some(index));
+            }
+        } else {
+            loader.pending.extend(received);
+        }
+
+        if finished {
+            let Some(mut loader) = self.loader.take() else {
+                return;
+            };
+            tracing::debug!(dir = %self.current_path.display(), elapsed = ?loader.started.elapsed(), "listed directory");
+            if !loader.streaming {
+                self.entries = std::mem::take(&mut loader.pending);
+                sort_entries(&mut self.entries);
+                let restored = loader.reselect.take().map(|(path, index)| {
+                    self.entries
+                        .iter()
+                        .position(|entry| entry.path == path)
+                        // 選択中のエントリが消えた場合は、近い位置を選択する
+                        .unwrap_or(index.min(self.entries.len().saturating_sub(1)))
+                });
+                let selected = (!self.entries.is_empty()).then(|| restored.unwrap_or(0));
+                self.list_state.select(selected);
+            }
+        }
+    }
+
+    /// 一定間隔でディレクトリの更新日時を確認し、外部で変更されていれば一覧を読み込み直す
+    fn refresh_if_changed(&mut self) {
+        if self.is_loading() || self.last_watch_check.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.last_watch_check = Instant::now();
+        if modified_time(&self.current_path) != self.dir_modified {
+            tracing::info!(dir = %self.current_path.display(), "changed on disk, reloading");
+            self.reload_entries();
+        }
+    }
+
+    /// 指定したディレクトリに移動し、戻る履歴に記録する。開けない場合は元のディレクトリに留まり、エラーを表示する
+    fn change_directory(&mut self, path: &Path) {
+        if let Some(previous) = self.open_directory(path) {
+            push_history(&mut self.back_history, previous);
+            self.forward_history.clear();
+        }
+    }
+
+    /// 履歴を変えずにディレクトリを開き、移動した場合は元のディレクトリを返す。
+    /// 空のパスはルートより上のドライブの一覧（Windowsのみ）を表し、元のドライブを選択しておく
+    fn open_directory(&mut self, path: &Path) -> Option<PathBuf> {
+        if path.as_os_str().is_empty() {
+            if self.current_path.as_os_str().is_empty() || platform::drives().is_empty() {
+                return None;
+            }
+            let previous = std::mem::take(&mut self.current_path);
+            self.previous_path = Some(previous.clone());
+            self.entries.clear();
+            self.list_offset = 0;
+            self.workspace_root = None;
+            self.reload_with(Some((previous.clone(), 0)));
+            return Some(previous);
+        }
+        // アーカイブの中は実在しないパスなので、アーカイブ自体のパスだけを正規化する
+        let opened = match archive::split(path) {
+            Some((archive, inner)) => dunce::canonicalize(archive).map(|archive| if inner.is_empty() { archive } else { archive.join(inner) }),
+            None => dunce::canonicalize(path).and_then(|path| fs::read_dir(&path).map(|_| path)),
+        };
+        match opened {
+            Ok(path) => {
+                tracing::info!("cd {}", path.display());
+                let previous = (path != self.current_path).then(|| std::mem::replace(&mut self.current_path, path));
+                if previous.is_some() {
+                    self.previous_path = previous.clone();
+                }
+                // 親ディレクトリに移動したときは、元いたディレクトリを選択する
+                self.load_entries(previous.clone());
+                previous
+            }
+            Err(e) => {
+                self.error_message = Some(format!("ディレクトリを開けません: {}: {}", platform::display(path), e));
+                None
+            }
+        }
+    }
+
+    /// 直前にいたディレクトリに戻る（シェルの `cd -` と同じ）
+    fn go_to_previous(&mut self) {
+        match self.previous_path.clone() {
+            Some(path) => self.change_directory(&path),
+            None => self.error_message = Some("直前のディレクトリがありません".to_string()),
+        }
+    }
+
+    /// 移動の履歴を1つ戻る
+    fn go_back(&mut self) {
+        let Some(path) = self.back_history.pop() else {
+            self.error_message = Some("戻る履歴がありません".to_string());
+            return;
+        };
+        if let Some(current) = self.open_directory(&path) {
+            push_history(&mut self.forward_history, current);
+        }
+    }
+
+    /// 戻った履歴を1つ進む
+    fn go_forward(&mut self) {
+        let Some(path) = self.forward_history.pop() else {
+            self.error_message = Some("進む履歴がありません".to_string());
+            return;
+        };
+        if let Some(current) = self.open_directory(&path) {
+            push_history(&mut self.back_history, current);
+        }
+    }
+
+    /// プロジェクト設定が切り替わったときに、表示に関する設定を反映する
+    fn apply_config(&mut self, config: &Config) {
+        self.show_icons = config.icons;
+        if self.hide_gitignored != config.hide_gitignored {
+            self.hide_gitignored = config.hide_gitignored;
+            self.reload_entries();
+        }
+    }
+
+    /// gitリポジトリのルートに移動する
+    fn go_to_workspace_root(&mut self) {
+        match self.workspace_root.clone() {
+            Some(root) => self.change_directory(&root),
+            None => self.error_message = Some("gitリポジトリの中ではありません".to_string()),
+        }
+    }
+
+    /// コマンド入力やタグの一覧、削除の確認など、キーをそのまま受け取る状態かどうか
+    fn has_prompt(&self) -> bool {
+        self.in_command_mode || self.tag_browser.is_some() || self.pending_delete.is_some()
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.list_state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    /// 選択中のエントリの印を切り替え、次のエントリに進む
+    fn toggle_mark(&mut self) {
+        let Some(path) = self.selected_entry().map(|entry| entry.path.clone()) else {
+            return;
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.move_selection(1);
+    }
+
+    /// 一括操作の対象。印を付けたエントリが無ければ選択中のエントリ
+    fn bulk_targets(&self) -> Vec<PathBuf> {
+        if self.marked.is_empty() {
+            self.selected_entry().map(|entry| entry.path.clone()).into_iter().collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
+    }
+
+    fn next(&mut self) {
+        if self.entries.is_empty() { return; }
+        let i = self.list_state.selected().map_or(0, |i| {
+            if i >= self.entries.len() - 1 { 0 } else { i + 1 }
+        });
+        self.list_state.select(Some(i));
+    }
+
+    fn previous(&mut self) {
+        if self.entries.is_empty() { return; }
+        let i = self.list_state.selected().map_or(0, |i| {
+            if i == 0 { self.entries.len() - 1 } else { i - 1 }
+        });
+        self.list_state.select(Some(i));
+    }
+
+    /// 選択位置を `delta` だけ動かす。端で折り返さずに止まる
+    fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let last = self.entries.len() as isize - 1;
+        self.list_state.select(Some((current + delta).clamp(0, last) as usize));
+    }
+
+    fn select_first(&mut self) {
+        if !self.entries.is_empty() {
+            self.list_state.select(Some(0));
+        }
+    }
+
+    fn select_last(&mut self) {
+        if !self.entries.is_empty() {
+            self.list_state.select(Some(self.entries.len() - 1));
+        }
+    }
+
+    fn page_size(&self) -> isize {
+        self.visible_rows.max(1) as isize
+    }
+
+    /// 選択中のエントリが見える範囲に収まるよう、表示開始位置を調整する
+    fn scroll_to_selection(&mut self) {
+        let rows = self.visible_rows.max(1);
+        if let Some(selected) = self.list_state.selected() {
+            if selected < self.list_offset {
+                self.list_offset = selected;
+            } else if selected >= self.list_offset + rows {
+                self.list_offset = selected + 1 - rows;
+            }
+        }
+        self.list_offset = self.list_offset.min(self.entries.len().saturating_sub(rows));
+    }
+}
+
+/// 履歴に追加する。古いものから捨てて `HISTORY_LIMIT` 件までにする
+fn push_history(history: &mut Vec<PathBuf>, path: PathBuf) {
+    history.push(path);
+    if history.len() > HISTORY_LIMIT {
+        history.remove(0);
+    }
+}
+
+/// 設定ファイルそれぞれの更新日時（無ければNone）
+fn config_modified(project_root: Option<&Path>) -> Vec<Option<SystemTime>> {
+    settings::config_files(project_root).iter().map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok()).collect()
+}
+
+/// `:annotations` の一覧に表示するコメント（場所と1行目）
+fn annotation_label(annotation: &annotations::Annotation) -> String {
+    let place = match &annotation.target {
+        annotations::Target::Heading(anchor) => format!("#{}", anchor),
+        annotations::Target::Line(line) => format!("L{}", line),
+    };
+    format!("{}: {}", place, annotation.text.lines().next().unwrap_or(""))
+}
+
+/// 表示用のテキストの行ごとの文字列
+fn line_texts(text: &Text) -> Vec<String> {
+    text.lines.iter().map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect()).collect()
+}
+
+/// ファイルやディレクトリの更新日時
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// ヒントモードでラベルを付ける対象
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HintKind {
+    Links,
+    Images,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+/// 2つに分けて表示するときの、操作していない方の表示
+struct SplitView {
+    other: SplitPane,
+    /// 下（左右なら右）を操作しているかどうか。操作している方の表示位置は `PreviewState::scroll` が持つ
+    second_focused: bool,
+    /// 操作していない方を一緒にスクロールする方法
+    sync: ScrollSync,
+    /// 上下ではなく左右に並べるかどうか（`:translation`）
+    vertical: bool,
+}
+
+impl SplitView {
+    /// 同じ文書を上下に分け、下を操作する
+    fn same_document(scroll: u16) -> Self {
+        Self { other: SplitPane::Scroll(scroll), second_focused: true, sync: ScrollSync::Off, vertical: false }
+    }
+}
+
+/// 分けて表示するときの、操作していない方のスクロールの合わせ方
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScrollSync {
+    Off,
+    /// 同じ割合の位置に合わせる（`:set scrollbind`）
+    Proportional,
+    /// 同じ順番の節と段落に合わせる（`:translation`）
+    Paragraphs,
+}
+
+/// 操作していない方のペインの中身
+enum SplitPane {
+    /// 同じ文書の別の表示位置
+    Scroll(u16),
+    /// 別の文書（`:split <ファイル>`）。表示位置はその文書の状態が持つ
+    Document(Box<PreviewState>),
+}
+
+/// 描き直しても同じ場所を表示するための目印
+struct ScrollAnchor {
+    /// 先頭の行より前にある最後の見出しのアンカー名と、その見出しからの行数
+    heading: Option<(String, usize)>,
+    /// 先頭の行の行番号（見出しが見つからないときに使う）
+    line: usize,
+}
+
+/// スムーススクロールの途中経過
+struct ScrollAnimation {
+    from: u16,
+    to: u16,
+    started: Instant,
+}
+
+/// `:changelog` のリリース一覧や `:toc` の目次のように、見出しを選んで移動するポップアップ
+struct HeadingPicker {
+    title: &'static str,
+    /// 見出しの行番号と表示するテキスト
+    entries: Vec<(usize, String)>,
+    list_state: ListState,
+    /// `:history` では行番号の代わりにこのコミットの番号を持ち、選ぶとその時点の内容を開く
+    commits: Vec<git::Commit>,
+    /// `:backlinks` と `:related` では行番号の代わりにこの文書の番号を持ち、選ぶとその文書の（リンクの）行を開く
+    documents: Vec<(PathBuf, usize)>,
+}
+
+pub struct PreviewState {
+    /// 表示中のファイル。標準入力ならNone
+    path: Option<PathBuf>,
+    /// フィルターなどを適用した後のMarkdown（HTMLの書き出しに使う）
+    source: Option<String>,
+    pub content: Text<'static>,
+    /// Markdownをレンダリングした場合の見出しの行番号と、見出しのテキスト
+    heading_lines: Vec<usize>,
+    headings: Vec<String>,
+    /// 見出しのレベルと節番号（`:toc` の目次に使う）
+    heading_levels: Vec<usize>,
+    heading_numbers: Vec<String>,
+    /// 描画した行ごとの開いたファイルでの行番号（Markdownをレンダリングした場合だけ）。
+    /// 取り込んだ行はインクルード指令の行、org-modeの文書は変換する前の行
+    source_lines: Vec<usize>,
+    /// 描画した行ごとの、描画したMarkdown（インクルードの展開やorg-modeの変換をした後）での行番号
+    markdown_lines: Vec<usize>,
+    /// 描画したMarkdownの行ごとの出どころ。空なら描画したMarkdownの行が開いたファイルの行と同じ
+    line_origins: Vec<include::SourceLine>,
+    /// 脚注参照の位置と、ラベルごとの脚注の本文
+    footnote_refs: Vec<FootnoteRef>,
+    footnotes: HashMap<String, String>,
+    /// `]` / `[` で選んでいる脚注参照（`footnote_refs` の添字）
+    focused_footnote: Option<usize>,
+    links: Vec<LinkSpan>,
+    images: Vec<LinkSpan>,
+    /// コードブロックの中身の行番号（折り返し表示でも折り返さない）
+    code_lines: Vec<usize>,
+    /// `f` / `o` で始めたヒントモードの対象と入力中のラベル
+    hint_input: Option<(HintKind, String)>,
+    /// ヒントモードで `Tab` で選んでいるリンクのラベルと、フッターに表示する行き先。ヒントモードの間だけ使う
+    hint_focus: Option<(String, String)>,
+    /// `:readability` などの結果を表示するポップアップのタイトルと本文
+    info_popup: Option<(String, Vec<String>)>,
+    /// スペルチェックが有効なときの、辞書に無い単語
+    misspelled: Option<HashSet<String>>,
+    /// `:set blame` のときの、元のMarkdownの行ごとの最後の変更
+    blame: Option<Vec<git::BlameLine>>,
+    /// 別スレッドで調べているスペルチェックとblameの番号。届いた結果が最新の要求のものかを見分ける
+    pending_spell: Option<u64>,
+    pending_blame: Option<u64>,
+    /// 別スレッドで行うよう予約した時間のかかる処理（呼び出し側で別スレッドを起動する）
+    background: Vec<PreviewWork>,
+    /// リンクから開くよう求められたファイルとアンカー（呼び出し側で開く）
+    open_request: Option<(PathBuf, Option<String>)>,
+    /// `:history` で選ばれた、内容を開くコミット（呼び出し側で開く）
+    revision_request: Option<git::Commit>,
+    /// エディターで開くよう求められたファイルと行番号（呼び出し側で開く）
+    edit_request: Option<(PathBuf, usize)>,
+    /// 書き出しなどに失敗したときのエラー画面（呼び出し側で表示する）
+    error_report: Option<ErrorReport>,
+    /// `:log` でログの表示を求められたかどうか（呼び出し側で表示する）
+    show_log: bool,
+    /// `:graph` でリンクのグラフを開く要求
+    show_graph: bool,
+    /// フッターにレンダリング時間・行数・メモリ量を表示するかどうか
+    debug_footer: bool,
+    /// 直近のレンダリングにかかった時間と、表示用のテキストのおおよそのメモリ量
+    render_time: Option<Duration>,
+    /// 読み込んだときのファイルの更新日時と、最後に確認した時刻（`watch` で使う）
+    modified: Option<SystemTime>,
+    last_watch_check: Instant,
+    /// 保存されて読み込み直したときに変わった行と、読み込み直した時刻
+    changes: Option<(Instant, Vec<diff::Change>)>,
+    /// 上下に分けて表示しているときの、もう一方の表示
+    split: Option<SplitView>,
+    /// 隣のファイル（`doc.md.comments.toml`）に書いたコメント
+    annotations: Vec<annotations::Annotation>,
+    /// `[preview]` のコマンドが出力した画像を置いた、自分だけが読める一時ディレクトリ。プレビューを閉じると消える
+    preview_images: Option<tempfile::TempDir>,
+    /// 圧縮されたファイルの形式と、圧縮後・展開後のバイト数
+    compression: Option<(compressed::Codec, usize, usize)>,
+    /// 大きなファイルの先頭だけを読み込んだときの、読み込んだバイト数とファイル全体のバイト数
+    truncated: Option<(usize, usize)>,
+    content_size: usize,
+    scroll: u16,
+    /// 大きく移動するときのアニメーション。完了すると None に戻る
+    animation: Option<ScrollAnimation>,
+    /// ファイルのパスなど、文書の出どころを表す名前
+    pub title: String,
+    /// 文書のタイトル（フロントマターの `title` か最初のH1見出し）
+    document_title: Option<String>,
+    /// 文書のタイトルがあってもフッターに `title` を表示するかどうか
+    show_path: bool,
+    char_count: usize,
+    /// 横スクロール量。折り返しを切っているときだけ使う
+    h_scroll: u16,
+    /// 長い行を折り返して表示するかどうか
+    wrap: bool,
+    /// 直近の描画で使えた本文の高さ（ページ送りの幅に使う）
+    viewport_height: u16,
+    viewport_width: u16,
+    /// `/` または `?` で入力中の検索語
+    search_input: Option<(SearchDirection, String)>,
+    last_search: Option<(SearchDirection, String)>,
+    /// `:` で入力中のコマンド
+    command_input: Option<String>,
+    heading_picker: Option<HeadingPicker>,
+    pub message: Option<String>,
+}
+
+impl PreviewState {
+    fn new(file_path: &Path, config: &Config) -> io::Result<Self> {
+        Self::with_limit(file_path, config, config.file_size_limit())
+    }
+
+    /// `limit` バイトより大きいファイルは先頭だけを読み込んで開く
+    pub fn with_limit(file_path: &Path, config: &Config, limit: Option<usize>) -> io::Result<Self> {
+        tracing::info!(path = %file_path.display(), "open");
+        let mut preview_images = None;
+        let (source, total_size) = read_source(file_path, limit, config, Some(&mut preview_images))?;
+        if let Some(total_size) = total_size {
+            tracing::warn!("{} is {} bytes, loaded the first {} bytes", file_path.display(), total_size, source.len());
+        }
+        let truncated = total_size.map(|total_size| (source.len(), total_size));
+        // 圧縮されたファイルは、圧縮前後の大きさをフッターに表示する
+        let compression = compressed::codec(file_path).and_then(|codec| {
+            let metadata = fs::metadata(file_path).ok()?;
+            Some((codec, metadata.len() as usize, source.len()))
+        });
+        let (original_markdown, line_origins) = prepare_markdown(source, file_path, config)?;
+        let title = workspace::display_path(file_path, workspace::find_root(file_path).as_deref());
+        // 画像はchafaで変換できればブロック文字で、できなければプレースホルダーで表示する
+        let image_art = |url: &str| {
+            if !config.show_image_art() {
+                return None;
+            }
+            let LinkTarget::File(path, _) = links::resolve(url, Some(file_path)) else {
+                return None;
+            };
+            let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+            image_art::render(&path, width.saturating_sub(2), height.saturating_sub(4))
+        };
+        let mut state = Self::from_markdown(&original_markdown, title, config, &image_art);
+        state.path = Some(file_path.to_path_buf());
+        state.source = Some(original_markdown);
+        state.set_line_origins(line_origins);
+        state.preview_images = preview_images;
+        state.compression = compression;
+        state.truncated = truncated;
+        if config.spell {
+            state.set_spell(true, config);
+        }
+        state.debug_footer = config.debug_footer;
+        state.modified = modified_time(file_path);
+        // コメントのファイルが壊れていても文書は開く
+        match annotations::load(file_path) {
+            Ok(annotations) => state.annotations = annotations,
+            Err(e) => {
+                tracing::warn!("failed to read annotations for {}: {}", file_path.display(), e);
+                state.message = Some(format!("コメントを読み込めません: {}", e));
+            }
+        }
+        Ok(state)
+    }
+
+    fn from_markdown(original_markdown: &str, title: String, config: &Config, image_art: ImageArt) -> Self {
+        let (rendered, char_count) = render_markdown_document(original_markdown, config.color_scheme(), image_art, &config.render_options());
+        tracing::debug!(lines = rendered.text.lines.len(), elapsed = ?rendered.render_time, "rendered");
+        let mut state = Self::from_text(Text::default(), title, char_count);
+        state.replace_content(rendered, char_count);
+        state
+    }
+
+    fn from_text(content: Text<'static>, title: String, char_count: usize) -> Self {
+        let content_size = estimated_size(&content);
+        Self {
+            path: None,
+            source: None,
+            content,
+            heading_lines: Vec::new(),
+            headings: Vec::new(),
+            heading_levels: Vec::new(),
+            heading_numbers: Vec::new(),
+            source_lines: Vec::new(),
+            markdown_lines: Vec::new(),
+            line_origins: Vec::new(),
+            footnote_refs: Vec::new(),
+            footnotes: HashMap::new(),
+            focused_footnote: None,
+            links: Vec::new(),
+            images: Vec::new(),
+            code_lines: Vec::new(),
+            hint_input: None,
+            hint_focus: None,
+            info_popup: None,
+            misspelled: None,
+            blame: None,
+            pending_spell: None,
+            pending_blame: None,
+            background: Vec::new(),
+            open_request: None,
+            revision_request: None,
+            edit_request: None,
+            error_report: None,
+            show_log: false,
+            show_graph: false,
+            debug_footer: false,
+            render_time: None,
+            modified: None,
+            last_watch_check: Instant::now(),
+            changes: None,
+            split: None,
+            annotations: Vec::new(),
+            preview_images: None,
+            compression: None,
+            truncated: None,
+            content_size,
+            scroll: 0,
+            animation: None,
+            title,
+            document_title: None,
+            show_path: false,
+            char_count,
+            h_scroll: 0,
+            wrap: true,
+            viewport_height: 0,
+            viewport_width: 0,
+            search_input: None,
+            last_search: None,
+            command_input: None,
+            heading_picker: None,
+            message: None,
+        }
+    }
+
+    /// スクロール位置や検索状態を保ったまま内容だけを差し替える
+    fn replace_content(&mut self, rendered: RenderedMarkdown, char_count: usize) {
+        let anchor = self.scroll_anchor();
+        self.content = rendered.text;
+        self.heading_lines = rendered.heading_lines;
+        self.headings = rendered.headings;
+        self.heading_levels = rendered.heading_levels;
+        self.heading_numbers = rendered.heading_numbers;
+        self.markdown_lines = rendered.source_lines;
+        self.map_source_lines();
+        self.footnote_refs = rendered.footnote_refs;
+        self.footnotes = rendered.footnotes;
+        self.focused_footnote = None;
+        self.links = rendered.links;
+        self.images = rendered.images;
+        self.code_lines = rendered.code_lines;
+        self.document_title = rendered.title;
+        self.hint_input = None;
+        self.char_count = char_count;
+        if rendered.script_error.is_some() {
+            self.message = rendered.script_error;
+        }
+        self.render_time = Some(rendered.render_time);
+        self.content_size = estimated_size(&self.content);
+        self.restore_scroll(&anchor);
+    }
+
+    /// 表示位置の目印
+    fn scroll_anchor(&self) -> ScrollAnchor {
+        let line = self.target_scroll() as usize;
+        let heading = self.heading_lines.iter().rposition(|&heading_line| heading_line <= line).map(|index| {
+            let anchor = links::heading_anchors(&self.headings).swap_remove(index);
+            (anchor, line - self.heading_lines[index])
+        });
+        ScrollAnchor { heading, line }
+    }
+
+    /// 描き直した後、前と同じ見出しからの位置に戻す。前の部分の長さが変わっても表示中の節が飛ばないようにする。
+    /// 見出しが無くなっていれば同じ行番号に戻す
+    fn restore_scroll(&mut self, anchor: &ScrollAnchor) {
+        let anchors = links::heading_anchors(&self.headings);
+        let heading = anchor.heading.as_ref().and_then(|(name, offset)| {
+            let index = anchors.iter().position(|slug| slug == name)?;
+            Some((index, *self.heading_lines.get(index)?, *offset))
+        });
+        let target = match heading {
+            Some((index, line, offset)) => {
+                // 節が短くなっていれば、次の見出しを越えない
+                let section_end = self.heading_lines.get(index + 1).map_or(usize::MAX, |next| next.saturating_sub(1));
+                (line + offset).min(section_end.max(line))
+            }
+            None => anchor.line,
+        };
+        self.scroll = (target.min(u16::MAX as usize) as u16).min(self.max_scroll());
+        self.animation = None;
+    }
+
+    /// フッターなどに表示する名前。文書のタイトルがあればそれを使い、`p` でパスに切り替えられる
+    fn display_title(&self) -> &str {
+        match &self.document_title {
+            Some(title) if !self.show_path => title,
+            _ => &self.title,
+        }
+    }
+
+    fn max_scroll(&self) -> u16 {
+        // スクロール位置は一番上に描く行の番号なので（折り返した段の数ではない）、
+        // 最後の行を一番上に描けるところまでとする。これにより、画面の高さや折り返しに関わらずコンテンツの最後までスクロールできる
+        self.content.height().saturating_sub(1) as u16
+    }
+
+    /// `line` 行目を今の画面の幅で折り返して描いたときの段数
+    fn rows(&self, line: usize) -> usize {
+        match self.content.lines.get(line) {
+            // コードブロックの行は折り返さずに切り詰める
+            Some(text) if self.wrap && self.code_lines.binary_search(&line).is_err() => wrapped_rows(text, self.viewport_width as usize),
+            Some(_) => 1,
+            None => 0,
+        }
+    }
+
+    /// `top` 行目から描いたときに画面に収まる最後の行の次の行
+    fn visible_end(&self, top: usize) -> usize {
+        let mut rows = 0;
+        let mut end = top;
+        while end < self.content.lines.len() && rows < self.viewport_height.max(1) as usize {
+            rows += self.rows(end);
+            end += 1;
+        }
+        end
+    }
+
+    /// `from` 行目から折り返した段で `rows` 段だけ進んだ（負なら戻った）ところの行
+    fn line_after_rows(&self, from: i32, rows: i32) -> i32 {
+        let mut line = from;
+        let mut remaining = rows.unsigned_abs() as usize;
+        while remaining > 0 {
+            let next = if rows > 0 { line } else { line - 1 };
+            if next < 0 || next as usize >= self.content.lines.len() {
+                break;
+            }
+            // 残りの段に収まらない行の手前で止める。ただし少なくとも1行は進む
+            let next_rows = self.rows(next as usize);
+            if next_rows > remaining && line != from {
+                break;
+            }
+            remaining = remaining.saturating_sub(next_rows);
+            line += rows.signum();
+        }
+        line
+    }
+
+    /// アニメーション中なら移動先、そうでなければ現在のスクロール位置
+    fn target_scroll(&self) -> u16 {
+        self.animation.as_ref().map_or(self.scroll, |animation| animation.to)
+    }
+
+    fn clamp_scroll(&self, position: i32) -> u16 {
+        position.clamp(0, self.max_scroll() as i32) as u16
+    }
+
+    /// 1行単位の移動。アニメーションはせずに即座に動かす
+    fn scroll_by(&mut self, delta: i32) {
+        self.scroll = self.clamp_scroll(self.target_scroll() as i32 + delta);
+        self.animation = None;
+    }
+
+    /// ページ送りや検索などの大きな移動。`animate` なら数フレームかけて移動する
+    fn jump_to(&mut self, target: u16, animate: bool) {
+        let target = self.clamp_scroll(target as i32);
+        if animate && target.abs_diff(self.scroll) > 1 {
+            self.animation = Some(ScrollAnimation {
+                from: self.scroll,
+                to: target,
+                started: Instant::now(),
+            });
+        } else {
+            self.scroll = target;
+            self.animation = None;
+        }
+    }
+
+    /// アニメーションを1フレーム進める。まだ続く場合は true を返す
+    fn tick_animation(&mut self) -> bool {
+        let Some(animation) = &self.animation else {
+            return false;
+        };
+        let progress = animation.started.elapsed().as_secs_f32() / SMOOTH_SCROLL_DURATION.as_secs_f32();
+        if progress >= 1.0 {
+            self.scroll = animation.to;
+            self.animation = None;
+            return false;
+        }
+        // ease-out: 動き始めは速く、移動先に近づくほどゆっくりにする
+        let eased = 1.0 - (1.0 - progress).powi(3);
+        let distance = animation.to as f32 - animation.from as f32;
+        self.scroll = (animation.from as f32 + distance * eased).round() as u16;
+        true
+    }
+
+    fn page_size(&self) -> i32 {
+        self.viewport_height.max(1) as i32
+    }
+
+    /// ページ送り。`snap` なら移動先の近くにある見出しの位置で止める
+    /// `delta` は画面の段数で、折り返した行は描いたときの段数で数える
+    fn page_by(&mut self, delta: i32, snap: bool, animate: bool) {
+        let current = self.target_scroll() as i32;
+        let target = self.clamp_scroll(self.line_after_rows(current, delta)) as i32;
+        if !snap || target == current {
+            self.jump_to(target as u16, animate);
+            return;
+        }
+        // 半分以上は進むようにし、その範囲で移動先に最も近い見出しを選ぶ
+        let halfway = self.line_after_rows(current, delta / 2);
+        let (low, high) = if delta > 0 { (halfway.max(current + 1), target) } else { (target, halfway.min(current - 1)) };
+        let snapped = self
+            .heading_lines
+            .iter()
+            .map(|line| *line as i32)
+            .filter(|line| (low..=high).contains(line))
+            .min_by_key(|line| line.abs_diff(target));
+        self.jump_to(snapped.unwrap_or(target) as u16, animate);
+    }
+
+    /// 横スクロールする。横スクロールは折り返しと両立しないので、折り返しを解除する
+    fn scroll_horizontally(&mut self, delta: i32) {
+        self.wrap = false;
+        let max_h_scroll = self.content.width().saturating_sub(self.viewport_width as usize) as i32;
+        self.h_scroll = (self.h_scroll as i32 + delta).clamp(0, max_h_scroll.max(0)) as u16;
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.h_scroll = 0;
+    }
+
+    /// 先頭だけを読み込んだファイルを全体で開き直す。スクロール位置と折り返しは引き継ぐ
+    fn load_all(&mut self, config: &Config) {
+        self.reload_with_limit(config, None);
+    }
+
+    /// 設定が変わったときに読み込み直す。Lで全部読み込んだ文書は全部読み込んだままにする
+    fn reload(&mut self, config: &Config) {
+        let limit = if self.truncated.is_some() { config.file_size_limit() } else { None };
+        self.reload_with_limit(config, limit);
+    }
+
+    /// 同じファイルを読み込み直す。表示位置と折り返し、blame・スペルチェック・コメントの状態は保つ
+    fn reload_with_limit(&mut self, config: &Config, limit: Option<usize>) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        match PreviewState::with_limit(&path, config, limit) {
+            Ok(mut state) => {
+                state.restore_scroll(&self.scroll_anchor());
+                state.wrap = self.wrap;
+                state.h_scroll = self.h_scroll;
+                // blameは別スレッドで調べ直し、届くまでは前の結果を表示する
+                if self.blame.is_some() || self.pending_blame.is_some() {
+                    state.blame = self.blame.take();
+                    state.set_blame(true);
+                }
+                // `:set spell` で切り替えたスペルチェックは、行が変わるので調べ直す
+                let spell = self.misspelled.is_some() || self.pending_spell.is_some();
+                if spell != state.pending_spell.is_some() {
+                    state.set_spell(spell, config);
+                }
+                // コメントのファイルが読めなくなっても、読めていたコメントは表示し続ける
+                if state.annotations.is_empty() && !self.annotations.is_empty() && annotations::load(&path).is_err() {
+                    state.annotations = std::mem::take(&mut self.annotations);
+                }
+                let max_scroll = state.max_scroll();
+                state.split = self.split.take();
+                if let Some(SplitView { other: SplitPane::Scroll(scroll), .. }) = &mut state.split {
+                    *scroll = (*scroll).min(max_scroll);
+                }
+                *self = state;
+            }
+            Err(e) => self.error_report = Some(ErrorReport::new(format!("Failed to load {}", path.display()), &e)),
+        }
+    }
+
+    /// 一定間隔でファイルの更新日時を確認し、保存されていれば読み込み直して変わった行を記録する
+    fn reload_if_changed(&mut self, config: &Config) {
+        if self.last_watch_check.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.last_watch_check = Instant::now();
+        let Some(path) = &self.path else {
+            return;
+        };
+        let modified = modified_time(path);
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        tracing::info!(path = %path.display(), "changed on disk, reloading");
+        // 読み込みに失敗しても、同じ変更で何度も読み込み直さない
+        self.modified = modified;
+        let old_lines = line_texts(&self.content);
+        self.reload(config);
+        if self.error_report.is_none() {
+            let changes = diff::changes(&old_lines, &line_texts(&self.content));
+            self.changes = (!changes.is_empty()).then(|| (Instant::now(), changes));
+        }
+    }
+
+    /// 表示中の行が左右に見切れているか（左, 右）
+    fn horizontal_overflow(&self) -> (bool, bool) {
+        if self.wrap {
+            return (false, false);
+        }
+        let visible_right = self.h_scroll as usize + self.viewport_width as usize;
+        let right = self
+            .content
+            .lines
+            .iter()
+            .skip(self.scroll as usize)
+            .take(self.viewport_height as usize)
+            .any(|line| line.width() > visible_right);
+        (self.h_scroll > 0, right)
+    }
+
+    /// 検索語の入力中のキー操作を処理する
+    fn handle_search_input(&mut self, key: KeyEvent, animate: bool) {
+        let Some((direction, input)) = &mut self.search_input else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                let search = (*direction, std::mem::take(input));
+                self.search_input = None;
+                if search.1.is_empty() {
+                    // less と同じく、空のまま確定したら前回の検索語で再検索する
+                    if let Some((_, query)) = self.last_search.clone() {
+                        self.last_search = Some((search.0, query));
+                    }
+                } else {
+                    self.last_search = Some(search);
+                }
+                self.search_next(false, animate);
+            }
+            KeyCode::Esc => self.search_input = None,
+            KeyCode::Backspace if input.pop().is_none() => self.search_input = None,
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+    }
+
+    /// 前回の検索語で次の一致行へ移動する。`reverse` なら逆方向に探す
+    fn search_next(&mut self, reverse: bool, animate: bool) {
+        let Some((direction, query)) = &self.last_search else {
+            self.message = Some("前回の検索語がありません".to_string());
+            return;
+        };
+        let forward = (*direction == SearchDirection::Forward) != reverse;
+        let current = self.target_scroll() as usize;
+        let matches = |index: &usize| self.line_text(*index).contains(query.as_str());
+        let found = if forward {
+            (current + 1..self.content.lines.len()).find(matches)
+        } else {
+            (0..current).rev().find(matches)
+        };
+        match found {
+            Some(index) => {
+                self.jump_to(index as u16, animate);
+                self.message = None;
+            }
+            None => self.message = Some(format!("見つかりません: {}", query)),
+        }
+    }
+
+    fn line_text(&self, index: usize) -> String {
+        self.content.lines[index]
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    /// `:export <形式> <パス> [幅]` でレンダリング結果をファイルに書き出す
+    fn export(&mut self, argument: &str, config: &Config) {
+        let Some(words) = shell_words::split(argument) else {
+            self.message = Some(format!("Unterminated quote: {}", argument.trim()));
+            return;
+        };
+        let arguments: Vec<&str> = words.iter().map(String::as_str).collect();
+        // 引数の誤りはフッターに表示し、書き出しの失敗はエラー画面に表示する
+        let planned: Result<(&str, io::Result<String>), String> = match arguments.as_slice() {
+            ["txt", path, rest @ ..] => {
+                let width = match rest {
+                    [] => Ok(export::DEFAULT_TEXT_WIDTH),
+                    [width] => width.parse().map_err(|_| format!("幅が正しくありません: {}", width)),
+                    _ => Err("使い方: :export txt <パス> [幅]".to_string()),
+                };
+                width.map(|width| (*path, Ok(export::plain_text(&self.content, width))))
+            }
+            ["html", rest @ ..] => {
+                let self_contained = rest.contains(&"--self-contained");
+                match (rest.iter().filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>().as_slice(), &self.source) {
+                    ([path], Some(markdown)) => {
+                        // テンプレートの相対パスは文書を含むリポジトリのルート（無ければカレントディレクトリ）から解決する
+                        let base = self
+                            .path
+                            .as_deref()
+                            .and_then(|path| dunce::canonicalize(path).ok())
+                            .and_then(|path| workspace::find_root(&path))
+                            .unwrap_or_default();
+                        let html = export::HtmlAssets::load(&config.html_template, &config.html_css, &base).map(|assets| {
+                            export::html_document(markdown, &self.export_title(), self.path.as_deref(), self_contained, &assets)
+                        });
+                        Ok((**path, html))
+                    }
+                    (_, None) => Err("この表示には書き出すMarkdownがありません".to_string()),
+                    _ => Err("使い方: :export html [--self-contained] <パス>".to_string()),
+                }
+            }
+            ["slides", path] => match &self.source {
+                Some(markdown) => Ok((*path, Ok(export::slides_document(markdown, &self.export_title())))),
+                None => Err("この表示には書き出すMarkdownがありません".to_string()),
+            },
+            ["slides", ..] => Err("使い方: :export slides <パス>".to_string()),
+            [format, ..] => Err(format!("書き出せない形式です: {}", format)),
+            [] => Err("使い方: :export txt|html|slides ...".to_string()),
+        };
+        match planned {
+            Ok((path, contents)) => {
+                let path = templates::expand_home(path);
+                match contents.and_then(|contents| fs::write(&path, contents)) {
+                    Ok(()) => {
+                        tracing::info!("exported {}", path.display());
+                        self.message = Some(format!("{} に書き出しました", path.display()));
+                    }
+                    Err(e) => {
+                        self.error_report = Some(ErrorReport::new(format!("{} に書き出せません", path.display()), &e));
+                    }
+                }
+            }
+            Err(usage) => self.message = Some(usage),
+        }
+    }
+
+    /// 書き出す文書のタイトル。ファイルならファイル名を使う
+    fn export_title(&self) -> String {
+        self.path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map_or_else(|| self.title.clone(), |name| name.to_string_lossy().to_string())
+    }
+
+    /// スペルチェックの表示を切り替える。有効にするときに文書全体を検査する
+    fn set_spell(&mut self, enabled: bool, config: &Config) {
+        if !enabled {
+            self.misspelled = None;
+            self.pending_spell = None;
+            return;
+        }
+        // hunspellの起動と辞書の読み込みは時間がかかるので、別スレッドで行う
+        let id = next_task_id();
+        self.pending_spell = Some(id);
+        let lines = self.content.lines.clone();
+        let theme = config.color_scheme();
+        let language = config.spell_language.clone();
+        self.run_in_background(move || {
+            let result = spell::check(&lines, theme, &language);
+            move |state: &mut PreviewState| {
+                if state.pending_spell != Some(id) {
+                    return;
+                }
+                state.pending_spell = None;
+                match result {
+                    Ok(misspelled) => state.misspelled = Some(misspelled),
+                    Err(e) => {
+                        state.misspelled = None;
+                        state.message = Some(e.to_string());
+                    }
+                }
+            }
+        });
+    }
+
+    /// 段落などのまとまりごとに、最後に変更した作者と日時を右端に表示するかどうかを設定する。
+    /// `git blame` は別スレッドで行い、結果が届くまでは前の結果を表示しておく
+    fn set_blame(&mut self, enabled: bool) {
+        if !enabled {
+            self.blame = None;
+            self.pending_blame = None;
+            return;
+        }
+        let Some(path) = self.path.clone() else {
+            self.message = Some("Blame needs a file".to_string());
+            return;
+        };
+        let id = next_task_id();
+        self.pending_blame = Some(id);
+        self.run_in_background(move || {
+            let result = git::blame(&path);
+            move |state: &mut PreviewState| {
+                if state.pending_blame != Some(id) {
+                    return;
+                }
+                state.pending_blame = None;
+                match result {
+                    Ok(blame) => state.blame = Some(blame),
+                    Err(e) => {
+                        state.blame = None;
+                        state.message = Some(format!("Failed to run git blame: {}", e));
+                    }
+                }
+            }
+        });
+    }
+
+    /// 時間のかかる処理を別スレッドで行うよう予約する。`work` が返した関数で、結果をこのプレビューに反映する
+    fn run_in_background<F>(&mut self, work: impl FnOnce() -> F + Send + 'static)
+    where
+        F: FnOnce(&mut PreviewState) + Send + 'static,
+    {
+        self.background.push(Box::new(move || Box::new(work()) as PreviewUpdate));
+    }
+
+    /// 空行で区切ったまとまりの先頭の行と、まとまりの中で最も新しい変更の作者と経過時間
+    fn blame_labels(&self) -> Vec<(usize, String)> {
+        let Some(blame) = &self.blame else {
+            return Vec::new();
+        };
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let texts = line_texts(&self.content);
+        let mut labels = Vec::new();
+        let mut index = 0;
+        while index < texts.len() {
+            if texts[index].trim().is_empty() {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < texts.len() && !texts[index].trim().is_empty() {
+                index += 1;
+            }
+            let newest = self.source_lines[start.min(self.source_lines.len())..index.min(self.source_lines.len())]
+                .iter()
+                .filter_map(|&line| blame.get(line.checked_sub(1)?))
+                .max_by_key(|line| line.time);
+            if let Some(newest) = newest {
+                let label = match &newest.author {
+                    Some(author) => format!("{} · {}", author, git::age(now.saturating_sub(newest.time))),
+                    None => "uncommitted".to_string(),
+                };
+                labels.push((start, label));
+            }
+        }
+        labels
+    }
+
+    /// 文書中のリリースの見出し
+    fn releases(&self) -> Vec<changelog::Release> {
+        let headings: Vec<(usize, usize, String)> = self
+            .heading_lines
+            .iter()
+            .zip(&self.headings)
+            .zip(&self.heading_levels)
+            .filter(|&((&line, _), _)| line < self.content.lines.len())
+            .map(|((&line, heading), &level)| (line, level, heading.clone()))
+            .collect();
+        changelog::releases(&headings)
+    }
+
+    fn handle_command_input(&mut self, key: KeyEvent, config: &Config) {
+        let Some(input) = &mut self.command_input else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                let command = std::mem::take(input);
+                self.command_input = None;
+                self.run_command(command.trim(), config);
+            }
+            KeyCode::Esc => self.command_input = None,
+            KeyCode::Backspace if input.pop().is_none() => self.command_input = None,
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+    }
+
+    fn run_command(&mut self, command: &str, config: &Config) {
+        let animate = config.animate_scroll();
+        let command = config.expand_alias(command);
+        let command = command.trim();
+        let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        match name {
+            "" => {}
+            "readability" => {
+                let theme = config.color_scheme();
+                let blocks: Vec<String> = self
+                    .content
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        line.spans
+                            .iter()
+                            .filter(|span| !spell::is_code(span.style, theme))
+                            .map(|span| span.content.as_ref())
+                            .collect()
+                    })
+                    .collect();
+                let stats = readability::analyze(blocks.iter().map(String::as_str));
+                self.info_popup = Some(("Readability".to_string(), stats.report()));
+            }
+            "export" => self.export(argument, config),
+            "log" => self.show_log = true,
+            // `"my notes.md"` のように引用符で囲めば空白を含むパスも渡せる
+            "split" => match shell_words::split(argument).as_deref() {
+                None => self.message = Some(format!("Unterminated quote: {}", argument.trim())),
+                Some([]) => self.split = Some(SplitView::same_document(self.target_scroll())),
+                Some([target]) => self.split_with(target, config),
+                Some(_) => self.message = Some("Usage: :split [path]".to_string()),
+            },
+            "translation" => self.open_translation(config),
+            "annotate" => self.annotate(argument.trim()),
+            "annotations" if !argument.trim().is_empty() => match shell_words::split(argument).as_deref() {
+                None => self.message = Some(format!("Unterminated quote: {}", argument.trim())),
+                Some([subcommand, path]) if subcommand == "export" => self.export_annotations(path),
+                _ => self.message = Some("使い方: :annotations [export <パス>]".to_string()),
+            },
+            "annotations" => {
+                let entries: Vec<(usize, String)> = self
+                    .annotation_lines()
+                    .into_iter()
+                    .map(|(line, annotation)| (line, annotation_label(annotation)))
+                    .collect();
+                let missing = self.annotations.len() - entries.len();
+                if entries.is_empty() {
+                    self.message = Some("コメントがありません".to_string());
+                } else {
+                    if missing > 0 {
+                        self.message = Some(format!("{} 件のコメントは付けた見出しか行が見つかりません", missing));
+                    }
+                    self.open_heading_picker("Annotations", entries);
+                }
+            }
+            "set" => match argument.trim() {
+                "scrollbind" => self.set_scroll_sync(Some(true)),
+                "noscrollbind" => self.set_scroll_sync(Some(false)),
+                "scrollbind!" | "invscrollbind" => self.set_scroll_sync(None),
+                "spell" => self.set_spell(true, config),
+                "nospell" => self.set_spell(false, config),
+                "spell!" | "invspell" => self.set_spell(self.misspelled.is_none() && self.pending_spell.is_none(), config),
+                "blame" => self.set_blame(true),
+                "noblame" => self.set_blame(false),
+                "blame!" | "invblame" => self.set_blame(self.blame.is_none() && self.pending_blame.is_none()),
+                "debug" => self.debug_footer = true,
+                "nodebug" => self.debug_footer = false,
+                "debug!" | "invdebug" => self.debug_footer = !self.debug_footer,
+                option => self.message = Some(format!("Unknown option: {}", option)),
+            },
+            "changelog" => {
+                let releases = self.releases();
+                if releases.is_empty() {
+                    self.message = Some("Not a changelog: no release headings found".to_string());
+                } else if argument.trim().is_empty() {
+                    let entries = releases.into_iter().map(|release| (release.line, release.heading)).collect();
+                    self.open_heading_picker("Releases", entries);
+                } else {
+                    match changelog::find(&releases, argument) {
+                        Some(release) => self.jump_to(release.line as u16, animate),
+                        None => self.message = Some(format!("Version not found: {}", argument.trim())),
+                    }
+                }
+            }
+            "goto" => match argument.trim().parse::<usize>() {
+                Ok(line) if line > 0 => self.goto_source_line(line, animate),
+                _ => self.message = Some("Usage: :goto <line>".to_string()),
+            },
+            "history" => self.open_history(),
+            "backlinks" => self.open_backlinks(config),
+            "graph" => self.show_graph = true,
+            "related" => self.open_related(config),
+            "toc" => {
+                let entries = self.table_of_contents();
+                if entries.is_empty() {
+                    self.message = Some("No headings found".to_string());
+                } else {
+                    self.open_heading_picker("Contents", entries);
+                }
+            }
+            _ => match config.script() {
+                Ok(Some(script)) if script.has_command(name) => self.run_script_command(script, name, argument, animate),
+                _ => self.message = Some(format!("Unknown command: {}", name)),
+            },
+        }
+    }
+
+    /// スクリプトの `command_<name>` を別スレッドで呼び、返されたメッセージの表示・ファイルを開く・行への移動を行う
+    fn run_script_command(&mut self, script: Arc<script::Script>, name: &str, argument: &str, animate: bool) {
+        let Some(args) = shell_words::split(argument) else {
+            self.message = Some(format!("Failed to parse the arguments: {}", argument));
+            return;
+        };
+        let name = name.to_string();
+        let path = self.path.clone();
+        let line = self.edit_target().map_or(1, |(_, line)| line);
+        self.run_in_background(move || {
+            let result = script.command(&name, args, path.as_deref(), line);
+            move |state: &mut PreviewState| match result {
+                Ok(result) => {
+                    state.message = result.message;
+                    if let Some(line) = result.line {
+                        state.goto_source_line(line, animate);
+                    }
+                    if let Some(target) = result.open {
+                        let base = state.path.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+                        state.open_request = Some((base.join(target), None));
+                    }
+                }
+                Err(e) => state.message = Some(e.to_string()),
+            }
+        });
+    }
+
+    /// 描画したMarkdownの行の出どころを覚え、描画した行ごとの開いたファイルでの行番号を作り直す
+    fn set_line_origins(&mut self, line_origins: Vec<include::SourceLine>) {
+        self.line_origins = line_origins;
+        self.map_source_lines();
+    }
+
+    fn map_source_lines(&mut self) {
+        let origins = &self.line_origins;
+        self.source_lines = self
+            .markdown_lines
+            .iter()
+            .map(|&line| line.checked_sub(1).and_then(|index| origins.get(index)).map_or(line, |origin| origin.line))
+            .collect();
+    }
+
+    /// 表示中の先頭の行を編集するときのファイルと行番号。取り込んだ行なら取り込んだファイルの行
+    fn edit_target(&self) -> Option<(PathBuf, usize)> {
+        let top = (self.target_scroll() as usize).min(self.markdown_lines.len().checked_sub(1)?);
+        let line = self.markdown_lines[top];
+        match self.line_origins.get(line.checked_sub(1)?) {
+            Some(include::SourceLine { included: Some((file, line)), .. }) => Some((file.clone(), *line)),
+            Some(origin) => Some((self.path.clone()?, origin.line)),
+            None => Some((self.path.clone()?, line)),
+        }
+    }
+
+    /// 開いたファイルの `line` 行目（から後で最初に描画された行）に移動する
+    fn goto_source_line(&mut self, line: usize, animate: bool) {
+        if self.source_lines.is_empty() {
+            self.message = Some("この文書は元の行との対応がありません".to_string());
+            return;
+        }
+        let target = self.source_lines.iter().position(|&source| source >= line).unwrap_or(self.source_lines.len() - 1);
+        self.jump_to(target as u16, animate);
+    }
+
+    /// 表示中の先頭の行に対応する元のMarkdownの行番号
+    fn current_source_line(&self) -> Option<usize> {
+        let top = (self.target_scroll() as usize).min(self.source_lines.len().checked_sub(1)?);
+        self.source_lines.get(top).copied()
+    }
+
+    /// 別の文書を開いて下に並べ、そちらを操作する。今の文書は上に残る
+    fn split_with(&mut self, target: &str, config: &Config) {
+        let LinkTarget::File(path, anchor) = links::resolve(target, self.path.as_deref()) else {
+            self.message = Some(format!("Not a local file: {}", target));
+            return;
+        };
+        let sync = self.split.as_ref().map_or(ScrollSync::Off, |split| split.sync);
+        self.open_split(&path, anchor.as_deref(), sync, false, config);
+    }
+
+    /// 原文と翻訳を左右に並べ、段落を合わせてスクロールする。操作するのは開いた方
+    fn open_translation(&mut self, config: &Config) {
+        let Some(path) = self.path.clone() else {
+            self.message = Some("No file to find a translation for".to_string());
+            return;
+        };
+        let candidates = translation::candidates(&path, &config.translation_patterns);
+        match candidates.iter().find(|candidate| candidate.is_file()) {
+            Some(found) => self.open_split(found, None, ScrollSync::Paragraphs, true, config),
+            None => {
+                let tried: Vec<String> = candidates.iter().map(|candidate| candidate.display().to_string()).collect();
+                self.message = Some(format!("No translation found (tried: {})", tried.join(", ")));
+            }
+        }
+    }
+
+    /// `path` を開いて今の文書の隣に並べ、そちらを操作する
+    fn open_split(&mut self, path: &Path, anchor: Option<&str>, sync: ScrollSync, vertical: bool, config: &Config) {
+        let mut other = match PreviewState::new(path, config) {
+            Ok(state) => state,
+            Err(e) => {
+                self.message = Some(format!("Failed to open {}: {}", path.display(), e));
+                return;
+            }
+        };
+        if let Some(anchor) = anchor {
+            other.jump_to_anchor(anchor, false);
+        }
+        self.animation = None;
+        std::mem::swap(self, &mut other);
+        self.viewport_height = other.viewport_height;
+        self.viewport_width = other.viewport_width;
+        other.split = None;
+        self.split = Some(SplitView { other: SplitPane::Document(Box::new(other)), second_focused: true, sync, vertical });
+    }
+
+    /// 上下のペインの操作する側を切り替える。別の文書なら、操作する文書ごと入れ替える
+    fn switch_split_pane(&mut self) {
+        let Some(mut split) = self.split.take() else {
+            return;
+        };
+        split.second_focused = !split.second_focused;
+        let scroll = self.animation.take().map_or(self.scroll, |animation| animation.to);
+        match &mut split.other {
+            SplitPane::Scroll(other) => self.scroll = std::mem::replace(other, scroll),
+            SplitPane::Document(other) => {
+                self.scroll = scroll;
+                let (height, width) = (self.viewport_height, self.viewport_width);
+                std::mem::swap(self, other.as_mut());
+                (self.viewport_height, self.viewport_width) = (height, width);
+            }
+        }
+        self.split = Some(split);
+    }
+
+    /// 2つのペインを一緒にスクロールするかどうかを設定する（Noneなら切り替える）
+    fn set_scroll_sync(&mut self, sync: Option<bool>) {
+        let Some(split) = &mut self.split else {
+            self.message = Some("scrollbind needs a split view (s or :split <file>)".to_string());
+            return;
+        };
+        let on = sync.unwrap_or(split.sync == ScrollSync::Off);
+        split.sync = if on { ScrollSync::Proportional } else { ScrollSync::Off };
+        self.message = Some(if on { "Scroll sync on" } else { "Scroll sync off" }.to_string());
+    }
+
+    /// 一緒にスクロールするとき、操作していない方を操作している方に合わせる
+    fn sync_split_scroll(&mut self) {
+        let ratio = self.scroll as f64 / self.max_scroll().max(1) as f64;
+        let outline = match &self.split {
+            Some(SplitView { sync: ScrollSync::Paragraphs, .. }) => Some(self.outline()),
+            _ => None,
+        };
+        match &mut self.split {
+            Some(SplitView { sync: ScrollSync::Off, .. }) | None => {}
+            Some(SplitView { other: SplitPane::Document(other), .. }) => match outline {
+                Some(outline) => {
+                    let line = outline.align(self.scroll as usize, &other.outline());
+                    other.scroll = (line as u16).min(other.max_scroll());
+                }
+                None => other.scroll = (ratio * other.max_scroll() as f64).round() as u16,
+            },
+            Some(SplitView { other: SplitPane::Scroll(other), .. }) => *other = self.scroll,
+        }
+    }
+
+    /// 段落を合わせるための、描画した行の節と段落の構成
+    fn outline(&self) -> translation::Outline {
+        let blank: Vec<bool> = line_texts(&self.content).iter().map(|line| line.trim().is_empty()).collect();
+        translation::Outline::new(&blank, &self.heading_lines)
+    }
+
+    /// 表示中の先頭の行にコメントを付け、隣のファイルに書き足す。先頭の行が見出しなら見出しに付ける
+    fn annotate(&mut self, text: &str) {
+        if text.is_empty() {
+            self.message = Some("使い方: :annotate <コメント>".to_string());
+            return;
+        }
+        let Some(path) = self.path.clone() else {
+            self.message = Some("コメントはファイルにしか付けられません".to_string());
+            return;
+        };
+        let top = self.target_scroll() as usize;
+        let target = match self.heading_lines.iter().position(|&line| line == top) {
+            Some(index) => annotations::Target::Heading(links::heading_anchors(&self.headings).swap_remove(index)),
+            None => match self.current_source_line() {
+                Some(line) => annotations::Target::Line(line),
+                None => {
+                    self.message = Some("この文書は元の行との対応がありません".to_string());
+                    return;
+                }
+            },
+        };
+        let annotation = annotations::Annotation { target, text: text.to_string(), date: Some(templates::today()) };
+        match annotations::append(&path, &annotation) {
+            Ok(()) => {
+                self.annotations.push(annotation);
+                self.message = Some(format!("{} にコメントを書き足しました", annotations::sidecar_path(&path).display()));
+            }
+            Err(e) => self.error_report = Some(ErrorReport::new("コメントを保存できません".to_string(), &e)),
+        }
+    }
+
+    /// コメントを引用付きのMarkdownの報告にして書き出す
+    fn export_annotations(&mut self, path: &str) {
+        let Some(source) = &self.source else {
+            self.message = Some("この表示には書き出すMarkdownがありません".to_string());
+            return;
+        };
+        if self.annotations.is_empty() {
+            self.message = Some("コメントがありません".to_string());
+            return;
+        }
+        // 場所の分かるコメントを文書の順に並べ、見つからないものは最後に置く
+        let mut entries: Vec<(Option<usize>, &annotations::Annotation)> = self
+            .annotation_lines()
+            .into_iter()
+            .map(|(line, annotation)| (self.source_lines.get(line).copied(), annotation))
+            .collect();
+        let missing: Vec<(Option<usize>, &annotations::Annotation)> = self
+            .annotations
+            .iter()
+            .filter(|annotation| !entries.iter().any(|(_, placed)| std::ptr::eq(*placed, *annotation)))
+            .map(|annotation| (None, annotation))
+            .collect();
+        entries.extend(missing);
+        // 行番号は開いたファイルの行なので、インクルードなどで行がずれていればファイルから引用する
+        let file_source = match &self.path {
+            Some(path) if !self.line_origins.is_empty() => fs::read_to_string(path).ok(),
+            _ => None,
+        };
+        let report = annotations::report(&self.title, file_source.as_deref().unwrap_or(source), &entries);
+        let path = templates::expand_home(path);
+        match fs::write(&path, report) {
+            Ok(()) => {
+                tracing::info!("exported annotations to {}", path.display());
+                self.message = Some(format!("{} 件のコメントを {} に書き出しました", entries.len(), path.display()));
+            }
+            Err(e) => self.error_report = Some(ErrorReport::new(format!("{} に書き出せません", path.display()), &e)),
+        }
+    }
+
+    /// コメントを付けた描画した行の一覧（行の順）。場所が見つからないコメントは含めない
+    fn annotation_lines(&self) -> Vec<(usize, &annotations::Annotation)> {
+        let mut lines: Vec<(usize, &annotations::Annotation)> = self
+            .annotations
+            .iter()
+            .filter_map(|annotation| {
+                let line = match &annotation.target {
+                    annotations::Target::Heading(anchor) => self.anchor_line(anchor)?,
+                    annotations::Target::Line(line) => self.source_lines.iter().position(|source| source >= line)?,
+                };
+                Some((line, annotation))
+            })
+            .collect();
+        lines.sort_by_key(|(line, _)| *line);
+        lines
+    }
+
+    /// 見出しの目次。レベルに応じて字下げし、節番号があれば前に付ける
+    fn table_of_contents(&self) -> Vec<(usize, String)> {
+        let top = self.heading_levels.iter().copied().min().unwrap_or(1);
+        self.heading_lines
+            .iter()
+            .zip(&self.headings)
+            .zip(self.heading_levels.iter().zip(&self.heading_numbers))
+            .filter(|&((&line, _), _)| line < self.content.lines.len())
+            .map(|((&line, heading), (&level, number))| {
+                let indent = "  ".repeat(level - top);
+                let label = if number.is_empty() { heading.clone() } else { format!("{} {}", number, heading) };
+                (line, format!("{}{}", indent, label))
+            })
+            .collect()
+    }
+
+    fn open_heading_picker(&mut self, title: &'static str, entries: Vec<(usize, String)>) {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        self.heading_picker = Some(HeadingPicker { title, entries, list_state, commits: Vec::new(), documents: Vec::new() });
+    }
+
+    /// 表示中のファイルを変更したコミットの一覧を開く
+    fn open_history(&mut self) {
+        let Some(path) = self.path.clone() else {
+            self.message = Some("履歴を表示するファイルがありません".to_string());
+            return;
+        };
+        self.run_in_background(move || {
+            let result = git::log(&path);
+            move |state: &mut PreviewState| state.show_history(result)
+        });
+    }
+
+    /// 別スレッドで読んだ `git log` の結果を一覧にする
+    fn show_history(&mut self, result: io::Result<Vec<git::Commit>>) {
+        match result {
+            Ok(commits) if commits.is_empty() => self.message = Some("このファイルを変更したコミットはありません".to_string()),
+            Ok(commits) => {
+                let entries = commits
+                    .iter()
+                    .enumerate()
+                    .map(|(index, commit)| (index, format!("{} {} {}", commit.hash, commit.date, commit.summary)))
+                    .collect();
+                self.open_heading_picker("History", entries);
+                if let Some(picker) = &mut self.heading_picker {
+                    picker.commits = commits;
+                }
+            }
+            Err(e) => self.message = Some(format!("履歴を読み込めません: {}", e)),
+        }
+    }
+
+    /// 表示中のファイルにリンクしている文書の一覧を開く
+    fn open_backlinks(&mut self, config: &Config) {
+        let Some(path) = self.path.as_deref().and_then(|path| dunce::canonicalize(path).ok()) else {
+            self.message = Some("No file to find backlinks to".to_string());
+            return;
+        };
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        // 索引のdaemonが動いていなければ、リポジトリ（無ければ同じディレクトリ）の文書を読んで探す
+        let backlinks = match daemon::query(&dir, &format!("backlinks {}", path.display())) {
+            Some(lines) => daemon::parse_backlinks(&lines),
+            None => load_vault(workspace::find_root(&path).unwrap_or_else(|| dir.clone()), config).backlinks(&path),
+        };
+        if backlinks.is_empty() {
+            self.message = Some("No documents link to this file".to_string());
+            return;
+        }
+        let root = workspace::find_root(&path).unwrap_or(dir);
+        let entries = backlinks
+            .iter()
+            .enumerate()
+            .map(|(index, (document, line))| (index, format!("{}:{}", document.strip_prefix(&root).unwrap_or(document).display(), line)))
+            .collect();
+        self.open_heading_picker("Backlinks", entries);
+        if let Some(picker) = &mut self.heading_picker {
+            picker.documents = backlinks;
+        }
+    }
+
+    /// 表示中のファイルに似た文書の一覧を開く
+    fn open_related(&mut self, config: &Config) {
+        let Some(path) = self.path.as_deref().and_then(|path| dunce::canonicalize(path).ok()) else {
+            self.message = Some("No file to find related documents for".to_string());
+            return;
+        };
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let root = workspace::find_root(&path).unwrap_or_else(|| dir.clone());
+        let related = match daemon::query(&dir, &format!("related {}", path.display())) {
+            Some(lines) => daemon::parse_related(&lines),
+            None => search::related(&load_vault(root.clone(), config), &path, search::RESULTS),
+        };
+        if related.is_empty() {
+            self.message = Some("No related documents found".to_string());
+            return;
+        }
+        let entries = related
+            .iter()
+            .enumerate()
+            .map(|(index, related)| {
+                let name = related.path.strip_prefix(&root).unwrap_or(&related.path).display().to_string();
+                let linked = if related.linked { " (linked)" } else { "" };
+                (index, format!("{}{}  {}", name, linked, related.terms.iter().take(5).cloned().collect::<Vec<_>>().join(", ")))
+            })
+            .collect();
+        self.open_heading_picker("Related", entries);
+        if let Some(picker) = &mut self.heading_picker {
+            picker.documents = related.into_iter().map(|related| (related.path, 1)).collect();
+        }
+    }
+
+    fn handle_heading_picker(&mut self, key: KeyEvent, animate: bool) {
+        let Some(HeadingPicker { entries, list_state, commits, documents, .. }) = &mut self.heading_picker else {
+            return;
+        };
+        let selected = list_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => list_state.select(Some((selected + 1).min(entries.len() - 1))),
+            KeyCode::Up | KeyCode::Char('k') => list_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Enter if !commits.is_empty() => {
+                self.revision_request = commits.get(entries[selected].0).cloned();
+                self.heading_picker = None;
+            }
+            KeyCode::Enter if !documents.is_empty() => {
+                self.open_request = documents.get(entries[selected].0).map(|(path, line)| (path.clone(), Some(format!("L{}", line))));
+                self.heading_picker = None;
+            }
+            KeyCode::Enter => {
+                let line = entries[selected].0;
+                self.heading_picker = None;
+                self.jump_to(line as u16, animate);
+            }
+            KeyCode::Esc | KeyCode::Char('q') => self.heading_picker = None,
+            _ => {}
+        }
+    }
+
+    /// 次（`reverse` なら前）の脚注参照を選ぶ。未選択なら表示中の位置から探す
+    fn focus_footnote(&mut self, reverse: bool, animate: bool) {
+        if self.footnote_refs.is_empty() {
+            self.message = Some("No footnote references".to_string());
+            return;
+        }
+        let last = self.footnote_refs.len() - 1;
+        let next = match self.focused_footnote {
+            Some(index) if reverse => index.checked_sub(1).unwrap_or(last),
+            Some(index) => if index == last { 0 } else { index + 1 },
+            None => {
+                let top = self.target_scroll() as usize;
+                let after = self.footnote_refs.iter().position(|reference| reference.line >= top);
+                if reverse {
+                    after.and_then(|index| index.checked_sub(1)).unwrap_or(last)
+                } else {
+                    after.unwrap_or(0)
+                }
+            }
+        };
+        self.focused_footnote = Some(next);
+        // 選んだ参照が画面外なら見える位置までスクロールする
+        let line = self.footnote_refs[next].line as u16;
+        let top = self.target_scroll();
+        if line < top || line >= top + self.page_size() as u16 {
+            self.jump_to(line.saturating_sub(self.page_size() as u16 / 3), animate);
+        }
+    }
+
+    /// 選んでいる脚注参照のラベルと本文
+    fn focused_footnote_text(&self) -> Option<(&str, &str)> {
+        let reference = &self.footnote_refs[self.focused_footnote?];
+        let text = self.footnotes.get(&reference.label).map_or("(undefined footnote)", String::as_str);
+        Some((&reference.label, text))
+    }
+
+    fn start_hints(&mut self, kind: HintKind, config: &Config) {
+        let visible = self.visible_links(kind);
+        match (kind, visible.as_slice()) {
+            (HintKind::Links, []) => self.message = Some("No links on screen".to_string()),
+            (HintKind::Images, []) => self.message = Some("No images on screen".to_string()),
+            // 画像が1つだけなら選ばせずに開く
+            (HintKind::Images, [(_, image)]) => {
+                let url = image.url.clone();
+                self.open_image(&url, config);
+            }
+            _ => {
+                self.hint_input = Some((kind, String::new()));
+                self.hint_focus = None;
+            }
+        }
+    }
+
+    /// ヒントモードで画面上のリンクを順に選び、行き先をフッターに表示する
+    fn move_hint_focus(&mut self, backward: bool, config: &Config) {
+        let Some((kind, _)) = &self.hint_input else {
+            return;
+        };
+        let visible = self.visible_links(*kind);
+        if visible.is_empty() {
+            return;
+        }
+        let count = visible.len();
+        let current = self.hint_focus.as_ref().and_then(|(label, _)| visible.iter().position(|(l, _)| l == label));
+        let index = match (current, backward) {
+            (None, false) => 0,
+            (None, true) => count - 1,
+            (Some(i), false) => (i + 1) % count,
+            (Some(i), true) => (i + count - 1) % count,
+        };
+        let (label, url) = (visible[index].0.clone(), visible[index].1.url.clone());
+        self.hint_focus = Some((label, self.link_status(&url, config)));
+    }
+
+    /// リンクの行き先と、文書内の見出しやローカルのファイルならあるかどうか
+    fn link_status(&self, url: &str, config: &Config) -> String {
+        match links::resolve(url, self.path.as_deref()) {
+            LinkTarget::Anchor(anchor) => {
+                let found = self.anchor_line(&anchor).is_some();
+                format!("#{} ({})", anchor, if found { "in this document" } else { "heading not found" })
+            }
+            LinkTarget::External(url) => url,
+            LinkTarget::File(path, anchor) => {
+                let target = match &anchor {
+                    Some(anchor) => format!("{}#{}", path.display(), anchor),
+                    None => path.display().to_string(),
+                };
+                if path.exists() {
+                    format!("{} (exists)", target)
+                } else if let Some(url) = self.web_url(&path, anchor.as_deref(), config) {
+                    format!("{} (missing, opens {})", target, url)
+                } else {
+                    format!("{} (missing)", target)
+                }
+            }
+        }
+    }
+
+    /// ローカルに無いファイルへのリンクを `link_base` で開くときのURL。
+    /// リポジトリのルート（無ければ文書のあるディレクトリ）からのパスを付ける
+    fn web_url(&self, path: &Path, anchor: Option<&str>, config: &Config) -> Option<String> {
+        if path.exists() || config.link_base.is_empty() {
+            return None;
+        }
+        let document = self.path.as_deref()?;
+        let root = workspace::find_root(document).or_else(|| document.parent().map(Path::to_path_buf))?;
+        links::web_url(&config.link_base, path, &root, anchor)
+    }
+
+    /// 画面に見えているリンク（または画像）と、そのヒントラベル
+    fn visible_links(&self, kind: HintKind) -> Vec<(String, &LinkSpan)> {
+        let top = self.scroll as usize;
+        let bottom = self.visible_end(top);
+        let targets = match kind {
+            HintKind::Links => &self.links,
+            HintKind::Images => &self.images,
+        };
+        let visible: Vec<&LinkSpan> = targets.iter().filter(|link| (top..bottom).contains(&link.line)).collect();
+        links::hint_labels(visible.len()).into_iter().zip(visible).collect()
+    }
+
+    /// 画像を `image_viewer` で開く。未設定ならOSの既定のアプリで開く
+    fn open_image(&mut self, url: &str, config: &Config) {
+        // ファイル名がUTF-8でなくても開けるよう、パスは文字列に変換せずに渡す
+        let target = match links::resolve(url, self.path.as_deref()) {
+            LinkTarget::External(url) => OsString::from(url),
+            LinkTarget::File(path, _) => path.into_os_string(),
+            LinkTarget::Anchor(_) => {
+                self.message = Some(format!("Not an image: {}", url));
+                return;
+            }
+        };
+        let result = if config.image_viewer.trim().is_empty() {
+            opener::open(&target).map_err(io::Error::other)
+        } else {
+            links::open_with(&config.image_viewer, &target)
+        };
+        if let Err(e) = result {
+            self.message = Some(format!("Failed to open {}: {}", Path::new(&target).display(), e));
+        }
+    }
+
+    fn handle_hint_input(&mut self, key: KeyEvent, config: &Config) {
+        let animate = config.animate_scroll();
+        let Some((kind, input)) = &mut self.hint_input else {
+            return;
+        };
+        let kind = *kind;
+        match key.code {
+            KeyCode::Tab | KeyCode::BackTab => return self.move_hint_focus(key.code == KeyCode::BackTab, config),
+            // `Tab` で選んだリンクのラベルを入力したものとして扱う
+            KeyCode::Enter if self.hint_focus.is_some() => *input = self.hint_focus.take().map(|(label, _)| label).unwrap_or_default(),
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+                return;
+            }
+            _ => {
+                self.hint_input = None;
+                return;
+            }
+        }
+        let input = input.clone();
+        let candidates: Vec<(String, String)> = self
+            .visible_links(kind)
+            .into_iter()
+            .filter(|(label, _)| label.starts_with(&input))
+            .map(|(label, link)| (label, link.url.clone()))
+            .collect();
+        match candidates.as_slice() {
+            [] => {
+                self.hint_input = None;
+                self.message = Some(format!("No link labeled {}", input));
+            }
+            [(label, url)] if *label == input => {
+                self.hint_input = None;
+                match kind {
+                    HintKind::Links => self.follow_link(&url.clone(), animate),
+                    HintKind::Images => self.open_image(&url.clone(), config),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// リンクをたどる。文書内のアンカーへは移動し、URLはブラウザで開き、ファイルは呼び出し側に開かせる
+    fn follow_link(&mut self, url: &str, animate: bool) {
+        match links::resolve(url, self.path.as_deref()) {
+            LinkTarget::Anchor(anchor) => {
+                if !self.jump_to_anchor(&anchor, animate) {
+                    self.message = Some(format!("Anchor not found: #{}", anchor));
+                }
+            }
+            LinkTarget::External(url) => {
+                if let Err(e) = opener::open(&url) {
+                    self.message = Some(format!("Failed to open {}: {}", url, e));
+                }
+            }
+            LinkTarget::File(path, anchor) => self.open_request = Some((path, anchor)),
+        }
+    }
+
+    /// アンカー名に一致する見出しへ移動する。`#L120` のような行へのアンカーなら、
+    /// 同じ名前の見出しが無い限り元のMarkdownのその行に移動する。見つからなければ false を返す
+    fn jump_to_anchor(&mut self, anchor: &str, animate: bool) -> bool {
+        match self.anchor_line(anchor) {
+            Some(line) => {
+                self.jump_to(line as u16, animate);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// アンカーの行き先の描画した行
+    fn anchor_line(&self, anchor: &str) -> Option<usize> {
+        let anchor = anchor.to_lowercase();
+        let heading = links::heading_anchors(&self.headings)
+            .iter()
+            .position(|slug| *slug == anchor)
+            .and_then(|index| self.heading_lines.get(index).copied())
+            .filter(|&line| line < self.content.lines.len());
+        heading.or_else(|| {
+            let line = links::line_anchor(&anchor)?;
+            self.source_lines.iter().position(|&source| source >= line)
+        })
+    }
+
+    /// 検索などの入力欄やポップアップが開いていて、キーをそのまま受け取るかどうか
+    fn has_prompt(&self) -> bool {
+        self.search_input.is_some()
+            || self.command_input.is_some()
+            || self.heading_picker.is_some()
+            || self.hint_input.is_some()
+            || self.info_popup.is_some()
+    }
+
+    /// 入力欄やポップアップへのキー操作を処理する
+    fn handle_prompt_key(&mut self, key: KeyEvent, config: &Config) {
+        let animate = config.animate_scroll();
+        if self.search_input.is_some() {
+            self.handle_search_input(key, animate);
+        } else if self.command_input.is_some() {
+            self.handle_command_input(key, config);
+        } else if self.heading_picker.is_some() {
+            self.handle_heading_picker(key, animate);
+        } else if self.hint_input.is_some() {
+            self.handle_hint_input(key, config);
+        } else if self.info_popup.is_some() && matches!(key.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q')) {
+            self.info_popup = None;
+        }
+    }
+
+    /// キーに割り当てられた操作を行う。プレビューを閉じる場合は true を返す
+    fn perform(&mut self, key: KeyEvent, action: Option<Action>, config: &Config) -> bool {
+        self.message = None;
+        if key.code == KeyCode::Esc {
+            self.focused_footnote = None;
+        }
+        let Some(action) = action else {
+            return false;
+        };
+
+        let animate = config.animate_scroll();
+        let snap = config.snap_to_headings;
+        let page = self.page_size();
+        let step = config.scroll_step as i32;
+        let fast_step = config.fast_scroll_step as i32;
+        let half_width = (self.viewport_width / 2).max(1) as i32;
+        match action {
+            Action::Close => return true,
+            Action::FastScrollUp => self.scroll_by(-fast_step),
+            Action::FastScrollDown => self.scroll_by(fast_step),
+            Action::MoveUp => self.scroll_by(-step),
+            Action::MoveDown => self.scroll_by(step),
+            Action::LineUp => self.scroll_by(-1),
+            Action::LineDown => self.scroll_by(1),
+            Action::ScrollLeft => self.scroll_horizontally(-half_width),
+            Action::ScrollRight => self.scroll_horizontally(half_width),
+            Action::ToggleWrap => self.toggle_wrap(),
+            Action::LoadAll if self.truncated.is_some() => self.load_all(config),
+            Action::PageDown => self.page_by(page, snap, animate),
+            Action::PageUp => self.page_by(-page, snap, animate),
+            Action::HalfPageDown => self.page_by(page / 2, snap, animate),
+            Action::HalfPageUp => self.page_by(-(page / 2), snap, animate),
+            Action::Top => self.jump_to(0, animate),
+            Action::Bottom => self.jump_to(self.max_scroll(), animate),
+            Action::SearchForward => self.search_input = Some((SearchDirection::Forward, String::new())),
+            Action::SearchBackward => self.search_input = Some((SearchDirection::Backward, String::new())),
+            Action::SearchNext => self.search_next(false, animate),
+            Action::SearchPrevious => self.search_next(true, animate),
+            Action::Command => self.command_input = Some(String::new()),
+            Action::FollowLink => self.start_hints(HintKind::Links, config),
+            Action::OpenImage => self.start_hints(HintKind::Images, config),
+            Action::NextFootnote => self.focus_footnote(false, animate),
+            Action::PreviousFootnote => self.focus_footnote(true, animate),
+            Action::TogglePath => self.show_path = !self.show_path,
+            // 分けた直後は下を操作し、上には元の位置を残す
+            Action::ToggleSplit => {
+                self.split = match self.split {
+                    Some(_) => None,
+                    None => Some(SplitView::same_document(self.target_scroll())),
+                }
+            }
+            Action::NextPane => self.switch_split_pane(),
+            Action::EditSource => match &self.path {
+                None => self.message = Some("Nothing to edit: not a file".to_string()),
+                Some(path) if compressed::codec(path).is_some() => self.message = Some("Cannot edit compressed files".to_string()),
+                Some(path) => self.edit_request = Some(self.edit_target().unwrap_or_else(|| (path.clone(), 1))),
+            },
+            _ => {}
+        }
+        false
+    }
+}
+
+// --- メインロジック ---
+
+/// `:log` で開く直近のログの表示
+pub struct LogView {
+    lines: Vec<String>,
+    /// 表示開始行。開いた時点では最新の記録が見えるよう末尾に合わせる
+    scroll: usize,
+    /// 直近の描画で表示できた行数
+    rows: usize,
+}
+
+impl LogView {
+    fn new() -> Self {
+        Self { lines: logging::recent(), scroll: usize::MAX, rows: 0 }
+    }
+
+    /// 操作に応じて表示位置を動かす。閉じる操作なら false を返す
+    fn perform(&mut self, action: Action) -> bool {
+        let max_scroll = self.lines.len().saturating_sub(self.rows);
+        let scroll = self.scroll.min(max_scroll);
+        self.scroll = match action {
+            Action::Close => return false,
+            Action::MoveDown => scroll + 1,
+            Action::MoveUp => scroll.saturating_sub(1),
+            Action::PageDown => scroll + self.rows.max(1),
+            Action::PageUp => scroll.saturating_sub(self.rows.max(1)),
+            Action::Top => 0,
+            Action::Bottom => max_scroll,
+            _ => scroll,
+        }
+        .min(max_scroll);
+        true
+    }
+}
+/// `App::update` が返す、状態の外で行う処理（端末の操作や別スレッドの起動）
+pub enum Effect {
+    /// アプリを終了する
+    Quit,
+    /// 続けられないエラーで終了する
+    Fail(io::Error),
+    /// 端末を元に戻して一時停止する（Ctrl-Z）
+    Suspend,
+    /// ファイルを `$EDITOR` で（行番号があればその行を開いて）編集する。
+    /// 失敗したら、同じ更新で返した残りの処理は行わない
+    Edit { path: PathBuf, line: Option<usize> },
+    /// プレビューを別スレッドで開く
+    OpenPreview { anchor: Option<String>, open: Box<dyn FnOnce() -> Result<PreviewState, ErrorReport> + Send> },
+    /// 読み込んだ標準入力を別スレッドでレンダリングする
+    RenderStdin { markdown: String, filters: Vec<String>, theme: &'static ColorScheme, options: RenderOptions },
+    /// スペルチェックや `git` の呼び出しなど時間のかかる処理を別スレッドで行い、
+    /// 返された関数を `AppEvent::Background` で届ける
+    Background(Box<dyn FnOnce() -> AppUpdate + Send>),
+}
+
+impl Effect {
+    fn preview(anchor: Option<String>, open: impl FnOnce() -> Result<PreviewState, ErrorReport> + Send + 'static) -> Self {
+        Self::OpenPreview { anchor, open: Box::new(open) }
+    }
+
+    fn background<F>(work: impl FnOnce() -> F + Send + 'static) -> Self
+    where
+        F: FnOnce(&mut App) + Send + 'static,
+    {
+        Self::Background(Box::new(move || Box::new(work()) as AppUpdate))
+    }
+}
+
+/// TUIの起動のしかた。コマンドラインの引数から作る
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// エクスプローラーを使わずに直接プレビューするファイル
+    pub file: Option<PathBuf>,
+    /// `file.md:120` や `file.md#install` のように指定された、最初に表示する位置
+    pub anchor: Option<String>,
+    /// `--dir` で指定された、エクスプローラーを開くディレクトリ
+    pub dir: Option<PathBuf>,
+    /// 標準入力を読むページャーモード
+    pub pager: bool,
+}
+
+impl Options {
+    /// エクスプローラーを使わずに1つの文書だけを表示するかどうか
+    pub fn is_standalone(&self) -> bool {
+        self.pager || self.file.is_some()
+    }
+}
+
+/// アプリ全体の状態。イベントを `update` に渡して遷移させ、`view` で描画する。
+/// 端末や別スレッドを使う処理は `Effect` として返し、`run` が実行する
+pub struct App {
+    settings: Settings,
+    base_config: Config,
+    /// 閲覧中のgitリポジトリの設定を重ねたもの
+    config: Config,
+    theme: &'static ColorScheme,
+    project_root: Option<PathBuf>,
+    /// 設定ファイルの更新日時。変わったら読み込み直す
+    config_modified: Vec<Option<SystemTime>>,
+    /// エクスプローラーを使わずに1つの文書だけを表示し、閉じたら終了するか
+    standalone: bool,
+    /// 表示中の画面。末尾が最前面で、最後の画面を閉じると終了する
+    screens: Vec<Screen>,
+    /// 2画面表示では、それぞれのペインが独立したエクスプローラーを持つ
+    panes: Vec<ExplorerState>,
+    active_pane: usize,
+    /// 設定の `keymap` から作ったキー割り当て
+    keymap: Keymap,
+    /// `gr` のような並びの途中まで押されたキー
+    pending_keys: Vec<Key>,
+    /// 別スレッドで開いているプレビューの番号。後から開いたものが優先される
+    pending_preview: Option<u64>,
+    /// ページャーモードで読み込んだ標準入力と、その描画の状況
+    stdin: Option<StdinBuffer>,
+}
+
+/// ページャーモードで読み込み中の標準入力
+struct StdinBuffer {
+    markdown: String,
+    /// 前回の描画の後に読み込んだ分があるか
+    dirty: bool,
+    /// EOFに達したか
+    finished: bool,
+    /// 別スレッドで描画中か
+    rendering: bool,
+    last_render: Instant,
+}
+
+impl App {
+    pub fn new(settings: Settings, args: &Options, events: &Sender<AppEvent>) -> io::Result<Self> {
+        let base_config = settings.resolve(None)?;
+        // `--dir` が無ければカレントディレクトリから始める
+        let start_dir = match &args.dir {
+            Some(dir) => dunce::canonicalize(dir)?,
+            None => env::current_dir()?,
+        };
+        let mut panes = vec![ExplorerState::new(&base_config, start_dir.clone(), events)?];
+        if base_config.dual_pane {
+            panes.push(ExplorerState::new(&base_config, start_dir, events)?);
+        }
+
+        // 閲覧中のgitリポジトリに `.md-preview.toml` があれば、ユーザー設定に重ねて使う
+        let project_root = args
+            .file
+            .as_deref()
+            .and_then(|path| dunce::canonicalize(path).ok())
+            .and_then(|path| workspace::find_root(&path));
+        let config = match &project_root {
+            Some(root) => settings.resolve(Some(root))?,
+            None => base_config.clone(),
+        };
+
+        let mut app = Self {
+            settings,
+            theme: config.color_scheme(),
+            keymap: Keymap::new(config.keymap),
+            pending_keys: Vec::new(),
+            base_config,
+            config,
+            config_modified: config_modified(project_root.as_deref()),
+            project_root,
+            standalone: args.is_standalone(),
+            screens: Vec::new(),
+            panes,
+            active_pane: 0,
+            pending_preview: None,
+            stdin: None,
+        };
+        // ファイルを直接開いた場合やページャーでは、プレビューを閉じたら終了する
+        if let Some(path) = &args.file {
+            let mut state = PreviewState::new(path, &app.config)?;
+            if let Some(anchor) = &args.anchor
+                && !state.jump_to_anchor(anchor, false)
+            {
+                state.message = Some(format!("Anchor not found: #{}", anchor));
+            }
+            app.screens.push(Screen::Preview(Box::new(state)));
+        } else if args.pager {
+            let mut state = PreviewState::from_text(Text::default(), "stdin (loading…)".to_string(), 0);
+            state.debug_footer = app.config.debug_footer;
+            app.screens.push(Screen::Preview(Box::new(state)));
+            app.stdin = Some(StdinBuffer {
+                markdown: String::new(),
+                dirty: false,
+                finished: false,
+                rendering: false,
+                last_render: Instant::now(),
+            });
+        } else {
+            app.screens.push(Screen::Explorer);
+        }
+        Ok(app)
+    }
+
+    /// 表示中の画面。末尾が最前面
+    pub fn screens(&self) -> &[Screen] {
+        &self.screens
+    }
+
+    /// 操作しているペインのエクスプローラー
+    pub fn explorer(&self) -> &ExplorerState {
+        &self.panes[self.active_pane]
+    }
+
+    /// 一番手前の、画面全体を覆う画面の位置
+    fn base_screen(&self) -> Option<usize> {
+        self.screens.iter().rposition(|screen| !screen.is_overlay())
+    }
+
+    /// いま見えているプレビュー
+    fn visible_preview(&mut self) -> Option<&mut PreviewState> {
+        match self.base_screen().map(|index| &mut self.screens[index]) {
+            Some(Screen::Preview(state)) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// 標準入力を表示しているプレビュー（ページャーモードでは一番奥の画面）
+    fn stdin_preview(&mut self) -> Option<&mut PreviewState> {
+        match self.screens.first_mut() {
+            Some(Screen::Preview(state)) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// プレビューを表示する。見えているプレビューがあれば置き換え（リンクをたどった場合）、
+    /// 無ければエクスプローラーの上に重ねる。エラー画面などは手前に残す
+    fn show_preview(&mut self, state: Box<PreviewState>) {
+        let index = self.base_screen();
+        match index.map(|index| &mut self.screens[index]) {
+            Some(Screen::Preview(current)) => *current = state,
+            _ => self.screens.insert(index.map_or(0, |index| index + 1), Screen::Preview(state)),
+        }
+    }
+
+    /// エラー画面を表示する。既にエラー画面が最前面にあれば新しいものに置き換える
+    fn show_error(&mut self, report: ErrorReport) {
+        if let Some(Screen::Error(_)) = self.screens.last() {
+            self.screens.pop();
+        }
+        self.screens.push(Screen::Error(report));
+    }
+
+    /// イベントを受けて状態を更新し、状態の外で行う処理を返す
+    pub fn update(&mut self, event: AppEvent) -> Vec<Effect> {
+        let mut effects = self.handle_event(event);
+        effects.extend(self.background_effects());
+        effects
+    }
+
+    /// プレビューが予約した時間のかかる処理を、別スレッドで行う処理にする。
+    /// 結果は、その時点で同じファイルを表示しているプレビューに反映する
+    fn background_effects(&mut self) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        for screen in &mut self.screens {
+            let Screen::Preview(state) = screen else {
+                continue;
+            };
+            for work in state.background.drain(..) {
+                let path = state.path.clone();
+                effects.push(Effect::background(move || {
+                    let update = work();
+                    move |app: &mut App| {
+                        let preview = app.screens.iter_mut().rev().find_map(|screen| match screen {
+                            Screen::Preview(state) if state.path == path => Some(state),
+                            _ => None,
+                        });
+                        if let Some(state) = preview {
+                            update(state);
+                        }
+                    }
+                }));
+            }
+        }
+        effects
+    }
+
+    fn handle_event(&mut self, event: AppEvent) -> Vec<Effect> {
+        match event {
+            AppEvent::Tick => return self.tick(),
+            AppEvent::Terminal(Ok(event)) => {
+                tracing::trace!("{:?}", event);
+                if let Event::Key(key) = event
+                    && key.kind == KeyEventKind::Press
+                {
+                    return self.handle_key(key);
+                }
+            }
+            // 端末から読めなくなった場合は続けられない
+            AppEvent::Terminal(Err(e)) => return vec![Effect::Fail(e)],
+            AppEvent::Stdin(StdinEvent::Line(line)) => {
+                if let Some(stdin) = &mut self.stdin {
+                    stdin.markdown.push_str(&line);
+                    stdin.dirty = true;
+                }
+            }
+            AppEvent::Stdin(StdinEvent::Closed) => {
+                let Some(stdin) = &mut self.stdin else {
+                    return Vec::new();
+                };
+                stdin.finished = true;
+                // 最後まで描画済みなら、読み込み中の表示を外す
+                if !stdin.dirty
+                    && !stdin.rendering
+                    && let Some(state) = self.stdin_preview()
+                {
+                    state.title = "stdin".to_string();
+                }
+            }
+            AppEvent::Entries { loader, batch, finished } => {
+                if let Some(pane) = self.panes.iter_mut().find(|pane| pane.loader_id() == Some(loader)) {
+                    pane.receive_entries(batch, finished);
+                }
+            }
+            AppEvent::Preview { request, anchor, result } if self.pending_preview == Some(request) => {
+                self.pending_preview = None;
+                match result {
+                    Ok(mut state) => {
+                        // `other.md#section` のリンクなら、開いた文書の見出しまで移動する
+                        if let Some(anchor) = anchor
+                            && !state.jump_to_anchor(&anchor, false)
+                        {
+                            state.message = Some(format!("Anchor not found: #{}", anchor));
+                        }
+                        self.show_preview(state);
+                    }
+                    Err(report) => self.show_error(report),
+                }
+            }
+            // 後から別のプレビューを開いた場合などは捨てる
+            AppEvent::Preview { .. } => {}
+            // 結果がリンク先を開く要求などを残していれば、キー操作の後と同じように処理する
+            AppEvent::Background(update) => {
+                update(self);
+                return self.after_preview_key();
+            }
+            AppEvent::StdinRendered { markdown, result, message } => {
+                let Some(stdin) = &mut self.stdin else {
+                    return Vec::new();
+                };
+                stdin.rendering = false;
+                let up_to_date = stdin.finished && !stdin.dirty;
+                let Some(state) = self.stdin_preview() else {
+                    return Vec::new();
+                };
+                if message.is_some() {
+                    state.message = message;
+                }
+                if up_to_date {
+                    state.title = "stdin".to_string();
+                }
+                match result {
+                    Ok((rendered, char_count)) => {
+                        state.replace_content(*rendered, char_count);
+                        state.source = Some(markdown);
+                    }
+                    Err(report) => self.show_error(report),
+                }
+            }
+        }
+        Vec::new()
+    }
+
+    /// 入力が無くても一定間隔で行う確認（プロジェクト設定の切り替え・ディレクトリの変更・標準入力の再描画）
+    fn tick(&mut self) -> Vec<Effect> {
+        if !self.standalone && self.panes[self.active_pane].workspace_root != self.project_root {
+            self.project_root = self.panes[self.active_pane].workspace_root.clone();
+            self.config = match &self.project_root {
+                Some(root) => self.settings.resolve(Some(root)).unwrap_or_else(|e| {
+                    self.panes[self.active_pane].error_message =
+                        Some(format!("{} を読み込めません: {}", settings::PROJECT_CONFIG_FILE, e));
+                    self.base_config.clone()
+                }),
+                None => self.base_config.clone(),
+            };
+            self.config_modified = config_modified(self.project_root.as_deref());
+            self.apply_config();
+        }
+        self.reload_config_if_changed();
+
+        let explorer_visible = matches!(self.base_screen().map(|index| &self.screens[index]), Some(Screen::Explorer));
+        for index in 0..self.panes.len() {
+            if explorer_visible {
+                self.panes[index].refresh_if_changed();
+            }
+            if let Some(report) = self.panes[index].error_report.take() {
+                self.show_error(report);
+            }
+        }
+        if self.config.watch
+            && let Some(index) = self.base_screen()
+            && let Screen::Preview(state) = &mut self.screens[index]
+        {
+            state.reload_if_changed(&self.config);
+            if let Some(report) = state.error_report.take() {
+                self.show_error(report);
+            }
+        }
+
+        let stdin_render_interval = if self.config.low_bandwidth { LOW_BANDWIDTH_INTERVAL } else { STDIN_RENDER_INTERVAL };
+        if let Some(stdin) = &mut self.stdin
+            && stdin.dirty
+            && !stdin.rendering
+            && (stdin.finished || stdin.last_render.elapsed() >= stdin_render_interval)
+        {
+            stdin.dirty = false;
+            stdin.rendering = true;
+            stdin.last_render = Instant::now();
+            return vec![Effect::RenderStdin {
+                markdown: stdin.markdown.clone(),
+                filters: self.config.filters.clone(),
+                theme: self.theme,
+                options: self.config.render_options(),
+            }];
+        }
+        Vec::new()
+    }
+
+    /// 設定から決まるテーマやキー割り当てを反映する
+    fn apply_config(&mut self) {
+        self.theme = self.config.color_scheme();
+        self.keymap = Keymap::new(self.config.keymap);
+        for pane in &mut self.panes {
+            pane.apply_config(&self.config);
+        }
+    }
+
+    /// 設定ファイルが変わっていたら読み込み直し、表示中の文書を新しい設定で描き直す
+    fn reload_config_if_changed(&mut self) {
+        let modified = config_modified(self.project_root.as_deref());
+        if modified == self.config_modified {
+            return;
+        }
+        self.config_modified = modified;
+        let reloaded = self.settings.reload_user().and_then(|()| {
+            let base_config = self.settings.resolve(None)?;
+            let config = match &self.project_root {
+                Some(root) => self.settings.resolve(Some(root))?,
+                None => base_config.clone(),
+            };
+            Ok((base_config, config))
+        });
+        // 書きかけの設定で失敗しても、直前の設定のまま続ける
+        let (base_config, config) = match reloaded {
+            Ok(configs) => configs,
+            Err(e) => {
+                tracing::warn!("failed to reload config: {}", e);
+                match self.visible_preview() {
+                    Some(state) => state.message = Some(format!("設定を読み込み直せません: {}", e)),
+                    None => self.panes[self.active_pane].error_message = Some(format!("設定を読み込み直せません: {}", e)),
+                }
+                return;
+            }
+        };
+        tracing::info!("config reloaded");
+        self.base_config = base_config;
+        self.config = config;
+        self.apply_config();
+        for screen in &mut self.screens {
+            if let Screen::Preview(state) = screen {
+                state.reload(&self.config);
+            }
+        }
+        if let Some(stdin) = &mut self.stdin {
+            stdin.dirty = true;
+        }
+        match self.visible_preview() {
+            Some(state) => state.message = Some("設定を読み込み直しました".to_string()),
+            None => self.panes[self.active_pane].message = Some("設定を読み込み直しました".to_string()),
+        }
+    }
+
+    /// キー操作を処理する
+    fn handle_key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        // Ctrl-C はどの画面からでも終了し、Ctrl-Z は端末を元に戻してから一時停止する
+        match self.keymap.lookup(Mode::Global, &[key.into()]) {
+            Lookup::Action(Action::Quit) => return vec![Effect::Quit],
+            Lookup::Action(Action::Suspend) => return vec![Effect::Suspend],
+            _ => {}
+        }
+        // 入力欄などはキーをそのまま受け取り、それ以外はキーを割り当てられた操作に変換して渡す
+        let mode = match self.screens.last_mut() {
+            // エラー画面は何かキーを押すと閉じる
+            Some(Screen::Error(_)) => {
+                self.screens.pop();
+                return Vec::new();
+            }
+            Some(Screen::Preview(state)) if state.has_prompt() => {
+                state.handle_prompt_key(key, &self.config);
+                return self.after_preview_key();
+            }
+            Some(Screen::Explorer) if self.panes[self.active_pane].has_prompt() => return self.handle_explorer_prompt_key(key),
+            Some(Screen::Graph(_)) => return self.handle_graph_key(key),
+            Some(Screen::Log(_)) => Mode::Log,
+            Some(Screen::Preview(_)) => Mode::Preview,
+            Some(Screen::Explorer) => Mode::Explorer,
+            None => return vec![Effect::Quit],
+        };
+        self.pending_keys.push(key.into());
+        let action = match self.keymap.lookup(mode, &self.pending_keys) {
+            Lookup::Pending => return Vec::new(),
+            Lookup::Action(action) => Some(action),
+            Lookup::Unbound => None,
+        };
+        self.pending_keys.clear();
+        match self.screens.last_mut() {
+            Some(Screen::Log(view)) => {
+                if action.is_some_and(|action| !view.perform(action)) {
+                    self.screens.pop();
+                }
+                Vec::new()
+            }
+            Some(Screen::Preview(_)) if action == Some(Action::ToggleChrome) => {
+                self.toggle_minimal_chrome();
+                Vec::new()
+            }
+            Some(Screen::Preview(state)) => {
+                if state.perform(key, action, &self.config) {
+                    // 閉じたら下の画面に戻る。ファイルを直接開いた場合など、戻る画面が無ければページャーと同じく終了する
+                    self.screens.pop();
+                    self.pending_preview = None;
+                    return if self.screens.is_empty() { vec![Effect::Quit] } else { Vec::new() };
+                }
+                self.after_preview_key()
+            }
+            _ => self.perform_explorer_action(action),
+        }
+    }
+
+    /// フッターとポップアップの枠線を隠すかどうかを切り替え、ユーザー設定ファイルに保存する
+    fn toggle_minimal_chrome(&mut self) {
+        let minimal = !self.config.minimal_chrome;
+        self.config.minimal_chrome = minimal;
+        self.base_config.minimal_chrome = minimal;
+        let saved = settings::save_user_value("minimal_chrome", &minimal.to_string()).and_then(|()| self.settings.reload_user());
+        // 自分で書き換えた設定ファイルは読み込み直さない
+        self.config_modified = config_modified(self.project_root.as_deref());
+        if let Err(e) = saved {
+            tracing::warn!("failed to save minimal_chrome: {}", e);
+            if let Some(state) = self.visible_preview() {
+                state.message = Some(format!("Failed to save minimal_chrome: {}", e));
+            }
+        }
+    }
+
+    /// プレビューへのキー操作の結果として、エラーやログの表示、リンク先を開く処理を行う
+    fn after_preview_key(&mut self) -> Vec<Effect> {
+        let Some(Screen::Preview(state)) = self.screens.last_mut() else {
+            return Vec::new();
+        };
+        let report = state.error_report.take();
+        let show_log = std::mem::take(&mut state.show_log);
+        let graph = std::mem::take(&mut state.show_graph).then(|| state.path.clone()).flatten().map(|path| {
+            let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+            let browser = link_graph(&dir, Some(&path), &self.config);
+            if browser.is_none() {
+                state.message = Some("This file is not in the document index".to_string());
+            }
+            browser
+        });
+        let mut effects = Vec::new();
+        if let Some((path, line)) = state.edit_request.take() {
+            effects.push(Effect::Edit { path, line: Some(line) });
+        }
+        if let Some(commit) = state.revision_request.take()
+            && let Some(path) = state.path.clone()
+        {
+            let config = self.config.clone();
+            effects.push(Effect::preview(None, move || open_revision(&path, &commit, &config)));
+        }
+        // リンク先のファイルはプレビューできればプレビューし、それ以外は既定のアプリで開く
+        if let Some((path, anchor)) = state.open_request.take() {
+            let name = compressed::strip_extension(&path);
+            // ローカルに無いファイルは、`link_base` があればWeb上の同じファイルを開く
+            if let Some(url) = state.web_url(&path, anchor.as_deref(), &self.config) {
+                if let Err(e) = opener::open(&url) {
+                    state.message = Some(format!("Failed to open {}: {}", url, e));
+                }
+            } else if self.config.is_previewable(&name) || org::is_org(&name) {
+                let config = self.config.clone();
+                effects.push(Effect::preview(anchor, move || open_preview(&path, &config)));
+            } else if let Err(e) = opener::open(&path) {
+                state.message = Some(format!("Failed to open {}: {}", path.display(), e));
+            }
+        }
+        if let Some(report) = report {
+            self.show_error(report);
+        }
+        if show_log {
+            self.screens.push(Screen::Log(LogView::new()));
+        }
+        if let Some(Some(browser)) = graph {
+            self.screens.push(Screen::Graph(browser));
+        }
+        effects
+    }
+
+    /// リンクのグラフへのキー操作を処理する
+    fn handle_graph_key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        let Some(Screen::Graph(browser)) = self.screens.last_mut() else {
+            return Vec::new();
+        };
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => browser.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => browser.move_selection(-1),
+            KeyCode::Left | KeyCode::Char('h') => browser.select_column(Column::Incoming),
+            KeyCode::Right | KeyCode::Char('l') => browser.select_column(Column::Outgoing),
+            KeyCode::Tab => browser.switch_column(),
+            KeyCode::Enter => {
+                browser.enter();
+            }
+            KeyCode::Backspace => {
+                browser.back();
+            }
+            // グラフを閉じ、選択している文書（無ければ中心の文書）をプレビューする
+            KeyCode::Char('o') => {
+                let path = browser.selected().unwrap_or(&browser.current).clone();
+                self.screens.pop();
+                let config = self.config.clone();
+                return vec![Effect::preview(None, move || open_preview(&path, &config))];
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.screens.pop();
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    /// エクスプローラーのタグの一覧・コマンド入力・削除の確認へのキー操作を処理する
+    fn handle_explorer_prompt_key(&mut self, key: KeyEvent) -> Vec<Effect> {
+        let explorer_state = &mut self.panes[self.active_pane];
+        if let Some(browser) = &mut explorer_state.tag_browser {
+            match key.code {
+                KeyCode::Down | KeyCode::Char('j') => browser.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => browser.move_selection(-1),
+                KeyCode::Enter | KeyCode::Char('l') => {
+                    if let Some(path) = browser.enter() {
+                        let config = self.config.clone();
+                        return vec![Effect::preview(None, move || open_preview(&path, &config))];
+                    }
+                }
+                // 文書の一覧ならタグの一覧に戻り、タグの一覧なら閉じる
+                KeyCode::Esc | KeyCode::Char('h') | KeyCode::Char('q') | KeyCode::Backspace
+                    if !browser.back() =>
+                {
+                    explorer_state.tag_browser = None;
+                }
+                _ => {}
+            }
+            return Vec::new();
+        }
+        if explorer_state.in_command_mode {
+            match key.code {
+                KeyCode::Enter => {
+                    let command_text = self.config.expand_alias(explorer_state.command_input.trim());
+                    explorer_state.command_input.clear();
+                    explorer_state.in_command_mode = false;
+                    explorer_state.error_message = None; // コマンド実行時にエラーをクリア
+
+                    // `"my notes.md"` のように引用符で囲めば空白を含む名前も渡せる
+                    let Some(words) = shell_words::split(&command_text) else {
+                        explorer_state.error_message = Some(format!("引用符が閉じていません: {}", command_text));
+                        return Vec::new();
+                    };
+                    let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+
+                    match parts.as_slice() {
+                        ["q"] => {
+                            return vec![Effect::Quit];
+                        }
+                        ["hp", filename] => {
+                            let file_path = explorer_state.current_path.join(filename);
+                            if !file_path.is_file() {
+                                explorer_state.error_message = Some(format!("ファイルが見つかりません: {}", filename));
+                                return Vec::new();
+                            }
+
+                            match fs::read_to_string(&file_path) {
+                                Ok(markdown_input) => {
+                                    // MarkdownをHTMLに変換
+                                    let parser = MarkdownParser::new(&markdown_input);
+                                    let mut html_output = String::new();
+                                    html::push_html(&mut html_output, parser);
+
+                                    let char_count = html_output.chars().count();
+                                    let content = Text::from(html_output);
+                                    let title = format!("HTML Preview: {}", file_path.to_string_lossy());
+
+                                    self.screens.push(Screen::Preview(Box::new(PreviewState::from_text(content, title, char_count))));
+                                }
+                                Err(e) => {
+                                    explorer_state.error_message = Some(format!("ファイル読み込みエラー: {}", e));
+                                }
+                            }
+                        }
+                        ["cat", filename] => {
+                            let file_path = explorer_state.current_path.join(filename);
+                            if !file_path.is_file() {
+                                explorer_state.error_message =
+                                    Some(format!("ファイルが見つかりません: {}", filename));
+                                return Vec::new();
+                            }
+
+                            match fs::read_to_string(&file_path) {
+                                Ok(file_content) => {
+                                    let char_count = file_content.chars().count();
+                                    let content = Text::from(file_content);
+                                    let title = format!(
+                                        "Cat: {}",
+                                        file_path.to_string_lossy()
+                                    );
+
+                                    self.screens.push(Screen::Preview(Box::new(PreviewState::from_text(content, title, char_count))));
+                                }
+                                Err(e) => {
+                                    explorer_state.error_message = Some(format!(
+                                        "ファイル読み込みエラー: {}",
+                                        e
+                                    ));
+                                }
+                            }
+                        }
+                        ["ob", filename] => {
+                            let file_path = explorer_state.current_path.join(filename);
+
+                            // ファイルの存在と拡張子をチェック
+                            if !file_path.is_file() {
+                                explorer_state.error_message = Some(format!("ファイルが見つかりません: {}", filename));
+                            } else if file_path.extension().and_then(|s| s.to_str()) != Some("html") {
+                                explorer_state.error_message = Some("HTMLファイルのみ開けます。".to_string());
+                            } else {
+                                // ブラウザで開く
+                                if let Err(e) = opener::open(&file_path) {
+                                    explorer_state.error_message = Some(format!("ブラウザで開けませんでした: {}", e));
+                                }
+                            }
+                        }
+                        ["root"] => explorer_state.go_to_workspace_root(),
+                        ["log"] => self.screens.push(Screen::Log(LogView::new())),
+                        ["today"] | ["today", "edit"] => {
+                            let path = match journal::today_note(&self.config) {
+                                Ok(path) => path,
+                                Err(e) => {
+                                    self.screens.push(Screen::Error(ErrorReport::new("今日のノートを開けません".to_string(), &e)));
+                                    return Vec::new();
+                                }
+                            };
+                            let config = self.config.clone();
+                            let open = Effect::preview(None, {
+                                let path = path.clone();
+                                move || error_report::catch("今日のノートを開けません", || PreviewState::new(&path, &config))
+                            });
+                            // 編集する場合は、エディターを閉じてから開く
+                            return if parts.len() == 2 { vec![Effect::Edit { path, line: None }, open] } else { vec![open] };
+                        }
+                        ["new", filename, rest @ ..] => {
+                            let template = match rest {
+                                [] => Ok(None),
+                                ["--template", name] => Ok(Some(*name)),
+                                [option] if let Some(name) = option.strip_prefix("--template=") => Ok(Some(name)),
+                                _ => Err(io::Error::other("使い方: :new <ファイル名> [--template <名前>]")),
+                            };
+                            let file_path = explorer_state.current_path.join(filename);
+                            let created = template.and_then(|template| new_file(&file_path, template));
+                            match created {
+                                Ok(()) => explorer_state.reload_selecting(file_path),
+                                Err(e) => {
+                                    explorer_state.error_message = Some(format!("ファイルを作成できません: {}", e));
+                                }
+                            }
+                        }
+                        ["open"] => {
+                            let targets: Vec<PathBuf> = explorer_state
+                                .bulk_targets()
+                                .into_iter()
+                                .filter(|path| self.config.is_previewable(path) || org::is_org(path))
+                                .collect();
+                            if targets.is_empty() {
+                                explorer_state.error_message = Some("Markdown・orgファイルが選ばれていません".to_string());
+                                return Vec::new();
+                            }
+                            let config = self.config.clone();
+                            return vec![Effect::preview(None, move || match targets.as_slice() {
+                                [path] => open_preview(path, &config),
+                                _ => open_concatenated(&targets, &config),
+                            })];
+                        }
+                        ["export", format, dir] if let Some(format) = bulk::ExportFormat::parse(format) => {
+                            let targets: Vec<PathBuf> = explorer_state
+                                .bulk_targets()
+                                .into_iter()
+                                .filter(|path| self.config.is_previewable(path) || org::is_org(path))
+                                .collect();
+                            if targets.is_empty() {
+                                explorer_state.error_message = Some("Markdown・orgファイルが選ばれていません".to_string());
+                                return Vec::new();
+                            }
+                            let dir = explorer_state.current_path.join(dir);
+                            let base = explorer_state.workspace_root.clone().unwrap_or_default();
+                            let root = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            let theme = self.theme;
+                            let pane = self.active_pane;
+                            // 文書を読んでレンダリングするのは時間がかかるので別スレッドで行う
+                            return vec![Effect::background(move || {
+                                let exported = export::HtmlAssets::load(&config.html_template, &config.html_css, &base).and_then(|assets| {
+                                    let documents = targets
+                                        .iter()
+                                        .map(|path| Ok((path.clone(), load_markdown(path, &config)?)))
+                                        .collect::<io::Result<Vec<_>>>()?;
+                                    bulk::export_all(&documents, &dir, format, &assets, theme)
+                                });
+                                move |app: &mut App| match exported {
+                                    Ok(written) => {
+                                        tracing::info!("exported {} files to {}", written.len(), dir.display());
+                                        let Some(explorer_state) = app.panes.get_mut(pane) else {
+                                            return;
+                                        };
+                                        explorer_state.message = Some(format!("{} 件を {} に書き出しました", written.len(), platform::display(&dir)));
+                                        for target in &targets {
+                                            explorer_state.marked.remove(target);
+                                        }
+                                        // 書き出し先のディレクトリが一覧に現れるよう読み直す
+                                        if explorer_state.current_path == root {
+                                            explorer_state.reload_entries();
+                                        }
+                                    }
+                                    Err(e) => app.screens.push(Screen::Error(ErrorReport::new("一括書き出しに失敗しました".to_string(), &e))),
+                                }
+                            })];
+                        }
+                        ["export", ..] => {
+                            explorer_state.error_message = Some("使い方: :export html|txt <ディレクトリ>".to_string());
+                        }
+                        ["delete"] => {
+                            let targets = explorer_state.bulk_targets();
+                            if targets.is_empty() {
+                                explorer_state.error_message = Some("削除するエントリがありません".to_string());
+                            } else {
+                                explorer_state.pending_delete = Some(targets);
+                            }
+                        }
+                        ["audit"] | ["audit", _] => {
+                            let days = match parts.get(1).map(|days| days.parse::<u64>()) {
+                                None => self.config.stale_days,
+                                Some(Ok(days)) if days > 0 => days,
+                                Some(_) => {
+                                    explorer_state.error_message = Some("使い方: :audit [日数]".to_string());
+                                    return Vec::new();
+                                }
+                            };
+                            let dir = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            return vec![Effect::preview(None, move || open_audit(&dir, days, &config))];
+                        }
+                        ["assets"] => {
+                            let dir = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            return vec![Effect::preview(None, move || open_assets(&dir, &config))];
+                        }
+                        ["search", ..] => {
+                            let query = command_text.trim().strip_prefix("search").unwrap_or_default().trim().to_string();
+                            if query.is_empty() {
+                                explorer_state.error_message = Some("使い方: :search <検索する語>".to_string());
+                                return Vec::new();
+                            }
+                            let dir = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            return vec![Effect::preview(None, move || open_search(&dir, &query, &config))];
+                        }
+                        ["index", ..] => {
+                            let query = command_text.trim().strip_prefix("index").unwrap_or_default().trim().to_string();
+                            let conditions = match metadata::parse(&query) {
+                                Ok(conditions) => conditions,
+                                Err(message) => {
+                                    explorer_state.error_message = Some(message);
+                                    return Vec::new();
+                                }
+                            };
+                            let dir = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            return vec![Effect::preview(None, move || open_index(&dir, &query, &conditions, &config))];
+                        }
+                        ["graph"] => {
+                            // 選択中の文書を中心にする。文書を選んでいなければ最もリンクの多い文書にする
+                            let selected = explorer_state
+                                .selected_entry()
+                                .filter(|entry| !entry.is_dir && self.config.is_markdown(&entry.path))
+                                .map(|entry| entry.path.clone());
+                            let dir = explorer_state.current_path.clone();
+                            match link_graph(&dir, selected.as_deref(), &self.config) {
+                                Some(browser) => self.screens.push(Screen::Graph(browser)),
+                                None => explorer_state.error_message = Some("リンクを調べる文書が見つかりません".to_string()),
+                            }
+                        }
+                        ["tags"] => {
+                            let root = explorer_state.current_path.clone();
+                            let config = self.config.clone();
+                            let pane = self.active_pane;
+                            // 文書を読むのは時間がかかるので別スレッドで行い、同じディレクトリを表示していれば一覧を開く
+                            return vec![Effect::background(move || {
+                                // 索引のdaemonが動いていれば、文書を読まずにその答えを使う
+                                let index = match daemon::query(&root, "tags") {
+                                    Some(lines) => daemon::parse_tags(&lines, &root),
+                                    None => tags::index(&root, |path| config.is_markdown(path)),
+                                };
+                                move |app: &mut App| {
+                                    let Some(explorer_state) = app.panes.get_mut(pane).filter(|state| state.current_path == root) else {
+                                        return;
+                                    };
+                                    if index.is_empty() {
+                                        explorer_state.error_message = Some("タグの付いた文書が見つかりません".to_string());
+                                    } else {
+                                        explorer_state.tag_browser = Some(TagBrowser::new(root, index));
+                                    }
+                                }
+                            })];
+                        }
+                        [] => {} // 空のコマンドは無視
+                        _ => {
+                            explorer_state.error_message = Some(format!("不明なコマンドです: {}", command_text));
+                        }
+                    }
+                }
+                KeyCode::Char(c) => explorer_state.command_input.push(c),
+                KeyCode::Backspace => {
+                    explorer_state.command_input.pop();
+                }
+                KeyCode::Esc => {
+                    explorer_state.command_input.clear();
+                    explorer_state.in_command_mode = false;
+                }
+                _ => {}
+            }
+        } else if let Some(targets) = explorer_state.pending_delete.take() {
+            explorer_state.error_message = None;
+            explorer_state.message = None;
+            // `:delete` の確認は `y` でだけ削除し、それ以外のキーでは取りやめる
+            if key.code == KeyCode::Char('y') {
+                let failures = bulk::delete(&targets);
+                tracing::info!("deleted {} of {} entries", targets.len() - failures.len(), targets.len());
+                if let Some((path, e)) = failures.first() {
+                    explorer_state.error_message = Some(format!(
+                        "{} 件を削除できませんでした: {}: {}",
+                        failures.len(),
+                        platform::display(path),
+                        e
+                    ));
+                } else {
+                    explorer_state.message = Some(format!("{} 件を削除しました", targets.len()));
+                }
+                explorer_state.marked.clear();
+                explorer_state.reload_entries();
+            }
+        }
+        Vec::new()
+    }
+
+    /// エクスプローラーでキーに割り当てられた操作を行う
+    fn perform_explorer_action(&mut self, action: Option<Action>) -> Vec<Effect> {
+        if action == Some(Action::NextPane) && self.panes.len() > 1 {
+            self.active_pane = (self.active_pane + 1) % self.panes.len();
+            return Vec::new();
+        }
+        let explorer_state = &mut self.panes[self.active_pane];
+        explorer_state.error_message = None; // 操作時にエラーをクリア
+        explorer_state.message = None;
+        let Some(action) = action else {
+            return Vec::new();
+        };
+        match action {
+            Action::WorkspaceRoot => explorer_state.go_to_workspace_root(),
+            Action::ToggleMark => explorer_state.toggle_mark(),
+            Action::ClearMarks => explorer_state.marked.clear(),
+            Action::HomeDirectory => explorer_state.change_directory(&templates::expand_home(&self.config.home_dir)),
+            Action::PreviousDirectory => explorer_state.go_to_previous(),
+            Action::Command => explorer_state.in_command_mode = true,
+            Action::MoveDown => explorer_state.next(),
+            Action::MoveUp => explorer_state.previous(),
+            Action::PageDown => explorer_state.move_selection(explorer_state.page_size()),
+            Action::PageUp => explorer_state.move_selection(-explorer_state.page_size()),
+            Action::Top => explorer_state.select_first(),
+            Action::Bottom => explorer_state.select_last(),
+            Action::Back => explorer_state.go_back(),
+            Action::Forward => explorer_state.go_forward(),
+            Action::Parent => {
+                if let Some(parent) = explorer_state.current_path.parent() {
+                    let parent = parent.to_path_buf();
+                    explorer_state.change_directory(&parent);
+                } else {
+                    // ルートより上はドライブの一覧（Windowsのみ）
+                    explorer_state.change_directory(Path::new(""));
+                }
+            }
+            Action::Open => {
+                if let Some(selected) = explorer_state.selected_entry() {
+                    let selected_path = selected.path.clone();
+                    // `.md.gz` などは圧縮前のファイル名で判定する
+                    let name = compressed::strip_extension(&selected_path);
+                    if selected.is_dir || archive::is_archive(&selected_path) {
+                        explorer_state.change_directory(&selected_path);
+                    } else if self.config.is_previewable(&name) || org::is_org(&name) {
+                        let config = self.config.clone();
+                        return vec![Effect::preview(None, move || open_preview(&selected_path, &config))];
+                    } else {
+                        explorer_state.error_message = Some("Markdown・orgファイル以外はプレビューできません。".to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    /// 開いている画面を描く
+    pub fn view(&mut self, f: &mut Frame) {
+        // 一番上の全画面の画面から順に、重ねて表示する画面を描く
+        let Some(base) = self.base_screen() else {
+            return;
+        };
+        let decorations = self.config.decorations();
+        for screen in &mut self.screens[base..] {
+            match screen {
+                Screen::Explorer => ui_explorer(f, &mut self.panes, self.active_pane, self.theme, decorations),
+                Screen::Preview(state) => ui_preview(f, state, self.theme, decorations, self.config.minimal_chrome),
+                Screen::Log(view) => ui_log(f, view, self.theme, decorations),
+                Screen::Graph(browser) => ui_graph(f, browser, self.theme, decorations),
+                Screen::Error(report) => ui_error_report(f, report, self.theme, decorations),
+            }
+        }
+    }
+}
+
+/// `--profile-startup` で計測した、起動の各段階が終わるまでの起動からの経過時間
+pub struct StartupProfile {
+    started: Instant,
+    pub terminal_setup: Option<Duration>,
+    /// 最初のディレクトリの一覧を読み終えるまで
+    directory_scan: Option<Duration>,
+    /// 最初に表示する文書をレンダリングし終えるまで
+    first_render: Option<Duration>,
+    /// 最初の画面を描き終えるまで
+    first_frame: Option<Duration>,
+}
+
+impl StartupProfile {
+    pub fn new(started: Instant) -> Self {
+        Self { started, terminal_setup: None, directory_scan: None, first_render: None, first_frame: None }
+    }
+
+    /// アプリの状態を見て、終わった段階の時間を記録する
+    fn observe(&mut self, app: &mut App) {
+        let elapsed = self.started.elapsed();
+        if self.directory_scan.is_none() && app.panes.iter().all(|pane| pane.loader.is_none()) {
+            self.directory_scan = Some(elapsed);
+        }
+        if self.first_render.is_none() && app.visible_preview().is_some_and(|state| state.render_time.is_some()) {
+            self.first_render = Some(elapsed);
+        }
+    }
+
+    /// 計測を終えてよいか。標準入力は読み終えて描き終わるまで待つ
+    fn finished(&self, app: &App) -> bool {
+        let stdin_pending = app.stdin.as_ref().is_some_and(|stdin| !stdin.finished || stdin.dirty || stdin.rendering);
+        self.first_frame.is_some() && self.directory_scan.is_some() && !stdin_pending
+    }
+
+    pub fn print(&self) {
+        println!("startup profile (elapsed since start):");
+        for (stage, time) in [
+            ("terminal setup", self.terminal_setup),
+            ("directory scan", self.directory_scan),
+            ("first render", self.first_render),
+            ("first frame", self.first_frame),
+        ] {
+            let time = time.map_or_else(|| "-".to_string(), |time| format!("{:.1?}", time));
+            println!("  {:<16}{}", stage, time);
+        }
+    }
+}
+
+pub fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    settings: Settings,
+    args: &Options,
+    mut profile: Option<&mut StartupProfile>,
+) -> io::Result<()> {
+    // キー入力も別スレッドの処理の結果も、すべてこのチャネルに届く。
+    // UIスレッドはここで待つだけにして、時間のかかる処理は別スレッドで行う
+    let (events, event_receiver) = mpsc::channel::<AppEvent>();
+    let input = InputReader::spawn(events.clone());
+    let mut app = App::new(settings, args, &events)?;
+    // ページャーモードでは標準入力を少しずつ読み込み、一定間隔でまとめて別スレッドで再描画する
+    if args.pager {
+        pager::spawn_reader(events.clone());
+    }
+
+    // 前回描いた後にイベントを処理したか
+    let mut changed = true;
+    let mut last_draw = Instant::now();
+    loop {
+        let animating = app.visible_preview().is_some_and(PreviewState::tick_animation);
+        let effects = app.update(AppEvent::Tick);
+        perform(effects, &mut app, terminal, &input, &events)?;
+
+        // 通信量を抑えるときは、イベントが無ければ一定間隔でしか描き直さない
+        let low_bandwidth = app.config.low_bandwidth;
+        if changed || animating || !low_bandwidth || last_draw.elapsed() >= LOW_BANDWIDTH_INTERVAL {
+            terminal.draw(|f| app.view(f))?;
+            changed = false;
+            last_draw = Instant::now();
+            if let Some(profile) = profile.as_deref_mut() {
+                profile.observe(&mut app);
+                profile.first_frame.get_or_insert(profile.started.elapsed());
+                if profile.finished(&app) {
+                    return Ok(());
+                }
+            }
+        }
+
+        // 届いているイベントをまとめて処理する。端末からの入力があれば、その結果を描画してから次を処理する。
+        // 通信量を抑えるときは、続けて届いているキー入力もまとめてから描く
+        let timeout = if animating { ANIMATION_FRAME } else { IDLE_INTERVAL };
+        let mut received = match event_receiver.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return Err(io::Error::other("event channel closed")),
+        };
+        while let Some(event) = received.take() {
+            changed = true;
+            let from_terminal = matches!(event, AppEvent::Terminal(_));
+            let effects = app.update(event);
+            perform(effects, &mut app, terminal, &input, &events)?;
+            if from_terminal && !low_bandwidth {
+                break;
+            }
+            received = event_receiver.try_recv().ok();
+        }
+    }
+}
+
+/// `update` が返した処理を順に実行する。終了する場合は "quit" エラーを返す
+fn perform<B: Backend>(
+    effects: Vec<Effect>,
+    app: &mut App,
+    terminal: &mut Terminal<B>,
+    input: &InputReader,
+    events: &Sender<AppEvent>,
+) -> io::Result<()> {
+    for effect in effects {
+        match effect {
+            Effect::Quit => return Err(io::Error::other("quit")),
+            Effect::Fail(e) => return Err(e),
+            Effect::Suspend => suspend(terminal, input)?,
+            Effect::Edit { path, line } => {
+                if let Err(e) = edit_in_editor(terminal, input, &path, line) {
+                    app.show_error(ErrorReport::new(format!("{} を編集できません", path.display()), &e));
+                    break;
+                }
+                // 編集したファイルを表示中なら、編集後の内容を読み込み直す
+                let config = app.config.clone();
+                if let Some(state) = app.visible_preview()
+                    && state.path.as_deref() == Some(path.as_path())
+                {
+                    state.reload(&config);
+                }
+            }
+            Effect::OpenPreview { anchor, open } => app.pending_preview = Some(spawn_preview(events, anchor, open)),
+            Effect::RenderStdin { markdown, filters, theme, options } => {
+                let events = events.clone();
+                thread::spawn(move || {
+                    // フィルターが失敗したら入力をそのまま表示し、エラーを知らせる
+                    let (markdown, message) = match filter::apply(markdown.clone(), &filters, None) {
+                        Ok(filtered) => (filtered, None),
+                        Err(e) => (markdown, Some(e.to_string())),
+                    };
+                    let result = error_report::catch("Failed to render stdin", || {
+                        let (rendered, char_count) = render_markdown_document(&markdown, theme, &|_| None, &options);
+                        Ok((Box::new(rendered), char_count))
+                    });
+                    let _ = events.send(AppEvent::StdinRendered { markdown, result, message });
+                });
+            }
+            Effect::Background(work) => {
+                let events = events.clone();
+                thread::spawn(move || {
+                    let _ = events.send(AppEvent::Background(work()));
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+
+// --- UI描画 ---
+
+/// エクスプローラーのタイトル。パスと「(選択位置/総数)」を表示する
+fn explorer_title(state: &ExplorerState) -> String {
+    let mut title = if state.current_path.as_os_str().is_empty() {
+        "Drives".to_string()
+    } else {
+        workspace::display_path(&state.current_path, state.workspace_root.as_deref())
+    };
+    let total = state.entries.len();
+    let index = state.list_state.selected().map_or(0, |i| i + 1);
+    title.push_str(&format!(" ({}/{})", index, total));
+    if !state.marked.is_empty() {
+        title.push_str(&format!(" [{} marked]", state.marked.len()));
+    }
+    if state.hide_gitignored {
+        title.push_str(" [gitignore]");
+    }
+    if state.is_loading() {
+        title.push_str(" (loading…)");
+    }
+    title
+}
+
+fn ui_explorer(f: &mut Frame, panes: &mut [ExplorerState], active_pane: usize, theme: &ColorScheme, decorations: Decorations) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(2)].as_ref())
+        .split(f.size());
+
+    // 2画面表示では左右に並べる
+    let pane_areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, panes.len() as u32); panes.len()])
+        .split(chunks[0]);
+    let dual_pane = panes.len() > 1;
+    for (index, (state, area)) in panes.iter_mut().zip(pane_areas.iter()).enumerate() {
+        let focused = !dual_pane || index == active_pane;
+        ui_explorer_pane(f, *area, state, focused, theme, decorations);
+    }
+
+    if let Some(browser) = &mut panes[active_pane].tag_browser {
+        let items: Vec<ListItem> = browser.items().into_iter().map(ListItem::new).collect();
+        let area = centered_rect(chunks[0], 60, items.len() as u16 + 2);
+        let list = List::new(items)
+            .block(bordered_block(decorations).title(browser.title()))
+            .style(Style::default().fg(theme.fg).bg(theme.bg))
+            .highlight_style(Style::default().fg(theme.selection_fg).bg(theme.selection_bg));
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, &mut browser.list_state);
+    }
+
+    let state = &panes[active_pane];
+    let status_bar_style = Style::default().fg(theme.fg).bg(theme.bg);
+    let status_text = if state.in_command_mode {
+        format!(":{}", state.command_input)
+    } else if let Some(targets) = &state.pending_delete {
+        format!("{} 件を削除しますか？ (y/n)", targets.len())
+    } else if let Some(err) = &state.error_message {
+        err.clone()
+    } else if let Some(message) = &state.message {
+        message.clone()
+    } else if dual_pane {
+        "j/k or ↓/↑: Move | l/Enter: Open | h: Up | Tab: Switch pane | :<command> Enter: Run".to_string()
+    } else {
+        "j/k or ↓/↑: Move | l/Enter: Open | h: Up | :<command> Enter: Run".to_string()
+    };
+    let status_bar = Paragraph::new(status_text).style(if state.error_message.is_some() {
+        status_bar_style.fg(Color::Red)
+    } else {
+        status_bar_style
+    });
+
+    f.render_widget(status_bar, chunks[1]);
+}
+
+/// エクスプローラーの一覧を1つ描画する。`focused` でないペインは枠線と選択を控えめに表示する
+fn ui_explorer_pane(f: &mut Frame, area: Rect, state: &mut ExplorerState, focused: bool, theme: &ColorScheme, decorations: Decorations) {
+    // 枠線の分を除いた、一覧に表示できる行数
+    state.visible_rows = area.height.saturating_sub(2) as usize;
+    state.scroll_to_selection();
+
+    let items: Vec<ListItem> = state
+        .entries
+        .iter()
+        .skip(state.list_offset)
+        .take(state.visible_rows)
+        .map(|entry| {
+            // ドライブのルートには名前が無いので、パスをそのまま表示する
+            let mut display_name = match entry.path.file_name() {
+                Some(name) if entry.is_dir => format!("{}/", name.to_string_lossy()),
+                Some(name) => name.to_string_lossy().to_string(),
+                None => platform::display(&entry.path),
+            };
+
+            let kind = FileKind::of(entry);
+            if state.show_icons {
+                display_name = format!("{} {}", kind.icon(), display_name);
+            }
+            // 印を付けたエントリは先頭に `*` を付けて太字にする
+            if state.marked.contains(&entry.path) {
+                ListItem::new(Line::from(vec![
+                    Span::styled("* ", Style::default().fg(theme.heading).add_modifier(Modifier::BOLD)),
+                    Span::styled(display_name, Style::default().fg(kind.color(theme)).add_modifier(Modifier::BOLD)),
+                ]))
+            } else {
+                ListItem::new(Span::styled(display_name, Style::default().fg(kind.color(theme))))
+            }
+        })
+        .collect();
+
+    let highlight_style = if focused {
+        Style::default()
+            .bg(theme.selection_bg)
+            .fg(theme.selection_fg)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    };
+    let border_style = Style::default().fg(if focused { theme.fg } else { theme.comment });
+    let list = List::new(items)
+        .block(
+            bordered_block(decorations)
+                .border_style(border_style)
+                .title(explorer_title(state))
+                .style(Style::default().fg(theme.fg).bg(theme.bg)),
+        )
+        .highlight_style(highlight_style)
+        .highlight_symbol(">> ");
+
+    // 表示範囲だけを描画しているので、選択位置も表示範囲内の位置に直して渡す
+    let mut visible_state = ListState::default()
+        .with_selected(state.list_state.selected().map(|i| i.saturating_sub(state.list_offset)));
+    f.render_stateful_widget(list, area, &mut visible_state);
+}
+
+/// 描画用の行のうち、`scroll` 行目から `area` に収まりうる分だけを取り出し、折り返して描く準備をする。
+/// 引用の枠線のために先に折り返して行が増えても、スクロール位置や行ごとの印の位置がずれないよう、取り出してから折り返す
+fn visible_text(mut content: Text<'static>, scroll: u16, code_lines: &[usize], wrap: bool, area: Rect, theme: &ColorScheme) -> Text<'static> {
+    let top = (scroll as usize).min(content.lines.len());
+    content.lines.drain(..top);
+    // 1行は少なくとも1段を使うので、画面の高さより後の行は描かれない
+    content.lines.truncate(area.height as usize);
+    if wrap {
+        let code_lines: Vec<usize> = code_lines.iter().filter_map(|line| line.checked_sub(top)).collect();
+        clip_code_lines(&mut content, &code_lines, area.width as usize, theme);
+        wrap_quote_lines(&mut content, area.width as usize);
+    }
+    content
+}
+
+/// 本文の段落。先頭の行から描くので、縦のスクロールは `visible_text` で済ませてある
+fn content_paragraph(text: Text<'static>, wrap: bool, h_scroll: u16, theme: &ColorScheme) -> Paragraph<'static> {
+    let paragraph = Paragraph::new(text).style(Style::default().fg(theme.fg).bg(theme.bg)).scroll((0, h_scroll));
+    if wrap { paragraph.wrap(Wrap { trim: false }) } else { paragraph }
+}
+
+/// プレビューを描画する。`minimal` ならフッターは入力中とメッセージがあるときだけ表示し、ポップアップの枠線も描かない
+fn ui_preview(f: &mut Frame, state: &mut PreviewState, theme: &ColorScheme, decorations: Decorations, minimal: bool) {
+    let show_footer = !minimal
+        || state.search_input.is_some()
+        || state.hint_input.is_some()
+        || state.command_input.is_some()
+        || state.message.is_some();
+    let popup_block = || if minimal { Block::default() } else { bordered_block(decorations) };
+    // Create a layout with a main area and a footer
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(if state.truncated.is_some() { 1 } else { 0 }), // Truncation banner
+            Constraint::Min(0), // Main content
+            Constraint::Length(if show_footer { 1 } else { 0 }), // Footer
+        ])
+        .split(f.size());
+    let chunks = [areas[1], areas[2]];
+
+    // 先頭だけを読み込んだファイルは、その旨と全体を読み込むキーを上端に表示する
+    if let Some((loaded, total)) = state.truncated {
+        let banner = format!(
+            "大きなファイルなので先頭の {} だけを表示しています（全体は {}）。L で全体を読み込みます",
+            format_size(loaded),
+            format_size(total)
+        );
+        f.render_widget(Paragraph::new(banner).style(Style::default().fg(Color::Black).bg(Color::Yellow)), areas[0]);
+    }
+
+    // 分けているときは間に区切りを入れ、操作している方を矢印で示す。別の文書なら、その名前も示す
+    state.sync_split_scroll();
+    let (focused_area, other_area) = match &state.split {
+        Some(split) if split.vertical => {
+            let areas = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Length(1), Constraint::Min(0)])
+                .split(chunks[0]);
+            // 左右のときは縦線の一番上に矢印を置く
+            let (rule, marker) = match (decorations, split.second_focused) {
+                (Decorations::Unicode, right) => ("│", if right { "▶" } else { "◀" }),
+                (Decorations::Ascii, right) => ("|", if right { ">" } else { "<" }),
+                (Decorations::ScreenReader, right) => (" ", if right { "R" } else { "L" }),
+            };
+            let mut lines = vec![Line::from(marker)];
+            lines.extend((1..areas[1].height).map(|_| Line::from(rule)));
+            f.render_widget(Paragraph::new(lines).style(Style::default().fg(theme.comment).bg(theme.bg)), areas[1]);
+            if split.second_focused { (areas[2], Some(areas[0])) } else { (areas[0], Some(areas[2])) }
+        }
+        Some(split) => {
+            let areas = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(50), Constraint::Length(1), Constraint::Min(0)])
+                .split(chunks[0]);
+            let (rule, marker) = match (decorations, split.second_focused) {
+                (Decorations::Unicode, bottom) => ("─", if bottom { " ▼ " } else { " ▲ " }),
+                (Decorations::Ascii, bottom) => ("-", if bottom { " v " } else { " ^ " }),
+                (Decorations::ScreenReader, bottom) => (" ", if bottom { "Bottom pane" } else { "Top pane" }),
+            };
+            let other_title = match &split.other {
+                SplitPane::Document(other) => format!("{} ", other.display_title()),
+                SplitPane::Scroll(_) => String::new(),
+            };
+            let sync = match split.sync {
+                ScrollSync::Off => "",
+                ScrollSync::Proportional => "(sync) ",
+                ScrollSync::Paragraphs => "(aligned) ",
+            };
+            let separator = format!("{}{}{}{}", rule.repeat(2), marker, other_title, sync);
+            let separator = format!("{}{}", separator, rule.repeat((areas[1].width as usize).saturating_sub(Span::raw(separator.as_str()).width())));
+            f.render_widget(Paragraph::new(separator).style(Style::default().fg(theme.comment).bg(theme.bg)), areas[1]);
+            if split.second_focused { (areas[2], Some(areas[0])) } else { (areas[0], Some(areas[2])) }
+        }
+        None => (chunks[0], None),
+    };
+    state.viewport_height = focused_area.height;
+    state.viewport_width = focused_area.width;
+
+    // Main content paragraph without a block/border
+    let mut content = state.content.clone();
+    if let Some(index) = state.focused_footnote {
+        let reference = &state.footnote_refs[index];
+        if let Some(span) = content.lines.get_mut(reference.line).and_then(|line| line.spans.get_mut(reference.span)) {
+            span.style = span.style.fg(theme.selection_fg).bg(theme.selection_bg);
+        }
+    }
+    // リンクヒントモードでは各リンクの直前にラベルを差し込む。添字がずれないよう後ろから挿入する
+    if let Some((kind, input)) = &state.hint_input {
+        let mut hints = state.visible_links(*kind);
+        hints.sort_by_key(|(_, link)| std::cmp::Reverse((link.line, link.span)));
+        let hint_style = Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let focused_style = Style::default().fg(theme.selection_fg).bg(theme.selection_bg).add_modifier(Modifier::BOLD);
+        let focused = state.hint_focus.as_ref().map(|(label, _)| label.as_str());
+        for (label, link) in hints.into_iter().filter(|(label, _)| label.starts_with(input.as_str())) {
+            if let Some(line) = content.lines.get_mut(link.line) {
+                let index = link.span.min(line.spans.len());
+                let style = if focused == Some(label.as_str()) { focused_style } else { hint_style };
+                line.spans.insert(index, Span::styled(label, style));
+            }
+        }
+    }
+    if let Some(misspelled) = &state.misspelled {
+        let top = state.scroll as usize;
+        let bottom = state.visible_end(top);
+        for line in content.lines.iter_mut().take(bottom).skip(top) {
+            spell::highlight(line, misspelled, theme);
+        }
+    }
+    // 保存で変わった部分は、初めは背景色で、その後は下線だけで強調する。
+    // ヒントのラベルを差し込んだ行では位置がずれるので、ヒントモードの間は強調しない
+    if let Some((changed_at, changes)) = &state.changes
+        && changed_at.elapsed() < CHANGE_HIGHLIGHT_DURATION
+        && state.hint_input.is_none()
+    {
+        let style = if changed_at.elapsed() < CHANGE_HIGHLIGHT_DURATION * 2 / 3 {
+            Style::default().fg(theme.selection_fg).bg(theme.selection_bg)
+        } else {
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        };
+        let top = state.scroll as usize;
+        let bottom = state.visible_end(top);
+        for change in changes {
+            match change {
+                diff::Change::Line(index) if (top..bottom).contains(index) => {
+                    if let Some(line) = content.lines.get_mut(*index) {
+                        line.spans.iter_mut().for_each(|span| span.style = span.style.patch(style));
+                    }
+                }
+                diff::Change::Words(index, ranges) if (top..bottom).contains(index) => {
+                    if let Some(line) = content.lines.get_mut(*index) {
+                        diff::highlight(line, ranges, style);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    // コメントを付けた行は末尾に印を付ける
+    let marker = match decorations {
+        Decorations::Unicode => " ✎",
+        Decorations::Ascii => " [*]",
+        Decorations::ScreenReader => " [comment]",
+    };
+    let mut annotated: Vec<usize> = state.annotation_lines().into_iter().map(|(index, _)| index).collect();
+    annotated.dedup();
+    for index in annotated {
+        if let Some(line) = content.lines.get_mut(index) {
+            line.spans.push(Span::styled(marker, Style::default().fg(Color::Black).bg(Color::Yellow)));
+        }
+    }
+    // 作者と経過時間は、まとまりの先頭の行の右端に寄せて薄く表示する
+    for (index, label) in state.blame_labels() {
+        if let Some(line) = content.lines.get_mut(index) {
+            let label = format!("{} ", label);
+            let padding = (focused_area.width as usize).saturating_sub(line.width() + Span::raw(label.as_str()).width()).max(1);
+            line.spans.push(Span::raw(" ".repeat(padding)));
+            line.spans.push(Span::styled(label, Style::default().fg(theme.comment).add_modifier(Modifier::DIM)));
+        }
+    }
+    let other_scroll = match &state.split {
+        Some(SplitView { other: SplitPane::Scroll(scroll), .. }) => Some(*scroll),
+        _ => None,
+    };
+    if let (Some(area), Some(scroll)) = (other_area, other_scroll) {
+        let text = visible_text(content.clone(), scroll, &state.code_lines, state.wrap, area, theme);
+        f.render_widget(content_paragraph(text, state.wrap, state.h_scroll, theme), area);
+    }
+    let text = visible_text(content, state.scroll, &state.code_lines, state.wrap, focused_area, theme);
+    f.render_widget(content_paragraph(text, state.wrap, state.h_scroll, theme), focused_area);
+    if let (Some(area), Some(SplitView { other: SplitPane::Document(other), .. })) = (other_area, &mut state.split) {
+        other.viewport_height = area.height;
+        other.viewport_width = area.width;
+        let text = visible_text(other.content.clone(), other.scroll, &other.code_lines, other.wrap, area, theme);
+        f.render_widget(content_paragraph(text, other.wrap, other.h_scroll, theme), area);
+    }
+
+    // Footer
+    let footer_style = Style::default().fg(theme.comment).bg(theme.bg);
+    let footer = if let Some((direction, input)) = &state.search_input {
+        let prefix = if *direction == SearchDirection::Forward { '/' } else { '?' };
+        Paragraph::new(format!("{}{}", prefix, input)).style(footer_style.fg(theme.fg))
+    } else if let Some((kind, input)) = &state.hint_input {
+        let prompt = match kind {
+            HintKind::Links => "follow",
+            HintKind::Images => "open image",
+        };
+        // `Tab` で選んだリンクがあれば、その行き先も表示する
+        let text = match &state.hint_focus {
+            Some((_, status)) => format!("{}: {} | {}", prompt, input, status),
+            None => format!("{}: {} (Tab: inspect)", prompt, input),
+        };
+        Paragraph::new(text).style(footer_style.fg(theme.fg))
+    } else if let Some(input) = &state.command_input {
+        Paragraph::new(format!(":{}", input)).style(footer_style.fg(theme.fg))
+    } else if let Some(message) = &state.message {
+        Paragraph::new(message.clone()).style(footer_style.fg(Color::Red))
+    } else {
+        // 横に見切れている方向を矢印で示す
+        let indicator = match state.horizontal_overflow() {
+            (false, false) => String::new(),
+            (left, right) => {
+                let (left_arrow, right_arrow) = if decorations == Decorations::Ascii { ("<", ">") } else { ("◀", "▶") };
+                format!("{}{} | ", if left { left_arrow } else { " " }, if right { right_arrow } else { " " })
+            }
+        };
+        let debug = if state.debug_footer {
+            let render_time = state.render_time.map_or_else(|| "-".to_string(), |time| format!("{:.1?}", time));
+            format!(
+                "render {} | {} lines | ~{} | ",
+                render_time,
+                state.content.lines.len(),
+                format_size(state.content_size)
+            )
+        } else {
+            String::new()
+        };
+        let compression = match state.compression {
+            Some((codec, compressed_size, size)) => {
+                format!("{} {} → {} | ", codec.name(), format_size(compressed_size), format_size(size))
+            }
+            None => String::new(),
+        };
+        let footer_text = format!(
+            "{}{}{}{} | {} chars | Press 'q' to close",
+            debug,
+            indicator,
+            compression,
+            state.display_title(),
+            state.char_count
+        );
+        Paragraph::new(footer_text).style(footer_style).alignment(Alignment::Right)
+    };
+    f.render_widget(footer, chunks[1]);
+
+    // 選んでいる脚注の本文を画面下部に小さく表示する
+    if let Some((label, text)) = state.focused_footnote_text() {
+        let width = 60.min(chunks[0].width);
+        let text_width = width.saturating_sub(2).max(1) as usize;
+        // 全角文字は2列分として数え、単語単位の折り返しで増える分を1行見込む
+        let text_columns: usize = text.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum();
+        let text_lines = text_columns.div_ceil(text_width).max(1);
+        let text_lines = if text_lines > 1 { text_lines + 1 } else { text_lines };
+        let height = (text_lines as u16 + 2).min(chunks[0].height);
+        let area = Rect {
+            x: chunks[0].x + (chunks[0].width - width) / 2,
+            y: chunks[0].bottom() - height,
+            width,
+            height,
+        };
+        let popup = Paragraph::new(text.to_string())
+            .wrap(Wrap { trim: true })
+            .style(Style::default().fg(theme.fg).bg(theme.code_bg))
+            .block(popup_block().title(format!(" [^{}] ", label)));
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
+    if let Some((title, body)) = &state.info_popup {
+        let width = body.iter().map(|line| Span::raw(line.as_str()).width() as u16).max().unwrap_or(0) + 4;
+        let area = centered_rect(chunks[0], width.max(title.len() as u16 + 6), body.len() as u16 + 2);
+        let popup = Paragraph::new(body.iter().map(|line| Line::from(format!(" {}", line))).collect::<Vec<_>>())
+            .style(Style::default().fg(theme.fg).bg(theme.bg))
+            .block(popup_block().title(format!(" {} ", title)));
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
+    if let Some(HeadingPicker { title, entries, list_state, .. }) = &mut state.heading_picker {
+        let area = centered_rect(chunks[0], 50, entries.len() as u16 + 2);
+        let items: Vec<ListItem> = entries.iter().map(|(_, label)| ListItem::new(label.clone())).collect();
+        let list = List::new(items)
+            .block(popup_block().title(format!(" {} ", title)))
+            .style(Style::default().fg(theme.fg).bg(theme.bg))
+            .highlight_style(Style::default().fg(theme.selection_fg).bg(theme.selection_bg));
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, list_state);
+    }
+}
+
+/// 操作の失敗を知らせるエラー画面。画面中央に詳細を表示する
+fn ui_error_report(f: &mut Frame, report: &ErrorReport, theme: &ColorScheme, decorations: Decorations) {
+    let area = f.size();
+    let width = report
+        .details
+        .iter()
+        .map(|line| Span::raw(line.as_str()).width() as u16 + 4)
+        .chain([Span::raw(report.title.as_str()).width() as u16 + 6, 32])
+        .max()
+        .unwrap_or(0)
+        .min(area.width.saturating_sub(4).max(20));
+    let mut lines: Vec<Line> = report.details.iter().map(|line| Line::from(line.as_str())).collect();
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled("Press any key to dismiss", Style::default().fg(theme.comment))));
+    // 折り返しで増える行を見込んで高さを決める
+    let inner_width = width.saturating_sub(4).max(1) as usize;
+    let height: usize = lines.iter().map(|line| line.width().div_ceil(inner_width).max(1)).sum();
+    let popup_area = centered_rect(area, width, height as u16 + 2);
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .style(Style::default().fg(theme.fg).bg(theme.bg))
+        .block(
+            bordered_block(decorations)
+                .border_style(Style::default().fg(Color::Red))
+                .title(Span::styled(format!(" {} ", report.title), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)))
+                .padding(Padding::horizontal(1)),
+        );
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+/// `:log` のログ表示。レベルごとに色を分ける
+fn ui_log(f: &mut Frame, view: &mut LogView, theme: &ColorScheme, decorations: Decorations) {
+    let size = f.size();
+    let area = centered_rect(size, size.width.saturating_sub(4), size.height.saturating_sub(2));
+    view.rows = area.height.saturating_sub(2) as usize;
+    view.scroll = view.scroll.min(view.lines.len().saturating_sub(view.rows));
+    let lines: Vec<Line> = view.lines[view.scroll..]
+        .iter()
+        .take(view.rows)
+        .map(|line| {
+            let level = line.split_once("] ").and_then(|(_, rest)| rest.split_whitespace().next());
+            let color = match level {
+                Some("ERROR") => Color::Red,
+                Some("WARN") => Color::Yellow,
+                Some("DEBUG" | "TRACE") => theme.comment,
+                _ => theme.fg,
+            };
+            Line::from(Span::styled(line.as_str(), Style::default().fg(color)))
+        })
+        .collect();
+    let title = if view.lines.is_empty() {
+        " Log (no events) ".to_string()
+    } else {
+        format!(" Log ({}-{}/{}) ", view.scroll + 1, view.scroll + lines.len(), view.lines.len())
+    };
+    let popup = Paragraph::new(lines)
+        .style(Style::default().fg(theme.fg).bg(theme.bg))
+        .block(bordered_block(decorations).title(title).title_bottom(" q: close "));
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// リンクのグラフを描画する。中心の文書の左にリンク元、右にリンク先を矢印でつないで並べる
+fn ui_graph(f: &mut Frame, browser: &GraphBrowser, theme: &ColorScheme, decorations: Decorations) {
+    let size = f.size();
+    let area = centered_rect(size, size.width.saturating_sub(4), size.height.saturating_sub(2));
+    let graph = &browser.graph;
+    let title = format!(
+        " Graph: {} documents, {} links, {} orphans ",
+        graph.outgoing.len(),
+        graph.edges(),
+        graph.orphans().len()
+    );
+    let help = if browser.history.is_empty() {
+        " j/k: move | h/l: column | Enter: center | o: open | q: close "
+    } else {
+        " j/k: move | h/l: column | Enter: center | Backspace: back | o: open | q: close "
+    };
+    let block = bordered_block(decorations).title(title).title_bottom(help);
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block.style(Style::default().fg(theme.fg).bg(theme.bg)), area);
+
+    let name = browser.name(&browser.current).to_string();
+    let center_width = (Span::raw(name.as_str()).width() as u16 + 4).clamp(16, inner.width / 3);
+    let side_width = inner.width.saturating_sub(center_width) / 2;
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(side_width), Constraint::Length(center_width), Constraint::Length(side_width)])
+        .split(inner);
+    let arrow = match decorations {
+        Decorations::Unicode => " ──▶ ",
+        Decorations::Ascii => " --> ",
+        Decorations::ScreenReader => " ",
+    };
+    let highlight = Style::default().fg(theme.selection_fg).bg(theme.selection_bg);
+    for (column, area) in [(Column::Incoming, columns[0]), (Column::Outgoing, columns[2])] {
+        let entries = browser.entries(column);
+        let heading = match column {
+            Column::Incoming => format!("Linked from ({})", entries.len()),
+            Column::Outgoing => format!("Links to ({})", entries.len()),
+        };
+        // リンク元は右に寄せ、中心の文書に向かう矢印を付ける
+        let align = |text: String| match column {
+            Column::Incoming => format!("{}{}", " ".repeat((area.width as usize).saturating_sub(Span::raw(text.as_str()).width())), text),
+            Column::Outgoing => text,
+        };
+        let items: Vec<ListItem> = if entries.is_empty() {
+            vec![ListItem::new(Span::styled(align("(none) ".to_string()), Style::default().fg(theme.comment)))]
+        } else {
+            entries
+                .iter()
+                .map(|path| {
+                    let name = browser.name(path);
+                    ListItem::new(match column {
+                        Column::Incoming => align(format!("{}{}", name, arrow)),
+                        Column::Outgoing => format!("{}{}", arrow.trim_start(), name),
+                    })
+                })
+                .collect()
+        };
+        let mut list_state = ListState::default();
+        if browser.column == column && !entries.is_empty() {
+            list_state.select(Some(browser.selection(column)));
+        }
+        let title = Line::from(Span::styled(heading, Style::default().add_modifier(Modifier::BOLD)));
+        let list = List::new(items).block(Block::default().title(match column {
+            Column::Incoming => title.alignment(Alignment::Right),
+            Column::Outgoing => title,
+        }));
+        f.render_stateful_widget(list.highlight_style(highlight), area, &mut list_state);
+    }
+
+    // 中心の文書は枠で囲み、矢印の先頭の行の高さに置く
+    let counts = format!("{} in / {} out", graph.incoming(&browser.current).len(), graph.outgoing(&browser.current).len());
+    let center = Rect { y: columns[1].y, height: columns[1].height.min(4), ..columns[1] };
+    let paragraph = Paragraph::new(vec![
+        Line::from(Span::styled(name, Style::default().add_modifier(Modifier::BOLD))),
+        Line::from(Span::styled(counts, Style::default().fg(theme.comment))),
+    ])
+    .alignment(Alignment::Center)
+    .block(bordered_block(decorations));
+    f.render_widget(paragraph, center);
+}
+
+/// ASCII文字だけで描く枠線
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+/// 枠線で囲んだブロック。スクリーンリーダーが罫線を読み上げないよう、`ScreenReader` では枠線を描かない
+fn bordered_block(decorations: Decorations) -> Block<'static> {
+    match decorations {
+        Decorations::Unicode => Block::default().borders(Borders::ALL),
+        Decorations::Ascii => Block::default().borders(Borders::ALL).border_set(ASCII_BORDER),
+        Decorations::ScreenReader => Block::default(),
+    }
+}
+
+/// バイト数を KiB / MiB 単位の読みやすい表記にする
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// `area` の中央に、収まる範囲で指定した大きさの領域を取る
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// ファイルをプレビューとして開く。読み込みや描画に失敗したらエラー画面の内容を返す
+fn open_preview(path: &Path, config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch(&format!("プレビューを開けません: {}", path.display()), || PreviewState::new(path, config))
+}
+
+/// `open` を別スレッドで実行してプレビューを開き、結果を `AppEvent::Preview` で送る。
+/// 返す番号で、届いた結果が最新の要求のものかを見分ける
+fn spawn_preview(
+    events: &Sender<AppEvent>,
+    anchor: Option<String>,
+    open: impl FnOnce() -> Result<PreviewState, ErrorReport> + Send + 'static,
+) -> u64 {
+    let request = next_task_id();
+    let events = events.clone();
+    thread::spawn(move || {
+        let result = open().map(Box::new);
+        let _ = events.send(AppEvent::Preview { request, anchor, result });
+    });
+    request
+}
+
+/// ファイルのコミットの時点の内容をプレビューとして開く。元のファイルとは別の文書として扱い、読み込み直さない
+fn open_revision(path: &Path, commit: &git::Commit, config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch(&format!("{} の {} を開けません", path.display(), commit.hash), || {
+        let (markdown, line_origins) = prepare_markdown(git::show(path, commit)?, path, config)?;
+        let title = format!("{} @ {}", workspace::display_path(path, workspace::find_root(path).as_deref()), commit.hash);
+        let mut state = PreviewState::from_markdown(&markdown, title, config, &|_| None);
+        state.source = Some(markdown);
+        state.set_line_origins(line_origins);
+        state.debug_footer = config.debug_footer;
+        state.message = Some(format!("{}（{}）の版: {}", commit.hash, commit.date, commit.summary));
+        Ok(state)
+    })
+}
+
+/// `dir` の下の古くなった文書の一覧をプレビューとして開く
+fn open_audit(dir: &Path, days: u64, config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch("古い文書を調べられません", || {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let stale = audit::stale_documents(dir, days, now, |path| config.is_markdown(path))?;
+        let markdown = audit::report(dir, days, now, &stale);
+        let mut state = PreviewState::from_markdown(&markdown, format!("Audit of {}", platform::display(dir)), config, &|_| None);
+        state.source = Some(markdown);
+        state.debug_footer = config.debug_footer;
+        Ok(state)
+    })
+}
+
+/// `:assets`: `dir` の下の文書から参照している画像やファイルのうち、見つからないものを文書ごとにプレビューする
+fn open_assets(dir: &Path, config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch("画像やファイルの参照を調べられません", || {
+        let documents = check::documents(&[dir.to_path_buf()], |path| config.is_markdown(path));
+        let mut problems = Vec::new();
+        for path in &documents {
+            match load_markdown(path, config) {
+                Ok(markdown) => problems.extend(check::missing_assets(path, &structure::analyze(&markdown), |path| config.is_markdown(path))),
+                Err(e) => tracing::warn!("failed to read {}: {}", path.display(), e),
+            }
+        }
+        let markdown = check::assets_report(dir, documents.len(), &problems);
+        let mut state = PreviewState::from_markdown(&markdown, format!("Assets of {}", platform::display(dir)), config, &|_| None);
+        state.source = Some(markdown);
+        state.debug_footer = config.debug_footer;
+        Ok(state)
+    })
+}
+
+/// `:search`: `dir` の下の文書を全文検索し、結果をプレビューする
+fn open_search(dir: &Path, query: &str, config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch("検索できません", || {
+        let started = Instant::now();
+        // 索引のdaemonが動いていれば、その索引で検索する
+        let hits = match daemon::query(dir, &format!("search {}", query)) {
+            Some(lines) => daemon::parse_hits(&lines, dir),
+            None => search::search(&load_vault(dir.to_path_buf(), config), query, search::RESULTS),
+        };
+        tracing::info!("searched {:?} under {}: {} hits in {:?}", query, dir.display(), hits.len(), started.elapsed());
+        let markdown = search::report(dir, query, &hits);
+        let mut state = PreviewState::from_markdown(&markdown, format!("Search: {}", query), config, &|_| None);
+        state.source = Some(markdown);
+        state.debug_footer = config.debug_footer;
+        Ok(state)
+    })
+}
+
+/// `:index`: `dir` の下の文書のうちフロントマターが条件を満たすものを一覧にしてプレビューする
+fn open_index(dir: &Path, query: &str, conditions: &[metadata::Condition], config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch("文書の一覧を作れません", || {
+        // 索引のdaemonが動いていれば、その索引で絞り込む
+        let entries = match daemon::query(dir, &format!("index {}", query)) {
+            Some(lines) => daemon::parse_entries(&lines, dir),
+            None => metadata::filter(&load_vault(dir.to_path_buf(), config), dir, conditions),
+        };
+        let markdown = metadata::report(dir, query, conditions, &entries);
+        let mut state = PreviewState::from_markdown(&markdown, format!("Index of {}", platform::display(dir)), config, &|_| None);
+        state.source = Some(markdown);
+        state.debug_footer = config.debug_footer;
+        Ok(state)
+    })
+}
+
+/// `root` の下の文書の索引。`search_index` が有効なら保存した索引を読み込み、更新された文書だけを読み直して保存し直す
+fn load_vault(root: PathBuf, config: &Config) -> Vault {
+    let cache = config.search_index.then(|| vault::cache_file(&root)).flatten();
+    let mut vault = cache
+        .as_deref()
+        .and_then(|file| Vault::load(root.clone(), file).inspect_err(|e| tracing::debug!("no saved index at {}: {}", file.display(), e)).ok())
+        .unwrap_or_else(|| Vault::new(root));
+    let parsed = vault.refresh(|path| config.is_markdown(path));
+    if let Some(file) = cache
+        && parsed > 0
+        && let Err(e) = vault.save(&file)
+    {
+        tracing::warn!("failed to save the index to {}: {}", file.display(), e);
+    }
+    vault
+}
+
+/// `dir` の下の文書のリンクのグラフを、`start`（無ければ最もリンクの多い文書）を中心にして開く。
+/// 中心にする文書が索引に無ければNone
+fn link_graph(dir: &Path, start: Option<&Path>, config: &Config) -> Option<GraphBrowser> {
+    let dir = dunce::canonicalize(dir).ok()?;
+    let root = workspace::find_root(&dir).unwrap_or_else(|| dir.clone());
+    // 索引のdaemonが動いていなければ、リポジトリ（無ければ `dir`）の文書を読んで作る
+    let graph = match daemon::query(&dir, "links") {
+        Some(lines) => daemon::parse_links(&lines),
+        None => Graph::from_vault(&load_vault(root.clone(), config)),
+    };
+    let start = match start {
+        Some(path) => dunce::canonicalize(path).ok()?,
+        None => graph.hub(&dir)?.clone(),
+    };
+    GraphBrowser::new(root, graph, &start)
+}
+
+/// 複数の文書を水平線で区切って1つのプレビューにする
+fn open_concatenated(paths: &[PathBuf], config: &Config) -> Result<PreviewState, ErrorReport> {
+    error_report::catch("選んだファイルを開けません", || {
+        let documents = paths.iter().map(|path| load_markdown(path, config)).collect::<io::Result<Vec<_>>>()?;
+        let markdown = bulk::concatenate(&documents);
+        let mut state = PreviewState::from_markdown(&markdown, format!("{} files", paths.len()), config, &|_| None);
+        state.source = Some(markdown);
+        state.debug_footer = config.debug_footer;
+        Ok(state)
+    })
+}
+
+/// テンプレートから新しいファイルを作る。テンプレートを指定しなければ空のファイルを作る
+fn new_file(path: &Path, template: Option<&str>) -> io::Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} は既に存在します", path.display())));
+    }
+    let content = match template {
+        Some(name) => {
+            // ファイル名の区切り文字を空白にしてタイトルとする（`use-postgres.md` なら `use postgres`）
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+                .unwrap_or_default();
+            templates::render(&templates::load(name)?, &[("date", &templates::today()), ("title", &title)])
+        }
+        None => String::new(),
+    };
+    fs::write(path, content)
+}
+
+/// ファイルを読み込み、org-modeの変換・インクルードの展開・フィルターを適用したMarkdownを返す
+pub fn load_markdown(file_path: &Path, config: &Config) -> io::Result<String> {
+    let (source, _) = read_source(file_path, None, config, None)?;
+    Ok(prepare_markdown(source, file_path, config)?.0)
+}
+
+/// ファイルの中身をそのまま読み込む。アーカイブの中のファイルや圧縮されたファイルは展開する。
+/// `[preview]` のコマンドがある拡張子なら、コマンドの出力を読み込む。
+/// `limit` より大きければ先頭の行だけを返し、あわせてファイル全体の大きさを返す。
+/// コマンドが出力した画像は `images` の一時ディレクトリに置き、`images` が None ならdata URIにして埋め込む
+fn read_source(
+    file_path: &Path,
+    limit: Option<usize>,
+    config: &Config,
+    images: Option<&mut Option<tempfile::TempDir>>,
+) -> io::Result<(String, Option<usize>)> {
+    let source = if let Some(command) = config.preview_command(file_path)
+        && archive::split(file_path).is_none()
+    {
+        convert_for_preview(command, file_path, images)?
+    } else if let Some((archive, inner)) = archive::split(file_path) {
+        archive::read_to_string(&archive, &inner)?
+    } else if let Some(codec) = compressed::codec(file_path) {
+        compressed::read_to_string(file_path, codec)?
+    } else {
+        let size = fs::metadata(file_path)?.len() as usize;
+        match limit {
+            // 巨大なファイルで固まらないよう、全体を読まずに先頭だけを読む
+            Some(limit) if size > limit => {
+                let mut head = Vec::with_capacity(limit);
+                fs::File::open(file_path)?.take(limit as u64).read_to_end(&mut head)?;
+                return Ok((truncate_at_line(head, file_path)?, Some(size)));
+            }
+            _ => fs::read_to_string(file_path)?,
+        }
+    };
+    // 展開したものは読み込み済みなので、描画する量だけを減らす
+    match limit {
+        Some(limit) if source.len() > limit => {
+            let size = source.len();
+            let mut head = source.into_bytes();
+            head.truncate(limit);
+            Ok((truncate_at_line(head, file_path)?, Some(size)))
+        }
+        _ => Ok((source, None)),
+    }
+}
+
+/// `[preview]` のコマンドでファイルをMarkdownにする。画像が出力されたら、他のユーザーから読めない一時ディレクトリに
+/// 新しく作ったファイルへ書き出して表示する（`images` が None ならdata URIにして埋め込む）
+fn convert_for_preview(command: &str, file_path: &Path, images: Option<&mut Option<tempfile::TempDir>>) -> io::Result<String> {
+    match filter::convert(command, file_path)? {
+        filter::Converted::Markdown(markdown) => Ok(markdown),
+        filter::Converted::Image(extension, bytes) => {
+            let stem = file_path.file_stem().map_or_else(|| "preview".into(), |stem| stem.to_string_lossy());
+            let name = file_path.file_name().map_or_else(|| stem.clone(), |name| name.to_string_lossy());
+            let Some(images) = images else {
+                let uri = export::image_data_uri(extension, &bytes).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("{} の画像は埋め込めません", extension))
+                })?;
+                return Ok(format!("![{}](<{}>)\n", name, uri));
+            };
+            let dir = tempfile::Builder::new().prefix("peek-").tempdir()?;
+            let image = dir.path().join(format!("{}.{}", stem, extension));
+            // 既にあるファイルやシンボリックリンクには書き込まない
+            fs::OpenOptions::new().write(true).create_new(true).open(&image)?.write_all(&bytes)?;
+            let markdown = format!("![{}](<{}>)\n", name, image.display());
+            *images = Some(dir);
+            Ok(markdown)
+        }
+    }
+}
+
+/// 読み込んだ先頭部分を最後の改行までに切り詰める（文字の途中で切れないようにする）
+fn truncate_at_line(mut head: Vec<u8>, file_path: &Path) -> io::Result<String> {
+    if let Some(end) = head.iter().rposition(|&b| b == b'\n') {
+        head.truncate(end + 1);
+    }
+    match String::from_utf8(head) {
+        Ok(text) => Ok(text),
+        // 改行が無く文字の途中で切れている場合は、最後の文字の切れ目までにする
+        Err(e) if e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            Ok(String::from_utf8_lossy(&e.into_bytes()[..valid]).into_owned())
+        }
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} はUTF-8のテキストではありません", file_path.display()))),
+    }
+}
+
+/// 読み込んだ中身にorg-modeの変換・インクルードの展開・フィルターを適用する。
+/// あわせて、変換や展開で行がずれたときの、できたMarkdownの行ごとの出どころを返す（ずれなければ空）。
+/// フィルターは行を増やしたり減らしたりしないものとして扱う
+fn prepare_markdown(mut markdown: String, file_path: &Path, config: &Config) -> io::Result<(String, Vec<include::SourceLine>)> {
+    let started = Instant::now();
+    let mut line_origins = Vec::new();
+    if org::is_org(&compressed::strip_extension(file_path)) {
+        let (converted, lines) = org::to_markdown(&markdown);
+        markdown = converted;
+        line_origins = lines.into_iter().map(include::SourceLine::new).collect();
+    } else if config.includes {
+        let (expanded, lines) = include::expand(&markdown, file_path);
+        // 指令が無ければ行はずれない
+        if lines.iter().any(|line| line.included.is_some()) || expanded != markdown {
+            line_origins = lines;
+        }
+        markdown = expanded;
+    }
+    let mut markdown = filter::apply(markdown, &config.filters, Some(file_path))?;
+    if let Some(script) = config.script()? {
+        markdown = script.on_open(file_path, markdown)?;
+    }
+    tracing::debug!(bytes = markdown.len(), elapsed = ?started.elapsed(), "prepared markdown");
+    Ok((markdown, line_origins))
+}
+
+/// TUIを一時的に抜けて `$VISUAL` / `$EDITOR` でファイルを編集する。
+/// 行番号はvi・Emacs・nanoなど多くのエディターが受け付ける `+行番号` の形で渡す
+fn edit_in_editor<B: Backend>(terminal: &mut Terminal<B>, input: &InputReader, path: &Path, line: Option<usize>) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() });
+    // `code --wait` のような引数付きの指定や、空白を含むパスを引用符で囲んだ指定も受け付ける
+    let words = shell_words::split(&editor).unwrap_or_default();
+    let Some((program, words)) = words.split_first() else {
+        return Err(io::Error::other(format!("エディターの指定を解釈できません: {}", editor)));
+    };
+
+    // エディターへのキー入力を横取りしないよう、入力の読み込みを止めておく
+    let _paused = input.pause();
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    let status = std::process::Command::new(program).args(words).args(line.map(|line| format!("+{}", line))).arg(path).status();
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    terminal.clear()?;
+
+    let status = status?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{} が失敗しました ({})", program, status)));
+    }
+    Ok(())
+}
+
+/// 端末を元に戻して SIGTSTP で一時停止し、`fg` で再開したら画面を作り直す
+#[cfg(unix)]
+fn suspend<B: Backend>(terminal: &mut Terminal<B>, input: &InputReader) -> io::Result<()> {
+    // 停止中にシェルへのキー入力を横取りしないよう、入力の読み込みを止めておく
+    let _paused = input.pause();
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    tracing::info!("suspended");
+    // SAFETY: 自分自身にシグナルを送るだけで、メモリには触れない
+    unsafe {
+        libc::raise(libc::SIGTSTP);
+    }
+    tracing::info!("resumed");
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    terminal.clear()
+}
+
+/// Windowsにはジョブ制御が無いので何もしない
+#[cfg(not(unix))]
+fn suspend<B: Backend>(_terminal: &mut Terminal<B>, _input: &InputReader) -> io::Result<()> {
+    Ok(())
+}
+
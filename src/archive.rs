@@ -0,0 +1,132 @@
+// --- アーカイブ（zip・tar）の中身の閲覧 ---
+//
+// 展開せずにアーカイブの中をディレクトリのようにたどれるようにする。
+// `docs.zip/guide/intro.md` のように、アーカイブのパスに中のパスをつないだ仮想的なパスで表す。
+// 一覧と読み込みは `unzip` / `tar` コマンドに任せる（`unzip` が無ければ、zipも読めるbsdtarの `tar` を使う）。
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::listing::Entry;
+
+/// アーカイブの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else if [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz"].iter().any(|ext| name.ends_with(ext)) {
+        Some(Kind::Tar)
+    } else {
+        None
+    }
+}
+
+/// 中をたどれるアーカイブのファイルかどうか
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some() && path.is_file()
+}
+
+/// 仮想的なパスをアーカイブのパスと中のパス（`/` 区切り、ルートなら空）に分ける。
+/// アーカイブの中を指していなければNone
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let archive = path.ancestors().find(|ancestor| is_archive(ancestor))?;
+    let inner = path.strip_prefix(archive).ok()?;
+    let inner: Vec<String> = inner
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    Some((archive.to_path_buf(), inner.join("/")))
+}
+
+/// アーカイブの中の `inner` ディレクトリ直下のエントリ。
+/// ディレクトリ自体のエントリが無いアーカイブでも、ファイルのパスから中間のディレクトリを補う
+pub fn entries(archive: &Path, inner: &str) -> io::Result<Vec<Entry>> {
+    let prefix = if inner.is_empty() { String::new() } else { format!("{}/", inner) };
+    let mut entries: Vec<Entry> = Vec::new();
+    for member in list(archive)? {
+        let name = normalize(&member);
+        let Some(rest) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let (child, is_dir) = match rest.split_once('/') {
+            Some((child, _)) => (child, true),
+            None => (rest, false),
+        };
+        if child.is_empty() {
+            continue;
+        }
+        let path = archive.join(&prefix).join(child);
+        match entries.iter_mut().find(|entry| entry.path == path) {
+            Some(entry) => entry.is_dir |= is_dir,
+            None => entries.push(Entry { path, is_dir }),
+        }
+    }
+    Ok(entries)
+}
+
+/// アーカイブの中のファイルをテキストとして読み込む
+pub fn read_to_string(archive: &Path, inner: &str) -> io::Result<String> {
+    let member = list(archive)?
+        .into_iter()
+        .find(|member| normalize(member) == inner)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("アーカイブに {} がありません", inner)))?;
+    // 名前がオプションとして読まれないよう、tarには `--` の後に渡す
+    let output = match kind(archive) {
+        Some(Kind::Zip) => {
+            let pattern = escape_pattern(&member);
+            run("unzip", &["-p".as_ref(), archive.as_os_str(), pattern.as_ref()])
+                .or_else(|_| run("tar", &["-xOf".as_ref(), archive.as_os_str(), "--".as_ref(), pattern.as_ref()]))?
+        }
+        _ => run("tar", &["-xOf".as_ref(), archive.as_os_str(), "--".as_ref(), member.as_ref()])?,
+    };
+    String::from_utf8(output).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} はUTF-8のテキストではありません", inner)))
+}
+
+/// アーカイブに含まれるパスの一覧（ディレクトリは `/` で終わる）
+fn list(archive: &Path) -> io::Result<Vec<String>> {
+    let output = match kind(archive) {
+        Some(Kind::Zip) => run("unzip", &["-Z1".as_ref(), archive.as_os_str()])
+            .or_else(|_| run("tar", &["-tf".as_ref(), archive.as_os_str()]))?,
+        _ => run("tar", &["-tf".as_ref(), archive.as_os_str()])?,
+    };
+    Ok(String::from_utf8_lossy(&output).lines().filter(|line| !line.is_empty()).map(str::to_string).collect())
+}
+
+/// `unzip` とbsdtarは中のパスをワイルドカードとして扱うので、そのままの名前に一致するよう特殊文字を `\` でエスケープする。
+/// `unzip` はファイル名の後の `-x` や `-d` もオプションとして読むので、先頭の `-` もエスケープする
+pub fn escape_pattern(member: &str) -> String {
+    let mut escaped = String::with_capacity(member.len());
+    for (index, c) in member.chars().enumerate() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') || (index == 0 && c == '-') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `./docs/` のような表記を `docs` に揃える
+fn normalize(member: &str) -> String {
+    member.trim_start_matches("./").trim_end_matches('/').to_string()
+}
+
+fn run(program: &str, args: &[&std::ffi::OsStr]) -> io::Result<Vec<u8>> {
+    let output = Command::new(program).args(args).stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("{} が失敗しました: {}", program, message)));
+    }
+    Ok(output.stdout)
+}
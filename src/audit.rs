@@ -0,0 +1,51 @@
+// --- 古くなった文書の検出 ---
+//
+// ディレクトリの下の文書のうち、gitで最後に変更されてから `stale_days` 日より経ったものを古い順に並べる。
+// 結果はリンク付きのMarkdownの表にして、プレビューで開けるようにする。
+
+use std::{io, path::Path};
+
+use crate::git::{self, LastChange};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// `dir` の下の文書のうち、`now` の時点で `days` 日より変更されていないものを古い順に返す。
+/// 今は無いファイルとgitが管理していないファイルは含めない
+pub fn stale_documents(dir: &Path, days: u64, now: u64, is_document: impl Fn(&Path) -> bool) -> io::Result<Vec<LastChange>> {
+    let changes = git::last_changes(dir)?;
+    let mut stale: Vec<LastChange> = changes
+        .into_iter()
+        .filter(|change| now.saturating_sub(change.time) > days * DAY)
+        .filter(|change| is_document(&change.path) && change.path.is_file())
+        .collect();
+    stale.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.path.cmp(&b.path)));
+    Ok(stale)
+}
+
+/// 古い文書の一覧のMarkdown。パスは `dir` からの相対パスで表示し、リンク先は絶対パスにする
+pub fn report(dir: &Path, days: u64, now: u64, stale: &[LastChange]) -> String {
+    let mut report = String::from("# Stale documents\n\n");
+    if stale.is_empty() {
+        report.push_str(&format!("No documents under `{}` are older than {} days.\n", dir.display(), days));
+        return report;
+    }
+    report.push_str(&format!(
+        "{} under `{}` {} not changed in the last {} days (oldest first).\n\n",
+        if stale.len() == 1 { "1 document".to_string() } else { format!("{} documents", stale.len()) },
+        dir.display(),
+        if stale.len() == 1 { "has" } else { "have" },
+        days
+    ));
+    report.push_str("| Document | Last change | Age |\n| --- | --- | --- |\n");
+    for change in stale {
+        let name = change.path.strip_prefix(dir).unwrap_or(&change.path).to_string_lossy().replace('|', "\\|");
+        report.push_str(&format!(
+            "| [{}](<{}>) | {} | {} |\n",
+            name,
+            change.path.display(),
+            change.date,
+            git::age(now.saturating_sub(change.time))
+        ));
+    }
+    report
+}
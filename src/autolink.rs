@@ -0,0 +1,275 @@
+// --- 課題番号などの自動リンク ---
+//
+// 設定の `[autolink]` に書いたパターンに一致する本文中の文字列（`#123` や `JIRA-42` など）を、
+// URLのテンプレートから作ったリンクにする。外部クレートに頼らず、正規表現のうち
+// 文字・`.`・`\d` `\w` `\s`・`[A-Z0-9]` のような文字クラス・`( )` のグループと
+// `+` `*` `?` の繰り返しだけを扱う。URLの `$0` は一致した全体、`$1`〜`$9` はグループに置き換える。
+
+/// パターンとURLのテンプレートの組
+#[derive(Debug, Clone)]
+pub struct Autolink {
+    nodes: Vec<Node>,
+    groups: usize,
+    url: String,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    atom: Atom,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Atom {
+    Char(char),
+    Any,
+    Digit,
+    Word,
+    Space,
+    /// 文字の範囲の一覧と、否定（`[^...]`）かどうか
+    Class(Vec<(char, char)>, bool),
+    /// グループの番号（1から）と中身
+    Group(usize, Vec<Node>),
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Char(expected) => c == *expected,
+            Atom::Any => true,
+            Atom::Digit => c.is_ascii_digit(),
+            Atom::Word => c.is_alphanumeric() || c == '_',
+            Atom::Space => c.is_whitespace(),
+            Atom::Class(ranges, negated) => ranges.iter().any(|(low, high)| (*low..=*high).contains(&c)) != *negated,
+            Atom::Group(..) => false,
+        }
+    }
+}
+
+/// グループごとの一致した範囲（文字の位置）
+type Captures = Vec<Option<(usize, usize)>>;
+
+impl Autolink {
+    /// パターンを解釈する。扱えない書き方ならその理由を返す
+    pub fn new(pattern: &str, url: &str) -> Result<Self, String> {
+        let mut chars = pattern.chars().peekable();
+        let mut groups = 0;
+        let nodes = parse_sequence(&mut chars, &mut groups, false)?;
+        if nodes.is_empty() {
+            return Err("パターンが空です".to_string());
+        }
+        Ok(Self { nodes, groups, url: url.to_string() })
+    }
+
+    /// `text` の `start` 文字目から一致すれば、一致の終わりの位置とグループの範囲を返す
+    fn match_at(&self, text: &[char], start: usize) -> Option<(usize, Captures)> {
+        let mut captures = vec![None; self.groups + 1];
+        let mut end = None;
+        match_sequence(&self.nodes, text, start, &mut captures, &mut |position, _| {
+            end = Some(position);
+            true
+        });
+        // 一致した時点のグループを取り直す（継続の中では借用できないので、もう一度たどる）
+        let end = end?;
+        let mut result = None;
+        match_sequence(&self.nodes, text, start, &mut captures, &mut |position, captures| {
+            if position == end {
+                result = Some(captures.clone());
+            }
+            position == end
+        });
+        let mut captures = result?;
+        captures[0] = Some((start, end));
+        Some((end, captures))
+    }
+
+    /// URLのテンプレートの `$0`〜`$9` を一致した文字列に置き換える
+    fn expand(&self, text: &[char], captures: &Captures) -> String {
+        let mut url = String::with_capacity(self.url.len());
+        let mut template = self.url.chars().peekable();
+        while let Some(c) = template.next() {
+            let group = template.peek().and_then(|next| next.to_digit(10)).filter(|_| c == '$');
+            match group {
+                Some(group) => {
+                    template.next();
+                    if let Some(Some((from, to))) = captures.get(group as usize) {
+                        url.extend(&text[*from..*to]);
+                    }
+                }
+                None => url.push(c),
+            }
+        }
+        url
+    }
+}
+
+/// テキストを、リンクにしない部分と自動リンクにする部分（とそのURL）に分ける。
+/// 語の途中（前後が英数字）での一致はリンクにしない
+pub fn split(text: &str, rules: &[Autolink]) -> Vec<(String, Option<String>)> {
+    if rules.is_empty() {
+        return vec![(text.to_string(), None)];
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut segments = Vec::new();
+    let mut plain_start = 0;
+    let mut position = 0;
+    while position < chars.len() {
+        let at_boundary = position == 0 || !chars[position - 1].is_alphanumeric();
+        let found = at_boundary
+            .then(|| {
+                rules.iter().find_map(|rule| {
+                    let (end, captures) = rule.match_at(&chars, position)?;
+                    let ends_word = end == chars.len() || !chars[end].is_alphanumeric();
+                    (end > position && ends_word).then(|| (end, rule.expand(&chars, &captures)))
+                })
+            })
+            .flatten();
+        match found {
+            Some((end, url)) => {
+                if plain_start < position {
+                    segments.push((chars[plain_start..position].iter().collect(), None));
+                }
+                segments.push((chars[position..end].iter().collect(), Some(url)));
+                position = end;
+                plain_start = end;
+            }
+            None => position += 1,
+        }
+    }
+    if plain_start < chars.len() {
+        segments.push((chars[plain_start..].iter().collect(), None));
+    }
+    segments
+}
+
+fn parse_sequence(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    groups: &mut usize,
+    in_group: bool,
+) -> Result<Vec<Node>, String> {
+    let mut nodes: Vec<Node> = Vec::new();
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            ')' if in_group => return Ok(nodes),
+            ')' => return Err("対応する `(` がありません".to_string()),
+            '(' => {
+                *groups += 1;
+                let index = *groups;
+                let inner = parse_sequence(chars, groups, true)?;
+                Atom::Group(index, inner)
+            }
+            '[' => parse_class(chars)?,
+            '.' => Atom::Any,
+            '\\' => match chars.next() {
+                Some('d') => Atom::Digit,
+                Some('w') => Atom::Word,
+                Some('s') => Atom::Space,
+                Some(escaped) => Atom::Char(escaped),
+                None => return Err("`\\` で終わっています".to_string()),
+            },
+            '+' | '*' | '?' | '|' | '{' => return Err(format!("`{}` はここでは使えません", c)),
+            c => Atom::Char(c),
+        };
+        let (min, max) = match chars.peek() {
+            Some('+') => (1, usize::MAX),
+            Some('*') => (0, usize::MAX),
+            Some('?') => (0, 1),
+            _ => (1, 1),
+        };
+        if (min, max) != (1, 1) {
+            chars.next();
+        }
+        nodes.push(Node { atom, min, max });
+    }
+    if in_group {
+        return Err("`(` が閉じていません".to_string());
+    }
+    Ok(nodes)
+}
+
+/// `[` の後の文字クラスを `]` まで読む
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Atom, String> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges = Vec::new();
+    loop {
+        let low = match chars.next() {
+            Some(']') if !ranges.is_empty() => return Ok(Atom::Class(ranges, negated)),
+            Some('\\') => chars.next().ok_or("`\\` で終わっています")?,
+            Some(c) => c,
+            None => return Err("`[` が閉じていません".to_string()),
+        };
+        let high = if chars.next_if_eq(&'-').is_some() {
+            match chars.next() {
+                // `[a-]` の `-` は文字として扱う
+                Some(']') => {
+                    ranges.push((low, low));
+                    ranges.push(('-', '-'));
+                    return Ok(Atom::Class(ranges, negated));
+                }
+                Some(high) => high,
+                None => return Err("`[` が閉じていません".to_string()),
+            }
+        } else {
+            low
+        };
+        ranges.push((low, high));
+    }
+}
+
+/// `nodes` を `position` から順に一致させ、一致するたびに続き `next` を呼ぶ。`next` がtrueを返せば終える
+fn match_sequence(
+    nodes: &[Node],
+    text: &[char],
+    position: usize,
+    captures: &mut Captures,
+    next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    match nodes.split_first() {
+        None => next(position, captures),
+        Some((node, rest)) => match_repeat(node, 0, text, position, captures, &mut |position, captures| {
+            match_sequence(rest, text, position, captures, next)
+        }),
+    }
+}
+
+/// `node` を繰り返し一致させる。できるだけ多く繰り返す方から試す
+fn match_repeat(
+    node: &Node,
+    count: usize,
+    text: &[char],
+    position: usize,
+    captures: &mut Captures,
+    next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    if count < node.max
+        && match_atom(&node.atom, text, position, captures, &mut |end, captures| {
+            // 空の一致を繰り返して止まらなくならないようにする
+            end > position && match_repeat(node, count + 1, text, end, captures, next)
+        })
+    {
+        return true;
+    }
+    count >= node.min && next(position, captures)
+}
+
+fn match_atom(
+    atom: &Atom,
+    text: &[char],
+    position: usize,
+    captures: &mut Captures,
+    next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    match atom {
+        Atom::Group(index, nodes) => match_sequence(nodes, text, position, captures, &mut |end, captures| {
+            let previous = captures[*index];
+            captures[*index] = Some((position, end));
+            if next(end, captures) {
+                return true;
+            }
+            captures[*index] = previous;
+            false
+        }),
+        atom => text.get(position).is_some_and(|&c| atom.matches(c)) && next(position + 1, captures),
+    }
+}
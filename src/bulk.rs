@@ -0,0 +1,103 @@
+// --- 複数のファイルへの一括操作 ---
+//
+// エクスプローラーで `Space` で選んだファイルをまとめてプレビュー・書き出し・削除する。
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    export::{self, HtmlAssets},
+    render::{render_markdown_document, RenderOptions},
+    theme::ColorScheme,
+};
+
+/// 連結した文書の区切り
+const SEPARATOR: &str = "\n\n---\n\n";
+
+/// 複数の文書を水平線で区切って1つの文書にする
+pub fn concatenate(documents: &[String]) -> String {
+    documents.join(SEPARATOR)
+}
+
+/// 一括書き出しの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Html,
+    Text,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(Self::Html),
+            "txt" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Text => "txt",
+        }
+    }
+}
+
+/// 文書（元のパスとMarkdown）をそれぞれ `dir` に書き出し、書き出したファイルのパスを返す。
+/// ファイル名は元のファイル名の拡張子を形式に合わせて付け替えたもの。`a.md` と `a.org` のように同じ名前になるものは、
+/// 互いに上書きしないよう2つ目から `a-2.html` `a-3.html` のように番号を付ける
+pub fn export_all(
+    documents: &[(PathBuf, String)],
+    dir: &Path,
+    format: ExportFormat,
+    assets: &HtmlAssets,
+    theme: &ColorScheme,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::with_capacity(documents.len());
+    let mut used = HashSet::new();
+    for (source, markdown) in documents {
+        let stem = source.file_stem().unwrap_or(source.as_os_str());
+        let name = (1..)
+            .map(|count| {
+                let mut name = stem.to_os_string();
+                if count > 1 {
+                    name.push(format!("-{}", count));
+                }
+                name.push(".");
+                name.push(format.extension());
+                name
+            })
+            .find(|name| !used.contains(&name.to_ascii_lowercase()))
+            .unwrap_or_default();
+        // 大文字と小文字を区別しないファイルシステムでも重ならないよう、小文字にして比べる
+        used.insert(name.to_ascii_lowercase());
+        let target = dir.join(name);
+        let title = stem.to_string_lossy();
+        let output = match format {
+            ExportFormat::Html => export::html_document(markdown, &title, Some(source), false, assets),
+            ExportFormat::Text => {
+                let (rendered, _) = render_markdown_document(markdown, theme, &|_| None, &RenderOptions::default());
+                export::plain_text(&rendered.text, export::DEFAULT_TEXT_WIDTH)
+            }
+        };
+        fs::write(&target, output)
+            .map_err(|e| io::Error::new(e.kind(), format!("{} に書き出せません: {}", target.display(), e)))?;
+        written.push(target);
+    }
+    Ok(written)
+}
+
+/// ファイルと空のディレクトリを削除する。失敗したものはパスとエラーを返し、残りは続けて削除する
+pub fn delete(paths: &[PathBuf]) -> Vec<(PathBuf, io::Error)> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let result = if path.is_dir() { fs::remove_dir(path) } else { fs::remove_file(path) };
+            result.err().map(|e| (path.clone(), e))
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+// --- 変更履歴（Keep a Changelog形式）の補助 ---
+//
+// `# Changelog` という題の文書か、`## [1.2.0] - 2024-05-01` のような括弧付きのバージョンの見出しがある文書を
+// 変更履歴とみなし、`## [1.2.0]` や `## 1.2.0`、`## [Unreleased]` のようなH2の見出しをリリースの見出しとして扱う。
+
+/// リリースの見出し
+#[derive(Debug, Clone)]
+pub struct Release {
+    /// `1.2.0` や `Unreleased` などのバージョン名
+    pub version: String,
+    /// 見出し全体のテキスト
+    pub heading: String,
+    /// 見出しが描画された行番号
+    pub line: usize,
+}
+
+/// 見出しのテキストがリリースを表していればバージョン名を返す。
+/// バージョンは `1.2` `1.2.3` `v1.2.3-rc.1` のように、少なくともメジャーとマイナーを点で区切ったもの
+pub fn version_of(heading: &str) -> Option<String> {
+    let first = heading.split_whitespace().next()?;
+    let version = first.trim_matches(|c| c == '[' || c == ']');
+    let is_release = is_semver(version.strip_prefix(['v', 'V']).unwrap_or(version)) || version.eq_ignore_ascii_case("unreleased");
+    is_release.then(|| version.to_string())
+}
+
+/// `1.2.3` のような数字を点で区切った並び（2つか3つ）に、`-rc.1` や `+build` が続いたもの
+fn is_semver(version: &str) -> bool {
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    (2..=3).contains(&parts.len()) && parts.iter().all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// 見出し（描画された行番号・レベル・テキスト）から、変更履歴の文書かどうかを判定する
+pub fn is_changelog<'a>(headings: impl IntoIterator<Item = (usize, &'a str)>) -> bool {
+    headings.into_iter().any(|(level, heading)| {
+        let heading = heading.trim().to_ascii_lowercase();
+        let titled = level == 1 && (heading.starts_with("changelog") || heading.starts_with("change log"));
+        let bracketed = level == 2 && heading.starts_with('[') && version_of(&heading).is_some();
+        titled || bracketed
+    })
+}
+
+/// 変更履歴の文書なら、見出し（行番号・レベル・テキスト）からリリースの見出しだけを取り出す。
+/// 変更履歴でなければ空
+pub fn releases(headings: &[(usize, usize, String)]) -> Vec<Release> {
+    if !is_changelog(headings.iter().map(|(_, level, heading)| (*level, heading.as_str()))) {
+        return Vec::new();
+    }
+    headings
+        .iter()
+        .filter(|(_, level, _)| *level == 2)
+        .filter_map(|(line, _, heading)| {
+            let version = version_of(heading)?;
+            Some(Release { version, heading: heading.clone(), line: *line })
+        })
+        .collect()
+}
+
+/// 指定したバージョンのリリースを探す。先頭の `v` の有無は区別しない
+pub fn find<'a>(releases: &'a [Release], query: &str) -> Option<&'a Release> {
+    let normalize = |version: &str| {
+        let version = version.trim();
+        version.strip_prefix(['v', 'V']).unwrap_or(version).to_ascii_lowercase()
+    };
+    let query = normalize(query);
+    releases.iter().find(|release| normalize(&release.version) == query)
+}
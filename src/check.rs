@@ -0,0 +1,238 @@
+// --- 文書の検査（`check-links` / `check-assets` / `lint`） ---
+//
+// TUIを使わずに、リンク切れや書き方の問題を `ファイル:行: 内容` の形で報告する。
+// リンクはローカルのファイルと見出しのアンカーだけを確かめ、Web上のURLには問い合わせない。
+// `check-assets` と `:assets` は、画像と文書以外のファイル（PDFや図など）の参照だけを確かめる。
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+
+use crate::{
+    links::{self, LinkTarget},
+    search::escape,
+    structure::{self, Link, Structure},
+};
+
+/// 見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub path: PathBuf,
+    /// 元のMarkdownの行番号（1始まり）
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// 検査した結果。`check-links`・`check-assets`・`lint` の終了コードとまとめの表示に使う
+#[derive(Debug, Default)]
+pub struct Report {
+    /// 調べた文書の数（読めなかった文書を含む）
+    pub documents: usize,
+    /// 読めなかった文書
+    pub unreadable: Vec<PathBuf>,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// 問題が無ければ0、問題があれば1、読めない文書があれば（問題の有無によらず）2
+    pub fn exit_code(&self) -> i32 {
+        if !self.unreadable.is_empty() {
+            2
+        } else if !self.problems.is_empty() {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// `12個の文書のうち2個に3件の問題があります` のような1行のまとめ
+    pub fn summary(&self) -> String {
+        let mut with_problems: Vec<&Path> = self.problems.iter().map(|problem| problem.path.as_path()).collect();
+        with_problems.dedup();
+        let mut summary = if self.problems.is_empty() {
+            format!("{}個の文書に問題はありません", self.documents - self.unreadable.len())
+        } else {
+            format!("{}個の文書のうち{}個に{}件の問題があります", self.documents, with_problems.len(), self.problems.len())
+        };
+        if !self.unreadable.is_empty() {
+            summary.push_str(&format!("（{}個の文書は読めませんでした）", self.unreadable.len()));
+        }
+        summary
+    }
+}
+
+/// 指定したファイルと、ディレクトリの下の文書（`.gitignore` に一致するものを除く）を名前順に返す
+pub fn documents(paths: &[PathBuf], is_document: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut documents = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = WalkBuilder::new(path)
+                .build()
+                .flatten()
+                .map(|entry| entry.into_path())
+                .filter(|path| path.is_file() && is_document(path))
+                .collect();
+            found.sort();
+            documents.extend(found);
+        } else {
+            documents.push(path.clone());
+        }
+    }
+    documents
+}
+
+/// 文書の中のローカルのリンクと画像の行き先を確かめる。`load` は文書を読み込む関数（フィルターなどを適用する）
+pub struct LinkChecker<F> {
+    load: F,
+    /// 読み込んだ文書の見出しのアンカー。読めなかった文書はNone
+    anchors: HashMap<PathBuf, Option<Vec<String>>>,
+}
+
+impl<F: FnMut(&Path) -> Option<String>> LinkChecker<F> {
+    pub fn new(load: F) -> Self {
+        Self { load, anchors: HashMap::new() }
+    }
+
+    /// `path` の文書のリンク切れ
+    pub fn check(&mut self, path: &Path, structure: &Structure) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let links = structure.links.iter().map(|link| (link, false));
+        for (link, is_image) in links.chain(structure.images.iter().map(|image| (image, true))) {
+            let problem = match links::resolve(&link.url, Some(path)) {
+                LinkTarget::External(_) => None,
+                LinkTarget::Anchor(anchor) => missing_anchor(&anchor, structure),
+                LinkTarget::File(target, _) if !target.exists() => Some(missing_file(link, is_image)),
+                LinkTarget::File(target, Some(anchor)) if target.is_file() => self.missing_anchor_in(&target, &anchor, &link.url),
+                LinkTarget::File(..) => None,
+            };
+            if let Some(message) = problem {
+                problems.push(Problem { path: path.to_path_buf(), line: link.line, message });
+            }
+        }
+        problems
+    }
+
+    /// 別の文書の中のアンカーが見つからなければその旨。Markdownとして読めない文書は確かめない
+    fn missing_anchor_in(&mut self, target: &Path, anchor: &str, url: &str) -> Option<String> {
+        if links::line_anchor(anchor).is_some() {
+            return None;
+        }
+        let load = &mut self.load;
+        let anchors = self.anchors.entry(target.to_path_buf()).or_insert_with(|| {
+            let markdown = load(target)?;
+            Some(structure::analyze(&markdown).headings.into_iter().map(|heading| heading.anchor).collect())
+        });
+        let found = anchors.as_ref().is_none_or(|anchors| anchors.iter().any(|slug| *slug == anchor.to_lowercase()));
+        (!found).then(|| format!("missing anchor in {}", url))
+    }
+}
+
+/// 見つからないファイルへのリンクや画像の問題の内容
+fn missing_file(link: &Link, is_image: bool) -> String {
+    if is_image {
+        format!("missing image {}", link.url)
+    } else {
+        format!("broken link to {}", link.url)
+    }
+}
+
+/// 画像と、文書以外のファイル（PDFや図など）へのリンクのうち、行き先の見つからないもの
+pub fn missing_assets(path: &Path, structure: &Structure, is_document: impl Fn(&Path) -> bool) -> Vec<Problem> {
+    let links = structure.links.iter().map(|link| (link, false));
+    let mut problems: Vec<Problem> = links
+        .chain(structure.images.iter().map(|image| (image, true)))
+        .filter(|(link, is_image)| match links::resolve(&link.url, Some(path)) {
+            LinkTarget::File(target, _) => !target.exists() && (*is_image || !is_document(&target)),
+            _ => false,
+        })
+        .map(|(link, is_image)| Problem { path: path.to_path_buf(), line: link.line, message: missing_file(link, is_image) })
+        .collect();
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
+
+/// `:assets` の結果を、文書ごとに見つからない画像やファイルを並べたMarkdownにする。行番号はその行へのリンクになる
+pub fn assets_report(dir: &Path, documents: usize, problems: &[Problem]) -> String {
+    let mut report = String::from("# Missing assets\n\n");
+    if problems.is_empty() {
+        report.push_str(&format!("All images and files referenced from the {} documents under `{}` exist.\n", documents, dir.display()));
+        return report;
+    }
+    let mut by_document: Vec<(&Path, Vec<&Problem>)> = Vec::new();
+    for problem in problems {
+        match by_document.last_mut() {
+            Some((path, problems)) if *path == problem.path => problems.push(problem),
+            _ => by_document.push((&problem.path, vec![problem])),
+        }
+    }
+    report.push_str(&format!(
+        "{} in {} of {} documents under `{}`.\n",
+        if problems.len() == 1 { "1 missing asset".to_string() } else { format!("{} missing assets", problems.len()) },
+        by_document.len(),
+        documents,
+        dir.display()
+    ));
+    for (path, problems) in by_document {
+        let name = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().to_string();
+        report.push_str(&format!("\n## [{}](<{}>)\n\n", escape(&name), path.display()));
+        for problem in problems {
+            report.push_str(&format!("- [line {}](<{}#L{}>): {}\n", problem.line, path.display(), problem.line, escape(&problem.message)));
+        }
+    }
+    report
+}
+
+/// 同じ文書の中のアンカーが見つからなければその旨
+fn missing_anchor(anchor: &str, structure: &Structure) -> Option<String> {
+    let found = links::line_anchor(anchor).is_some() || structure.headings.iter().any(|heading| heading.anchor == anchor.to_lowercase());
+    (!found).then(|| format!("missing anchor #{}", anchor))
+}
+
+/// 書き方の問題（見出しのレベルの飛び・複数のH1・重複した見出し・代替テキストの無い画像・空のリンク）
+pub fn lint(path: &Path, structure: &Structure) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |line: usize, message: String| problems.push(Problem { path: path.to_path_buf(), line, message });
+    let mut previous_level = 0;
+    let mut seen_titles: HashMap<&str, usize> = HashMap::new();
+    let mut first_h1 = None;
+    for heading in &structure.headings {
+        if previous_level > 0 && heading.level > previous_level + 1 {
+            report(heading.line, format!("heading level jumps from h{} to h{}", previous_level, heading.level));
+        }
+        previous_level = heading.level;
+        if heading.level == 1 {
+            match first_h1 {
+                Some(first) => report(heading.line, format!("more than one h1 (first on line {})", first)),
+                None => first_h1 = Some(heading.line),
+            }
+        }
+        match seen_titles.get(heading.text.trim()) {
+            Some(first) => report(heading.line, format!("duplicate heading \"{}\" (first on line {})", heading.text.trim(), first)),
+            None => {
+                seen_titles.insert(heading.text.trim(), heading.line);
+            }
+        }
+    }
+    for image in &structure.images {
+        if image.text.trim().is_empty() {
+            report(image.line, format!("image without alt text: {}", image.url));
+        }
+    }
+    for link in &structure.links {
+        if link.url.is_empty() {
+            report(link.line, format!("link without a destination: {}", link.text));
+        }
+    }
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
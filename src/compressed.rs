@@ -0,0 +1,71 @@
+// --- 圧縮されたファイルの読み込み ---
+//
+// `notes.md.gz` / `notes.md.zst` を展開したテキストとして読み込む。
+// 展開は `gzip` / `zstd` コマンドに任せ、一時ファイルは作らない。
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// 圧縮形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// フッターに表示する名前
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// 拡張子から圧縮形式を判定する。`.tar.gz` はアーカイブとして扱うので除く
+pub fn codec(path: &Path) -> Option<Codec> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".tar.gz") {
+        return None;
+    }
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "gz" => Some(Codec::Gzip),
+        "zst" => Some(Codec::Zstd),
+        _ => None,
+    }
+}
+
+/// 圧縮の拡張子を除いたパス（`notes.md.gz` → `notes.md`）。圧縮されていなければそのまま
+pub fn strip_extension(path: &Path) -> PathBuf {
+    match codec(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+/// ファイルを展開してテキストとして読み込む
+pub fn read_to_string(path: &Path, codec: Codec) -> io::Result<String> {
+    let output = Command::new(codec.program())
+        .arg("-dc")
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("{} を実行できません: {}", codec.program(), e)))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("{} が失敗しました: {}", codec.program(), message)));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} はUTF-8のテキストではありません", path.display())))
+}
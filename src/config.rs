@@ -0,0 +1,611 @@
+// --- 設定項目と設定ファイルの書式 ---
+//
+// 外部クレートに頼らず、TOMLのうち `key = value` と `[section]` だけを扱う簡易パーサーで読む。
+// どの設定をどの順に重ねるかは settings モジュールで決める。
+
+use std::{collections::BTreeMap, io, path::Path, sync::Arc};
+
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    autolink::Autolink,
+    render::{Decorations, RenderOptions},
+    script::{self, Script},
+    templates, theme,
+};
+
+/// 設定できるキーの一覧（コマンドライン引数の検証に使う）
+pub const KEYS: &[&str] = &[
+    "keymap",
+    "scroll_step",
+    "fast_scroll_step",
+    "smooth_scroll",
+    "snap_to_headings",
+    "low_bandwidth",
+    "screen_reader",
+    "ascii",
+    "number_headings",
+    "minimal_chrome",
+    "watch",
+    "hide_gitignored",
+    "icons",
+    "dual_pane",
+    "home_dir",
+    "theme",
+    "extensions",
+    "translation_patterns",
+    "max_file_size",
+    "filters",
+    "script",
+    "includes",
+    "image_viewer",
+    "trusted_projects",
+    "link_base",
+    "image_art",
+    "spell",
+    "spell_language",
+    "stale_days",
+    "search_index",
+    "journal_dir",
+    "journal_template",
+    "html_template",
+    "html_css",
+    "log_level",
+    "log_file",
+    "debug_footer",
+];
+
+/// 設定ファイルの値
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+/// プレビュー画面のキー操作プロファイル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeymapProfile {
+    /// 従来のVimライクな操作
+    #[default]
+    Default,
+    /// lessと同じ操作 (Space/b, d/u, g/G, /?, q)
+    Less,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keymap: KeymapProfile,
+    /// `j` / `k` で動く行数
+    pub scroll_step: u16,
+    /// `J` / `K` で動く行数
+    pub fast_scroll_step: u16,
+    /// ページ送りなどの大きな移動をアニメーションさせるかどうか
+    pub smooth_scroll: bool,
+    /// ページ送りを見出しの位置で止めるかどうか
+    pub snap_to_headings: bool,
+    /// 遅いSSH接続向けに、描き直す回数を減らし、アニメーションと画像の表示をやめるかどうか
+    pub low_bandwidth: bool,
+    /// スクリーンリーダー向けに、罫線や色を使わず見出しなどを言葉で示すかどうか
+    pub screen_reader: bool,
+    /// 罫線素片の代わりに `|` `+` `-` `>` などのASCII文字で枠や区切りを描くかどうか
+    pub ascii: bool,
+    /// 見出しの前に節番号（`1.` `1.2.` `1.2.3.`）を付けるかどうか
+    pub number_headings: bool,
+    /// プレビューでフッターとポップアップの枠線を隠すかどうか。プレビューの `m` で切り替えると保存される
+    pub minimal_chrome: bool,
+    /// プレビュー中のファイルが保存されたら読み込み直し、変わった部分を少しの間強調するかどうか
+    pub watch: bool,
+    /// エクスプローラーで .gitignore に一致するエントリを隠すかどうか
+    pub hide_gitignored: bool,
+    /// エクスプローラーでNerd Fontのアイコンを表示するかどうか
+    pub icons: bool,
+    /// エクスプローラーを左右2画面で表示するかどうか
+    pub dual_pane: bool,
+    /// エクスプローラーの `~` で移動するディレクトリ（`~` はホームディレクトリ）
+    pub home_dir: String,
+    /// 配色テーマの名前
+    pub theme: String,
+    /// Markdownとしてプレビューする拡張子
+    pub extensions: Vec<String>,
+    /// `:translation` で探す翻訳のファイル名（`*` が原文の拡張子を除いた名前）。書いた順に試す
+    pub translation_patterns: Vec<String>,
+    /// これより大きいファイル（KiB）は先頭のこの大きさだけを読み込む。0なら制限しない
+    pub max_file_size: usize,
+    /// レンダリング前にMarkdownを通す外部コマンド（標準入力から読み、標準出力へ書く）
+    pub filters: Vec<String>,
+    /// `on_open`・`on_render`・独自のコマンドのフックを書いたRhaiのスクリプト。空なら使わない。
+    /// ユーザー設定では設定ディレクトリから、プロジェクト設定ではリポジトリのルートからの相対パスで書ける
+    pub script: String,
+    /// `<!-- include: other.md -->` などのインクルード指令を展開するかどうか
+    pub includes: bool,
+    /// 画像を開くコマンド（`feh` など）。空ならOSの既定のアプリで開く
+    pub image_viewer: String,
+    /// プロジェクト設定のコマンドやパスの設定（`needs_trust` のキー）を使ってよいリポジトリのルート（`~` はホームディレクトリ）。
+    /// プロジェクト設定には書けない
+    pub trusted_projects: Vec<String>,
+    /// ローカルに無いファイルへの相対リンクを開くときのURLの基準（`https://github.com/org/repo/blob/main/` など）。
+    /// 空ならリンク先のファイルが無いことを知らせるだけ
+    pub link_base: String,
+    /// `chafa` があれば画像を色付きのブロック文字で表示するかどうか
+    pub image_art: bool,
+    /// プレビューを開いたときにスペルチェックを有効にするかどうか
+    pub spell: bool,
+    /// スペルチェックに使うhunspellの辞書名
+    pub spell_language: String,
+    /// `:audit` で古いとみなす、gitで最後に変更されてからの日数
+    pub stale_days: u64,
+    /// `:search` の索引をキャッシュに保存し、次から更新された文書だけを読み直すかどうか
+    pub search_index: bool,
+    /// `:today` で開く日付ごとのノートを置くディレクトリ（`~` はホームディレクトリ）
+    pub journal_dir: String,
+    /// 新しいノートに使うテンプレートの名前。空なら日付の見出しだけのノートを作る
+    pub journal_template: String,
+    /// HTML書き出しに使うテンプレートとCSSのファイル。空なら組み込みのものを使う
+    pub html_template: String,
+    pub html_css: String,
+    /// 記録するログのレベルと、書き出すファイル（空ならファイルには書き出さない）
+    pub log_level: LevelFilter,
+    pub log_file: String,
+    /// プレビューのフッターにレンダリング時間などを表示する
+    pub debug_footer: bool,
+    /// コマンドの別名（`alias.ex = "export html"` なら `:ex` が `:export html` になる）
+    pub aliases: BTreeMap<String, String>,
+    /// 本文中の課題番号などをリンクにする規則（パターンとURLのテンプレート）。書いた順に試す
+    pub autolinks: Vec<(String, String)>,
+    /// 拡張子（小文字）ごとの、ファイルをMarkdownか画像に変換してプレビューするコマンド（`[preview]`）
+    pub preview_commands: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: KeymapProfile::default(),
+            scroll_step: 1,
+            fast_scroll_step: 10,
+            smooth_scroll: true,
+            snap_to_headings: false,
+            low_bandwidth: false,
+            screen_reader: false,
+            ascii: false,
+            number_headings: false,
+            minimal_chrome: false,
+            watch: false,
+            hide_gitignored: false,
+            icons: false,
+            dual_pane: false,
+            home_dir: "~".to_string(),
+            theme: "github-dark".to_string(),
+            extensions: vec!["md".to_string()],
+            translation_patterns: vec!["*.ja.md".to_string()],
+            max_file_size: 10 * 1024,
+            filters: Vec::new(),
+            script: String::new(),
+            includes: true,
+            image_viewer: String::new(),
+            trusted_projects: Vec::new(),
+            link_base: String::new(),
+            image_art: false,
+            spell: false,
+            spell_language: "en_US".to_string(),
+            stale_days: 180,
+            search_index: false,
+            journal_dir: "~/journal".to_string(),
+            journal_template: String::new(),
+            html_template: String::new(),
+            html_css: String::new(),
+            log_level: LevelFilter::INFO,
+            log_file: String::new(),
+            debug_footer: false,
+            aliases: BTreeMap::new(),
+            autolinks: Vec::new(),
+            preview_commands: BTreeMap::new(),
+        }
+    }
+}
+
+/// 真偽値の設定項目かどうか。コマンドラインでは `--watch` / `--no-watch` のように値を付けずに指定できる
+pub fn is_switch(key: &str) -> bool {
+    Config::default().apply(&[(key.to_string(), Value::Bool(true))]).is_ok()
+}
+
+/// 信頼していないプロジェクトの設定では無視するキー。コマンドやスクリプトを実行するもの、
+/// ファイルのパスを取るもの（読み込んで書き出しに含める・書き込む・開くもの）、開くURLやコマンドを変えるもの、信頼する範囲そのもの
+pub fn needs_trust(key: &str) -> bool {
+    matches!(
+        key,
+        "filters"
+            | "image_viewer"
+            | "script"
+            | "trusted_projects"
+            | "html_template"
+            | "html_css"
+            | "log_file"
+            | "home_dir"
+            | "journal_dir"
+            | "journal_template"
+            | "spell_language"
+            | "translation_patterns"
+            | "link_base"
+    ) || ["preview.", "alias.", "autolink."].iter().any(|prefix| key.starts_with(prefix))
+}
+
+impl Config {
+    /// `root` のリポジトリのプロジェクト設定のコマンドを使ってよいか
+    pub fn trusts(&self, root: &Path) -> bool {
+        let root = dunce::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        self.trusted_projects.iter().any(|trusted| {
+            let trusted = templates::expand_home(trusted);
+            dunce::canonicalize(&trusted).unwrap_or(trusted) == root
+        })
+    }
+
+    /// コマンドの先頭の単語が別名なら展開する。別名の中の別名も展開するが、循環は打ち切る
+    pub fn expand_alias(&self, command: &str) -> String {
+        let mut expanded = command.to_string();
+        let mut seen = Vec::new();
+        loop {
+            let trimmed = expanded.trim_start();
+            let (name, rest) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+            let Some(replacement) = self.aliases.get(name) else {
+                return expanded;
+            };
+            if seen.iter().any(|seen_name| seen_name == name) {
+                return expanded;
+            }
+            seen.push(name.to_string());
+            expanded = if rest.is_empty() {
+                replacement.clone()
+            } else {
+                format!("{} {}", replacement, rest)
+            };
+        }
+    }
+
+    /// `path` がMarkdownとしてプレビューできる拡張子かどうか
+    pub fn is_markdown(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(ext)))
+    }
+
+    /// `path` の拡張子に `[preview]` のコマンドがあれば返す
+    pub fn preview_command(&self, path: &Path) -> Option<&str> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.preview_commands.get(&extension).map(String::as_str)
+    }
+
+    /// Markdownとして、または `[preview]` のコマンドを通してプレビューできるかどうか
+    pub fn is_previewable(&self, path: &Path) -> bool {
+        self.is_markdown(path) || self.preview_command(path).is_some()
+    }
+
+    /// プレビューで一度に読み込むバイト数の上限
+    pub fn file_size_limit(&self) -> Option<usize> {
+        (self.max_file_size > 0).then(|| self.max_file_size.saturating_mul(1024))
+    }
+
+    /// スクロールをアニメーションさせるか。`low_bandwidth` なら常にしない
+    pub fn animate_scroll(&self) -> bool {
+        self.smooth_scroll && !self.low_bandwidth
+    }
+
+    /// 画像をブロック文字で表示するか。`low_bandwidth` なら常にしない
+    pub fn show_image_art(&self) -> bool {
+        self.image_art && !self.low_bandwidth
+    }
+
+    /// 配色。`screen_reader` なら `theme` に関係なく色をほとんど使わない
+    pub fn color_scheme(&self) -> &'static theme::ColorScheme {
+        if self.screen_reader {
+            return &theme::MONOCHROME_THEME;
+        }
+        theme::by_name(&self.theme).unwrap_or(&theme::GITHUB_DARK_THEME)
+    }
+
+    /// 枠や区切りの描き方。`screen_reader` を `ascii` より優先する
+    pub fn decorations(&self) -> Decorations {
+        if self.screen_reader {
+            Decorations::ScreenReader
+        } else if self.ascii {
+            Decorations::Ascii
+        } else {
+            Decorations::Unicode
+        }
+    }
+
+    /// `script` に書いたスクリプト。設定していなければ None
+    pub fn script(&self) -> io::Result<Option<Arc<Script>>> {
+        if self.script.is_empty() {
+            return Ok(None);
+        }
+        script::load(&templates::expand_home(&self.script)).map(Some)
+    }
+
+    /// レンダリングの設定
+    pub fn render_options(&self) -> RenderOptions {
+        let autolinks = self.autolinks.iter().filter_map(|(pattern, url)| Autolink::new(pattern, url).ok()).collect();
+        // 読み込めないスクリプトは、文書を開くとき（`on_open` を呼ぶとき）に知らせる
+        let script = self.script().ok().flatten();
+        RenderOptions { decorations: self.decorations(), autolinks, number_headings: self.number_headings, script }
+    }
+
+    /// 設定値を上書きする。未知のキーは将来の設定項目として無視する
+    pub fn apply(&mut self, entries: &[(String, Value)]) -> io::Result<()> {
+        for (key, value) in entries {
+            match (key.as_str(), value) {
+                ("keymap", Value::Str(name)) => {
+                    self.keymap = match name.as_str() {
+                        "default" | "vim" => KeymapProfile::Default,
+                        "less" => KeymapProfile::Less,
+                        _ => return Err(invalid(format!("不明なkeymapです: {}", name))),
+                    };
+                }
+                ("keymap", _) => return Err(invalid("keymapには文字列を指定してください".to_string())),
+                ("scroll_step", value) => self.scroll_step = positive(key, value)?,
+                ("fast_scroll_step", value) => self.fast_scroll_step = positive(key, value)?,
+                ("smooth_scroll", value) => self.smooth_scroll = boolean(key, value)?,
+                ("snap_to_headings", value) => self.snap_to_headings = boolean(key, value)?,
+                ("low_bandwidth", value) => self.low_bandwidth = boolean(key, value)?,
+                ("screen_reader", value) => self.screen_reader = boolean(key, value)?,
+                ("ascii", value) => self.ascii = boolean(key, value)?,
+                ("number_headings", value) => self.number_headings = boolean(key, value)?,
+                ("minimal_chrome", value) => self.minimal_chrome = boolean(key, value)?,
+                ("watch", value) => self.watch = boolean(key, value)?,
+                ("hide_gitignored", value) => self.hide_gitignored = boolean(key, value)?,
+                ("icons", value) => self.icons = boolean(key, value)?,
+                ("dual_pane", value) => self.dual_pane = boolean(key, value)?,
+                ("theme", Value::Str(name)) if theme::by_name(name).is_some() => self.theme = name.clone(),
+                ("theme", _) => {
+                    return Err(invalid(format!(
+                        "themeには次のいずれかを指定してください: {}",
+                        theme::THEME_NAMES.join(", ")
+                    )));
+                }
+                ("extensions", Value::List(extensions)) => {
+                    self.extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_string()).collect();
+                }
+                // 環境変数などからはカンマ区切りの文字列で渡される
+                ("extensions", Value::Str(extensions)) => {
+                    self.extensions = extensions
+                        .split(',')
+                        .map(|ext| ext.trim().trim_start_matches('.').to_string())
+                        .filter(|ext| !ext.is_empty())
+                        .collect();
+                }
+                ("extensions", _) => return Err(invalid("extensionsには文字列の配列を指定してください".to_string())),
+                ("translation_patterns", Value::List(patterns)) if patterns.iter().all(|p| p.matches('*').count() == 1) => {
+                    self.translation_patterns = patterns.clone();
+                }
+                ("translation_patterns", Value::Str(patterns)) if patterns.split(',').all(|p| p.matches('*').count() == 1) => {
+                    self.translation_patterns = patterns.split(',').map(|p| p.trim().to_string()).collect();
+                }
+                ("translation_patterns", _) => {
+                    return Err(invalid("translation_patternsには `*` を1つ含むファイル名の配列を指定してください".to_string()));
+                }
+                ("max_file_size", Value::Int(size)) if *size >= 0 => self.max_file_size = *size as usize,
+                ("max_file_size", _) => return Err(invalid("max_file_sizeには0以上の整数（KiB）を指定してください".to_string())),
+                ("filters", Value::List(filters)) => self.filters = filters.clone(),
+                // コマンドにはカンマが含まれうるので、文字列は1つのコマンドとして扱う
+                ("filters", Value::Str(filter)) => {
+                    self.filters = if filter.trim().is_empty() { Vec::new() } else { vec![filter.clone()] };
+                }
+                ("filters", _) => return Err(invalid("filtersにはコマンドの配列を指定してください".to_string())),
+                ("script", Value::Str(path)) => self.script = path.clone(),
+                ("script", _) => return Err(invalid("scriptにはファイルのパスを文字列で指定してください".to_string())),
+                ("includes", value) => self.includes = boolean(key, value)?,
+                ("image_viewer", Value::Str(command)) => self.image_viewer = command.clone(),
+                ("image_viewer", _) => return Err(invalid("image_viewerにはコマンドを文字列で指定してください".to_string())),
+                ("trusted_projects", Value::List(roots)) => self.trusted_projects = roots.clone(),
+                // 環境変数などからはカンマ区切りの文字列で渡される
+                ("trusted_projects", Value::Str(roots)) => {
+                    self.trusted_projects = roots.split(',').map(str::trim).filter(|root| !root.is_empty()).map(str::to_string).collect();
+                }
+                ("trusted_projects", _) => {
+                    return Err(invalid("trusted_projectsにはディレクトリの配列を指定してください".to_string()));
+                }
+                ("link_base", Value::Str(url)) => self.link_base = url.clone(),
+                ("link_base", _) => return Err(invalid("link_baseにはURLを文字列で指定してください".to_string())),
+                ("image_art", value) => self.image_art = boolean(key, value)?,
+                ("spell", value) => self.spell = boolean(key, value)?,
+                ("spell_language", Value::Str(language)) => self.spell_language = language.clone(),
+                ("spell_language", _) => return Err(invalid("spell_languageには辞書名を文字列で指定してください".to_string())),
+                ("home_dir", Value::Str(dir)) => self.home_dir = dir.clone(),
+                ("home_dir", _) => return Err(invalid("home_dirにはディレクトリを文字列で指定してください".to_string())),
+                ("stale_days", Value::Int(days)) if *days >= 1 => self.stale_days = *days as u64,
+                ("stale_days", _) => return Err(invalid("stale_daysには1以上の日数を指定してください".to_string())),
+                ("search_index", value) => self.search_index = boolean(key, value)?,
+                ("journal_dir", Value::Str(dir)) => self.journal_dir = dir.clone(),
+                ("journal_dir", _) => return Err(invalid("journal_dirにはディレクトリを文字列で指定してください".to_string())),
+                ("journal_template", Value::Str(name)) => self.journal_template = name.clone(),
+                ("journal_template", _) => {
+                    return Err(invalid("journal_templateにはテンプレート名を文字列で指定してください".to_string()));
+                }
+                ("html_template", Value::Str(path)) => self.html_template = path.clone(),
+                ("html_css", Value::Str(path)) => self.html_css = path.clone(),
+                ("html_template" | "html_css", _) => {
+                    return Err(invalid(format!("{}にはファイルのパスを文字列で指定してください", key)));
+                }
+                ("log_level", Value::Str(level)) if let Ok(level) = level.parse() => self.log_level = level,
+                ("log_level", _) => {
+                    return Err(invalid(
+                        "log_levelにはoff, error, warn, info, debug, traceのいずれかを指定してください".to_string(),
+                    ));
+                }
+                ("log_file", Value::Str(path)) => self.log_file = path.clone(),
+                ("debug_footer", value) => self.debug_footer = boolean(key, value)?,
+                ("log_file", _) => return Err(invalid("log_fileにはファイルのパスを文字列で指定してください".to_string())),
+                (key, Value::Str(command)) if key.starts_with("alias.") => {
+                    self.aliases.insert(key["alias.".len()..].to_string(), command.clone());
+                }
+                (key, _) if key.starts_with("alias.") => {
+                    return Err(invalid(format!("{}にはコマンドを文字列で指定してください", key)));
+                }
+                (key, Value::Str(url)) if key.starts_with("autolink.") => {
+                    let pattern = &key["autolink.".len()..];
+                    if let Err(reason) = Autolink::new(pattern, url) {
+                        return Err(invalid(format!("autolinkのパターン {} を解釈できません: {}", pattern, reason)));
+                    }
+                    self.autolinks.retain(|(existing, _)| existing != pattern);
+                    self.autolinks.push((pattern.to_string(), url.clone()));
+                }
+                (key, _) if key.starts_with("autolink.") => {
+                    return Err(invalid(format!("{}にはURLを文字列で指定してください", key)));
+                }
+                (key, Value::Str(command)) if key.starts_with("preview.") => {
+                    let extension = key["preview.".len()..].trim_start_matches('.').to_ascii_lowercase();
+                    self.preview_commands.insert(extension, command.clone());
+                }
+                (key, _) if key.starts_with("preview.") => {
+                    return Err(invalid(format!("{}にはコマンドを文字列で指定してください", key)));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 1以上の行数として解釈する
+fn positive(key: &str, value: &Value) -> io::Result<u16> {
+    match value {
+        Value::Int(n) if *n >= 1 => Ok((*n).min(u16::MAX as i64) as u16),
+        _ => Err(invalid(format!("{}には1以上の整数を指定してください", key))),
+    }
+}
+
+fn boolean(key: &str, value: &Value) -> io::Result<bool> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(invalid(format!("{}には true か false を指定してください", key))),
+    }
+}
+
+pub fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// `[section]` 内のキーは `section.key` の形に展開して返す。`source` はエラー表示用のファイル名
+pub fn parse(text: &str, source: &str) -> io::Result<Vec<(String, Value)>> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |what: &str| invalid(format!("{} {}行目: {}", source, index + 1, what));
+
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| error("`key = value` の形式ではありません"))?;
+        // パターンを書きやすいよう、キーは `'...'` でも囲める
+        let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+        if key.is_empty() {
+            return Err(error("キーが空です"));
+        }
+        let value = parse_value(value.trim()).ok_or_else(|| error("値を解釈できません"))?;
+        let full_key = if section.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", section, key)
+        };
+        entries.push((full_key, value));
+    }
+    Ok(entries)
+}
+
+/// 設定ファイルのテキストのうち、セクションの外にある `key` の値を `value` に書き換える。
+/// 無ければ最初のセクションの前に追加する。ほかの行とコメントはそのまま残す
+pub fn set_value(text: &str, key: &str, value: &str) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let top_level = lines.iter().position(|line| strip_comment(line).trim().starts_with('[')).unwrap_or(lines.len());
+    let entry = format!("{} = {}", key, value);
+    let existing = lines[..top_level]
+        .iter()
+        .position(|line| strip_comment(line).split_once('=').is_some_and(|(name, _)| name.trim().trim_matches('"') == key));
+    match existing {
+        Some(index) => lines[index] = entry,
+        None => {
+            // セクションの前の空行より前に入れる
+            let mut at = top_level;
+            while at > 0 && lines[at - 1].trim().is_empty() {
+                at -= 1;
+            }
+            lines.insert(at, entry);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+pub fn parse_value(text: &str) -> Option<Value> {
+    if let Some(inner) = text.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let items = inner
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(parse_string)
+            .collect::<Option<Vec<_>>>()?;
+        return Some(Value::List(items));
+    }
+    match text {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Ok(number) = text.parse::<i64>() {
+        return Some(Value::Int(number));
+    }
+    parse_string(text).map(Value::Str)
+}
+
+fn parse_string(text: &str) -> Option<String> {
+    if let Some(inner) = text.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        return Some(inner.to_string());
+    }
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                other => result.push(other),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    Some(result)
+}
+
+/// 文字列の外にある `#` 以降をコメントとして取り除く
+pub fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && q == '"' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '#' => return &line[..i],
+                _ => {}
+            },
+        }
+    }
+    line
+}
@@ -0,0 +1,357 @@
+// --- 索引の常駐（`peek --daemon`） ---
+//
+// 文書の集まりの索引（vault）を作ったまま待ち受け、後から起動したpeekにローカルのソケットで答える。
+// 大きな文書の集まりでも、索引を作るのは最初の1回だけで、以後はファイルの変更の知らせを受けた文書だけを読み直す
+// （監視できなければ、要求のたびにツリーをたどって更新日時を比べる）。
+// 1つの接続で1行の要求を受け取り、答えを1行ずつ返して閉じる。
+//
+//     tags               → `タグ<TAB>パス` の行
+//     backlinks <パス>   → `パス<TAB>行番号` の行
+//     search <語>        → `パス<TAB>行番号<TAB>点数<TAB>抜粋` の行（順位の高い順）
+//     related <パス>     → `パス<TAB>点数<TAB>リンクの有無（1か0）<TAB>共通の語（空白区切り）` の行（似ている順）
+//     index [条件]       → `パス<TAB>タイトル` に `<TAB>キー<TAB>値` を値ごとに続けた行（`:index` の条件を満たす文書）
+//     links              → `パス` に `<TAB>リンク先の文書のパス` を続けた行（文書ごと）
+//
+// ソケットは `$XDG_RUNTIME_DIR/peek`（無ければ一時ディレクトリの `peek-<uid>`）という自分だけが使えるディレクトリに
+// ルートごとに作るので、同じルートのdaemonは1つだけ動かせる。
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    graph::Graph,
+    metadata::{self, Entry},
+    search::{self, Hit, Related},
+    vault::{self, Vault},
+};
+
+/// `root` の索引を常駐させたdaemonのソケット
+pub fn socket_path(root: &Path) -> io::Result<PathBuf> {
+    Ok(socket::dir()?.join(format!("{}.sock", vault::root_id(root))))
+}
+
+/// `dir` かその親ディレクトリの索引を常駐させたdaemonのルート
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    let sockets = socket::dir().ok()?;
+    dir.ancestors()
+        .find(|root| sockets.join(format!("{}.sock", vault::root_id(root))).exists())
+        .map(Path::to_path_buf)
+}
+
+/// 要求への答えの行。不明な要求ならErr
+pub fn answer(vault: &Vault, request: &str) -> Result<Vec<String>, String> {
+    let (name, argument) = request.split_once(' ').unwrap_or((request, ""));
+    match name {
+        "tags" => Ok(vault
+            .tags()
+            .into_iter()
+            .flat_map(|(tag, documents)| documents.into_iter().map(move |path| format!("{}\t{}", tag, path.display())))
+            .collect()),
+        "backlinks" if !argument.is_empty() => Ok(vault
+            .backlinks(Path::new(argument))
+            .into_iter()
+            .map(|(path, line)| format!("{}\t{}", path.display(), line))
+            .collect()),
+        "search" if !argument.trim().is_empty() => Ok(search::search(vault, argument, search::RESULTS)
+            .into_iter()
+            .map(|hit| format!("{}\t{}\t{}\t{}", hit.path.display(), hit.line, hit.score, hit.snippet))
+            .collect()),
+        "related" if !argument.is_empty() => Ok(search::related(vault, Path::new(argument), search::RESULTS)
+            .into_iter()
+            .map(|related| format!("{}\t{}\t{}\t{}", related.path.display(), related.score, u8::from(related.linked), related.terms.join(" ")))
+            .collect()),
+        "links" => Ok(Graph::from_vault(vault)
+            .outgoing
+            .into_iter()
+            .map(|(path, targets)| {
+                std::iter::once(path).chain(targets).map(|path| path.display().to_string()).collect::<Vec<_>>().join("\t")
+            })
+            .collect()),
+        "index" => {
+            let conditions = metadata::parse(argument)?;
+            Ok(metadata::filter(vault, &vault.root, &conditions)
+                .into_iter()
+                .map(|entry| {
+                    let mut line = format!("{}\t{}", entry.path.display(), field(&entry.title));
+                    for (key, values) in &entry.metadata {
+                        // 値の無いキーは空の値1つで表す
+                        for value in values.iter().map(String::as_str).chain(values.is_empty().then_some("")) {
+                            line.push_str(&format!("\t{}\t{}", field(key), field(value)));
+                        }
+                    }
+                    line
+                })
+                .collect())
+        }
+        _ => Err(format!("unknown request: {}", request)),
+    }
+}
+
+/// 答えの欄に入れられるよう、タブと改行を空白にする
+fn field(text: &str) -> String {
+    text.replace(['\t', '\n'], " ")
+}
+
+/// `tags` の答えを `tags::index` と同じ形にする。`dir` の下の文書だけを残す
+pub fn parse_tags(lines: &[String], dir: &Path) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut tags: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (tag, path) in lines.iter().filter_map(|line| line.split_once('\t')) {
+        let path = PathBuf::from(path);
+        if path.starts_with(dir) {
+            tags.entry(tag.to_string()).or_default().push(path);
+        }
+    }
+    tags
+}
+
+/// `backlinks` の答えを文書と行番号の組にする
+pub fn parse_backlinks(lines: &[String]) -> Vec<(PathBuf, usize)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (path, number) = line.rsplit_once('\t')?;
+            Some((PathBuf::from(path), number.parse().ok()?))
+        })
+        .collect()
+}
+
+/// `search` の答えを検索結果にする。`dir` の下の文書だけを残す
+pub fn parse_hits(lines: &[String], dir: &Path) -> Vec<Hit> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let path = PathBuf::from(fields.next()?);
+            let line = fields.next()?.parse().ok()?;
+            let score = fields.next()?.parse().ok()?;
+            let snippet = fields.next().unwrap_or_default().to_string();
+            Some(Hit { path, score, line, snippet })
+        })
+        .filter(|hit| hit.path.starts_with(dir))
+        .collect()
+}
+
+/// `related` の答えを似た文書の一覧にする
+pub fn parse_related(lines: &[String]) -> Vec<Related> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let path = PathBuf::from(fields.next()?);
+            let score = fields.next()?.parse().ok()?;
+            let linked = fields.next()? == "1";
+            let terms = fields.next().unwrap_or_default().split_whitespace().map(str::to_string).collect();
+            Some(Related { path, score, linked, terms })
+        })
+        .collect()
+}
+
+/// `links` の答えをグラフにする
+pub fn parse_links(lines: &[String]) -> Graph {
+    Graph::new(
+        lines
+            .iter()
+            .filter_map(|line| {
+                let mut paths = line.split('\t').map(PathBuf::from);
+                Some((paths.next()?, paths.collect()))
+            })
+            .collect(),
+    )
+}
+
+/// `index` の答えを一覧の行にする。`dir` の下の文書だけを残す
+pub fn parse_entries(lines: &[String], dir: &Path) -> Vec<Entry> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let path = PathBuf::from(fields.next()?);
+            let title = fields.next()?.to_string();
+            let mut metadata: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+                let values = metadata.entry(key.to_string()).or_default();
+                if !value.is_empty() {
+                    values.push(value.to_string());
+                }
+            }
+            Some(Entry { path, title, metadata })
+        })
+        .filter(|entry| entry.path.starts_with(dir))
+        .collect()
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::{
+        env, fs,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::{
+            fs::{DirBuilderExt, MetadataExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        sync::mpsc::{self, Receiver},
+        time::{Duration, Instant},
+    };
+
+    use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+    use super::{answer, socket_path};
+    use crate::vault::Vault;
+
+    /// daemonが答えるのを待つ時間。これより遅ければ自分で索引を作る。
+    /// daemonの側も、要求を送ってこない接続をこれ以上は待たない
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// ソケットを置く、自分だけが使えるディレクトリ。無ければ作る
+    pub fn dir() -> io::Result<PathBuf> {
+        // SAFETY: 自分の実効ユーザーIDを返すだけで、失敗しない
+        let uid = unsafe { libc::geteuid() };
+        let dir = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("peek"),
+            _ => env::temp_dir().join(format!("peek-{}", uid)),
+        };
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        // 他のユーザーが先に作ったディレクトリや、他のユーザーも入れるディレクトリにはソケットを置かない
+        let metadata = fs::symlink_metadata(&dir)?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} は自分だけが使えるディレクトリではありません", dir.display()),
+            ));
+        }
+        Ok(dir)
+    }
+
+    pub fn run(root: &Path, is_document: impl Fn(&Path) -> bool) -> io::Result<()> {
+        let socket = socket_path(root)?;
+        if socket.exists() {
+            if UnixStream::connect(&socket).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} のdaemonは既に動いています", root.display())));
+            }
+            // 前のdaemonが残したソケット
+            fs::remove_file(&socket)?;
+        }
+        let started = Instant::now();
+        let mut vault = Vault::new(root.to_path_buf());
+        // 索引を作る間に変わった文書も知らせてもらうため、監視を先に始める
+        let watcher = watch(root);
+        if let Err(e) = &watcher {
+            tracing::warn!("daemon: cannot watch {} ({}); walking the tree on every request", root.display(), e);
+        }
+        let count = vault.refresh(&is_document);
+        let listener = UnixListener::bind(&socket)?;
+        eprintln!("Indexed {} documents under {} in {:?}; listening on {}", count, root.display(), started.elapsed(), socket.display());
+        tracing::info!("daemon started: root={} documents={}", root.display(), count);
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| {
+                // 要求を送らずに黙っている接続で、他の要求を止めない
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request)?;
+                let request = request.trim_end_matches(['\r', '\n']);
+                // 答える前に、更新された文書だけを読み直す
+                let parsed = match &watcher {
+                    Ok((_, events)) => changed_paths(events)
+                        .map(|paths| vault.refresh_paths(&paths, &is_document))
+                        .unwrap_or_else(|| vault.refresh(&is_document)),
+                    Err(_) => vault.refresh(&is_document),
+                };
+                tracing::debug!("daemon: {} (reparsed {} documents)", request, parsed);
+                let mut stream = io::BufWriter::new(stream);
+                match answer(&vault, request) {
+                    Ok(lines) => lines.iter().try_for_each(|line| writeln!(stream, "{}", line))?,
+                    Err(message) => writeln!(stream, "error\t{}", message)?,
+                }
+                stream.flush()
+            });
+            if let Err(e) = result {
+                tracing::warn!("daemon: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// `root` の下の変更を監視する。監視をやめないよう、知らせを受け取る側と一緒にwatcherを返す
+    fn watch(root: &Path) -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        Ok((watcher, events))
+    }
+
+    /// これまでに知らされた変更のあったパス。知らせを取りこぼしていればNone（ツリーをたどり直す）
+    fn changed_paths(events: &Receiver<notify::Result<Event>>) -> Option<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let mut rescan = false;
+        for event in events.try_iter() {
+            match event {
+                Ok(event) if event.need_rescan() => rescan = true,
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) => paths.extend(event.paths),
+                Err(_) => rescan = true,
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        (!rescan).then_some(paths)
+    }
+
+    pub fn query(root: &Path, request: &str) -> io::Result<Vec<String>> {
+        let mut stream = UnixStream::connect(socket_path(root)?)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        writeln!(stream, "{}", request)?;
+        let lines = BufReader::new(stream).lines().collect::<io::Result<Vec<String>>>()?;
+        match lines.first().and_then(|line| line.strip_prefix("error\t")) {
+            Some(message) => Err(io::Error::other(message.to_string())),
+            None => Ok(lines),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod socket {
+    use std::{
+        io,
+        path::{Path, PathBuf},
+    };
+
+    pub fn dir() -> io::Result<PathBuf> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn run(_root: &Path, _is_document: impl Fn(&Path) -> bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "--daemon はこのプラットフォームでは使えません"))
+    }
+
+    pub fn query(_root: &Path, _request: &str) -> io::Result<Vec<String>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// `root` の索引を作り、終了されるまで要求に答える
+pub fn run(root: &Path, is_document: impl Fn(&Path) -> bool) -> io::Result<()> {
+    socket::run(root, is_document)
+}
+
+/// `dir` を含む索引のdaemonに要求を送り、答えの行を返す。daemonが無ければNone
+pub fn query(dir: &Path, request: &str) -> Option<Vec<String>> {
+    let root = find(dir)?;
+    match socket::query(&root, request) {
+        Ok(lines) => Some(lines),
+        Err(e) => {
+            tracing::warn!("daemon for {} did not answer: {}", root.display(), e);
+            None
+        }
+    }
+}
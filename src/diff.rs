@@ -0,0 +1,164 @@
+// --- 再読み込みの前後の差分 ---
+//
+// 監視中のファイルが保存されたとき、描画し直した文書のどこが変わったかを示すために使う。
+// 行の単位で最長共通部分列を取り、書き換わった行どうしは単語の単位でも比べる。
+
+use std::ops::Range;
+
+use ratatui::prelude::*;
+
+/// これより大きい範囲は比べずに、すべて変わったものとして扱う（比較の表の大きさ）
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+/// 新しい文書で変わった行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// 行全体が追加された（または比べられないほど変わった）
+    Line(usize),
+    /// 行の中の変わった部分（バイト単位の範囲）
+    Words(usize, Vec<Range<usize>>),
+}
+
+/// 古い行の一覧と新しい行の一覧を比べ、新しい方で変わった行を返す
+pub fn changes(old: &[String], new: &[String]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (old_range, new_range) in hunks(old, new) {
+        // 書き換わった行は前から順に古い行と組にして単語で比べ、余った行は追加された行とする
+        for (offset, line) in new_range.clone().enumerate() {
+            let paired = old_range.start + offset;
+            if paired < old_range.end {
+                let words = changed_words(&old[paired], &new[line]);
+                if !words.is_empty() {
+                    changes.push(Change::Words(line, words));
+                }
+            } else if !new[line].trim().is_empty() {
+                changes.push(Change::Line(line));
+            }
+        }
+    }
+    changes
+}
+
+/// `old` を `new` に変えたときに `new` の中で変わった部分（バイト単位の範囲）。空白だけの違いは無視する
+pub fn changed_words(old: &str, new: &str) -> Vec<Range<usize>> {
+    let old_words = tokens(old);
+    let new_words = tokens(new);
+    let old_texts: Vec<&str> = old_words.iter().map(|range| &old[range.clone()]).collect();
+    let new_texts: Vec<&str> = new_words.iter().map(|range| &new[range.clone()]).collect();
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (_, new_range) in hunks(&old_texts, &new_texts) {
+        for token in &new_words[new_range] {
+            if new[token.clone()].trim().is_empty() {
+                continue;
+            }
+            // 空白だけを挟んで続く部分は1つにまとめる
+            match ranges.last_mut() {
+                Some(last) if new[last.end..token.start].trim().is_empty() => last.end = token.end,
+                _ => ranges.push(token.clone()),
+            }
+        }
+    }
+    ranges
+}
+
+/// 行の `ranges`（行全体をつなげたテキストでのバイト単位の範囲）に `style` を重ねる
+pub fn highlight(line: &mut Line<'static>, ranges: &[Range<usize>], style: Style) {
+    let mut spans = Vec::with_capacity(line.spans.len() + ranges.len() * 2);
+    let mut offset = 0;
+    for span in line.spans.drain(..) {
+        let content = span.content.to_string();
+        let end = offset + content.len();
+        // スパンの中の範囲の境目で区切る
+        let mut cuts = vec![0, content.len()];
+        for range in ranges.iter().filter(|range| range.start < end && range.end > offset) {
+            cuts.push(range.start.saturating_sub(offset).min(content.len()));
+            cuts.push(range.end.saturating_sub(offset).min(content.len()));
+        }
+        cuts.retain(|&cut| content.is_char_boundary(cut));
+        cuts.sort_unstable();
+        cuts.dedup();
+        for pair in cuts.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let changed = ranges.iter().any(|range| range.start <= offset + from && offset + to <= range.end);
+            let part_style = if changed { span.style.patch(style) } else { span.style };
+            spans.push(Span::styled(content[from..to].to_string(), part_style));
+        }
+        offset = end;
+    }
+    line.spans = spans;
+}
+
+/// 単語・空白・記号1文字ずつに分ける
+fn tokens(text: &str) -> Vec<Range<usize>> {
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut previous: Option<u8> = None;
+    for (index, c) in text.char_indices() {
+        // 0: 単語の文字、1: 空白、2: 記号（1文字ずつ）
+        let kind = if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        };
+        match tokens.last_mut() {
+            Some(last) if previous == Some(kind) && kind != 2 => last.end = index + c.len_utf8(),
+            _ => tokens.push(index..index + c.len_utf8()),
+        }
+        previous = Some(kind);
+    }
+    tokens
+}
+
+/// 2つの並びで一致しない部分（古い方の範囲と新しい方の範囲）の一覧
+fn hunks<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(Range<usize>, Range<usize>)> {
+    // 前後の一致する部分は表を作らずに除く
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+    if old_middle.is_empty() || new_middle.is_empty() || old_middle.len() * new_middle.len() > MAX_TABLE_SIZE {
+        return vec![(prefix..old.len() - suffix, prefix..new.len() - suffix)];
+    }
+
+    // common[i][j]: old_middle[i..] と new_middle[j..] の最長共通部分列の長さ
+    let (rows, columns) = (old_middle.len(), new_middle.len());
+    let mut common = vec![0u32; (rows + 1) * (columns + 1)];
+    let at = |i: usize, j: usize| i * (columns + 1) + j;
+    for i in (0..rows).rev() {
+        for j in (0..columns).rev() {
+            common[at(i, j)] = if old_middle[i] == new_middle[j] {
+                common[at(i + 1, j + 1)] + 1
+            } else {
+                common[at(i + 1, j)].max(common[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut start: Option<(usize, usize)> = None;
+    while i < rows || j < columns {
+        if i < rows && j < columns && old_middle[i] == new_middle[j] {
+            if let Some((old_start, new_start)) = start.take() {
+                hunks.push((prefix + old_start..prefix + i, prefix + new_start..prefix + j));
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        start.get_or_insert((i, j));
+        if j < columns && (i == rows || common[at(i, j + 1)] >= common[at(i + 1, j)]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if let Some((old_start, new_start)) = start {
+        hunks.push((prefix + old_start..prefix + rows, prefix + new_start..prefix + columns));
+    }
+    hunks
+}
@@ -0,0 +1,66 @@
+// --- 操作の失敗を知らせるエラー画面 ---
+//
+// 1つのファイルの読み込みや書き出しに失敗しても終了せず、内容を表示して閲覧を続けられるようにする。
+
+use std::{
+    any::Any,
+    fmt::Display,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+/// エラー画面に表示する内容
+pub struct ErrorReport {
+    /// 失敗した操作
+    pub title: String,
+    pub details: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn new(title: impl Into<String>, error: &dyn Display) -> Self {
+        let title = title.into();
+        tracing::error!("{}: {}", title, error);
+        Self {
+            title,
+            details: error.to_string().lines().map(str::to_string).collect(),
+        }
+    }
+
+    fn from_panic(title: &str, location: Option<String>, payload: Box<dyn Any + Send>) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "不明なパニック".to_string());
+        let mut details = vec!["内部エラーが発生しました（不具合の可能性があります）".to_string(), message];
+        details.extend(location);
+        tracing::error!("{}: {}", title, details[1..].join(" "));
+        Self { title: title.to_string(), details }
+    }
+}
+
+/// `f` を実行し、エラーやパニックをエラー画面の内容に変換する。
+/// パニックの既定の出力はTUIの表示を崩すので、実行中は抑えて発生箇所だけを記録する
+pub fn catch<T>(title: &str, f: impl FnOnce() -> io::Result<T>) -> Result<T, ErrorReport> {
+    let location = Arc::new(Mutex::new(None));
+    let hook = panic::take_hook();
+    {
+        let location = Arc::clone(&location);
+        panic::set_hook(Box::new(move |info| {
+            if let (Ok(mut slot), Some(at)) = (location.lock(), info.location()) {
+                *slot = Some(format!("at {}:{}:{}", at.file(), at.line(), at.column()));
+            }
+        }));
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(ErrorReport::new(title, &e)),
+        Err(payload) => {
+            let location = location.lock().ok().and_then(|mut slot| slot.take());
+            Err(ErrorReport::from_panic(title, location, payload))
+        }
+    }
+}
@@ -0,0 +1,344 @@
+// --- レンダリング結果の書き出し ---
+
+use std::{fs, io, path::Path};
+
+use pulldown_cmark::{
+    html, CowStr, Event as MarkdownEvent, HeadingLevel, Options, Parser as MarkdownParser, Tag, TagEnd,
+};
+use ratatui::prelude::*;
+
+use crate::{
+    links::{self, LinkTarget},
+    templates,
+};
+
+/// `:export txt` で幅を指定しなかったときの折り返し幅
+pub const DEFAULT_TEXT_WIDTH: usize = 80;
+
+/// レンダリング結果を `width` 列で折り返したプレーンテキストにする。
+/// リストの項目は本文の位置に揃え、引用は各行に `▎` を付け直す。コードブロックと表は折り返さない。
+/// ASCII文字で描いた枠（`|` `+` `-` `>`）も同じように扱う
+pub fn plain_text(text: &Text, width: usize) -> String {
+    let mut output = String::new();
+    for line in &text.lines {
+        let content: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+        let content = content.trim_end();
+        let quote_len = content.len() - strip_quote_borders(content).len();
+        let body = &content[quote_len..];
+        if body.starts_with(['│', '┌', '└', '─', '|']) || body.starts_with("+-") || body.starts_with("--") {
+            output.push_str(content);
+            output.push('\n');
+            continue;
+        }
+        let (first_prefix, rest_prefix, body) = split_prefix(content);
+        for (i, wrapped) in wrap(body, width.saturating_sub(column_width(&first_prefix)).max(1)).iter().enumerate() {
+            output.push_str(if i == 0 { &first_prefix } else { &rest_prefix });
+            output.push_str(wrapped);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// 行頭の字下げ・リストの記号・引用の記号を本文から分け、
+/// 1行目に付ける接頭辞と折り返した行に付ける接頭辞を返す
+fn split_prefix(line: &str) -> (String, String, &str) {
+    let indent_len = line.len() - line.trim_start_matches(' ').len();
+    let (indent, rest) = line.split_at(indent_len);
+    let body = strip_quote_borders(rest);
+    if body.len() < rest.len() {
+        let prefix = format!("{}{}", indent, &rest[..rest.len() - body.len()]);
+        return (prefix.clone(), prefix, body);
+    }
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let marker_len = if digits > 0 && rest[digits..].starts_with(". ") {
+        digits + 2
+    } else if rest.starts_with("• ") {
+        "• ".len()
+    } else if rest.starts_with("* ") {
+        "* ".len()
+    } else {
+        0
+    };
+    let marker = &rest[..marker_len];
+    let first = format!("{}{}", indent, marker);
+    let continuation = " ".repeat(column_width(&first));
+    (first, continuation, &rest[marker_len..])
+}
+
+/// 行頭の引用の枠線（`▎ ` または `> `）を深さの分だけ取り除く
+fn strip_quote_borders(line: &str) -> &str {
+    let mut rest = line;
+    while let Some(stripped) = rest.strip_prefix("▎ ").or_else(|| rest.strip_prefix("> ")) {
+        rest = stripped;
+    }
+    rest
+}
+
+/// 単語単位で折り返す。全角文字はどこでも折り返せるものとして扱う
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_width = 0;
+    for unit in units(text) {
+        let unit_width = column_width(unit);
+        if unit == " " {
+            if current_width > 0 && current_width < width {
+                current.push(' ');
+                current_width += 1;
+            }
+            continue;
+        }
+        if current_width + unit_width > width && current_width > 0 {
+            lines.push(current.trim_end().to_string());
+            current.clear();
+            current_width = 0;
+        }
+        current.push_str(unit);
+        current_width += unit_width;
+    }
+    if !current.trim_end().is_empty() {
+        lines.push(current.trim_end().to_string());
+    }
+    lines
+}
+
+/// 折り返しの単位に分ける。半角の単語・空白・全角文字1文字がそれぞれ1単位
+fn units(text: &str) -> Vec<&str> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let breakable = c == ' ' || column_width(&text[i..i + c.len_utf8()]) > 1;
+        if breakable {
+            if start < i {
+                units.push(&text[start..i]);
+            }
+            units.push(&text[i..i + c.len_utf8()]);
+            start = i + c.len_utf8();
+        }
+    }
+    if start < text.len() {
+        units.push(&text[start..]);
+    }
+    units
+}
+
+fn column_width(text: &str) -> usize {
+    Span::raw(text).width()
+}
+
+/// HTML書き出しの既定のテンプレート。`{{title}}`・`{{css}}`・`{{body}}` を置き換える
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}}</title>
+<style>
+{{css}}
+</style>
+</head>
+<body>
+<article class="markdown-body">
+{{body}}
+</article>
+</body>
+</html>
+"#;
+
+/// HTML書き出しの既定のスタイル（GitHub Dark風）
+const HTML_CSS: &str = r#"body { margin: 0; background: #0d1117; color: #c9d1d9; font-family: -apple-system, "Segoe UI", "Noto Sans", "Hiragino Sans", sans-serif; line-height: 1.6; }
+.markdown-body { max-width: 880px; margin: 0 auto; padding: 32px 16px; }
+h1, h2 { border-bottom: 1px solid #21262d; padding-bottom: .3em; }
+h1, h2, h3, h4, h5, h6 { color: #58a6ff; }
+a { color: #58a6ff; }
+code { background: #282d35; padding: .2em .4em; border-radius: 6px; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; }
+pre { background: #161b22; padding: 16px; overflow: auto; border-radius: 6px; }
+pre code { background: none; padding: 0; }
+blockquote { margin: 0; padding: 0 1em; color: #8b949e; border-left: .25em solid #3b434b; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #30363d; padding: 6px 13px; }
+hr { border: 0; height: .25em; background: #30363d; }
+img { max-width: 100%; }"#;
+
+/// HTML書き出しに使うテンプレートとスタイル。Noneなら既定のものを使う
+#[derive(Default)]
+pub struct HtmlAssets {
+    pub template: Option<String>,
+    pub css: Option<String>,
+}
+
+impl HtmlAssets {
+    /// 設定の `html_template` / `html_css` のファイルを読み込む。相対パスは `base` から解決する
+    pub fn load(template: &str, css: &str, base: &Path) -> io::Result<Self> {
+        let read = |path: &str| -> io::Result<Option<String>> {
+            if path.is_empty() {
+                return Ok(None);
+            }
+            let path = base.join(templates::expand_home(path));
+            fs::read_to_string(&path)
+                .map(Some)
+                .map_err(|e| io::Error::new(e.kind(), format!("{} を読み込めません: {}", path.display(), e)))
+        };
+        Ok(Self { template: read(template)?, css: read(css)? })
+    }
+}
+
+/// Markdownを1つのHTML文書に変換する。`self_contained` ならローカルの画像をdata URIとして埋め込む
+pub fn html_document(
+    markdown: &str,
+    title: &str,
+    document: Option<&Path>,
+    self_contained: bool,
+    assets: &HtmlAssets,
+) -> String {
+    let parser = MarkdownParser::new_ext(markdown, Options::all()).map(|event| match event {
+        MarkdownEvent::Start(Tag::Image { link_type, dest_url, title, id }) if self_contained => {
+            let dest_url = match links::resolve(&dest_url, document) {
+                LinkTarget::File(path, _) => data_uri(&path).map_or(dest_url, CowStr::from),
+                _ => dest_url,
+            };
+            MarkdownEvent::Start(Tag::Image { link_type, dest_url, title, id })
+        }
+        event => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, parser);
+    templates::render(
+        assets.template.as_deref().unwrap_or(HTML_TEMPLATE),
+        &[
+            ("title", &escape_html(title)),
+            ("css", assets.css.as_deref().unwrap_or(HTML_CSS)),
+            ("body", &body),
+        ],
+    )
+}
+
+/// スライドの書き出しに使うreveal.jsのテンプレート
+const SLIDES_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}}</title>
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/reveal.js@5/dist/reveal.css">
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/reveal.js@5/dist/theme/black.css">
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/reveal.js@5/plugin/highlight/monokai.css">
+</head>
+<body>
+<div class="reveal">
+<div class="slides">
+{{slides}}
+</div>
+</div>
+<script src="https://cdn.jsdelivr.net/npm/reveal.js@5/dist/reveal.js"></script>
+<script src="https://cdn.jsdelivr.net/npm/reveal.js@5/plugin/highlight/highlight.js"></script>
+<script>Reveal.initialize({ hash: true, plugins: [RevealHighlight] });</script>
+</body>
+</html>
+"#;
+
+/// Markdownをreveal.jsのスライドに変換する。トップレベルの `---` で区切り、
+/// 区切りが無ければ見出し（H1・H2）ごとに1枚のスライドにする
+pub fn slides_document(markdown: &str, title: &str) -> String {
+    // フロントマターはスライドに含めない
+    let mut in_metadata = false;
+    let events: Vec<_> = MarkdownParser::new_ext(markdown, Options::all())
+        .filter(|event| {
+            match event {
+                MarkdownEvent::Start(Tag::MetadataBlock(_)) => in_metadata = true,
+                MarkdownEvent::End(TagEnd::MetadataBlock(_)) => {
+                    in_metadata = false;
+                    return false;
+                }
+                _ => {}
+            }
+            !in_metadata
+        })
+        .collect();
+    let mut depth = 0usize;
+    let has_rule = events.iter().any(|event| {
+        match event {
+            MarkdownEvent::Start(_) => depth += 1,
+            MarkdownEvent::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        depth == 0 && matches!(event, MarkdownEvent::Rule)
+    });
+    let mut slides: Vec<Vec<MarkdownEvent>> = vec![Vec::new()];
+    let mut depth = 0usize;
+    for event in events {
+        let top_level = depth == 0;
+        match &event {
+            MarkdownEvent::Rule if top_level && has_rule => {
+                slides.push(Vec::new());
+                continue;
+            }
+            MarkdownEvent::Start(Tag::Heading { level, .. })
+                if top_level && !has_rule && *level <= HeadingLevel::H2 && !slides.last().is_some_and(Vec::is_empty) =>
+            {
+                slides.push(Vec::new());
+            }
+            _ => {}
+        }
+        match &event {
+            MarkdownEvent::Start(_) => depth += 1,
+            MarkdownEvent::End(_) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        slides.last_mut().expect("最低1枚はある").push(event);
+    }
+    let mut sections = String::new();
+    for slide in slides.into_iter().filter(|slide| !slide.is_empty()) {
+        sections.push_str("<section>\n");
+        html::push_html(&mut sections, slide.into_iter());
+        sections.push_str("</section>\n");
+    }
+    templates::render(SLIDES_TEMPLATE, &[("title", &escape_html(title)), ("slides", &sections)])
+}
+
+/// ファイルを読み込んでdata URIにする。画像として扱えない拡張子や読めないファイルならNone
+fn data_uri(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    image_data_uri(extension, &fs::read(path).ok()?)
+}
+
+/// 画像のバイト列を拡張子に合うdata URIにする。画像として扱えない拡張子ならNone
+pub fn image_data_uri(extension: &str, bytes: &[u8]) -> Option<String> {
+    let mime = match extension.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        _ => return None,
+    };
+    Some(format!("data:{};base64,{}", mime, base64(bytes)))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (u32::from(chunk[0]) << 16)
+            | (u32::from(*chunk.get(1).unwrap_or(&0)) << 8)
+            | u32::from(*chunk.get(2).unwrap_or(&0));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,113 @@
+// --- 外部フィルター ---
+//
+// 設定の `filters` に書いたコマンドを順に実行し、標準入力に渡したMarkdownを
+// 標準出力で受け取った内容に置き換えてからレンダリングする。
+// インクルードの展開やテンプレート処理、秘密情報の伏せ字などをレンダラーの外で行うためのもの。
+// 設定の `[preview]` に書いた拡張子ごとのコマンドも同じように実行し、出力したMarkdownや画像をプレビューする。
+
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::Instant,
+};
+
+/// フィルターに処理中のファイルを伝える環境変数
+pub const FILE_ENV: &str = "MD_PREVIEW_FILE";
+
+/// `filters` を順に通したMarkdownを返す
+pub fn apply(markdown: String, filters: &[String], file: Option<&Path>) -> io::Result<String> {
+    filters.iter().try_fold(markdown, |input, filter| {
+        let output = run("フィルター", filter, input.into_bytes(), file)?;
+        String::from_utf8(output).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("フィルター `{}` の出力がUTF-8ではありません", filter)))
+    })
+}
+
+/// プレビューのためのコマンドの出力
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Converted {
+    Markdown(String),
+    /// PNGなどの画像（拡張子とバイト列）
+    Image(&'static str, Vec<u8>),
+}
+
+/// `[preview]` のコマンドで `file` を変換する。出力が画像ならそのまま、それ以外はMarkdownとして返す
+pub fn convert(command: &str, file: &Path) -> io::Result<Converted> {
+    let output = run("プレビューコマンド", command, Vec::new(), Some(file))?;
+    if let Some(extension) = image_extension(&output) {
+        return Ok(Converted::Image(extension, output));
+    }
+    String::from_utf8(output)
+        .map(Converted::Markdown)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("プレビューコマンド `{}` の出力がUTF-8でも画像でもありません", command)))
+}
+
+/// 先頭のバイト列から画像の形式を見分ける
+pub fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"GIF8") {
+        Some("gif")
+    } else {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(256)]).to_string();
+        let head = head.trim_start();
+        (head.starts_with("<svg") || head.starts_with("<?xml") && head.contains("<svg")).then_some("svg")
+    }
+}
+
+/// `kind`（「フィルター」など）はエラーの表示に使う
+fn run(kind: &str, filter: &str, input: Vec<u8>, file: Option<&Path>) -> io::Result<Vec<u8>> {
+    let started = Instant::now();
+    let mut command = shell_command(filter);
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(file) = file {
+        command.env(FILE_ENV, file);
+        if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            command.current_dir(dir);
+        }
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{} `{}` を起動できません: {}", kind, filter, e)))?;
+
+    // 大きな入力でパイプが詰まらないよう、書き込みは別スレッドで行う
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    // 標準出力と標準エラー出力は並行して読む（片方のパイプが詰まって止まらないように）
+    let output = child.wait_with_output()?;
+    // フィルターが入力を最後まで読まずに終了した場合の書き込みエラーは無視する
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} `{}` が失敗しました ({}): {}",
+            kind,
+            filter,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    tracing::debug!(kind, command = filter, elapsed = ?started.elapsed(), "ran external command");
+    Ok(output.stdout)
+}
+
+#[cfg(windows)]
+fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
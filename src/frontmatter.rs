@@ -0,0 +1,82 @@
+// --- YAMLフロントマター ---
+//
+// 文書の先頭の `---` で囲まれたブロックから、`key: value` と、`key: [a, b]` / `key:` に続く
+// `- a` 形式のリストだけを読み取る簡易パーサー。
+
+/// 先頭のフロントマターの中身を返す。無ければNone
+pub fn block(markdown: &str) -> Option<&str> {
+    let markdown = markdown.strip_prefix('\u{feff}').unwrap_or(markdown);
+    let rest = markdown.strip_prefix("---")?;
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(&rest[..offset]);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// `key: [a, b]`、`key: a, b`、または続く行の `- a` 形式のリストを返す
+pub fn list(block: &str, key: &str) -> Vec<String> {
+    let mut lines = block.lines();
+    let Some(value) = lines.by_ref().find_map(|line| value_of(line, key)) else {
+        return Vec::new();
+    };
+    let items: Vec<String> = if value.is_empty() {
+        lines
+            .map_while(|line| line.trim_start().strip_prefix("- ").or_else(|| (line.trim() == "-").then_some("")))
+            .map(|item| unquote(item).to_string())
+            .collect()
+    } else {
+        let inner = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).unwrap_or(value);
+        inner.split(',').map(|item| unquote(item).to_string()).collect()
+    };
+    items.into_iter().filter(|item| !item.is_empty()).collect()
+}
+
+/// `key: value` の値を返す。値が空（続く行がリストなど）ならNone
+pub fn scalar(block: &str, key: &str) -> Option<String> {
+    let value = unquote(block.lines().find_map(|line| value_of(line, key))?);
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// トップレベルのすべてのキーと値（リストなら各項目、そうでなければ値そのもの）を、書かれている順に返す
+pub fn fields(block: &str) -> Vec<(String, Vec<String>)> {
+    let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+    for line in block.lines() {
+        if line.starts_with(char::is_whitespace) || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() || key.starts_with('-') || fields.iter().any(|(name, _)| name == key) {
+            continue;
+        }
+        // `a, b` のような値はリストとして読むと分かれてしまうので、`[a, b]` と続く行のリストだけを項目に分ける
+        let value = value.trim();
+        let values = if value.is_empty() || value.starts_with('[') { list(block, key) } else { scalar(block, key).into_iter().collect() };
+        fields.push((key.to_string(), values));
+    }
+    fields
+}
+
+/// トップレベルの `key:` の行なら値の部分を返す
+fn value_of<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (name, value) = line.split_once(':')?;
+    (name.trim() == key).then(|| value.trim())
+}
+
+fn unquote(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .or_else(|| text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')))
+        .unwrap_or(text)
+}
@@ -0,0 +1,160 @@
+// --- gitの履歴 ---
+//
+// 表示中の文書を変更したコミットを `git log` で一覧にし、その時点の内容を `git show` で読み込む。
+// 名前を変えたファイルも追えるよう、コミットごとにその時点のパスを覚えておく。
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::workspace;
+
+/// 文書を変更したコミット
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// 短縮したハッシュ
+    pub hash: String,
+    /// `2024-05-01` の形の日付
+    pub date: String,
+    pub summary: String,
+    /// そのコミットでのファイルのパス（リポジトリのルートから `/` 区切り）
+    pub path: String,
+}
+
+/// コミットの区切り（`git log` の出力でコミットの前に置く）
+const RECORD_SEPARATOR: char = '\u{1e}';
+
+/// `path` のファイルを変更したコミットを新しい順に返す
+pub fn log(path: &Path) -> io::Result<Vec<Commit>> {
+    let (root, relative) = locate(path)?;
+    let output = run(
+        &root,
+        &["log", "--follow", "--name-only", "--date=short", "--format=%x1e%h%x09%ad%x09%s", "--", &relative],
+    )?;
+    Ok(parse_log(&output))
+}
+
+/// コミットの時点でのファイルの内容
+pub fn show(path: &Path, commit: &Commit) -> io::Result<String> {
+    let (root, _) = locate(path)?;
+    run(&root, &["show", &format!("{}:{}", commit.hash, commit.path)])
+}
+
+/// `git log --name-only --format=%x1e%h%x09%ad%x09%s` の出力を読む
+pub fn parse_log(output: &str) -> Vec<Commit> {
+    output
+        .split(RECORD_SEPARATOR)
+        .filter_map(|record| {
+            let mut lines = record.lines();
+            let mut fields = lines.next()?.splitn(3, '\t');
+            let hash = fields.next()?.to_string();
+            let date = fields.next()?.to_string();
+            let summary = fields.next().unwrap_or("").to_string();
+            let path = lines.map(str::trim).find(|line| !line.is_empty())?.to_string();
+            Some(Commit { hash, date, summary, path })
+        })
+        .collect()
+}
+
+/// ファイルを含むリポジトリのルートと、ルートからのパス
+fn locate(path: &Path) -> io::Result<(PathBuf, String)> {
+    let not_tracked = || io::Error::new(io::ErrorKind::NotFound, format!("{} はgitリポジトリの中にありません", path.display()));
+    let path = dunce::canonicalize(path)?;
+    let root = workspace::find_root(&path).ok_or_else(not_tracked)?;
+    let relative = path.strip_prefix(&root).map_err(|_| not_tracked())?;
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    Ok((root, relative))
+}
+
+fn run(root: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(root).args(args).stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("git が失敗しました: {}", message)));
+    }
+    String::from_utf8(output.stdout).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "UTF-8のテキストではありません"))
+}
+
+/// ファイルの最後の変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastChange {
+    pub path: PathBuf,
+    /// 作者の日時（Unix時刻の秒）と、`2024-05-01` の形の日付
+    pub time: u64,
+    pub date: String,
+}
+
+/// `dir` の下でgitが管理しているファイルそれぞれの最後の変更。削除されたファイルも含む
+pub fn last_changes(dir: &Path) -> io::Result<Vec<LastChange>> {
+    let (root, relative) = locate(dir)?;
+    let pathspec = if relative.is_empty() { ".".to_string() } else { relative };
+    let output = run(&root, &["log", "--name-only", "--date=short", "--format=%x1e%at%x09%ad", "--", &pathspec])?;
+    Ok(parse_last_changes(&output).into_iter().map(|change| LastChange { path: root.join(change.path), ..change }).collect())
+}
+
+/// `git log --name-only --format=%x1e%at%x09%ad` の出力から、ファイルごとに最も新しい変更を取り出す。パスはルートからのまま
+pub fn parse_last_changes(output: &str) -> Vec<LastChange> {
+    let mut changes: Vec<LastChange> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for record in output.split(RECORD_SEPARATOR) {
+        let mut lines = record.lines();
+        let Some((time, date)) = lines.next().and_then(|header| header.split_once('\t')) else {
+            continue;
+        };
+        let time = time.parse().unwrap_or(0);
+        // 新しい順に並んでいるので、最初に出てきたものが最後の変更
+        for path in lines.map(str::trim).filter(|line| !line.is_empty()) {
+            if seen.insert(path.to_string()) {
+                changes.push(LastChange { path: PathBuf::from(path), time, date: date.to_string() });
+            }
+        }
+    }
+    changes
+}
+
+/// 行ごとの最後の変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// 作者の名前。コミットしていない行ならNone
+    pub author: Option<String>,
+    /// 作者の日時（Unix時刻の秒）
+    pub time: u64,
+}
+
+/// `path` の行ごとの最後の変更。保存していない変更は含めない
+pub fn blame(path: &Path) -> io::Result<Vec<BlameLine>> {
+    let (root, relative) = locate(path)?;
+    Ok(parse_blame(&run(&root, &["blame", "--line-porcelain", "--", &relative])?))
+}
+
+/// `git blame --line-porcelain` の出力を読む
+pub fn parse_blame(output: &str) -> Vec<BlameLine> {
+    let mut lines = Vec::new();
+    let (mut author, mut time) = (None, 0);
+    for line in output.lines() {
+        // 行の中身はタブで始まり、1行分の情報の終わりを表す
+        if line.starts_with('\t') {
+            lines.push(BlameLine { author: author.take(), time });
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = (name != "Not Committed Yet").then(|| name.to_string());
+        } else if let Some(seconds) = line.strip_prefix("author-time ") {
+            time = seconds.parse().unwrap_or(0);
+        }
+    }
+    lines
+}
+
+/// `seconds` 秒前を `3 months ago` のように表す
+pub fn age(seconds: u64) -> String {
+    const DAY: u64 = 24 * 60 * 60;
+    let (count, unit) = match seconds / DAY {
+        0 => return "today".to_string(),
+        days @ 1..=13 => (days, "day"),
+        days @ 14..=59 => (days / 7, "week"),
+        days @ 60..=729 => (days / 30, "month"),
+        days => (days / 365, "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
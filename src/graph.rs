@@ -0,0 +1,200 @@
+// --- 文書のリンクのグラフ（`:graph`） ---
+//
+// 索引の文書どうしの相対リンクをグラフにし、1つの文書を中心にリンク元とリンク先を並べて表示する。
+// リンク先を選んでEnterを押すとその文書を中心にし、たどった順に戻れる。
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::vault::{self, Vault};
+
+/// 文書どうしのリンク
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    /// 文書ごとのリンク先の文書（重複と自分へのリンクは除く）。リンクの無い文書も空の一覧で入れる
+    pub outgoing: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// 文書ごとのリンク元の文書
+    pub incoming: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Graph {
+    /// 文書ごとのリンク先からグラフを作る。索引に無いファイルへのリンクは除く
+    pub fn new(links: BTreeMap<PathBuf, Vec<PathBuf>>) -> Self {
+        let mut outgoing = BTreeMap::new();
+        let mut incoming: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+        for (path, targets) in &links {
+            let mut documents: Vec<PathBuf> = Vec::new();
+            for target in targets {
+                if target != path && links.contains_key(target) && !documents.contains(target) {
+                    documents.push(target.clone());
+                    incoming.entry(target.clone()).or_default().push(path.clone());
+                }
+            }
+            outgoing.insert(path.clone(), documents);
+        }
+        Self { outgoing, incoming }
+    }
+
+    pub fn from_vault(vault: &Vault) -> Self {
+        Self::new(
+            vault
+                .documents
+                .iter()
+                .map(|(path, document)| (path.clone(), document.links.iter().map(|(target, _)| target.clone()).collect()))
+                .collect(),
+        )
+    }
+
+    pub fn outgoing(&self, path: &Path) -> &[PathBuf] {
+        self.outgoing.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn incoming(&self, path: &Path) -> &[PathBuf] {
+        self.incoming.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// リンクの数
+    pub fn edges(&self) -> usize {
+        self.outgoing.values().map(Vec::len).sum()
+    }
+
+    /// `dir` の下で、リンク元とリンク先が最も多い文書
+    pub fn hub(&self, dir: &Path) -> Option<&PathBuf> {
+        self.outgoing
+            .iter()
+            .filter(|(path, _)| path.starts_with(dir))
+            .max_by_key(|(path, targets)| (targets.len() + self.incoming(path).len(), std::cmp::Reverse(*path)))
+            .map(|(path, _)| path)
+    }
+
+    /// リンク元もリンク先も無い文書
+    pub fn orphans(&self) -> Vec<&PathBuf> {
+        self.outgoing
+            .iter()
+            .filter(|(path, targets)| targets.is_empty() && self.incoming(path).is_empty())
+            .map(|(path, _)| path)
+            .collect()
+    }
+}
+
+/// グラフの列。中心の文書の左にリンク元、右にリンク先を並べる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Incoming,
+    Outgoing,
+}
+
+/// `:graph` で開く、1つの文書を中心にしたリンクの表示
+#[derive(Debug, Clone)]
+pub struct GraphBrowser {
+    pub root: PathBuf,
+    pub graph: Graph,
+    /// 中心の文書
+    pub current: PathBuf,
+    /// 選択している列
+    pub column: Column,
+    /// リンク元とリンク先の列それぞれで選択している位置
+    pub selected: [usize; 2],
+    /// これまで中心にした文書（`back` で戻る）
+    pub history: Vec<PathBuf>,
+}
+
+impl GraphBrowser {
+    /// `start` を中心にして開く。索引に無ければNone
+    pub fn new(root: PathBuf, graph: Graph, start: &Path) -> Option<Self> {
+        let current = vault::normalize(start);
+        if !graph.outgoing.contains_key(&current) {
+            return None;
+        }
+        let mut browser = Self { root, graph, current, column: Column::Outgoing, selected: [0, 0], history: Vec::new() };
+        browser.focus_non_empty();
+        Some(browser)
+    }
+
+    /// 列の文書
+    pub fn entries(&self, column: Column) -> &[PathBuf] {
+        match column {
+            Column::Incoming => self.graph.incoming(&self.current),
+            Column::Outgoing => self.graph.outgoing(&self.current),
+        }
+    }
+
+    /// 列で選択している位置
+    pub fn selection(&self, column: Column) -> usize {
+        self.selected[column as usize]
+    }
+
+    /// 選択している文書
+    pub fn selected(&self) -> Option<&PathBuf> {
+        self.entries(self.column).get(self.selection(self.column))
+    }
+
+    /// `root` からの相対パスで表した名前
+    pub fn name<'a>(&self, path: &'a Path) -> std::borrow::Cow<'a, str> {
+        path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy()
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.entries(self.column).len();
+        if len == 0 {
+            return;
+        }
+        let index = &mut self.selected[self.column as usize];
+        *index = (*index as isize + delta).clamp(0, len as isize - 1) as usize;
+    }
+
+    /// 列を切り替える。切り替え先が空なら切り替えない
+    pub fn select_column(&mut self, column: Column) {
+        if !self.entries(column).is_empty() {
+            self.column = column;
+        }
+    }
+
+    pub fn switch_column(&mut self) {
+        self.select_column(match self.column {
+            Column::Incoming => Column::Outgoing,
+            Column::Outgoing => Column::Incoming,
+        });
+    }
+
+    /// 選択している文書を中心にする。選択が無ければ false を返す
+    pub fn enter(&mut self) -> bool {
+        let Some(path) = self.selected().cloned() else {
+            return false;
+        };
+        let previous = std::mem::replace(&mut self.current, path);
+        self.history.push(previous);
+        self.selected = [0, 0];
+        self.focus_non_empty();
+        true
+    }
+
+    /// 1つ前に中心にしていた文書に戻り、そこから進んだ文書を選択する。戻る先が無ければ false を返す
+    pub fn back(&mut self) -> bool {
+        let Some(previous) = self.history.pop() else {
+            return false;
+        };
+        let came_from = std::mem::replace(&mut self.current, previous);
+        self.selected = [0, 0];
+        self.focus_non_empty();
+        for column in [Column::Outgoing, Column::Incoming] {
+            if let Some(index) = self.entries(column).iter().position(|path| *path == came_from) {
+                self.column = column;
+                self.selected[column as usize] = index;
+                break;
+            }
+        }
+        true
+    }
+
+    /// リンク先が無ければリンク元の列を選択する
+    fn focus_non_empty(&mut self) {
+        self.column = if self.entries(Column::Outgoing).is_empty() && !self.entries(Column::Incoming).is_empty() {
+            Column::Incoming
+        } else {
+            Column::Outgoing
+        };
+    }
+}
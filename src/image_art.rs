@@ -0,0 +1,113 @@
+// --- ブロック文字による画像の近似表示 ---
+//
+// 端末で画像を表示するプロトコルには対応していないため、`chafa` が使えるときは
+// 画像を色付きのブロック文字に変換して表示する。`chafa` の出力するANSIの色指定を
+// ratatuiのスタイルに置き換えて取り込む。
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use ratatui::prelude::*;
+
+/// 画像を幅 `width` 列、高さ `height` 行以内のブロック文字に変換する。変換できなければ None
+pub fn render(path: &Path, width: u16, height: u16) -> Option<Vec<Line<'static>>> {
+    if !path.is_file() {
+        return None;
+    }
+    let output = Command::new("chafa")
+        .arg("--format=symbols")
+        .arg("--symbols=block")
+        .arg("--colors=full")
+        .arg("--animate=off")
+        .arg(format!("--size={}x{}", width.max(1), height.max(1)))
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let lines = parse_ansi(&String::from_utf8_lossy(&output.stdout));
+    (!lines.is_empty()).then_some(lines)
+}
+
+/// SGRの色指定を含むテキストをスタイル付きの行に変換する。その他のエスケープシーケンスは捨てる
+fn parse_ansi(input: &str) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    for raw_line in input.lines() {
+        let mut spans = Vec::new();
+        let mut style = Style::default();
+        let mut text = String::new();
+        let mut chars = raw_line.chars();
+        while let Some(c) = chars.next() {
+            if c != '\x1b' {
+                text.push(c);
+                continue;
+            }
+            if chars.next() != Some('[') {
+                continue;
+            }
+            let mut params = String::new();
+            let mut terminator = None;
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    terminator = Some(c);
+                    break;
+                }
+                params.push(c);
+            }
+            if terminator != Some('m') {
+                continue;
+            }
+            if !text.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut text), style));
+            }
+            style = apply_sgr(style, &params);
+        }
+        if !text.is_empty() {
+            spans.push(Span::styled(text, style));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let codes: Vec<u16> = params.split(';').map(|code| code.parse().unwrap_or(0)).collect();
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 => style = Style::default(),
+            7 => style = style.add_modifier(Modifier::REVERSED),
+            27 => style = style.remove_modifier(Modifier::REVERSED),
+            39 => style.fg = None,
+            49 => style.bg = None,
+            code @ (38 | 48) => {
+                let color = match codes.get(i + 1) {
+                    Some(2) if i + 4 < codes.len() => {
+                        let color = Color::Rgb(codes[i + 2] as u8, codes[i + 3] as u8, codes[i + 4] as u8);
+                        i += 4;
+                        Some(color)
+                    }
+                    Some(5) if i + 2 < codes.len() => {
+                        let color = Color::Indexed(codes[i + 2] as u8);
+                        i += 2;
+                        Some(color)
+                    }
+                    _ => None,
+                };
+                if code == 38 {
+                    style.fg = color;
+                } else {
+                    style.bg = color;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    style
+}
@@ -0,0 +1,181 @@
+// --- インクルード指令 ---
+//
+// `<!-- include: other.md -->` または mdBook 形式の `{{#include other.md}}` だけの行を、
+// 参照先ファイルの内容に置き換える。パスはその指令を書いたファイルからの相対パス。
+// 展開は再帰的に行い、同じファイルを循環して読み込む場合は打ち切る。
+// 読み込めるのはワークスペース（gitリポジトリ、無ければ最初のファイルのディレクトリ）の中のファイルだけで、
+// 取り込んだファイルの相対リンクと画像は、取り込む側のファイルからのパスに書き換える。
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::workspace;
+
+/// 展開した後の1行の出どころ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// 開いたファイルでの行番号（1始まり）。取り込んだ行ならインクルード指令の行
+    pub line: usize,
+    /// 取り込んだ行なら、取り込んだファイルとそのファイルでの行番号
+    pub included: Option<(PathBuf, usize)>,
+}
+
+impl SourceLine {
+    pub fn new(line: usize) -> Self {
+        Self { line, included: None }
+    }
+}
+
+/// `file` の内容 `markdown` に含まれるインクルード指令を展開し、展開した後の行ごとの出どころを返す
+pub fn expand(markdown: &str, file: &Path) -> (String, Vec<SourceLine>) {
+    let file = canonical(file);
+    let root = workspace::find_root(&file).unwrap_or_else(|| file.parent().unwrap_or(Path::new("")).to_path_buf());
+    let mut stack = vec![file.clone()];
+    expand_inner(markdown, &file, &root, &mut stack)
+}
+
+fn expand_inner(markdown: &str, file: &Path, root: &Path, stack: &mut Vec<PathBuf>) -> (String, Vec<SourceLine>) {
+    let base = file.parent().unwrap_or(Path::new(""));
+    let mut output = String::with_capacity(markdown.len());
+    let mut lines = Vec::new();
+    let mut fence: Option<String> = None;
+
+    for (index, line) in markdown.split_inclusive('\n').enumerate() {
+        let number = index + 1;
+        // コードブロックの中の指令はそのまま表示する
+        let trimmed = line.trim();
+        if let Some(marker) = fence_marker(trimmed) {
+            match &fence {
+                Some(open) if marker.starts_with(open.as_str()) => fence = None,
+                None => fence = Some(marker),
+                _ => {}
+            }
+        }
+        let target = if fence.is_none() { directive(trimmed) } else { None };
+        let Some(target) = target else {
+            output.push_str(line);
+            lines.push(SourceLine::new(number));
+            continue;
+        };
+
+        let path = canonical(&base.join(target));
+        let message = if !path.starts_with(root) {
+            format!("> include: `{}` はワークスペースの外にあるため展開しません\n", target)
+        } else if stack.contains(&path) {
+            format!("> include: `{}` は循環参照のため展開しません\n", target)
+        } else {
+            match fs::read_to_string(&path) {
+                Ok(included) => {
+                    stack.push(path.clone());
+                    let (expanded, included_lines) = expand_inner(&included, &path, root, stack);
+                    stack.pop();
+                    let expanded = rebase_links(&expanded, Path::new(target).parent().unwrap_or(Path::new("")));
+                    output.push_str(&expanded);
+                    if !expanded.ends_with('\n') {
+                        output.push('\n');
+                    }
+                    // 取り込んだ行は、入れ子で取り込んだものも含めて元のファイルの行を覚えておく
+                    lines.extend(included_lines.into_iter().map(|source| SourceLine {
+                        line: number,
+                        included: Some(source.included.unwrap_or((path.clone(), source.line))),
+                    }));
+                    if expanded.is_empty() {
+                        lines.push(SourceLine::new(number));
+                    }
+                    continue;
+                }
+                Err(e) => format!("> include: `{}` を読み込めません: {}\n", target, e),
+            }
+        };
+        output.push_str(&message);
+        lines.push(SourceLine::new(number));
+    }
+    (output, lines)
+}
+
+/// 取り込んだファイルの相対リンクと画像（参照形式の定義を含む）の前に、取り込む側から見たそのファイルのディレクトリ `dir` を付ける
+fn rebase_links(markdown: &str, dir: &Path) -> String {
+    if dir.as_os_str().is_empty() {
+        return markdown.to_string();
+    }
+    // 空白を含むディレクトリでも `(...)` の中に書けるよう、区切りは `/` にして空白は `%20` にする
+    let prefix = dir
+        .components()
+        .map(|component| match component {
+            Component::ParentDir => "..".to_string(),
+            component => component.as_os_str().to_string_lossy().replace(' ', "%20"),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+        + "/";
+    let parser = Parser::new_ext(markdown, Options::all());
+    // 行き先の書き始めの位置（`](` や `]:` の後）
+    let mut starts: Vec<usize> = parser
+        .reference_definitions()
+        .iter()
+        .filter(|(_, definition)| is_relative(&definition.dest))
+        .filter_map(|(_, definition)| destination_start(markdown, definition.span.clone(), "]:"))
+        .collect();
+    for (event, range) in parser.into_offset_iter() {
+        if let Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) = event
+            && is_relative(&dest_url)
+            && let Some(start) = destination_start(markdown, range, "](")
+        {
+            starts.push(start);
+        }
+    }
+    starts.sort_unstable();
+    starts.dedup();
+    let mut rebased = markdown.to_string();
+    for start in starts.into_iter().rev() {
+        rebased.insert_str(start, &prefix);
+    }
+    rebased
+}
+
+/// `range` の中で最後の `separator` に続く行き先の書き始めの位置。`<...>` で囲んでいれば `<` の後
+fn destination_start(markdown: &str, range: std::ops::Range<usize>, separator: &str) -> Option<usize> {
+    let source = &markdown[range.clone()];
+    let after = source.rfind(separator)? + separator.len();
+    let rest = &source[after..];
+    let skipped = rest.len() - rest.trim_start().len();
+    let start = range.start + after + skipped;
+    Some(if markdown[start..].starts_with('<') { start + 1 } else { start })
+}
+
+/// ファイルからの相対パスのリンクかどうか（URLやアンカーだけのリンク、絶対パスでないもの）
+fn is_relative(url: &str) -> bool {
+    let scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+    !url.is_empty() && !scheme && !url.starts_with(['/', '#', '~', '\\'])
+}
+
+/// 行全体がインクルード指令なら参照先のパスを返す
+fn directive(line: &str) -> Option<&str> {
+    let target = line
+        .strip_prefix("<!--")
+        .and_then(|rest| rest.strip_suffix("-->"))
+        .and_then(|inner| inner.trim().strip_prefix("include:"))
+        .or_else(|| {
+            line.strip_prefix("{{#include")
+                .and_then(|rest| rest.strip_suffix("}}"))
+        })?
+        .trim();
+    (!target.is_empty()).then_some(target)
+}
+
+/// コードフェンスの開始・終了行ならフェンス記号を返す
+fn fence_marker(line: &str) -> Option<String> {
+    let marker: String = line.chars().take_while(|c| *c == '`' || *c == '~').collect();
+    (marker.len() >= 3 && marker.chars().all(|c| c == marker.chars().next().unwrap())).then_some(marker)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    dunce::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,97 @@
+// --- 端末からの入力 ---
+//
+// キー入力などの端末のイベントを別スレッドで読み、ほかの処理の結果と同じチャネルに送る。
+// UIスレッドはそのチャネルを待つだけになり、入力を待つ間もほかの結果を受け取れる。
+// エディターの起動中や一時停止中は、入力を横取りしないよう読み込みを止める。
+
+use std::{
+    io,
+    sync::{Arc, Condvar, Mutex, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+use crossterm::event::{self, Event};
+
+/// 読み込みを止める要求に応じるまでの最大の待ち時間
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+struct PauseState {
+    /// 読み込みを止めるよう求められているか
+    requested: bool,
+    /// 読み込みを止めて待機している（またはスレッドが終了した）か
+    parked: bool,
+}
+
+pub struct InputReader {
+    pause: Arc<(Mutex<PauseState>, Condvar)>,
+}
+
+/// `InputReader::pause` の間だけ存在し、破棄されると読み込みを再開する
+pub struct PauseGuard<'a> {
+    reader: &'a InputReader,
+}
+
+impl InputReader {
+    /// 端末のイベントを読むスレッドを起動する。読み込みに失敗したらそのエラーを送って終了し、
+    /// 受け取り側が無くなったときも終了する
+    pub fn spawn<E: From<io::Result<Event>> + Send + 'static>(sender: Sender<E>) -> Self {
+        let pause = Arc::new((Mutex::new(PauseState::default()), Condvar::new()));
+        let shared = Arc::clone(&pause);
+        thread::spawn(move || {
+            read_events(&sender, &shared);
+            // 終了後に止める要求が来ても待ち続けないよう、止まっている扱いにする
+            let (lock, condvar) = &*shared;
+            lock.lock().unwrap_or_else(|e| e.into_inner()).parked = true;
+            condvar.notify_all();
+        });
+        Self { pause }
+    }
+
+    /// 入力の読み込みを止め、読み込み中のスレッドが止まるまで待つ
+    pub fn pause(&self) -> PauseGuard<'_> {
+        let (lock, condvar) = &*self.pause;
+        let mut state = lock.lock().unwrap_or_else(|e| e.into_inner());
+        state.requested = true;
+        while !state.parked {
+            state = condvar.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        PauseGuard { reader: self }
+    }
+}
+
+impl Drop for PauseGuard<'_> {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.reader.pause;
+        lock.lock().unwrap_or_else(|e| e.into_inner()).requested = false;
+        condvar.notify_all();
+    }
+}
+
+fn read_events<E: From<io::Result<Event>>>(sender: &Sender<E>, pause: &(Mutex<PauseState>, Condvar)) {
+    let (lock, condvar) = pause;
+    loop {
+        {
+            let mut state = lock.lock().unwrap_or_else(|e| e.into_inner());
+            if state.requested {
+                state.parked = true;
+                condvar.notify_all();
+                while state.requested {
+                    state = condvar.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                state.parked = false;
+            }
+        }
+        // 止める要求に応じられるよう、時間を区切って待つ
+        let event = match event::poll(POLL_INTERVAL) {
+            Ok(true) => event::read(),
+            Ok(false) => continue,
+            Err(e) => Err(e),
+        };
+        let failed = event.is_err();
+        if sender.send(event.into()).is_err() || failed {
+            return;
+        }
+    }
+}
@@ -0,0 +1,23 @@
+// --- 日付ごとのノート ---
+
+use std::{fs, io, path::PathBuf};
+
+use crate::{config::Config, templates};
+
+/// 今日のノートのパスを返す。まだ無ければテンプレートから作る
+pub fn today_note(config: &Config) -> io::Result<PathBuf> {
+    let date = templates::today();
+    let dir = templates::expand_home(&config.journal_dir);
+    let path = dir.join(format!("{}.md", date));
+    if path.exists() {
+        return Ok(path);
+    }
+    let template = if config.journal_template.is_empty() {
+        "# {{date}}\n\n".to_string()
+    } else {
+        templates::load(&config.journal_template)?
+    };
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, templates::render(&template, &[("date", &date), ("title", &date)]))?;
+    Ok(path)
+}
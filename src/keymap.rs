@@ -0,0 +1,266 @@
+// --- キー割り当て ---
+//
+// 画面ごとに「どのキー（の並び）でどの操作をするか」を表にまとめる。
+// イベントループはキーをこの表で操作に変換してから画面に渡すので、
+// 画面の側はキーではなく操作だけを扱えばよい。`gr` のような2文字以上の並びも同じ表で扱う。
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::KeymapProfile;
+
+/// キー割り当ての対象の画面
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// どの画面でも、入力欄に文字を入力している間も有効
+    Global,
+    Explorer,
+    Preview,
+    Log,
+}
+
+/// キーに割り当てる操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Suspend,
+    /// 画面を閉じる
+    Close,
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    /// `:` のコマンド入力を始める
+    Command,
+    // --- プレビュー ---
+    FastScrollUp,
+    FastScrollDown,
+    /// 設定の `scroll_step` に関係なく1行だけ動く
+    LineUp,
+    LineDown,
+    HalfPageUp,
+    HalfPageDown,
+    ScrollLeft,
+    ScrollRight,
+    ToggleWrap,
+    /// 途中までしか読み込んでいないファイルを全部読み込む
+    LoadAll,
+    SearchForward,
+    SearchBackward,
+    SearchNext,
+    SearchPrevious,
+    FollowLink,
+    OpenImage,
+    NextFootnote,
+    PreviousFootnote,
+    /// フッターとポップアップの枠線を隠す表示を切り替える
+    ToggleChrome,
+    /// フッターに文書のタイトルとパスのどちらを表示するかを切り替える
+    TogglePath,
+    /// 表示中の位置に対応する行を開いて、元のファイルをエディターで編集する
+    EditSource,
+    /// 同じ文書を上下2つに分けて表示する
+    ToggleSplit,
+    // --- エクスプローラー ---
+    Open,
+    Parent,
+    Back,
+    Forward,
+    /// 直前にいたディレクトリ
+    PreviousDirectory,
+    HomeDirectory,
+    WorkspaceRoot,
+    ToggleMark,
+    ClearMarks,
+    /// 操作するペイン（プレビューでは上下に分けた表示）を切り替える
+    NextPane,
+}
+
+/// 修飾キーを含めたキー。文字キーのShiftは文字そのもので区別するので持たない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// 修飾キーを押していないキー
+    pub fn plain(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::NONE)
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+/// キー（の並び）を表から引いた結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// 並びの途中なので、次のキーを待つ
+    Pending,
+    Unbound,
+}
+
+struct Binding {
+    mode: Mode,
+    keys: Vec<Key>,
+    action: Action,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    /// プロファイルの既定の割り当て
+    pub fn new(profile: KeymapProfile) -> Self {
+        use KeyCode::*;
+        let mut keymap = Self { bindings: Vec::new() };
+        let ctrl = |c| Key::new(Char(c), KeyModifiers::CONTROL);
+        let alt = |code| Key::new(code, KeyModifiers::ALT);
+        let shift = |code| Key::new(code, KeyModifiers::SHIFT);
+
+        keymap.bind(Mode::Global, &[ctrl('c')], Action::Quit);
+        keymap.bind(Mode::Global, &[ctrl('z')], Action::Suspend);
+
+        for (codes, action) in [
+            (&[Down, Char('j')][..], Action::MoveDown),
+            (&[Up, Char('k')], Action::MoveUp),
+            (&[PageDown], Action::PageDown),
+            (&[PageUp], Action::PageUp),
+            (&[Home], Action::Top),
+            (&[End], Action::Bottom),
+            (&[Right, Char('l'), Enter], Action::Open),
+            (&[Left, Char('h'), Backspace], Action::Parent),
+            (&[Char('H')], Action::Back),
+            (&[Char('L')], Action::Forward),
+            (&[Char('-')], Action::PreviousDirectory),
+            (&[Char('~')], Action::HomeDirectory),
+            (&[Char(' ')], Action::ToggleMark),
+            (&[Esc], Action::ClearMarks),
+            (&[Char(':')], Action::Command),
+            (&[Tab], Action::NextPane),
+        ] {
+            keymap.bind_each(Mode::Explorer, codes, action);
+        }
+        keymap.bind(Mode::Explorer, &[alt(Left)], Action::Back);
+        keymap.bind(Mode::Explorer, &[alt(Right)], Action::Forward);
+        keymap.bind(Mode::Explorer, &[Key::plain(Char('g')), Key::plain(Char('r'))], Action::WorkspaceRoot);
+
+        for (codes, action) in [
+            (&[Char('q')][..], Action::Close),
+            (&[Char('K')], Action::FastScrollUp),
+            (&[Char('J')], Action::FastScrollDown),
+            (&[Up, Char('k')], Action::MoveUp),
+            (&[Down, Char('j')], Action::MoveDown),
+            (&[Left], Action::ScrollLeft),
+            (&[Right], Action::ScrollRight),
+            (&[Char('w')], Action::ToggleWrap),
+            (&[Char('L')], Action::LoadAll),
+            (&[PageDown], Action::PageDown),
+            (&[PageUp], Action::PageUp),
+            (&[Home], Action::Top),
+            (&[End], Action::Bottom),
+            (&[Char('/')], Action::SearchForward),
+            (&[Char('?')], Action::SearchBackward),
+            (&[Char('n')], Action::SearchNext),
+            (&[Char('N')], Action::SearchPrevious),
+            (&[Char(':')], Action::Command),
+            (&[Char('o')], Action::OpenImage),
+            (&[Char(']')], Action::NextFootnote),
+            (&[Char('[')], Action::PreviousFootnote),
+            (&[Char('m')], Action::ToggleChrome),
+            (&[Char('p')], Action::TogglePath),
+            (&[Char('v')], Action::EditSource),
+            (&[Char('s')], Action::ToggleSplit),
+            (&[Tab], Action::NextPane),
+        ] {
+            keymap.bind_each(Mode::Preview, codes, action);
+        }
+        keymap.bind(Mode::Preview, &[shift(Up)], Action::FastScrollUp);
+        keymap.bind(Mode::Preview, &[shift(Down)], Action::FastScrollDown);
+        match profile {
+            KeymapProfile::Default => keymap.bind_each(Mode::Preview, &[Char('f')], Action::FollowLink),
+            // lessプロファイルでは `f` がページ送りなので `F` を使う
+            KeymapProfile::Less => {
+                for (codes, action) in [
+                    (&[Char('F')][..], Action::FollowLink),
+                    (&[Char(' '), Char('f')], Action::PageDown),
+                    (&[Char('b')], Action::PageUp),
+                    (&[Char('d')], Action::HalfPageDown),
+                    (&[Char('u')], Action::HalfPageUp),
+                    (&[Char('g'), Char('<')], Action::Top),
+                    (&[Char('G'), Char('>')], Action::Bottom),
+                    (&[Enter, Char('e')], Action::LineDown),
+                    (&[Char('y')], Action::LineUp),
+                ] {
+                    keymap.bind_each(Mode::Preview, codes, action);
+                }
+            }
+        }
+
+        for (codes, action) in [
+            (&[Esc, Char('q')][..], Action::Close),
+            (&[Down, Char('j')], Action::MoveDown),
+            (&[Up, Char('k')], Action::MoveUp),
+            (&[PageDown, Char(' ')], Action::PageDown),
+            (&[PageUp, Char('b')], Action::PageUp),
+            (&[Home, Char('g')], Action::Top),
+            (&[End, Char('G')], Action::Bottom),
+        ] {
+            keymap.bind_each(Mode::Log, codes, action);
+        }
+        keymap
+    }
+
+    /// キーの並びに操作を割り当てる。同じ並びに割り当て済みなら置き換える
+    pub fn bind(&mut self, mode: Mode, keys: &[Key], action: Action) {
+        self.bindings.retain(|binding| binding.mode != mode || binding.keys != keys);
+        self.bindings.push(Binding { mode, keys: keys.to_vec(), action });
+    }
+
+    /// 修飾キーなしのキーそれぞれに同じ操作を割り当てる
+    fn bind_each(&mut self, mode: Mode, codes: &[KeyCode], action: Action) {
+        for &code in codes {
+            self.bind(mode, &[Key::plain(code)], action);
+        }
+    }
+
+    /// 押されたキーの並びに割り当てられた操作を引く。修飾キー付きのキーに割り当てが無ければ、
+    /// 修飾キーなしの割り当てを使う（Ctrl-j も `j` として扱う）
+    pub fn lookup(&self, mode: Mode, keys: &[Key]) -> Lookup {
+        let found = self.lookup_exact(mode, keys);
+        match keys.split_last() {
+            Some((last, rest)) if found == Lookup::Unbound && last.modifiers != KeyModifiers::NONE => {
+                let mut keys = rest.to_vec();
+                keys.push(Key::plain(last.code));
+                self.lookup_exact(mode, &keys)
+            }
+            _ => found,
+        }
+    }
+
+    fn lookup_exact(&self, mode: Mode, keys: &[Key]) -> Lookup {
+        let mut pending = false;
+        for binding in self.bindings.iter().filter(|binding| binding.mode == mode) {
+            if binding.keys == keys {
+                return Lookup::Action(binding.action);
+            }
+            pending |= binding.keys.len() > keys.len() && binding.keys.starts_with(keys);
+        }
+        if pending { Lookup::Pending } else { Lookup::Unbound }
+    }
+}
@@ -0,0 +1,51 @@
+// --- ライブラリ部分 ---
+//
+// バイナリ（main.rs）は引数の解析と端末の準備だけを持ち、TUI（app）やレンダリング・
+// 設定などの処理はこちらにまとめる。tests/ の結合テストからも参照する。
+
+pub mod annotations;
+pub mod app;
+pub mod archive;
+pub mod audit;
+pub mod autolink;
+pub mod bulk;
+pub mod changelog;
+pub mod check;
+pub mod compressed;
+pub mod config;
+pub mod daemon;
+pub mod diff;
+pub mod error_report;
+pub mod export;
+pub mod filter;
+pub mod frontmatter;
+pub mod git;
+pub mod graph;
+pub mod image_art;
+pub mod include;
+pub mod input;
+pub mod journal;
+pub mod keymap;
+pub mod links;
+pub mod listing;
+pub mod logging;
+pub mod metadata;
+pub mod org;
+pub mod pager;
+pub mod plain;
+pub mod platform;
+pub mod readability;
+pub mod render;
+pub mod script;
+pub mod search;
+pub mod serve;
+pub mod settings;
+pub mod shell_words;
+pub mod spell;
+pub mod structure;
+pub mod tags;
+pub mod templates;
+pub mod theme;
+pub mod translation;
+pub mod vault;
+pub mod workspace;
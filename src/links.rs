@@ -0,0 +1,206 @@
+// --- リンクの解決とヒントラベル ---
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// ヒントラベルに使う文字（ホームポジションから順に）
+const HINT_CHARS: &[char] = &['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'q', 'w', 'e', 'r', 't', 'u', 'i', 'o', 'p'];
+
+/// リンクの行き先
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkTarget {
+    /// 同じ文書内の見出し（`#anchor`）
+    Anchor(String),
+    /// ブラウザなどで開くURL
+    External(String),
+    /// 文書からの相対パスで解決したファイルと、その中のアンカー
+    File(PathBuf, Option<String>),
+}
+
+/// `count` 個の2文字のヒントラベルを作る
+pub fn hint_labels(count: usize) -> Vec<String> {
+    HINT_CHARS
+        .iter()
+        .flat_map(|first| HINT_CHARS.iter().map(move |second| format!("{}{}", first, second)))
+        .take(count)
+        .collect()
+}
+
+/// リンク先のURLを解決する。`document` は相対パスの基準にする文書（標準入力ならNone）
+pub fn resolve(url: &str, document: Option<&Path>) -> LinkTarget {
+    if let Some(anchor) = url.strip_prefix('#') {
+        return LinkTarget::Anchor(decode_anchor(anchor));
+    }
+    if url.contains("://") || url.starts_with("mailto:") {
+        return LinkTarget::External(url.to_string());
+    }
+    let (path, anchor) = match url.split_once('#') {
+        Some((path, anchor)) => (path, Some(decode_anchor(anchor))),
+        None => (url, None),
+    };
+    let base = document.and_then(Path::parent).unwrap_or(Path::new(""));
+    LinkTarget::File(base.join(decode_path(path)), anchor)
+}
+
+/// `my%20notes.md` のような `%xx` を元のバイト列に戻してパスにする。
+/// UnixではUTF-8でないファイル名もバイト列のまま扱い、文字列を経由して別のパスにならないようにする
+fn decode_path(path: &str) -> PathBuf {
+    if !path.contains('%') {
+        return PathBuf::from(path);
+    }
+    let bytes = percent_decode(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// `#%E6%A6%82%E8%A6%81` のように `%xx` で書かれたアンカーを元の文字に戻す
+fn decode_anchor(anchor: &str) -> String {
+    if !anchor.contains('%') {
+        return anchor.to_string();
+    }
+    String::from_utf8_lossy(&percent_decode(anchor)).into_owned()
+}
+
+/// `%xx` を元のバイトに戻す。`%` の後が16進数2桁でなければそのまま残す
+fn percent_decode(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    bytes
+}
+
+/// ローカルに無いファイルへのリンクを、`base` にリポジトリのルート `root` からの相対パスを付けたURLにする。
+/// `..` でルートの外を指していればNone
+pub fn web_url(base: &str, path: &Path, root: &Path, anchor: Option<&str>) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.strip_prefix(root).ok()?.components() {
+        match component {
+            Component::Normal(name) => parts.push(encode_path_segment(&name.to_string_lossy())),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let mut url = format!("{}/{}", base.trim_end_matches('/'), parts.join("/"));
+    if let Some(anchor) = anchor {
+        url.push('#');
+        url.push_str(anchor);
+    }
+    Some(url)
+}
+
+/// URLのパスの1区切りとして使えない文字を `%xx` にする
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// GitHubと同じ規則で見出しのテキストからアンカー名を作る
+pub fn slugify(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 見出しのテキストの一覧から、それぞれのアンカー名を作る。
+/// GitHubと同じく、同じ名前の2つ目以降には `-1`、`-2` と番号を付ける
+pub fn heading_anchors(headings: &[String]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    headings
+        .iter()
+        .map(|heading| {
+            let slug = slugify(heading);
+            let count = seen.entry(slug.clone()).or_default();
+            let anchor = if *count == 0 { slug } else { format!("{}-{}", slug, count) };
+            *count += 1;
+            anchor
+        })
+        .collect()
+}
+
+/// GitHubの行へのアンカー（`L120` や範囲の `L120-L130`）なら、最初の行番号を返す
+pub fn line_anchor(anchor: &str) -> Option<usize> {
+    let first = anchor.split_once('-').map_or(anchor, |(first, _)| first);
+    let line: usize = first.strip_prefix(['L', 'l'])?.parse().ok()?;
+    (line > 0).then_some(line)
+}
+
+/// コマンドラインの `file.md:120`（`:120:5` のように列があってもよい）や `file.md#install` を、
+/// ファイルのパスとアンカー（行番号なら `L120`）に分ける。どちらの形でもなければNone
+pub fn split_location(argument: &str) -> Option<(&str, String)> {
+    if let Some((path, anchor)) = argument.rsplit_once('#')
+        && !path.is_empty()
+        && !anchor.is_empty()
+    {
+        return Some((path, anchor.to_string()));
+    }
+    let mut rest = argument;
+    let mut line = None;
+    // 後ろから数字の部分を取り除き、最初の数字を行番号とする
+    while let Some((path, number)) = rest.rsplit_once(':')
+        && !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+    {
+        line = Some(number);
+        rest = path;
+    }
+    let line: usize = line?.parse().ok()?;
+    (!rest.is_empty() && line > 0).then(|| (rest, format!("L{}", line)))
+}
+
+/// `command`（`feh` や `imv -f` など）の引数の最後に `target` を付けて、終了を待たずに起動する
+pub fn open_with(command: &str, target: &OsStr) -> io::Result<()> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or_else(|| io::Error::other("コマンドが空です"))?;
+    Command::new(program)
+        .args(words)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
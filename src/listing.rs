@@ -0,0 +1,49 @@
+// --- ディレクトリの一覧 ---
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+
+use crate::{archive, platform};
+
+/// エクスプローラーに表示するエントリ
+pub struct Entry {
+    pub path: PathBuf,
+    /// 描画のたびにファイルシステムへ問い合わせないよう、読み込み時に判定しておく
+    pub is_dir: bool,
+}
+
+/// ディレクトリ直下のエントリを順に返す。`hide_gitignored` なら .gitignore に一致するものを除く。
+/// 空のパスはドライブの一覧（Windowsでルートより上に移動したとき）を、アーカイブの中のパスはその中身を表す
+pub fn read_entries(path: &Path, hide_gitignored: bool) -> io::Result<Box<dyn Iterator<Item = Entry>>> {
+    if path.as_os_str().is_empty() {
+        return Ok(Box::new(platform::drives().into_iter().map(|path| Entry { path, is_dir: true })));
+    }
+    if let Some((archive, inner)) = archive::split(path) {
+        return Ok(Box::new(archive::entries(&archive, &inner)?.into_iter()));
+    }
+    let read_dir = fs::read_dir(path)?;
+    let paths: Box<dyn Iterator<Item = PathBuf>> = if hide_gitignored {
+        // 親ディレクトリの .gitignore も含めて判定するため、深さ1だけ走査する
+        let walk = WalkBuilder::new(path).max_depth(Some(1)).hidden(false).build();
+        Box::new(
+            walk.filter_map(Result::ok)
+                .filter(|entry| entry.depth() > 0)
+                .map(|entry| entry.into_path()),
+        )
+    } else {
+        Box::new(read_dir.filter_map(Result::ok).map(|entry| entry.path()))
+    };
+    Ok(Box::new(paths.map(|path| {
+        let is_dir = path.is_dir();
+        Entry { path, is_dir }
+    })))
+}
+
+/// ディレクトリを先に、それぞれ名前順に並べる
+pub fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| a.is_dir.cmp(&b.is_dir).reverse().then_with(|| a.path.cmp(&b.path)));
+}
@@ -0,0 +1,123 @@
+// --- ログ ---
+//
+// `tracing` の記録を、直近の記録（`:log` で表示する）と、指定があればファイルに書き出す。
+// 端末ごとの不具合の報告を調べるために、ファイルの読み込み・描画時間・ディレクトリの変更などを記録する。
+// 依存クレートが `log` で書いた記録も同じように扱う。
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    registry::Registry,
+    reload,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+/// `:log` で表示するために残しておく件数
+const RECENT_CAPACITY: usize = 1000;
+
+/// 直近の記録を残しておく層
+struct Recent {
+    started: Instant,
+    lines: Mutex<VecDeque<String>>,
+}
+
+static RECENT: OnceLock<Recent> = OnceLock::new();
+
+/// 記録するレベル。設定を読み込み直したときに変える
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+impl<S: Subscriber> Layer<S> for &'static Recent {
+    fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let line = format!(
+            "[{:>9.3}s] {:<5} {}: {}{}",
+            self.started.elapsed().as_secs_f64(),
+            metadata.level(),
+            metadata.target(),
+            fields.message,
+            fields.rest
+        );
+        if let Ok(mut lines) = self.lines.lock() {
+            if lines.len() == RECENT_CAPACITY {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+}
+
+/// 記録の本文と、`path = ...` のように付けたそれ以外の項目
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            // `log` から受け取った記録の出どころは、本文の前の target と重なるので省く
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.rest, " {}={:?}", name, value);
+            }
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else if !field.name().starts_with("log.") {
+            let _ = write!(self.rest, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// ログの記録を始める。`file` があれば追記で書き出す。2回目以降の呼び出しではレベルだけを変える
+pub fn init(level: LevelFilter, file: Option<&Path>) -> io::Result<()> {
+    if let Some(handle) = LEVEL.get() {
+        return handle.modify(|current| *current = level).map_err(io::Error::other);
+    }
+    let file = file
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("ログファイル {} を開けません: {}", path.display(), e)))
+        })
+        .transpose()?;
+    let recent = RECENT.get_or_init(|| Recent { started: Instant::now(), lines: Mutex::new(VecDeque::new()) });
+    let (filter, handle) = reload::Layer::new(level);
+    let file_layer = file.map(|file| tracing_subscriber::fmt::layer().with_ansi(false).with_writer(Mutex::<File>::new(file)));
+    tracing_subscriber::registry().with(filter).with(recent).with(file_layer).try_init().map_err(io::Error::other)?;
+    let _ = LEVEL.set(handle);
+    Ok(())
+}
+
+/// 直近の記録を古い順に返す
+pub fn recent() -> Vec<String> {
+    RECENT
+        .get()
+        .and_then(|recent| recent.lines.lock().ok())
+        .map(|lines| lines.iter().cloned().collect())
+        .unwrap_or_default()
+}
@@ -2,176 +2,359 @@ use std::{
     env,
     error::Error,
     fs,
-    io::{self, stdout},
+    io::{self, IsTerminal, Read, Write, stdout},
     path::{Path, PathBuf},
-    time::Duration,
+    time::Instant,
 };
 
+use clap::{value_parser, Arg, ArgAction, ArgMatches};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-// pulldown_cmarkからhtmlモジュールをインポート
-use pulldown_cmark::{
-    html, Alignment as MarkdownAlignment, CodeBlockKind, Event as MarkdownEvent, HeadingLevel,
-    Options, Parser as MarkdownParser, Tag, TagEnd,
-};
-use ratatui::{
-    prelude::*,
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+use ratatui::prelude::*;
+
+use peek::{
+    app, check, config, daemon, export, filter, links, logging, pager, plain, platform, render, serve, settings, structure, templates,
+    workspace,
 };
-use opener;
+use app::{load_markdown, run, Options, PreviewState, StartupProfile};
+use config::{Config, Value};
+use render::render_markdown_document;
+use settings::Settings;
+
+/// 設定項目のオプションを --help でまとめて表示する見出し
+const SETTINGS_HEADING: &str = "設定（設定ファイルと同じ項目）";
+
+/// `peek serve` が待ち受けるポート番号の既定値
+const DEFAULT_SERVE_PORT: u16 = 8000;
+
+/// コマンドラインの定義。サブコマンドを省いたときは `view` と同じ引数を受け付ける。
+/// サブコマンドの名前は常にサブコマンドとして扱うので、同じ名前のファイルは `./lint` のように指定する
+fn cli() -> clap::Command {
+    let paths = || {
+        Arg::new("paths")
+            .value_name("PATH")
+            .num_args(0..)
+            .value_parser(value_parser!(PathBuf))
+            .help("調べるファイルかディレクトリ（省くとカレントディレクトリ）")
+    };
+    let command = clap::Command::new("peek")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("ターミナルでMarkdownをプレビューする")
+        .after_help("サブコマンドと同じ名前のファイルは `peek ./lint` のようにパスで指定してください")
+        .args_conflicts_with_subcommands(true)
+        .args(view_args())
+        .subcommand(with_settings(clap::Command::new("view").about("文書かディレクトリをTUIで表示する（サブコマンドを省いたときと同じ）").args(view_args())))
+        .subcommand(with_settings(
+            clap::Command::new("export")
+                .about("レンダリング結果をファイルか標準出力に書き出す")
+                .arg(Arg::new("format").required(true).value_parser(["html", "txt", "slides"]).help("書き出す形式"))
+                .arg(Arg::new("file").required(true).value_parser(value_parser!(PathBuf)).help("書き出す文書"))
+                .arg(Arg::new("output").short('o').long("output").value_name("PATH").value_parser(value_parser!(PathBuf)).help("出力先（省くと標準出力）"))
+                .arg(Arg::new("self_contained").long("self-contained").action(ArgAction::SetTrue).help("画像などを埋め込んだ1つのHTMLにする（html）"))
+                .arg(Arg::new("width").long("width").value_name("COLUMNS").value_parser(value_parser!(usize)).help("折り返す幅（txt）")),
+        ))
+        .subcommand(with_settings(
+            clap::Command::new("serve")
+                .about("文書をHTMLにしてローカルのブラウザに配信する")
+                .arg(Arg::new("dir").value_name("DIR").default_value(".").value_parser(value_parser!(PathBuf)).help("配信するディレクトリ"))
+                .arg(Arg::new("port").long("port").value_name("PORT").default_value(DEFAULT_SERVE_PORT.to_string()).value_parser(value_parser!(u16)).help("待ち受けるポート番号")),
+        ))
+        .subcommand(with_settings(clap::Command::new("check-links").about("ローカルのリンク切れを報告する").arg(paths())))
+        .subcommand(with_settings(clap::Command::new("check-assets").about("見つからない画像と、文書以外のファイルへのリンク切れを報告する").arg(paths())))
+        .subcommand(with_settings(clap::Command::new("lint").about("見出しの構成などの書き方の問題を報告する").arg(paths())));
+    with_settings(command)
+}
 
-// --- 配色テーマ定義 ---
-struct ColorScheme {
-    bg: Color,
-    fg: Color,
-    selection_bg: Color,
-    selection_fg: Color,
-    comment: Color,
-    link: Color,
-    heading: Color,
-    code_bg: Color,
-    inline_code_bg: Color,
-    quote_fg: Color,
-    quote_border: Color,
-    hr: Color,
+/// TUIで表示するときの引数
+fn view_args() -> [Arg; 7] {
+    let switch = |name: &'static str| Arg::new(name).long(name).action(ArgAction::SetTrue);
+    [
+        Arg::new("file")
+            .value_name("FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("表示するファイル。`file.md:120` や `file.md#install` で最初に表示する位置も指定でき、`-` なら標準入力を読む"),
+        switch("pager").help("標準入力を読むページャーモード"),
+        Arg::new("dir").long("dir").value_name("DIR").value_parser(value_parser!(PathBuf)).help("エクスプローラーを開くディレクトリ"),
+        switch("plain").help("TUIを使わずにレンダリング結果を標準出力に書き出す"),
+        Arg::new("render_json")
+            .long("render-json")
+            .value_name("FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("文書の構造をJSONで書き出す（`-` なら標準入力）"),
+        Arg::new("profile_startup").long("profile-startup").action(ArgAction::SetTrue).help("起動の各段階にかかった時間を計測し、最初の画面を描いたら終了する"),
+        switch("daemon").help("--dir（無ければカレントディレクトリ）の索引を常駐させる"),
+    ]
 }
 
-const GITHUB_DARK_THEME: ColorScheme = ColorScheme {
-    bg: Color::Rgb(13, 17, 23),         // #0d1117
-    fg: Color::Rgb(201, 209, 217),      // #c9d1d9
-    selection_bg: Color::Rgb(3, 34, 82), // A selection color
-    selection_fg: Color::Rgb(201, 209, 217),
-    comment: Color::Rgb(139, 148, 158), // #8b949e
-    link: Color::Rgb(88, 166, 255),     // #58a6ff
-    heading: Color::Rgb(88, 166, 255),  // Using link color for headings
-    code_bg: Color::Rgb(22, 27, 34),    // #161b22
-    inline_code_bg: Color::Rgb(40, 45, 53),
-    quote_fg: Color::Rgb(139, 148, 158), // #8b949e
-    quote_border: Color::Rgb(48, 54, 61), // #30363d
-    hr: Color::Rgb(33, 38, 45),         // #21262d
-};
+/// 設定ファイルの各項目を `--theme github-dark`・`--theme=github-dark` の形で受け付ける。
+/// 真偽値は `--watch` / `--no-watch` / `--watch=false` と書ける
+fn with_settings(mut command: clap::Command) -> clap::Command {
+    for key in config::KEYS {
+        let flag = key.replace('_', "-");
+        let arg = Arg::new(*key).long(flag.clone()).help_heading(SETTINGS_HEADING);
+        if config::is_switch(key) {
+            let negated = format!("no_{}", key);
+            command = command
+                .arg(arg.value_name("BOOL").num_args(0..=1).require_equals(true).default_missing_value("true").overrides_with(negated.clone()).help(format!("--no-{} で false", flag)))
+                .arg(Arg::new(negated).long(format!("no-{}", flag)).action(ArgAction::SetTrue).overrides_with(*key).hide(true));
+        } else {
+            command = command.arg(arg.value_name("VALUE"));
+        }
+    }
+    command
+}
 
+/// コマンドラインで指定された設定値
+fn settings_from(matches: &ArgMatches) -> Vec<(String, Value)> {
+    config::KEYS
+        .iter()
+        .filter_map(|key| {
+            if config::is_switch(key) && matches.get_flag(&format!("no_{}", key)) {
+                return Some((key.to_string(), Value::Bool(false)));
+            }
+            matches.get_one::<String>(key).map(|raw| (key.to_string(), settings::parse_override(raw)))
+        })
+        .collect()
+}
 
-// --- アプリケーションの状態管理 ---
+#[derive(Default)]
+struct Args {
+    /// 標準入力を読むページャーモード
+    pager: bool,
+    /// TUIを使わずにレンダリング結果を標準出力に書き出す
+    plain: bool,
+    /// `--render-json` で指定された、構造をJSONで書き出す文書（`-` なら標準入力）
+    render_json: Option<PathBuf>,
+    /// 起動の各段階にかかった時間を計測し、最初の画面を描いたら終了する
+    profile_startup: bool,
+    /// `--daemon`: `--dir`（無ければカレントディレクトリ）の索引を常駐させる
+    daemon: bool,
+    file: Option<PathBuf>,
+    /// `file.md:120` や `file.md#install` のように指定された、最初に表示する位置
+    anchor: Option<String>,
+    /// `--dir` で指定された、エクスプローラーを開くディレクトリ
+    dir: Option<PathBuf>,
+    /// `--theme=github-dark` のように指定された設定値
+    settings: Vec<(String, Value)>,
+    /// `peek export` などのサブコマンド。`peek view` と省略したときはNone
+    command: Option<Command>,
+}
 
-enum AppMode {
-    Explorer,
-    Preview,
+/// TUIを起動せずに実行するサブコマンド
+enum Command {
+    /// `peek export <html|txt|slides> <ファイル>`: レンダリング結果をファイルか標準出力に書き出す
+    Export { format: String, file: PathBuf, output: Option<PathBuf>, self_contained: bool, width: usize },
+    /// `peek serve [ディレクトリ]`: 文書をHTMLにしてローカルのブラウザに配信する
+    Serve { dir: PathBuf, port: u16 },
+    /// `peek check-links <パス>...`: ローカルのリンク切れを報告する
+    CheckLinks(Vec<PathBuf>),
+    /// `peek check-assets <パス>...`: 見つからない画像と、文書以外のファイルへのリンク切れを報告する
+    CheckAssets(Vec<PathBuf>),
+    /// `peek lint <パス>...`: 見出しの構成などの書き方の問題を報告する
+    Lint(Vec<PathBuf>),
 }
 
-struct ExplorerState {
-    current_path: PathBuf,
-    entries: Vec<PathBuf>,
-    list_state: ListState,
-    error_message: Option<String>,
-    command_input: String,
-    in_command_mode: bool,
+impl Command {
+    /// サブコマンドの引数を読む
+    fn from_matches(name: &str, matches: &ArgMatches) -> Result<Self, String> {
+        let paths = || matches.get_many::<PathBuf>("paths").map_or_else(|| vec![PathBuf::from(".")], |paths| paths.cloned().collect());
+        match name {
+            "export" => {
+                let format = matches.get_one::<String>("format").cloned().unwrap_or_default();
+                let self_contained = matches.get_flag("self_contained");
+                let width = matches.get_one::<usize>("width").copied();
+                if self_contained && format != "html" {
+                    return Err("--self-contained は html でだけ使えます".to_string());
+                }
+                if width.is_some() && format != "txt" {
+                    return Err("--width は txt でだけ使えます".to_string());
+                }
+                Ok(Self::Export {
+                    format,
+                    file: matches.get_one::<PathBuf>("file").cloned().unwrap_or_default(),
+                    output: matches.get_one::<PathBuf>("output").cloned(),
+                    self_contained,
+                    width: width.unwrap_or(export::DEFAULT_TEXT_WIDTH),
+                })
+            }
+            "serve" => Ok(Self::Serve {
+                dir: matches.get_one::<PathBuf>("dir").cloned().unwrap_or_else(|| PathBuf::from(".")),
+                port: matches.get_one::<u16>("port").copied().unwrap_or(DEFAULT_SERVE_PORT),
+            }),
+            "check-links" => Ok(Self::CheckLinks(paths())),
+            "check-assets" => Ok(Self::CheckAssets(paths())),
+            "lint" => Ok(Self::Lint(paths())),
+            _ => Err(format!("不明なサブコマンドです: {}", name)),
+        }
+    }
 }
 
-impl ExplorerState {
-    fn new() -> io::Result<Self> {
-        let mut state = Self {
-            current_path: env::current_dir()?,
-            entries: Vec::new(),
-            list_state: ListState::default(),
-            error_message: None,
-            command_input: String::new(),
-            in_command_mode: false,
+impl Args {
+    /// コマンドラインを読む。`--help` や使い方の誤りは、clapが表示して終了する
+    fn parse() -> Result<Self, String> {
+        let matches = cli().get_matches();
+        let mut args = Self::default();
+        let matches = match matches.subcommand() {
+            Some(("view", matches)) => matches,
+            Some((name, matches)) => {
+                args.settings = settings_from(matches);
+                args.command = Some(Command::from_matches(name, matches)?);
+                return Ok(args);
+            }
+            None => &matches,
         };
-        state.load_entries()?;
-        Ok(state)
+        args.settings = settings_from(matches);
+        args.pager = matches.get_flag("pager");
+        args.plain = matches.get_flag("plain");
+        args.daemon = matches.get_flag("daemon");
+        args.profile_startup = matches.get_flag("profile_startup");
+        args.dir = matches.get_one::<PathBuf>("dir").cloned();
+        args.render_json = matches.get_one::<PathBuf>("render_json").cloned();
+        args.file = matches.get_one::<PathBuf>("file").cloned();
+        // ファイルが無ければ、`:行番号` や `#アンカー` を付けた指定として読む
+        if let Some(file) = &args.file
+            && !file.exists()
+            && let Some((path, anchor)) = file.to_str().and_then(links::split_location)
+            && Path::new(path).is_file()
+        {
+            args.file = Some(PathBuf::from(path));
+            args.anchor = Some(anchor);
+        }
+        if args.daemon && args.file.is_some() {
+            return Err("--daemon では索引を作るディレクトリを --dir で指定してください".to_string());
+        }
+        // `-` は標準入力を表す
+        if args.file.as_deref() == Some(Path::new("-")) {
+            args.file = None;
+            args.pager = true;
+        }
+        // `--plain` でファイルが無ければ標準入力を読む
+        if args.plain && args.file.is_none() {
+            args.pager = true;
+        }
+        Ok(args)
     }
+}
 
-    /// ディレクトリ読み込み時にカーソル位置を必ずリセットする
-    fn load_entries(&mut self) -> io::Result<()> {
-        let mut entries = fs::read_dir(&self.current_path)?
-            .filter_map(Result::ok)
-            .map(|entry| entry.path())
-            .collect::<Vec<_>>();
-
-        entries.sort_by(|a, b| {
-            let a_is_dir = a.is_dir();
-            let b_is_dir = b.is_dir();
-            a_is_dir.cmp(&b_is_dir).reverse().then_with(|| a.cmp(b))
-        });
+fn main() -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let mut args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
 
-        self.entries = entries;
+    // RGBの色やアイコンを表示できない旧コンソールでは、16色のテーマを使いアイコンを隠す
+    let legacy_console = platform::legacy_console();
+    if legacy_console {
+        args.settings.push(("theme".to_string(), Value::Str("ansi-16".to_string())));
+        args.settings.push(("icons".to_string(), Value::Bool(false)));
+    }
 
-        if !self.entries.is_empty() {
-            self.list_state.select(Some(0));
-        } else {
-            self.list_state.select(None);
+    // 設定の誤りはTUIを起動する前に報告する
+    let settings = match Settings::load(args.settings.clone()).and_then(|settings| {
+        settings.resolve(None)?;
+        Ok(settings)
+    }) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("設定の読み込みに失敗しました: {}", err);
+            std::process::exit(1);
         }
-        Ok(())
-    }
+    };
 
-    fn next(&mut self) {
-        if self.entries.is_empty() { return; }
-        let i = self.list_state.selected().map_or(0, |i| {
-            if i >= self.entries.len() - 1 { 0 } else { i + 1 }
-        });
-        self.list_state.select(Some(i));
+    // ログはTUIを起動する前から記録する
+    let log_config = settings.resolve(None)?;
+    let log_file = (!log_config.log_file.is_empty()).then(|| templates::expand_home(&log_config.log_file));
+    if let Err(err) = logging::init(log_config.log_level, log_file.as_deref()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
     }
-
-    fn previous(&mut self) {
-        if self.entries.is_empty() { return; }
-        let i = self.list_state.selected().map_or(0, |i| {
-            if i == 0 { self.entries.len() - 1 } else { i - 1 }
-        });
-        self.list_state.select(Some(i));
+    tracing::info!(
+        "peek {} started: TERM={:?} COLORTERM={:?} TERM_PROGRAM={:?} size={:?}",
+        env!("CARGO_PKG_VERSION"),
+        env::var("TERM").ok(),
+        env::var("COLORTERM").ok(),
+        env::var("TERM_PROGRAM").ok(),
+        crossterm::terminal::size().ok()
+    );
+    if legacy_console {
+        tracing::warn!("legacy console without ANSI support, using the ansi-16 theme without icons");
     }
-}
-
-struct PreviewState {
-    content: Text<'static>,
-    scroll: u16,
-    title: String,
-    char_count: usize,
-}
 
-impl PreviewState {
-    fn new(file_path: &Path, theme: &ColorScheme) -> io::Result<Self> {
-        let original_markdown = fs::read_to_string(file_path)?;
-        let char_count = original_markdown.chars().count();
-        let placeholder = "[[BR_TAG]]";
-        let processed_markdown = original_markdown
-            .replace("<br>", placeholder)
-            .replace("<BR>", placeholder);
-        let content = render_markdown(&processed_markdown, placeholder, theme);
-
-        Ok(Self {
-            content,
-            scroll: 0,
-            title: file_path.to_string_lossy().to_string(),
-            char_count,
-        })
+    // 引数でファイルが渡された場合は、エクスプローラーを経由せず直接プレビューする
+    if let Some(path) = &args.file
+        && !path.is_file()
+    {
+        eprintln!("ファイルが見つかりません: {}", path.display());
+        std::process::exit(1);
     }
-
-    fn scroll_up(&mut self) {
-        self.scroll = self.scroll.saturating_sub(1);
+    if let Some(dir) = &args.dir
+        && !dir.is_dir()
+    {
+        eprintln!("ディレクトリが見つかりません: {}", dir.display());
+        std::process::exit(1);
     }
-
-    // スクロールダウンのロジックを修正
-    fn scroll_down(&mut self) {
-        // コンテンツの高さから1を引いた値を最大スクロール位置とする
-        // これにより、画面の高さに関わらずコンテンツの最後までスクロールできる
-        let max_scroll = self.content.height().saturating_sub(1) as u16;
-        if self.scroll < max_scroll {
-            self.scroll = self.scroll.saturating_add(1);
+    if args.pager && io::stdin().is_terminal() {
+        if args.plain {
+            eprintln!("--plain ではファイルを指定するか、標準入力から文書を渡してください");
+        } else {
+            eprintln!("--pager では標準入力から文書を渡してください");
         }
+        std::process::exit(2);
     }
-}
 
-// --- メインロジック ---
+    if let Some(command) = &args.command {
+        match run_command(&settings, command) {
+            Ok(0) => return Ok(()),
+            Ok(code) => std::process::exit(code),
+            Err(err) => {
+                eprintln!("エラーが発生しました: {}", err);
+                std::process::exit(2);
+            }
+        }
+    }
+    if args.daemon {
+        let dir = args.dir.clone().unwrap_or_else(|| PathBuf::from("."));
+        let config = settings.resolve(None)?;
+        let result = dunce::canonicalize(&dir).and_then(|root| daemon::run(&root, |path| config.is_markdown(path)));
+        if let Err(err) = result {
+            eprintln!("エラーが発生しました: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(path) = &args.render_json {
+        if let Err(err) = print_json(&settings, path) {
+            eprintln!("エラーが発生しました: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.plain {
+        if let Err(err) = print_plain(&settings, &args) {
+            eprintln!("エラーが発生しました: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-fn main() -> Result<(), Box<dyn Error>> {
     // TUIモードの起動
+    let mut profile = args.profile_startup.then(|| StartupProfile::new(started));
     let mut terminal = setup_terminal()?;
-    let result = run(&mut terminal);
+    if let Some(profile) = &mut profile {
+        profile.terminal_setup = Some(started.elapsed());
+    }
+    let options = Options { file: args.file.clone(), anchor: args.anchor.clone(), dir: args.dir.clone(), pager: args.pager };
+    let result = run(&mut terminal, settings, &options, profile.as_mut());
     restore_terminal()?;
+    if let Some(profile) = &profile {
+        profile.print();
+    }
 
     if let Err(err) = result {
         // "quit"エラーはユーザーによる正常終了なので、エラーメッセージは表示しない
@@ -182,293 +365,128 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn run<B: Backend>(terminal: &mut Terminal<B>) -> io::Result<()> {
-    let mut mode = AppMode::Explorer;
-    let mut explorer_state = ExplorerState::new()?;
-    let mut preview_state: Option<PreviewState> = None;
-    let theme = &GITHUB_DARK_THEME;
+/// `--plain`: レンダリング結果を代替スクリーンを使わずに標準出力へ書き出す
+fn print_plain(settings: &Settings, args: &Args) -> io::Result<()> {
+    let text = match &args.file {
+        Some(path) => {
+            let root = dunce::canonicalize(path).ok().and_then(|path| workspace::find_root(&path));
+            let config = settings.resolve(root.as_deref())?;
+            // 端末を占有しないので、大きなファイルも全体を出力する
+            PreviewState::with_limit(path, &config, None)?.content
+        }
+        None => {
+            let config = settings.resolve(None)?;
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            let markdown = filter::apply(pager::strip_ansi(&input), &config.filters, None)?;
+            render_markdown_document(&markdown, config.color_scheme(), &|_| None, &config.render_options()).0.text
+        }
+    };
+    plain::write_text(&mut io::stdout().lock(), &text)
+}
 
-    loop {
-        terminal.draw(|f| match mode {
-            AppMode::Explorer => ui_explorer(f, &mut explorer_state, theme),
-            AppMode::Preview => {
-                if let Some(state) = &mut preview_state {
-                    ui_preview(f, state, theme);
+/// TUIを使わないサブコマンドを実行し、終了コードを返す
+fn run_command(settings: &Settings, command: &Command) -> io::Result<i32> {
+    match command {
+        Command::Export { format, file, output, self_contained, width } => {
+            let config = document_config(settings, file)?;
+            let title = file.file_name().map_or_else(String::new, |name| name.to_string_lossy().to_string());
+            let contents = match format.as_str() {
+                // テキストはTUIと同じレンダリング結果を幅で折り返す
+                "txt" => export::plain_text(&PreviewState::with_limit(file, &config, None)?.content, *width),
+                "slides" => export::slides_document(&load_markdown(file, &config)?, &title),
+                _ => {
+                    let base = dunce::canonicalize(file).ok().and_then(|path| workspace::find_root(&path)).unwrap_or_default();
+                    let assets = export::HtmlAssets::load(&config.html_template, &config.html_css, &base)?;
+                    export::html_document(&load_markdown(file, &config)?, &title, Some(file), *self_contained, &assets)
                 }
+            };
+            match output {
+                Some(output) => fs::write(output, contents)?,
+                None => io::stdout().lock().write_all(contents.as_bytes())?,
             }
-        })?;
-
-        if !event::poll(Duration::from_millis(50))? {
-            continue;
+            Ok(0)
         }
-
-        if let Event::Key(key) = event::read()? {
-            if key.kind != KeyEventKind::Press {
-                continue;
+        Command::Serve { dir, port } => {
+            if !dir.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("ディレクトリが見つかりません: {}", dir.display())));
             }
-
-            match mode {
-                AppMode::Preview => {
-                    if let Some(state) = &mut preview_state {
-                        match key.code {
-                            KeyCode::Char('q') => {
-                                preview_state = None;
-                                mode = AppMode::Explorer;
-                            }
-                            KeyCode::Up | KeyCode::Char('k') => state.scroll_up(),
-                            // 修正したscroll_downを呼ぶ
-                            KeyCode::Down | KeyCode::Char('j') => state.scroll_down(),
-                            _ => {}
-                        }
-                    }
+            let config = document_config(settings, dir)?;
+            let base = dunce::canonicalize(dir).ok().and_then(|path| workspace::find_root(&path)).unwrap_or_default();
+            let assets = export::HtmlAssets::load(&config.html_template, &config.html_css, &base)?;
+            serve::run(dir, *port, |path| {
+                if !config.is_previewable(path) {
+                    return Ok(None);
                 }
-                AppMode::Explorer => {
-                    if explorer_state.in_command_mode {
-                        match key.code {
-                            KeyCode::Enter => {
-                                let command_text = explorer_state.command_input.trim().to_string();
-                                explorer_state.command_input.clear();
-                                explorer_state.in_command_mode = false;
-                                explorer_state.error_message = None; // コマンド実行時にエラーをクリア
-
-                                let parts: Vec<&str> = command_text.split_whitespace().collect();
-
-                                match parts.as_slice() {
-                                    ["q"] => {
-                                        return Err(io::Error::new(io::ErrorKind::Other, "quit"));
-                                    }
-                                    ["hp", filename] => {
-                                        let file_path = explorer_state.current_path.join(filename);
-                                        if !file_path.is_file() {
-                                            explorer_state.error_message = Some(format!("ファイルが見つかりません: {}", filename));
-                                            continue;
-                                        }
-
-                                        match fs::read_to_string(&file_path) {
-                                            Ok(markdown_input) => {
-                                                // MarkdownをHTMLに変換
-                                                let parser = MarkdownParser::new(&markdown_input);
-                                                let mut html_output = String::new();
-                                                html::push_html(&mut html_output, parser);
-
-                                                let char_count = html_output.chars().count();
-                                                let content = Text::from(html_output);
-                                                let title = format!("HTML Preview: {}", file_path.to_string_lossy());
-
-                                                preview_state = Some(PreviewState {
-                                                    content,
-                                                    scroll: 0,
-                                                    title,
-                                                    char_count,
-                                                });
-                                                mode = AppMode::Preview;
-                                            }
-                                            Err(e) => {
-                                                explorer_state.error_message = Some(format!("ファイル読み込みエラー: {}", e));
-                                            }
-                                        }
-                                    }
-                                    ["cat", filename] => {
-                                        let file_path = explorer_state.current_path.join(filename);
-                                        if !file_path.is_file() {
-                                            explorer_state.error_message =
-                                                Some(format!("ファイルが見つかりません: {}", filename));
-                                            continue;
-                                        }
-
-                                        match fs::read_to_string(&file_path) {
-                                            Ok(file_content) => {
-                                                let char_count = file_content.chars().count();
-                                                let content = Text::from(file_content);
-                                                let title = format!(
-                                                    "Cat: {}",
-                                                    file_path.to_string_lossy()
-                                                );
-
-                                                preview_state = Some(PreviewState {
-                                                    content,
-                                                    scroll: 0,
-                                                    title,
-                                                    char_count,
-                                                });
-                                                mode = AppMode::Preview;
-                                            }
-                                            Err(e) => {
-                                                explorer_state.error_message = Some(format!(
-                                                    "ファイル読み込みエラー: {}",
-                                                    e
-                                                ));
-                                            }
-                                        }
-                                    }
-                                    ["ob", filename] => {
-                                        let file_path = explorer_state.current_path.join(filename);
-
-                                        // ファイルの存在と拡張子をチェック
-                                        if !file_path.is_file() {
-                                            explorer_state.error_message = Some(format!("ファイルが見つかりません: {}", filename));
-                                        } else if file_path.extension().and_then(|s| s.to_str()) != Some("html") {
-                                            explorer_state.error_message = Some("HTMLファイルのみ開けます。".to_string());
-                                        } else {
-                                            // ブラウザで開く
-                                            if let Err(e) = opener::open(&file_path) {
-                                                explorer_state.error_message = Some(format!("ブラウザで開けませんでした: {}", e));
-                                            }
-                                        }
-                                    }
-                                    [] => {} // 空のコマンドは無視
-                                    _ => {
-                                        explorer_state.error_message = Some(format!("不明なコマンドです: {}", command_text));
-                                    }
-                                }
-                            }
-                            KeyCode::Char(c) => explorer_state.command_input.push(c),
-                            KeyCode::Backspace => {
-                                explorer_state.command_input.pop();
-                            }
-                            KeyCode::Esc => {
-                                explorer_state.command_input.clear();
-                                explorer_state.in_command_mode = false;
-                            }
-                            _ => {}
-                        }
-                    } else {
-                        explorer_state.error_message = None; // 操作時にエラーをクリア
-                        match key.code {
-                            KeyCode::Char(':') => {
-                                explorer_state.in_command_mode = true;
-                            }
-                            KeyCode::Down | KeyCode::Char('j') => explorer_state.next(),
-                            KeyCode::Up | KeyCode::Char('k') => explorer_state.previous(),
-                            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
-                                if let Some(parent) = explorer_state.current_path.parent() {
-                                    explorer_state.current_path = parent.to_path_buf();
-                                    explorer_state.load_entries()?;
-                                }
-                            }
-                             KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => {
-                                if let Some(selected_index) = explorer_state.list_state.selected() {
-                                    if let Some(selected_path) = explorer_state.entries.get(selected_index) {
-                                        let selected_path = selected_path.clone();
-                                        if selected_path.is_dir() {
-                                            explorer_state.current_path = dunce::canonicalize(selected_path)?;
-                                            explorer_state.load_entries()?;
-                                        } else {
-                                            if selected_path.extension().and_then(|s| s.to_str()) == Some("md") {
-                                                match PreviewState::new(&selected_path, theme) {
-                                                    Ok(state) => {
-                                                        preview_state = Some(state);
-                                                        mode = AppMode::Preview;
-                                                    }
-                                                    Err(e) => {
-                                                        explorer_state.error_message = Some(format!("プレビューを開けません: {}", e));
-                                                    }
-                                                }
-                                            } else {
-                                                explorer_state.error_message = Some("Markdownファイル以外はプレビューできません。".to_string());
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
+                let title = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().to_string());
+                let markdown = load_markdown(path, &document_config(settings, path)?)?;
+                Ok(Some(export::html_document(&markdown, &title, Some(path), false, &assets)))
+            })?;
+            Ok(0)
+        }
+        Command::CheckLinks(paths) | Command::CheckAssets(paths) | Command::Lint(paths) => {
+            let config = settings.resolve(None)?;
+            let load = |path: &Path| check_config(settings, path).and_then(|config| load_markdown(path, &config));
+            let mut checker = check::LinkChecker::new(|path: &Path| load(path).ok());
+            let mut report = check::Report::default();
+            for path in check::documents(paths, |path| config.is_markdown(path)) {
+                report.documents += 1;
+                let markdown = match load(&path) {
+                    Ok(markdown) => markdown,
+                    Err(err) => {
+                        eprintln!("{}: {}", path.display(), err);
+                        report.unreadable.push(path);
+                        continue;
                     }
+                };
+                let structure = structure::analyze(&markdown);
+                let problems = match command {
+                    Command::CheckLinks(_) => checker.check(&path, &structure),
+                    Command::CheckAssets(_) => check::missing_assets(&path, &structure, |path| config.is_markdown(path)),
+                    _ => check::lint(&path, &structure),
+                };
+                // 問題は標準出力に、まとめは標準エラー出力に書くので、出力をそのまま他のツールに渡せる
+                for problem in &problems {
+                    println!("{}", problem);
                 }
+                report.problems.extend(problems);
             }
+            eprintln!("{}", report.summary());
+            Ok(report.exit_code())
         }
     }
 }
 
+/// 文書を含むリポジトリのプロジェクトの設定を反映した設定
+fn document_config(settings: &Settings, path: &Path) -> io::Result<Config> {
+    let root = dunce::canonicalize(path).ok().and_then(|path| workspace::find_root(&path));
+    settings.resolve(root.as_deref())
+}
 
-// --- UI描画 ---
-
-fn ui_explorer(f: &mut Frame, state: &mut ExplorerState, theme: &ColorScheme) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0), Constraint::Length(2)].as_ref())
-        .split(f.size());
-
-    let items: Vec<ListItem> = state
-        .entries
-        .iter()
-        .map(|path| {
-            let file_name = path
-                .file_name()
-                .map_or_else(|| "..".into(), |s| s.to_string_lossy());
-
-            let display_name = if path.is_dir() {
-                format!("{}/", file_name)
-            } else {
-                file_name.to_string()
-            };
-
-            let style = if path.is_dir() {
-                Style::default().fg(theme.link)
-            } else {
-                Style::default().fg(theme.fg)
-            };
-            ListItem::new(Span::styled(display_name, style))
-        })
-        .collect();
-
-    let list = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(state.current_path.to_string_lossy().to_string())
-                .style(Style::default().fg(theme.fg).bg(theme.bg)),
-        )
-        .highlight_style(
-            Style::default()
-                .bg(theme.selection_bg)
-                .fg(theme.selection_fg)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol(">> ");
-
-    f.render_stateful_widget(list, chunks[0], &mut state.list_state);
+/// チェックで文書を読むときの設定。CIで他人のリポジトリを調べても安全なよう、
+/// フィルターなどのコマンドやスクリプトは実行せず、インクルードも展開せずに元のファイルをそのまま読む
+fn check_config(settings: &Settings, path: &Path) -> io::Result<Config> {
+    let mut config = document_config(settings, path)?;
+    config.filters.clear();
+    config.preview_commands.clear();
+    config.includes = false;
+    config.script.clear();
+    Ok(config)
+}
 
-    let status_bar_style = Style::default().fg(theme.fg).bg(theme.bg);
-    let status_text = if state.in_command_mode {
-        format!(":{}", state.command_input)
-    } else if let Some(err) = &state.error_message {
-        err.clone()
+/// `--render-json`: 文書の見出し・リンク・コードブロック・語数をJSONで標準出力へ書き出す
+fn print_json(settings: &Settings, path: &Path) -> io::Result<()> {
+    // 色付きの出力を渡されても、エスケープシーケンスを見出しやリンクの文字に含めない
+    let markdown = if path == Path::new("-") {
+        let config = settings.resolve(None)?;
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        filter::apply(pager::strip_ansi(&input), &config.filters, None)?
     } else {
-        "j/k or ↓/↑: Move | l/Enter: Open | h: Up | :<command> Enter: Run".to_string()
+        pager::strip_ansi(&load_markdown(path, &document_config(settings, path)?)?)
     };
-    let status_bar = Paragraph::new(status_text).style(if state.error_message.is_some() {
-        status_bar_style.fg(Color::Red)
-    } else {
-        status_bar_style
-    });
-
-    f.render_widget(status_bar, chunks[1]);
-}
-
-fn ui_preview(f: &mut Frame, state: &mut PreviewState, theme: &ColorScheme) {
-    // Create a layout with a main area and a footer
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Min(0), // Main content
-            Constraint::Length(1), // Footer
-        ])
-        .split(f.size());
-
-    // Main content paragraph without a block/border
-    let paragraph = Paragraph::new(state.content.clone())
-        .style(Style::default().fg(theme.fg).bg(theme.bg))
-        .wrap(Wrap { trim: false })
-        .scroll((state.scroll, 0));
-    f.render_widget(paragraph, chunks[0]);
-
-    // Footer
-    let footer_text = format!("{} | {} chars | Press 'q' to close", state.title, state.char_count);
-    let footer = Paragraph::new(footer_text)
-        .style(Style::default().fg(theme.comment).bg(theme.bg))
-        .alignment(Alignment::Right);
-    f.render_widget(footer, chunks[1]);
+    println!("{}", structure::analyze(&markdown).to_json());
+    Ok(())
 }
 
 // --- ターミナル設定 ---
@@ -486,232 +504,3 @@ fn restore_terminal() -> Result<(), Box<dyn Error>> {
     execute!(io::stdout(), LeaveAlternateScreen)?;
     Ok(())
 }
-
-// --- Markdownレンダリング ---
-fn render_markdown(markdown_input: &str, br_placeholder: &str, theme: &ColorScheme) -> Text<'static> {
-    let mut lines: Vec<Line<'static>> = Vec::new();
-    let mut current_spans: Vec<Span<'static>> = Vec::new();
-    let mut style_stack: Vec<Style> = vec![Style::default().fg(theme.fg)];
-    let mut list_stack: Vec<u64> = Vec::new();
-    let mut table_alignments: Vec<MarkdownAlignment> = Vec::new();
-    let mut in_table_header = false;
-    let mut in_code_block = false;
-
-    let parser = MarkdownParser::new_ext(markdown_input, Options::all());
-    for event in parser {
-        match event {
-            MarkdownEvent::Start(tag) => {
-                let current_style = *style_stack.last().unwrap_or(&Style::default());
-                match tag {
-                    Tag::Heading { level, .. } => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        lines.push(Line::default());
-                        let base_style = Style::default()
-                                .add_modifier(Modifier::BOLD)
-                                .fg(theme.heading);
-                        let style = if level >= HeadingLevel::H3 {
-                            base_style.add_modifier(Modifier::DIM)
-                        } else {
-                            base_style
-                        };
-                        style_stack.push(style);
-                    }
-                    Tag::BlockQuote => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        let style = Style::default().fg(theme.quote_fg);
-                        current_spans.push(Span::styled("▎".to_string(), Style::default().fg(theme.quote_border)));
-                        current_spans.push(Span::raw(" ".to_string()));
-                        style_stack.push(style);
-                    }
-                    Tag::CodeBlock(kind) => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        lines.push(Line::default());
-                        in_code_block = true;
-                        let lang = match kind {
-                            CodeBlockKind::Fenced(lang) => lang.into_string(),
-                            CodeBlockKind::Indented => String::new(),
-                        };
-                        let border_style = Style::default().fg(theme.comment);
-                        lines.push(Line::from(vec![
-                            Span::styled("┌─── ".to_string(), border_style),
-                            Span::styled(lang, Style::default().fg(Color::Yellow)),
-                        ]));
-                        style_stack.push(Style::default().bg(theme.code_bg));
-                    }
-                    Tag::Table(aligns) => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        table_alignments = aligns;
-                    }
-                    Tag::TableHead => {
-                        in_table_header = true;
-                    }
-                    Tag::TableRow => {
-                        current_spans
-                            .push(Span::styled("│ ".to_string(), Style::default().fg(theme.comment)));
-                    }
-                    Tag::TableCell => { /* No action needed */ }
-                    Tag::List(start_num) => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        list_stack.push(start_num.unwrap_or(1));
-                    }
-                    Tag::Item => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        let indent = "  ".repeat(list_stack.len().saturating_sub(1));
-                        let marker = if let Some(num) = list_stack.last_mut() {
-                            let m = format!("{}. ", *num);
-                            *num += 1;
-                            m
-                        } else {
-                            "• ".to_string()
-                        };
-                        current_spans.push(Span::raw(indent));
-                        current_spans
-                            .push(Span::styled(marker, Style::default().fg(theme.comment)));
-                    }
-                    Tag::Emphasis => {
-                        style_stack.push(current_style.add_modifier(Modifier::ITALIC));
-                    }
-                    Tag::Strong => {
-                        style_stack.push(current_style.add_modifier(Modifier::BOLD));
-                    }
-                    Tag::Strikethrough => {
-                        style_stack.push(current_style.add_modifier(Modifier::CROSSED_OUT));
-                    }
-                    Tag::Link { .. } => {
-                        style_stack
-                        .push(Style::default().fg(theme.link).add_modifier(Modifier::UNDERLINED));
-                    }
-                    _ => {}
-                }
-            }
-            MarkdownEvent::End(tag) => {
-                match tag {
-                    TagEnd::Heading(_) | TagEnd::BlockQuote | TagEnd::Item => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        style_stack.pop();
-                    }
-                    TagEnd::CodeBlock => {
-                        in_code_block = false;
-                        lines.push(Line::from(Span::styled(
-                            "└──────────────────".to_string(),
-                            Style::default().fg(theme.comment),
-                        )));
-                        lines.push(Line::default());
-                        style_stack.pop();
-                    }
-                    TagEnd::Table => {
-                        table_alignments.clear();
-                        lines.push(Line::default());
-                    }
-                    TagEnd::TableHead => {
-                        in_table_header = false;
-                    }
-                    TagEnd::TableRow => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                    }
-                    TagEnd::TableCell => {
-                        current_spans.push(Span::styled(" │ ".to_string(), Style::default().fg(theme.comment)));
-                    }
-                    TagEnd::List(_) => {
-                        list_stack.pop();
-                        lines.push(Line::default());
-                    }
-                    TagEnd::Paragraph => {
-                        if !current_spans.is_empty() {
-                            lines.push(Line::from(std::mem::take(&mut current_spans)));
-                        }
-                        lines.push(Line::default());
-                    }
-                    TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link => {
-                        style_stack.pop();
-                    }
-                    _ => {}
-                }
-            }
-            MarkdownEvent::Text(text) => {
-                let style = *style_stack.last().unwrap_or(&Style::default());
-                if in_code_block {
-                    for line in text.lines() {
-                        lines.push(Line::from(vec![
-                            Span::styled("│ ".to_string(), Style::default().fg(theme.comment)),
-                            Span::styled(line.to_string(), style.fg(theme.fg)),
-                        ]));
-                    }
-                } else {
-                    let final_style = if in_table_header {
-                        style.add_modifier(Modifier::BOLD)
-                    } else {
-                        style
-                    };
-
-                    if !br_placeholder.is_empty() && text.contains(br_placeholder) {
-                        let mut last_pos = 0;
-                        while let Some(placeholder_pos) = text[last_pos..].find(br_placeholder) {
-                            let absolute_pos = last_pos + placeholder_pos;
-                            let before = &text[last_pos..absolute_pos];
-                            if !before.is_empty() {
-                                current_spans.push(Span::styled(before.to_string(), final_style));
-                            }
-                            if !current_spans.is_empty() {
-                                lines.push(Line::from(std::mem::take(&mut current_spans)));
-                            }
-                            last_pos = absolute_pos + br_placeholder.len();
-                        }
-                        let remaining = &text[last_pos..];
-                        if !remaining.is_empty() {
-                            current_spans.push(Span::styled(remaining.to_string(), final_style));
-                        }
-                    } else {
-                        current_spans.push(Span::styled(text.to_string(), final_style));
-                    }
-                }
-            }
-            MarkdownEvent::Html(html) => {
-                current_spans.push(Span::styled(html.to_string(), Style::default().fg(theme.comment)));
-            }
-            MarkdownEvent::Code(text) => {
-                let style = Style::default().fg(theme.fg).bg(theme.inline_code_bg);
-                current_spans.push(Span::styled(format!(" {} ", text), style));
-            }
-            MarkdownEvent::HardBreak => {
-                if !current_spans.is_empty() {
-                    lines.push(Line::from(std::mem::take(&mut current_spans)));
-                }
-            }
-            MarkdownEvent::SoftBreak => {
-                current_spans.push(Span::raw(" ".to_string()));
-            }
-            MarkdownEvent::Rule => {
-                if !current_spans.is_empty() {
-                    lines.push(Line::from(std::mem::take(&mut current_spans)));
-                }
-                lines.push(Line::from(Span::styled(
-                    "─".repeat(80),
-                    Style::default().fg(theme.hr),
-                )));
-                lines.push(Line::default());
-            }
-            _ => {}
-        }
-    }
-    if !current_spans.is_empty() {
-        lines.push(Line::from(std::mem::take(&mut current_spans)));
-    }
-    Text::from(lines)
-}
@@ -0,0 +1,135 @@
+// --- フロントマターによる文書の絞り込み（`:index`） ---
+//
+// `:index tag=architecture status=draft` のような条件で、索引（vault）の文書をフロントマターの値で絞り込む。
+// 条件は空白で区切り、すべてを満たす文書を残す。
+//
+//     key=value    値のどれかがvalueに一致する（大文字と小文字は区別しない）
+//     key!=value   どの値もvalueに一致しない（キーが無くてもよい）
+//     key          キーがある
+//     !key         キーが無い
+//
+// `tag` のように単数形で書いたキーが文書に無ければ、`tags` の値を見る。
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{shell_words, vault::Vault};
+
+/// 1つの条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub key: String,
+    /// 一致させる値。Noneならキーがあるかどうかだけを見る
+    pub value: Option<String>,
+    /// 条件を反対にする（`!=` と `!key`）
+    pub negated: bool,
+}
+
+impl Condition {
+    /// フロントマターのキーごとの値が条件を満たすかどうか
+    pub fn matches(&self, metadata: &BTreeMap<String, Vec<String>>) -> bool {
+        let values = metadata.get(&self.key).or_else(|| metadata.get(&format!("{}s", self.key)));
+        let found = match (&self.value, values) {
+            (None, values) => values.is_some(),
+            (Some(_), None) => false,
+            (Some(value), Some(values)) => values.iter().any(|candidate| candidate.to_lowercase() == value.to_lowercase()),
+        };
+        found != self.negated
+    }
+}
+
+/// 条件を読む。値に空白を含めるときは `status="in review"` のように引用符で囲む
+pub fn parse(query: &str) -> Result<Vec<Condition>, String> {
+    let words = shell_words::split(query).ok_or_else(|| format!("引用符が閉じていません: {}", query))?;
+    words
+        .iter()
+        .map(|word| {
+            let condition = if let Some((key, value)) = word.split_once("!=") {
+                Condition { key: key.to_string(), value: Some(value.to_string()), negated: true }
+            } else if let Some((key, value)) = word.split_once('=') {
+                Condition { key: key.to_string(), value: Some(value.to_string()), negated: false }
+            } else if let Some(key) = word.strip_prefix('!') {
+                Condition { key: key.to_string(), value: None, negated: true }
+            } else {
+                Condition { key: word.to_string(), value: None, negated: false }
+            };
+            if condition.key.trim().is_empty() {
+                return Err(format!("条件にキーがありません: {}", word));
+            }
+            Ok(condition)
+        })
+        .collect()
+}
+
+/// 一覧の1行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// フロントマターの `title`、無ければ最初の見出し、それも無ければファイル名
+    pub title: String,
+    pub metadata: BTreeMap<String, Vec<String>>,
+}
+
+/// `dir` の下の文書のうち、すべての条件を満たすものをパスの順に返す
+pub fn filter(vault: &Vault, dir: &Path, conditions: &[Condition]) -> Vec<Entry> {
+    vault
+        .documents
+        .iter()
+        .filter(|(path, document)| path.starts_with(dir) && conditions.iter().all(|condition| condition.matches(&document.metadata)))
+        .map(|(path, document)| {
+            let title = document
+                .metadata
+                .get("title")
+                .and_then(|values| values.first())
+                .or(document.headings.first())
+                .cloned()
+                .unwrap_or_else(|| path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().to_string()));
+            Entry { path: path.clone(), title, metadata: document.metadata.clone() }
+        })
+        .collect()
+}
+
+/// 一覧のMarkdown。タグと、条件に使ったキーの値を列にする
+pub fn report(dir: &Path, query: &str, conditions: &[Condition], entries: &[Entry]) -> String {
+    let mut report = format!("# Index of `{}`\n\n", dir.display());
+    let filtered = if query.trim().is_empty() { String::new() } else { format!(" matching `{}`", query.trim()) };
+    if entries.is_empty() {
+        report.push_str(&format!("No documents{}.\n", filtered));
+        return report;
+    }
+    report.push_str(&format!(
+        "{}{}.\n\n",
+        if entries.len() == 1 { "1 document".to_string() } else { format!("{} documents", entries.len()) },
+        filtered
+    ));
+    let mut columns = vec!["tags".to_string()];
+    for condition in conditions {
+        // `tag` で絞り込んだときは `tags` の列を見せる
+        let has = |key: &str| entries.iter().any(|entry| entry.metadata.contains_key(key));
+        let plural = format!("{}s", condition.key);
+        let key = if !has(&condition.key) && has(&plural) { plural } else { condition.key.clone() };
+        if !columns.contains(&key) && key != "title" {
+            columns.push(key);
+        }
+    }
+    report.push_str(&format!("| Document | Title | {} |\n", columns.iter().map(|column| cell(&capitalize(column))).collect::<Vec<_>>().join(" | ")));
+    report.push_str(&format!("| --- | --- |{}\n", " --- |".repeat(columns.len())));
+    for entry in entries {
+        let name = entry.path.strip_prefix(dir).unwrap_or(&entry.path).to_string_lossy().to_string();
+        let values: Vec<String> = columns.iter().map(|column| cell(&entry.metadata.get(column).map(|values| values.join(", ")).unwrap_or_default())).collect();
+        report.push_str(&format!("| [{}](<{}>) | {} | {} |\n", cell(&name), entry.path.display(), cell(&entry.title), values.join(" | ")));
+    }
+    report
+}
+
+/// 表のセルに入れられるようにする
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
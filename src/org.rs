@@ -0,0 +1,129 @@
+// --- org-mode の簡易読み込み ---
+//
+// org-mode の文書をMarkdownに書き換えて、Markdownと同じレンダリング処理に渡す。
+// 見出し・リスト・ブロック・リンクだけを扱う、ざっと目を通すための変換。
+
+use std::path::Path;
+
+/// org-mode のファイルかどうか
+pub fn is_org(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("org"))
+}
+
+/// org-mode の文書をMarkdownに変換し、変換したMarkdownの行ごとの元の文書の行番号（1始まり）を返す
+pub fn to_markdown(org: &str) -> (String, Vec<usize>) {
+    let mut output = String::with_capacity(org.len());
+    let mut lines = Vec::new();
+    let mut counted = 0;
+    // `#+BEGIN_SRC` などのブロックの中にいる間は、その終了行の名前を持つ
+    let mut block: Option<String> = None;
+
+    for (index, line) in org.lines().enumerate() {
+        // 前の行までに書き出した行を、前の行から変換したものとして数える
+        count_lines(&output, &mut counted, &mut lines, index);
+        let trimmed = line.trim();
+        let keyword = trimmed.to_ascii_lowercase();
+
+        if let Some(end) = &block {
+            if keyword == *end {
+                if end != "#+end_quote" {
+                    output.push_str("```\n");
+                }
+                block = None;
+            } else if end == "#+end_quote" {
+                output.push_str(&format!("> {}\n", convert_inline(trimmed)));
+            } else {
+                output.push_str(line);
+                output.push('\n');
+            }
+            continue;
+        }
+
+        if let Some(kind) = keyword.strip_prefix("#+begin_") {
+            let name = kind.split_whitespace().next().unwrap_or_default().to_string();
+            match name.as_str() {
+                "quote" => {}
+                "src" => {
+                    let language = trimmed.split_whitespace().nth(1).unwrap_or_default();
+                    output.push_str(&format!("```{}\n", language));
+                }
+                _ => output.push_str("```\n"),
+            }
+            block = Some(format!("#+end_{}", name));
+            continue;
+        }
+        if keyword.starts_with("#+title:") {
+            output.push_str(&format!("# {}\n", convert_inline(trimmed["#+title:".len()..].trim())));
+            continue;
+        }
+        // その他の `#+KEYWORD:` 行と `# ` で始まるコメント行は表示しない
+        if trimmed.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+            continue;
+        }
+
+        let stars = line.chars().take_while(|c| *c == '*').count();
+        if stars > 0 && line[stars..].starts_with(' ') {
+            output.push_str(&format!("{} {}\n", "#".repeat(stars.min(6)), convert_inline(line[stars..].trim())));
+            continue;
+        }
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        if let Some(item) = trimmed.strip_prefix("+ ").or_else(|| trimmed.strip_prefix("- ")) {
+            output.push_str(&format!("{}- {}\n", indent, convert_inline(item)));
+            continue;
+        }
+        if let Some((number, item)) = ordered_item(trimmed) {
+            output.push_str(&format!("{}{}. {}\n", indent, number, convert_inline(item)));
+            continue;
+        }
+        output.push_str(&convert_inline(line));
+        output.push('\n');
+    }
+    let last = org.lines().count();
+    count_lines(&output, &mut counted, &mut lines, last);
+    if block.as_deref().is_some_and(|end| end != "#+end_quote") {
+        output.push_str("```\n");
+    }
+    count_lines(&output, &mut counted, &mut lines, last);
+    (output, lines)
+}
+
+/// `output` の `counted` バイト目から後の行を、元の文書の `line` 行目から変換したものとして `lines` に加える
+fn count_lines(output: &str, counted: &mut usize, lines: &mut Vec<usize>, line: usize) {
+    let added = output[*counted..].matches('\n').count();
+    lines.extend(std::iter::repeat_n(line, added));
+    *counted = output.len();
+}
+
+/// `1. item` や `1) item` を番号と本文に分ける
+fn ordered_item(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    let rest = &line[digits..];
+    let item = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") "))?;
+    Some((&line[..digits], item))
+}
+
+/// `[[url][説明]]` と `[[url]]` 形式のリンクをMarkdownのリンクに変換する
+fn convert_inline(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]") else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let inner = &rest[start + 2..start + end];
+        match inner.split_once("][") {
+            Some((target, description)) => output.push_str(&format!("[{}]({})", description, target)),
+            None => output.push_str(&format!("[{}]({})", inner, inner)),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
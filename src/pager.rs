@@ -0,0 +1,80 @@
+// --- ページャーモード ---
+//
+// `--pager` で起動したとき、標準入力を別スレッドで少しずつ読み込む。
+// git や man から渡される入力には色付けのためのANSIエスケープや
+// 重ね打ち（`x\x08x`）が含まれることがあるので、取り除いてからMarkdownとして扱う。
+
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::Sender,
+    thread,
+};
+
+/// 標準入力から読み込んだもの
+pub enum StdinEvent {
+    /// 読み込んだ1行（改行を含む）
+    Line(String),
+    /// EOFに達した
+    Closed,
+}
+
+/// 標準入力を別スレッドで1行ずつ読み込み、`sender` に送る。EOFに達したら `Closed` を送る
+pub fn spawn_reader<E: From<StdinEvent> + Send + 'static>(sender: Sender<E>) {
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match stdin.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = strip_ansi(&String::from_utf8_lossy(&buffer));
+                    if sender.send(StdinEvent::Line(line).into()).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        let _ = sender.send(StdinEvent::Closed.into());
+    });
+}
+
+/// ANSIエスケープシーケンスと重ね打ちを取り除く
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: ESC [ ... 終端文字(0x40-0x7e)
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: ESC ] ... BEL または ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // その他の2文字シーケンス
+                _ => {}
+            },
+            // 重ね打ちは直前の文字を消して後ろの文字を残す
+            '\x08' => {
+                output.pop();
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
@@ -0,0 +1,76 @@
+// --- `--plain` の出力 ---
+//
+// レンダリング結果をTUIを使わずにそのまま標準出力へ書き出す。
+// 色や装飾はANSIエスケープシーケンスで表し、スクロールバックに残るようにする。
+
+use std::io::{self, Write};
+
+use ratatui::prelude::*;
+
+/// `text` をANSIの色付きで書き出す
+pub fn write_text(out: &mut impl Write, text: &Text) -> io::Result<()> {
+    for line in &text.lines {
+        for span in &line.spans {
+            let style = line.style.patch(span.style);
+            let sgr = sgr(style);
+            if sgr.is_empty() {
+                write!(out, "{}", span.content)?;
+            } else {
+                write!(out, "\x1b[{}m{}\x1b[0m", sgr, span.content)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// スタイルをSGRのパラメーターに変換する
+fn sgr(style: Style) -> String {
+    let mut codes: Vec<String> = Vec::new();
+    let modifiers = [
+        (Modifier::BOLD, "1"),
+        (Modifier::DIM, "2"),
+        (Modifier::ITALIC, "3"),
+        (Modifier::UNDERLINED, "4"),
+        (Modifier::REVERSED, "7"),
+        (Modifier::CROSSED_OUT, "9"),
+    ];
+    for (modifier, code) in modifiers {
+        if style.add_modifier.contains(modifier) {
+            codes.push(code.to_string());
+        }
+    }
+    if let Some(color) = style.fg.and_then(|color| color_code(color, false)) {
+        codes.push(color);
+    }
+    if let Some(color) = style.bg.and_then(|color| color_code(color, true)) {
+        codes.push(color);
+    }
+    codes.join(";")
+}
+
+fn color_code(color: Color, background: bool) -> Option<String> {
+    let base = if background { 40 } else { 30 };
+    let code = match color {
+        Color::Reset => return None,
+        Color::Black => format!("{}", base),
+        Color::Red => format!("{}", base + 1),
+        Color::Green => format!("{}", base + 2),
+        Color::Yellow => format!("{}", base + 3),
+        Color::Blue => format!("{}", base + 4),
+        Color::Magenta => format!("{}", base + 5),
+        Color::Cyan => format!("{}", base + 6),
+        Color::Gray => format!("{}", base + 7),
+        Color::DarkGray => format!("{}", base + 60),
+        Color::LightRed => format!("{}", base + 61),
+        Color::LightGreen => format!("{}", base + 62),
+        Color::LightYellow => format!("{}", base + 63),
+        Color::LightBlue => format!("{}", base + 64),
+        Color::LightMagenta => format!("{}", base + 65),
+        Color::LightCyan => format!("{}", base + 66),
+        Color::White => format!("{}", base + 67),
+        Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        Color::Indexed(index) => format!("{};5;{}", base + 8, index),
+    };
+    Some(code)
+}
@@ -0,0 +1,44 @@
+// --- OSごとの差異 ---
+//
+// Windowsのドライブ一覧・`\\?\` 形式のパスの表示・旧コンソール（conhost）の判定をまとめる。
+// Windows以外ではドライブは無く、旧コンソールでもないものとして扱う。
+
+use std::path::{Path, PathBuf};
+
+/// 存在するドライブのルート（`C:\` など）。Windows以外では空
+#[cfg(windows)]
+pub fn drives() -> Vec<PathBuf> {
+    (b'A'..=b'Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+        .filter(|root| root.is_dir())
+        .collect()
+}
+
+#[cfg(not(windows))]
+pub fn drives() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// 表示用のパス。`\\?\C:\` は `C:\` に、`\\?\UNC\server\share` は `\\server\share` に直す
+pub fn display(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        text.into_owned()
+    }
+}
+
+/// ANSIエスケープシーケンスを解釈できない旧コンソールかどうか。
+/// この場合RGBの色は黒になってしまい、Nerd Fontのアイコンも表示できない
+#[cfg(windows)]
+pub fn legacy_console() -> bool {
+    !crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+pub fn legacy_console() -> bool {
+    false
+}
@@ -0,0 +1,126 @@
+// --- 文章の読みやすさの指標 ---
+//
+// 英語の文章を対象に、Flesch Reading Ease と Flesch-Kincaid Grade Level、
+// 平均文長、受動態らしい文の数を求める。音節数と受動態はいずれも簡易的な推定。
+
+/// 受動態の判定に使うbe動詞
+const BE_VERBS: &[&str] = &["am", "is", "are", "was", "were", "be", "been", "being"];
+/// `-ed` で終わらない主な過去分詞
+const IRREGULAR_PARTICIPLES: &[&str] = &[
+    "built", "made", "done", "given", "known", "seen", "shown", "taken", "written", "found", "held", "kept",
+    "left", "lost", "meant", "paid", "put", "read", "run", "said", "sent", "set", "sold", "told", "thought",
+    "understood", "chosen", "driven", "broken", "spoken", "forgotten", "hidden", "begun", "brought", "bought",
+    "caught", "taught", "drawn", "grown", "thrown", "worn", "torn", "born", "led", "met", "cut", "hit", "let",
+];
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub sentences: usize,
+    pub words: usize,
+    pub syllables: usize,
+    /// 受動態らしい文の数
+    pub passive_sentences: usize,
+}
+
+impl Stats {
+    pub fn average_sentence_length(&self) -> f64 {
+        self.words as f64 / self.sentences.max(1) as f64
+    }
+
+    fn syllables_per_word(&self) -> f64 {
+        self.syllables as f64 / self.words.max(1) as f64
+    }
+
+    pub fn reading_ease(&self) -> f64 {
+        206.835 - 1.015 * self.average_sentence_length() - 84.6 * self.syllables_per_word()
+    }
+
+    pub fn grade_level(&self) -> f64 {
+        0.39 * self.average_sentence_length() + 11.8 * self.syllables_per_word() - 15.59
+    }
+
+    /// 表示用の行
+    pub fn report(&self) -> Vec<String> {
+        if self.words == 0 {
+            return vec!["No prose to analyze".to_string()];
+        }
+        vec![
+            format!("Flesch reading ease:   {:.1} ({})", self.reading_ease(), ease_label(self.reading_ease())),
+            format!("Flesch-Kincaid grade:  {:.1}", self.grade_level()),
+            format!("Sentences:             {}", self.sentences),
+            format!("Words:                 {}", self.words),
+            format!("Avg sentence length:   {:.1} words", self.average_sentence_length()),
+            format!(
+                "Passive voice (est.):  {} sentences ({:.0}%)",
+                self.passive_sentences,
+                self.passive_sentences as f64 * 100.0 / self.sentences.max(1) as f64
+            ),
+        ]
+    }
+}
+
+fn ease_label(score: f64) -> &'static str {
+    match score {
+        s if s >= 80.0 => "easy",
+        s if s >= 60.0 => "plain",
+        s if s >= 30.0 => "difficult",
+        _ => "very difficult",
+    }
+}
+
+/// 段落ごとのテキストから指標を求める。各段落の終わりも文の区切りとして扱う
+pub fn analyze<'a>(blocks: impl IntoIterator<Item = &'a str>) -> Stats {
+    let mut stats = Stats::default();
+    for block in blocks {
+        for sentence in block.split(['.', '!', '?', '。', '！', '？']) {
+            let words: Vec<String> = sentence
+                .split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '-'))
+                .filter(|word| word.chars().any(char::is_alphabetic))
+                .map(str::to_lowercase)
+                .collect();
+            if words.is_empty() {
+                continue;
+            }
+            stats.sentences += 1;
+            stats.words += words.len();
+            stats.syllables += words.iter().map(|word| syllables(word)).sum::<usize>();
+            if is_passive(&words) {
+                stats.passive_sentences += 1;
+            }
+        }
+    }
+    stats
+}
+
+/// 母音の連続を数えて音節数を推定する。語末の黙字の `e` は数えない
+fn syllables(word: &str) -> usize {
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+/// be動詞の直後（副詞を1つ挟んでもよい）に過去分詞が続く文を受動態とみなす
+fn is_passive(words: &[String]) -> bool {
+    let is_participle = |word: &str| word.ends_with("ed") || IRREGULAR_PARTICIPLES.contains(&word);
+    words.iter().enumerate().any(|(i, word)| {
+        if !BE_VERBS.contains(&word.as_str()) {
+            return false;
+        }
+        match (words.get(i + 1), words.get(i + 2)) {
+            (Some(next), _) if is_participle(next) => true,
+            (Some(adverb), Some(next)) => adverb.ends_with("ly") && is_participle(next),
+            _ => false,
+        }
+    })
+}
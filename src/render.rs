@@ -0,0 +1,960 @@
+// --- Markdownレンダリング ---
+//
+// Markdownを端末に表示するための装飾付きテキストに変換する。
+// TUIの状態に依存しないので、スナップショットテストからも直接呼び出せる。
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    mem::size_of,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use pulldown_cmark::{
+    Alignment as MarkdownAlignment, CodeBlockKind, Event as MarkdownEvent, HeadingLevel, Options,
+    Parser as MarkdownParser, Tag, TagEnd,
+};
+use ratatui::{
+    prelude::*,
+    widgets::{Paragraph, Wrap},
+};
+
+use crate::{autolink::{self, Autolink}, frontmatter, script::Script, theme::ColorScheme};
+
+/// レンダリング結果
+pub struct RenderedMarkdown {
+    pub text: Text<'static>,
+    /// 見出しが描画された行番号
+    pub heading_lines: Vec<usize>,
+    /// 見出しのテキスト（`heading_lines` と同じ順）。アンカー名を作るのに使う
+    pub headings: Vec<String>,
+    /// 見出しのレベル（1〜6）と、付けた節番号（`1.2.` など。番号を付けないときは空）
+    pub heading_levels: Vec<usize>,
+    pub heading_numbers: Vec<String>,
+    /// 描画した行ごとの元のMarkdownの行番号（1から。`text` の行と同じ順）
+    pub source_lines: Vec<usize>,
+    /// `on_render` のフックが失敗したときのエラー。フックを使わずに描画する
+    pub script_error: Option<String>,
+    pub footnote_refs: Vec<FootnoteRef>,
+    /// 脚注のラベルと本文（装飾を除いたテキスト）
+    pub footnotes: HashMap<String, String>,
+    pub links: Vec<LinkSpan>,
+    /// 画像のプレースホルダーの先頭行と画像のURL
+    pub images: Vec<LinkSpan>,
+    /// コードブロックの中身の行番号。折り返し表示でも折り返さない
+    pub code_lines: Vec<usize>,
+    /// 文書のタイトル。フロントマターの `title`、無ければ最初のH1見出しのテキスト
+    pub title: Option<String>,
+    /// 解析とレンダリングにかかった時間
+    pub render_time: Duration,
+}
+
+/// 本文中のリンクの開始位置とリンク先
+pub struct LinkSpan {
+    pub line: usize,
+    /// 行内の最初のスパンの添字
+    pub span: usize,
+    pub url: String,
+}
+
+/// 本文中の脚注参照の位置
+pub struct FootnoteRef {
+    pub line: usize,
+    /// 行内のスパンの添字
+    pub span: usize,
+    pub label: String,
+}
+
+/// 画像のURLからブロック文字による近似表示を作る関数。作れなければNoneを返す
+pub type ImageArt<'a> = &'a dyn Fn(&str) -> Option<Vec<Line<'static>>>;
+
+/// コードブロックや引用などの区切りの示し方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decorations {
+    /// 罫線素片などの記号で囲む
+    #[default]
+    Unicode,
+    /// 罫線素片を表示できない端末やフォント向けに、`|` `+` `-` `>` などのASCII文字で囲む
+    Ascii,
+    /// スクリーンリーダー向けに、記号の代わりに読み上げられる言葉（`Heading level 2:` など）で示す
+    ScreenReader,
+}
+
+impl Decorations {
+    /// 縦の罫線（コードブロック・表・画像の枠）
+    fn vertical(self) -> &'static str {
+        if self == Self::Ascii { "|" } else { "│" }
+    }
+
+    /// 横の罫線（コードブロック・水平線・画像の枠）
+    fn horizontal(self) -> &'static str {
+        if self == Self::Ascii { "-" } else { "─" }
+    }
+
+    /// 枠の左上・右上・左下・右下の角
+    fn corners(self) -> [&'static str; 4] {
+        if self == Self::Ascii { ["+"; 4] } else { ["┌", "┐", "└", "┘"] }
+    }
+
+    /// 引用の行頭の枠線
+    fn quote_border(self) -> &'static str {
+        if self == Self::Ascii { ">" } else { "▎" }
+    }
+
+    /// 画像の代替テキストの前に付ける印
+    fn image_marker(self) -> &'static str {
+        match self {
+            Self::Unicode => "▣",
+            Self::Ascii => "[img]",
+            Self::ScreenReader => "Image:",
+        }
+    }
+}
+
+/// レンダリングの設定
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub decorations: Decorations,
+    /// 本文中の課題番号などをリンクにする規則
+    pub autolinks: Vec<Autolink>,
+    /// 見出しの前に節番号（`1.` `1.2.` `1.2.3.`）を付ける
+    pub number_headings: bool,
+    /// 描画する前にイベントの並びを `on_render` で書き換えるスクリプト
+    pub script: Option<Arc<Script>>,
+}
+
+/// 見出しのレベルの並びから節番号を数える
+#[derive(Debug, Default)]
+pub struct SectionNumbers {
+    counts: [usize; 6],
+}
+
+impl SectionNumbers {
+    /// `level`（1〜6）の見出しの番号を返す。文書がH2から始まるときなどは、使っていない上のレベルを省く
+    pub fn next(&mut self, level: usize) -> String {
+        let level = level.clamp(1, 6);
+        self.counts[level - 1] += 1;
+        self.counts[level..].fill(0);
+        let first = self.counts[..level].iter().position(|&count| count > 0).unwrap_or(0);
+        self.counts[first..level].iter().map(|count| format!("{}.", count)).collect()
+    }
+}
+
+/// Markdown文書全体をレンダリングし、表示用のテキストと元の文字数を返す
+pub fn render_markdown_document(
+    original_markdown: &str,
+    theme: &ColorScheme,
+    image_art: ImageArt,
+    options: &RenderOptions,
+) -> (RenderedMarkdown, usize) {
+    let started = Instant::now();
+    let char_count = original_markdown.chars().count();
+    let mut rendered = render_markdown(original_markdown, theme, image_art, options);
+    rendered.render_time = started.elapsed();
+    (rendered, char_count)
+}
+
+/// 表示用のテキストが使っているおおよそのメモリ量（バイト）
+pub fn estimated_size(text: &Text) -> usize {
+    let spans: usize = text
+        .lines
+        .iter()
+        .map(|line| {
+            let content: usize = line
+                .spans
+                .iter()
+                .map(|span| match &span.content {
+                    Cow::Owned(content) => content.capacity(),
+                    Cow::Borrowed(_) => 0,
+                })
+                .sum();
+            line.spans.capacity() * size_of::<Span>() + content
+        })
+        .sum();
+    size_of::<Text>() + text.lines.capacity() * size_of::<Line>() + spans
+}
+
+pub fn render_markdown(markdown_input: &str, theme: &ColorScheme, image_art: ImageArt, options: &RenderOptions) -> RenderedMarkdown {
+    let decorations = options.decorations;
+    let spoken = decorations == Decorations::ScreenReader;
+    let mut lines: Vec<Line<'static>> = Vec::new();
+    let mut heading_lines: Vec<usize> = Vec::new();
+    let mut headings: Vec<String> = Vec::new();
+    let mut current_spans: Vec<Span<'static>> = Vec::new();
+    let mut style_stack: Vec<Style> = vec![Style::default().fg(theme.fg)];
+    let mut list_stack: Vec<u64> = Vec::new();
+    // 描画中の表。列幅を揃えるため、表の終わりまでセルを溜めておく
+    let mut table: Option<TableBuilder> = None;
+    let mut in_table_header = false;
+    // 開いている引用の開始行と、引用の深さが変わる行の一覧。枠線はレンダリング後にまとめて付ける
+    let mut quote_starts: Vec<usize> = Vec::new();
+    let mut quote_changes: Vec<(usize, usize)> = Vec::new();
+    let mut in_code_block = false;
+    // リンクの中の文字列は自動リンクにしない
+    let mut link_depth = 0;
+    // 読み込み中のコードブロックの中身。Text イベントは行の途中で分かれることがあるので、終わりまで溜める
+    let mut code_text = String::new();
+    let mut code_lines: Vec<usize> = Vec::new();
+    let mut footnote_refs: Vec<FootnoteRef> = Vec::new();
+    let mut footnotes: HashMap<String, String> = HashMap::new();
+    // 読み込み中の脚注定義のラベルと本文
+    let mut footnote_definition: Option<(String, String)> = None;
+    let mut links: Vec<LinkSpan> = Vec::new();
+    let mut images: Vec<LinkSpan> = Vec::new();
+    // 読み込み中の画像のURL・タイトル・代替テキスト
+    let mut image: Option<(String, String, String)> = None;
+    let mut title = frontmatter::block(markdown_input).and_then(|block| frontmatter::scalar(block, "title"));
+    // 描画中の見出しの本文が始まるスパンの位置（読み上げ用の接頭辞の後）
+    let mut heading_start = 0;
+    let mut heading_levels: Vec<usize> = Vec::new();
+    let mut heading_numbers: Vec<String> = Vec::new();
+    let mut section_numbers = SectionNumbers::default();
+    // 描画した行ごとの元のMarkdownの行番号（1から）。`lines` と同じ長さに揃える
+    let mut source_lines: Vec<usize> = Vec::new();
+    let line_starts: Vec<usize> = std::iter::once(0).chain(markdown_input.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let source_line = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+    // 描画中の行（`current_spans`）の内容が始まった元の行
+    let mut spans_source: Option<usize> = None;
+    // コードブロックの中身の最初の行（フェンスの次の行）
+    let mut code_source = 0;
+
+    let mut events: Vec<_> = MarkdownParser::new_ext(markdown_input, Options::all()).into_offset_iter().collect();
+    let mut script_error = None;
+    if let Some(script) = &options.script {
+        match script.on_render(events.clone()) {
+            Ok(transformed) => events = transformed,
+            Err(e) => script_error = Some(e.to_string()),
+        }
+    }
+    for (event, range) in events {
+        let first_line = source_line(range.start);
+        let last_line = source_line(range.end.saturating_sub(1)).max(first_line);
+        let lines_before = lines.len();
+        let had_spans = !current_spans.is_empty();
+        let mut block_start = first_line;
+        match event {
+            MarkdownEvent::Start(tag) => {
+                // 子要素のスタイルは常に親のスタイルに重ねる（リンク内の太字、引用内の強調など）
+                let current_style = *style_stack.last().unwrap_or(&Style::default());
+                match tag {
+                    Tag::Heading { level, .. } => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        lines.push(Line::default());
+                        heading_lines.push(lines.len());
+                        let base_style = Style::default()
+                                .add_modifier(Modifier::BOLD)
+                                .fg(theme.heading);
+                        let style = if level >= HeadingLevel::H3 {
+                            base_style.add_modifier(Modifier::DIM)
+                        } else {
+                            base_style
+                        };
+                        let style = current_style.patch(style);
+                        if spoken {
+                            current_spans.push(Span::styled(format!("Heading level {}: ", level as usize), style));
+                        }
+                        heading_levels.push(level as usize);
+                        if options.number_headings {
+                            let number = section_numbers.next(level as usize);
+                            current_spans.push(Span::styled(format!("{} ", number), style));
+                            heading_numbers.push(number);
+                        } else {
+                            heading_numbers.push(String::new());
+                        }
+                        heading_start = current_spans.len();
+                        style_stack.push(style);
+                    }
+                    Tag::BlockQuote => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        if spoken {
+                            lines.push(Line::from(Span::styled("Quote:".to_string(), Style::default().fg(theme.comment))));
+                        }
+                        quote_starts.push(lines.len());
+                        quote_changes.push((lines.len(), quote_starts.len()));
+                        style_stack.push(current_style.fg(theme.quote_fg));
+                    }
+                    Tag::CodeBlock(kind) => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        lines.push(Line::default());
+                        in_code_block = true;
+                        code_source = first_line + matches!(kind, CodeBlockKind::Fenced(_)) as usize;
+                        let lang = match kind {
+                            CodeBlockKind::Fenced(lang) => lang.into_string(),
+                            CodeBlockKind::Indented => String::new(),
+                        };
+                        let border_style = Style::default().fg(theme.comment);
+                        let opening = match (spoken, lang.is_empty()) {
+                            (false, _) => format!("{}{} ", decorations.corners()[0], decorations.horizontal().repeat(3)),
+                            (true, true) => "Code block".to_string(),
+                            (true, false) => "Code block: ".to_string(),
+                        };
+                        lines.push(Line::from(vec![
+                            Span::styled(opening, border_style),
+                            Span::styled(lang, Style::default().fg(Color::Yellow)),
+                        ]));
+                        style_stack.push(current_style.bg(theme.code_bg));
+                    }
+                    Tag::Table(aligns) => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        table = Some(TableBuilder::new(aligns));
+                    }
+                    // 見出し行は TableRow を持たないので、ここで行を始める
+                    Tag::TableHead | Tag::TableRow => {
+                        in_table_header |= matches!(tag, Tag::TableHead);
+                        if let Some(table) = &mut table {
+                            table.rows.push(Vec::new());
+                        }
+                    }
+                    Tag::TableCell => {
+                        if let Some(row) = table.as_mut().and_then(|table| table.rows.last_mut()) {
+                            row.push(Vec::new());
+                        }
+                    }
+                    Tag::List(start_num) => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        list_stack.push(start_num.unwrap_or(1));
+                    }
+                    Tag::Item => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        let indent = "  ".repeat(list_stack.len().saturating_sub(1));
+                        let marker = if let Some(num) = list_stack.last_mut() {
+                            let m = if spoken { format!("Item {}: ", *num) } else { format!("{}. ", *num) };
+                            *num += 1;
+                            m
+                        } else if decorations == Decorations::Ascii {
+                            "* ".to_string()
+                        } else {
+                            "• ".to_string()
+                        };
+                        current_spans.push(Span::raw(indent));
+                        current_spans
+                            .push(Span::styled(marker, Style::default().fg(theme.comment)));
+                        // 終了時に取り除くので、項目の中身は親のスタイルのまま積んでおく
+                        style_stack.push(current_style);
+                    }
+                    Tag::Emphasis => {
+                        style_stack.push(current_style.add_modifier(Modifier::ITALIC));
+                    }
+                    Tag::Strong => {
+                        style_stack.push(current_style.add_modifier(Modifier::BOLD));
+                    }
+                    Tag::Strikethrough => {
+                        style_stack.push(current_style.add_modifier(Modifier::CROSSED_OUT));
+                    }
+                    Tag::Link { dest_url, .. } => {
+                        link_depth += 1;
+                        if let Some(table) = &mut table {
+                            table.anchor(TableAnchor::Link(links.len()), current_spans.len());
+                        }
+                        links.push(LinkSpan {
+                            line: lines.len(),
+                            span: current_spans.len(),
+                            url: dest_url.to_string(),
+                        });
+                        style_stack.push(current_style.fg(theme.link).add_modifier(Modifier::UNDERLINED));
+                    }
+                    Tag::Image { dest_url, title, .. } => {
+                        if table.is_none() && !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        image = Some((dest_url.to_string(), title.to_string(), String::new()));
+                    }
+                    Tag::FootnoteDefinition(label) => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        current_spans.push(Span::styled(format!("[^{}]: ", label), Style::default().fg(theme.comment)));
+                        footnote_definition = Some((label.to_string(), String::new()));
+                    }
+                    _ => {}
+                }
+            }
+            MarkdownEvent::End(tag) => {
+                match tag {
+                    TagEnd::BlockQuote => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        style_stack.pop();
+                        // 最後の段落の後の空行は引用の外に出す
+                        let start = quote_starts.pop().unwrap_or_default();
+                        let mut end = lines.len();
+                        if end > start && lines[end - 1].spans.is_empty() {
+                            end -= 1;
+                        }
+                        if spoken {
+                            let closing = Line::from(Span::styled("End of quote".to_string(), Style::default().fg(theme.comment)));
+                            lines.insert(end, closing);
+                            source_lines.insert(end.min(source_lines.len()), last_line);
+                        }
+                        quote_changes.push((end, quote_starts.len()));
+                    }
+                    TagEnd::Heading(level) => {
+                        let text: String = current_spans.iter().skip(heading_start).map(|span| span.content.as_ref()).collect();
+                        // インラインコードの前後の余白などは1つの空白にまとめる
+                        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                        if level == HeadingLevel::H1 && title.is_none() && !text.is_empty() {
+                            title = Some(text.clone());
+                        }
+                        headings.push(text);
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        style_stack.pop();
+                    }
+                    TagEnd::Item => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        style_stack.pop();
+                    }
+                    TagEnd::CodeBlock => {
+                        in_code_block = false;
+                        block_start = code_source;
+                        let style = style_stack.last().copied().unwrap_or_default().fg(theme.fg);
+                        let code = std::mem::take(&mut code_text);
+                        // 空行も含めて元の行をそのまま枠の中に並べる
+                        if !code.is_empty() {
+                            for line in code.strip_suffix('\n').unwrap_or(&code).split('\n') {
+                                code_lines.push(lines.len());
+                                let border = if spoken { "  ".to_string() } else { format!("{} ", decorations.vertical()) };
+                                lines.push(Line::from(vec![
+                                    Span::styled(border, Style::default().fg(theme.comment)),
+                                    Span::styled(expand_tabs(line.strip_suffix('\r').unwrap_or(line)), style),
+                                ]));
+                            }
+                        }
+                        let closing = if spoken {
+                            "End of code block".to_string()
+                        } else {
+                            format!("{}{}", decorations.corners()[2], decorations.horizontal().repeat(18))
+                        };
+                        lines.push(Line::from(Span::styled(closing, Style::default().fg(theme.comment))));
+                        lines.push(Line::default());
+                        style_stack.pop();
+                    }
+                    TagEnd::Table => {
+                        if let Some(table) = table.take() {
+                            let first_line = lines.len();
+                            lines.extend(table.finish(first_line, theme, decorations, &mut links, &mut footnote_refs));
+                        }
+                        lines.push(Line::default());
+                    }
+                    TagEnd::TableHead => {
+                        in_table_header = false;
+                    }
+                    TagEnd::TableCell => {
+                        let spans = std::mem::take(&mut current_spans);
+                        if let Some(cell) = table.as_mut().and_then(|table| table.rows.last_mut()?.last_mut()) {
+                            *cell = spans;
+                        }
+                    }
+                    TagEnd::List(_) => {
+                        list_stack.pop();
+                        lines.push(Line::default());
+                    }
+                    TagEnd::Paragraph => {
+                        if !current_spans.is_empty() {
+                            lines.push(Line::from(std::mem::take(&mut current_spans)));
+                        }
+                        lines.push(Line::default());
+                    }
+                    TagEnd::Link => {
+                        link_depth -= 1;
+                        style_stack.pop();
+                    }
+                    TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                        style_stack.pop();
+                    }
+                    // 表の中では枠を描けないので、代替テキストだけを表示する
+                    TagEnd::Image if table.is_some() => {
+                        if let Some((_, _, alt)) = image.take() {
+                            let alt = if alt.is_empty() { "image".to_string() } else { alt };
+                            let marker = decorations.image_marker();
+                            current_spans.push(Span::styled(format!("{} {}", marker, alt), Style::default().fg(theme.file_image)));
+                        }
+                    }
+                    TagEnd::Image => {
+                        if let Some((url, title, alt)) = image.take() {
+                            images.push(LinkSpan { line: lines.len(), span: 0, url: url.clone() });
+                            match image_art(&url) {
+                                Some(art) => {
+                                    lines.extend(art);
+                                    if !alt.is_empty() {
+                                        lines.push(Line::from(Span::styled(alt, Style::default().fg(theme.comment))));
+                                    }
+                                }
+                                None => lines.extend(image_placeholder(&url, &title, &alt, theme, decorations)),
+                            }
+                        }
+                    }
+                    TagEnd::FootnoteDefinition => {
+                        if let Some((label, text)) = footnote_definition.take() {
+                            footnotes.insert(label, text.trim().to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // 画像の代替テキストは枠の中に表示するので、本文には出さない
+            MarkdownEvent::Text(text) | MarkdownEvent::Code(text) if let Some((_, _, alt)) = &mut image => {
+                alt.push_str(&text);
+            }
+            MarkdownEvent::Text(text) => {
+                if let Some((_, definition)) = &mut footnote_definition {
+                    definition.push_str(&text);
+                }
+                let style = *style_stack.last().unwrap_or(&Style::default());
+                if in_code_block {
+                    code_text.push_str(&text);
+                } else {
+                    let final_style = if in_table_header {
+                        style.add_modifier(Modifier::BOLD)
+                    } else {
+                        style
+                    };
+                    let rules = if link_depth == 0 { &options.autolinks[..] } else { &[] };
+                    for (segment, url) in autolink::split(&text, rules) {
+                        let Some(url) = url else {
+                            current_spans.push(Span::styled(segment, final_style));
+                            continue;
+                        };
+                        if let Some(table) = &mut table {
+                            table.anchor(TableAnchor::Link(links.len()), current_spans.len());
+                        }
+                        links.push(LinkSpan { line: lines.len(), span: current_spans.len(), url });
+                        current_spans.push(Span::styled(segment, final_style.fg(theme.link).add_modifier(Modifier::UNDERLINED)));
+                    }
+                }
+            }
+            // `<br>` はHTMLのまま表示せず、その位置で改行する
+            MarkdownEvent::Html(html) | MarkdownEvent::InlineHtml(html) if is_line_break_tag(&html) => {
+                if let Some((_, definition)) = &mut footnote_definition {
+                    definition.push(' ');
+                }
+                if table.is_some() {
+                    current_spans.push(Span::raw(" ".to_string()));
+                } else if !current_spans.is_empty() {
+                    lines.push(Line::from(std::mem::take(&mut current_spans)));
+                }
+            }
+            MarkdownEvent::Html(html) => {
+                current_spans.push(Span::styled(html.to_string(), Style::default().fg(theme.comment)));
+            }
+            MarkdownEvent::Code(text) => {
+                if let Some((_, definition)) = &mut footnote_definition {
+                    definition.push_str(&text);
+                }
+                // 見出しの色や表の見出し行の太字を引き継ぎ、背景だけをインラインコードのものにする
+                let style = style_stack.last().copied().unwrap_or_default().bg(theme.inline_code_bg);
+                let style = if in_table_header { style.add_modifier(Modifier::BOLD) } else { style };
+                current_spans.push(Span::styled(format!(" {} ", text), style));
+            }
+            MarkdownEvent::HardBreak if !current_spans.is_empty() => {
+                lines.push(Line::from(std::mem::take(&mut current_spans)));
+            }
+            MarkdownEvent::FootnoteReference(label) => {
+                if let Some(table) = &mut table {
+                    table.anchor(TableAnchor::Footnote(footnote_refs.len()), current_spans.len());
+                }
+                footnote_refs.push(FootnoteRef {
+                    line: lines.len(),
+                    span: current_spans.len(),
+                    label: label.to_string(),
+                });
+                current_spans.push(Span::styled(format!("[^{}]", label), Style::default().fg(theme.link)));
+            }
+            MarkdownEvent::SoftBreak => {
+                if let Some((_, definition)) = &mut footnote_definition {
+                    definition.push(' ');
+                }
+                current_spans.push(Span::raw(" ".to_string()));
+            }
+            MarkdownEvent::Rule => {
+                if !current_spans.is_empty() {
+                    lines.push(Line::from(std::mem::take(&mut current_spans)));
+                }
+                let rule = if spoken { "Separator".to_string() } else { decorations.horizontal().repeat(80) };
+                lines.push(Line::from(Span::styled(rule, Style::default().fg(theme.hr))));
+                lines.push(Line::default());
+            }
+            _ => {}
+        }
+        // 溜めていた行は内容が始まった行、それ以外はブロックの先頭から順に対応させる（空行は数えない）
+        let mut offset = 0;
+        for (index, rendered) in lines.iter().enumerate().skip(source_lines.len()) {
+            let line = match spans_source {
+                Some(line) if index == lines_before && had_spans => line,
+                _ => (block_start + offset).min(last_line),
+            };
+            source_lines.push(line);
+            offset += !rendered.spans.is_empty() as usize;
+        }
+        if current_spans.is_empty() {
+            spans_source = None;
+        } else if spans_source.is_none() || lines.len() > lines_before {
+            spans_source = Some(first_line);
+        }
+    }
+    if !current_spans.is_empty() {
+        lines.push(Line::from(std::mem::take(&mut current_spans)));
+        source_lines.push(spans_source.unwrap_or(1));
+    }
+    // 読み上げでは引用の始まりと終わりを言葉で示したので、行ごとの枠線は付けない
+    if !spoken {
+        add_quote_borders(&mut lines, &quote_changes, theme, decorations, &mut links, &mut footnote_refs);
+    }
+    RenderedMarkdown {
+        text: Text::from(lines),
+        heading_lines,
+        headings,
+        heading_levels,
+        heading_numbers,
+        source_lines,
+        script_error,
+        footnote_refs,
+        footnotes,
+        links,
+        images,
+        code_lines,
+        title,
+        render_time: Duration::ZERO,
+    }
+}
+
+/// タブを4桁ごとのタブ位置まで空白で埋める。端末にタブをそのまま渡すと幅が崩れる
+fn expand_tabs(line: &str) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut expanded = String::with_capacity(line.len() + 8);
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = 4 - column % 4;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += Span::raw(c.to_string()).width();
+        }
+    }
+    expanded
+}
+
+/// 折り返し表示でもコードブロックの行は折り返さず、`width` 列に収まらない部分を `…` で切り詰める
+pub fn clip_code_lines(text: &mut Text<'static>, code_lines: &[usize], width: usize, theme: &ColorScheme) {
+    for &index in code_lines {
+        let Some(line) = text.lines.get_mut(index) else {
+            continue;
+        };
+        if line.width() <= width {
+            continue;
+        }
+        let mut remaining = width.saturating_sub(1);
+        let mut clipped = Vec::with_capacity(line.spans.len() + 1);
+        for span in line.spans.drain(..) {
+            let span_width = span.width();
+            if span_width <= remaining {
+                remaining -= span_width;
+                clipped.push(span);
+                continue;
+            }
+            let mut content = String::new();
+            for c in span.content.chars() {
+                let char_width = Span::raw(c.to_string()).width();
+                if char_width > remaining {
+                    break;
+                }
+                remaining -= char_width;
+                content.push(c);
+            }
+            clipped.push(Span::styled(content, span.style));
+            break;
+        }
+        clipped.push(Span::styled("…".to_string(), Style::default().fg(theme.comment)));
+        line.spans = clipped;
+    }
+}
+
+/// 引用の中の行すべてに深さの分だけ枠線を付け、その行のリンクと脚注参照の位置をずらす
+fn add_quote_borders(
+    lines: &mut [Line<'static>],
+    changes: &[(usize, usize)],
+    theme: &ColorScheme,
+    decorations: Decorations,
+    links: &mut [LinkSpan],
+    footnote_refs: &mut [FootnoteRef],
+) {
+    if changes.is_empty() {
+        return;
+    }
+    let mut depths = vec![0; lines.len()];
+    let mut changes = changes.iter().peekable();
+    let mut depth = 0;
+    for (i, line_depth) in depths.iter_mut().enumerate() {
+        while let Some((_, d)) = changes.next_if(|(at, _)| *at <= i) {
+            depth = *d;
+        }
+        *line_depth = depth;
+    }
+    let border_style = Style::default().fg(theme.quote_border);
+    for (line, &depth) in lines.iter_mut().zip(&depths) {
+        if depth > 0 {
+            let border = [Span::styled(decorations.quote_border().to_string(), border_style), Span::raw(" ".to_string())];
+            line.spans.splice(0..0, border.iter().cycle().take(depth * 2).cloned());
+        }
+    }
+    for link in links.iter_mut() {
+        link.span += depths.get(link.line).copied().unwrap_or_default() * 2;
+    }
+    for reference in footnote_refs.iter_mut() {
+        reference.span += depths.get(reference.line).copied().unwrap_or_default() * 2;
+    }
+}
+
+/// 引用の枠線のスパンの数。枠線は `▎`（ASCIIでは `>`）と空白の2つのスパンを深さの分だけ並べたもの
+fn quote_border_len(line: &Line) -> usize {
+    line.spans
+        .chunks_exact(2)
+        .take_while(|pair| matches!(pair[0].content.as_ref(), "▎" | ">") && pair[1].content == " ")
+        .count()
+        * 2
+}
+
+/// 端末の折り返しでは引用の枠線が途切れるので、引用の行は先に `width` 列で折り返して各行に枠線を付ける
+pub fn wrap_quote_lines(text: &mut Text<'static>, width: usize) {
+    if !text.lines.iter().any(|line| quote_border_len(line) > 0 && line.width() > width) {
+        return;
+    }
+    let mut wrapped = Vec::with_capacity(text.lines.len());
+    for line in std::mem::take(&mut text.lines) {
+        let border_len = quote_border_len(&line);
+        if border_len == 0 || line.width() <= width {
+            wrapped.push(line);
+            continue;
+        }
+        let (border, body) = line.spans.split_at(border_len);
+        let border_width: usize = border.iter().map(Span::width).sum();
+        let available = width.saturating_sub(border_width).max(1);
+        let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new()];
+        let mut row_width = 0;
+        for span in body {
+            for word in span.content.split_inclusive(' ') {
+                let word_width = Span::raw(word.trim_end()).width();
+                // 折り返した行の先頭の空白は捨てる
+                if word_width == 0 && row_width == 0 && rows.len() > 1 {
+                    continue;
+                }
+                if word_width <= available {
+                    if row_width + word_width > available && row_width > 0 {
+                        rows.push(Vec::new());
+                        row_width = 0;
+                    }
+                    push_styled(rows.last_mut().unwrap(), word, span.style);
+                    row_width += Span::raw(word).width();
+                    continue;
+                }
+                // 空白の無い長い語（日本語の文など）は文字単位で折り返す
+                for c in word.chars() {
+                    let mut buffer = [0; 4];
+                    let c = c.encode_utf8(&mut buffer);
+                    let char_width = Span::raw(&*c).width();
+                    if row_width + char_width > available && row_width > 0 {
+                        rows.push(Vec::new());
+                        row_width = 0;
+                    }
+                    push_styled(rows.last_mut().unwrap(), c, span.style);
+                    row_width += char_width;
+                }
+            }
+        }
+        for mut row in rows {
+            if let Some(last) = row.last_mut() {
+                last.content = Cow::Owned(last.content.trim_end().to_string());
+            }
+            let mut spans = border.to_vec();
+            spans.extend(row);
+            wrapped.push(Line::from(spans).style(line.style));
+        }
+    }
+    text.lines = wrapped;
+}
+
+/// `line` を `width` 列で折り返して描いたときの段数。引用の行は `wrap_quote_lines` と同じように折り返して数える
+pub fn wrapped_rows(line: &Line<'static>, width: usize) -> usize {
+    if width == 0 || line.width() <= width {
+        return 1;
+    }
+    let mut text = Text::from(line.clone());
+    wrap_quote_lines(&mut text, width);
+    Paragraph::new(text).wrap(Wrap { trim: false }).line_count(width as u16).max(1)
+}
+
+/// 直前のスパンと同じスタイルならつなげ、違えば新しいスパンにする
+fn push_styled(row: &mut Vec<Span<'static>>, content: &str, style: Style) {
+    match row.last_mut() {
+        Some(last) if last.style == style => last.content.to_mut().push_str(content),
+        _ => row.push(Span::styled(content.to_string(), style)),
+    }
+}
+
+/// 表の中のリンクや脚注参照。表を描画した後に行とスパンの位置を直す
+enum TableAnchor {
+    Link(usize),
+    Footnote(usize),
+}
+
+/// 描画中の表。セルごとのスパンを溜めておき、表の終わりで列幅を揃えて行にする
+struct TableBuilder {
+    alignments: Vec<MarkdownAlignment>,
+    /// 行ごとのセルのスパン。先頭の行が見出し行
+    rows: Vec<Vec<Vec<Span<'static>>>>,
+    /// 表の中のリンク・脚注参照と、その行・セル・セル内のスパンの位置
+    anchors: Vec<(TableAnchor, usize, usize, usize)>,
+}
+
+impl TableBuilder {
+    fn new(alignments: Vec<MarkdownAlignment>) -> Self {
+        Self { alignments, rows: Vec::new(), anchors: Vec::new() }
+    }
+
+    /// 描画中のセルの `span` 番目のスパンにリンクなどがあることを記録する
+    fn anchor(&mut self, anchor: TableAnchor, span: usize) {
+        let row = self.rows.len().saturating_sub(1);
+        let cell = self.rows.last().map_or(0, |cells| cells.len().saturating_sub(1));
+        self.anchors.push((anchor, row, cell, span));
+    }
+
+    /// 列幅を揃えた行にする。`first_line` は表の先頭の行番号で、記録したリンクなどの位置を直すのに使う
+    fn finish(
+        self,
+        first_line: usize,
+        theme: &ColorScheme,
+        decorations: Decorations,
+        links: &mut [LinkSpan],
+        footnote_refs: &mut [FootnoteRef],
+    ) -> Vec<Line<'static>> {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let cell_width = |cell: &[Span]| cell.iter().map(Span::width).sum::<usize>();
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| self.rows.iter().filter_map(|row| row.get(column)).map(|cell| cell_width(cell)).max().unwrap_or(0))
+            .collect();
+        let border = Style::default().fg(theme.comment);
+
+        let mut lines = Vec::with_capacity(self.rows.len());
+        // 行ごとの、各セルの中身が始まるスパンの添字
+        let mut cell_starts: Vec<Vec<usize>> = Vec::with_capacity(self.rows.len());
+        let spoken = decorations == Decorations::ScreenReader;
+        for (index, mut row) in self.rows.into_iter().enumerate() {
+            row.resize_with(columns, Vec::new);
+            // 読み上げでは罫線の代わりに行の種類を言い、セルを読点で区切る
+            let opening = match (spoken, index) {
+                (false, _) => format!("{} ", decorations.vertical()),
+                (true, 0) => "Table header: ".to_string(),
+                (true, _) => format!("Row {}: ", index),
+            };
+            let mut spans = vec![Span::styled(opening, border)];
+            let mut starts = Vec::with_capacity(columns);
+            for (column, cell) in row.into_iter().enumerate() {
+                let padding = if spoken { 0 } else { widths[column] - cell_width(&cell) };
+                let (left, right) = match self.alignments.get(column) {
+                    Some(MarkdownAlignment::Right) => (padding, 0),
+                    Some(MarkdownAlignment::Center) => (padding / 2, padding - padding / 2),
+                    _ => (0, padding),
+                };
+                // 位置を計算しやすいよう、余白が無くても左右の余白のスパンを置く
+                spans.push(Span::raw(" ".repeat(left)));
+                starts.push(spans.len());
+                spans.extend(cell);
+                spans.push(Span::raw(" ".repeat(right)));
+                let separator = match (spoken, column + 1 == columns) {
+                    (false, true) => format!(" {}", decorations.vertical()),
+                    (false, false) => format!(" {} ", decorations.vertical()),
+                    (true, true) => String::new(),
+                    (true, false) => ", ".to_string(),
+                };
+                spans.push(Span::styled(separator, border));
+            }
+            lines.push(Line::from(spans));
+            cell_starts.push(starts);
+        }
+
+        for (anchor, row, cell, span) in self.anchors {
+            let Some(start) = cell_starts.get(row).and_then(|starts| starts.get(cell)) else {
+                continue;
+            };
+            let (line, span) = (first_line + row, start + span);
+            match anchor {
+                TableAnchor::Link(index) => {
+                    if let Some(link) = links.get_mut(index) {
+                        (link.line, link.span) = (line, span);
+                    }
+                }
+                TableAnchor::Footnote(index) => {
+                    if let Some(footnote) = footnote_refs.get_mut(index) {
+                        (footnote.line, footnote.span) = (line, span);
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// `<br>`・`<br/>`・`<br />` などの改行タグかどうか。大文字小文字は区別しない
+fn is_line_break_tag(html: &str) -> bool {
+    let Some(inner) = html.trim().strip_prefix('<').and_then(|tag| tag.strip_suffix('>')) else {
+        return false;
+    };
+    inner
+        .trim_end_matches('/')
+        .split_whitespace()
+        .next()
+        .is_some_and(|name| name.eq_ignore_ascii_case("br"))
+}
+
+/// 画像の代わりに表示する、代替テキストとファイル名を書いた枠
+fn image_placeholder(url: &str, title: &str, alt: &str, theme: &ColorScheme, decorations: Decorations) -> Vec<Line<'static>> {
+    let border_style = Style::default().fg(theme.file_image);
+    let spoken = decorations == Decorations::ScreenReader;
+    let mut rows = vec![(
+        format!("{} {}", decorations.image_marker(), if alt.is_empty() { "image" } else { alt }),
+        Style::default().fg(theme.file_image).add_modifier(Modifier::BOLD),
+    )];
+    if !title.is_empty() {
+        rows.push((title.to_string(), Style::default().fg(theme.fg).add_modifier(Modifier::ITALIC)));
+    }
+    rows.push((url.to_string(), Style::default().fg(theme.comment)));
+    if spoken {
+        return rows.into_iter().map(|(text, style)| Line::from(Span::styled(text, style))).collect();
+    }
+
+    let width = rows.iter().map(|(text, _)| Span::raw(text.as_str()).width()).max().unwrap_or(0);
+    let [top_left, top_right, bottom_left, bottom_right] = decorations.corners();
+    let (horizontal, vertical) = (decorations.horizontal().repeat(width + 2), decorations.vertical());
+    let mut lines = vec![Line::from(Span::styled(format!("{}{}{}", top_left, horizontal, top_right), border_style))];
+    for (text, style) in rows {
+        let padding = " ".repeat(width - Span::raw(text.as_str()).width());
+        lines.push(Line::from(vec![
+            Span::styled(format!("{} ", vertical), border_style),
+            Span::styled(text, style),
+            Span::styled(format!("{} {}", padding, vertical), border_style),
+        ]));
+    }
+    lines.push(Line::from(Span::styled(format!("{}{}{}", bottom_left, horizontal, bottom_right), border_style)));
+    lines
+}
@@ -0,0 +1,246 @@
+// --- Rhaiのスクリプトによるフック ---
+//
+// 設定の `script` に書いたRhaiのスクリプトの関数を、次の場面で呼ぶ。定義していない関数は呼ばない。
+//
+// * `on_open(path, markdown)`: ファイルを読み込んだ後、描画する前のMarkdownを受け取る。文字列を返すとそれを描画する
+// * `on_render(events)`: 描画するイベントの並び（`#{kind: "text", text: "..."}` などのマップの配列）を受け取り、
+//   書き換えた配列を返す。課題番号をリンクにするなら、テキストを `start`/`end` の `link` で囲んだイベントに分ける
+// * `command_<名前>(args, doc)`: プレビューの `:<名前> 引数...` で呼ぶ独自のコマンド。`doc` は `#{path, line}`。
+//   文字列を返すとフッターに表示し、`#{message, open, line}` のマップなら表示・ファイルを開く・行へ移動する
+//
+// スクリプトはファイルやコマンドに触れられず、無限ループしないよう実行できる処理の数も制限する。
+
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+
+/// 1回の呼び出しで実行できる処理の数。超えたら打ち切ってエラーにする
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// 読み込んだスクリプト
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+}
+
+impl std::fmt::Debug for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Script").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+/// `command_<名前>` が返した、プレビューで行うこと
+#[derive(Debug, Default, PartialEq)]
+pub struct CommandResult {
+    /// フッターに表示するメッセージ
+    pub message: Option<String>,
+    /// 開くファイル。相対パスは表示中の文書のディレクトリから
+    pub open: Option<PathBuf>,
+    /// 移動する元のMarkdownの行番号（1から）
+    pub line: Option<usize>,
+}
+
+/// 読み込んだスクリプトのパスと、読み込んだときのファイルの更新日時
+type Loaded = (PathBuf, Option<SystemTime>, Arc<Script>);
+
+/// 読み込んだスクリプト。プレビューを開くたびに読み直さないよう覚えておく
+static LOADED: Mutex<Vec<Loaded>> = Mutex::new(Vec::new());
+
+/// スクリプトを読み込む。前回から更新されていなければ、前回読み込んだものを返す
+pub fn load(path: &Path) -> io::Result<Arc<Script>> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut loaded = LOADED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((_, _, script)) = loaded.iter().find(|(loaded_path, loaded_modified, _)| loaded_path == path && *loaded_modified == modified) {
+        return Ok(script.clone());
+    }
+    let script = Arc::new(Script::compile(path)?);
+    loaded.retain(|(loaded_path, _, _)| loaded_path != path);
+    loaded.push((path.to_path_buf(), modified, script.clone()));
+    Ok(script)
+}
+
+impl Script {
+    fn compile(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("スクリプト {} を読み込めません: {}", path.display(), e)))?;
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        // 入れ子の深さの上限は、デバッグビルドでもリリースビルドと同じにする
+        engine.set_max_expr_depths(64, 32);
+        let ast = engine.compile(&source).map_err(|e| invalid(format!("スクリプト {} を解釈できません: {}", path.display(), e)))?;
+        Ok(Self { path: path.to_path_buf(), engine, ast })
+    }
+
+    /// `name` という引数 `arity` 個の関数を定義しているか
+    fn defines(&self, name: &str, arity: usize) -> bool {
+        self.ast.iter_functions().any(|function| function.name == name && function.params.len() == arity)
+    }
+
+    fn call(&self, name: &str, args: impl FuncArgs) -> io::Result<Dynamic> {
+        // 関数の外の文は読み込んだときのままにして、呼び出しのたびに実行しない
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| invalid(format!("{} の {} が失敗しました: {}", self.path.display(), name, e)))
+    }
+
+    /// 読み込んだMarkdownを `on_open` に渡す。定義していないか、文字列以外を返したらそのまま返す
+    pub fn on_open(&self, path: &Path, markdown: String) -> io::Result<String> {
+        if !self.defines("on_open", 2) {
+            return Ok(markdown);
+        }
+        let result = self.call("on_open", (path.display().to_string(), markdown.clone()))?;
+        Ok(result.into_string().unwrap_or(markdown))
+    }
+
+    /// 描画するイベントの並びを `on_render` で書き換える。定義していなければそのまま返す。
+    /// スクリプトが加えたイベントの元の位置は、直前のイベントの位置にする
+    pub fn on_render<'a>(&self, events: Vec<(Event<'a>, Range<usize>)>) -> io::Result<Vec<(Event<'a>, Range<usize>)>> {
+        if !self.defines("on_render", 1) {
+            return Ok(events);
+        }
+        let array: Array = events.iter().enumerate().map(|(index, (event, _))| event_to_map(index, event).into()).collect();
+        let result = self.call("on_render", (array,))?;
+        let array = result.try_cast::<Array>().ok_or_else(|| invalid(format!("{} の on_render はイベントの配列を返してください", self.path.display())))?;
+        let mut originals: Vec<Option<(Event<'a>, Range<usize>)>> = events.into_iter().map(Some).collect();
+        let mut range = 0..0;
+        let mut transformed = Vec::with_capacity(array.len());
+        for item in array {
+            let map = item.try_cast::<Map>().ok_or_else(|| invalid("on_render のイベントはマップで返してください".to_string()))?;
+            let index = map.get("index").and_then(|index| index.as_int().ok()).and_then(|index| usize::try_from(index).ok());
+            if let Some((_, original_range)) = index.and_then(|index| originals.get(index)).and_then(Option::as_ref) {
+                range = original_range.clone();
+            }
+            let event = match map_to_event(&map)? {
+                Some(event) => event,
+                // 書き換えられない種類のイベントは、添字で元のイベントを取り出す
+                None => match index.and_then(|index| originals.get_mut(index)).and_then(Option::take) {
+                    Some((event, _)) => event,
+                    None => return Err(invalid(format!("on_render が不明なイベントを返しました: {:?}", map))),
+                },
+            };
+            transformed.push((event, range.clone()));
+        }
+        Ok(transformed)
+    }
+
+    /// `:name` で呼ぶ `command_<name>` を定義しているか
+    pub fn has_command(&self, name: &str) -> bool {
+        self.defines(&command_function(name), 2)
+    }
+
+    /// `command_<name>` を呼ぶ。`path` と `line` は表示中の文書と先頭の行
+    pub fn command(&self, name: &str, args: Vec<String>, path: Option<&Path>, line: usize) -> io::Result<CommandResult> {
+        let args: Array = args.into_iter().map(Dynamic::from).collect();
+        let mut doc = Map::new();
+        doc.insert("path".into(), path.map_or_else(|| Dynamic::UNIT, |path| path.display().to_string().into()));
+        doc.insert("line".into(), (line as i64).into());
+        let result = self.call(&command_function(name), (args, doc))?;
+        if result.is_unit() {
+            return Ok(CommandResult::default());
+        }
+        if result.is_string() {
+            return Ok(CommandResult { message: result.into_string().ok(), ..Default::default() });
+        }
+        let Some(map) = result.try_cast::<Map>() else {
+            return Err(invalid(format!("command_{} は文字列かマップを返してください", name)));
+        };
+        let string = |key: &str| map.get(key).and_then(|value| value.clone().into_string().ok());
+        Ok(CommandResult {
+            message: string("message"),
+            open: string("open").map(PathBuf::from),
+            line: map.get("line").and_then(|line| line.as_int().ok()).and_then(|line| usize::try_from(line).ok()).filter(|line| *line > 0),
+        })
+    }
+}
+
+/// `:my-command` は `command_my_command` を呼ぶ
+fn command_function(name: &str) -> String {
+    format!("command_{}", name.replace('-', "_"))
+}
+
+/// イベントをスクリプトに渡すマップにする。`index` は元の並びでの位置で、
+/// スクリプトで書き換えられないイベント（見出しや表など）を戻すときに使う
+fn event_to_map(index: usize, event: &Event) -> Map {
+    let mut map = Map::new();
+    map.insert("index".into(), (index as i64).into());
+    let (kind, tag, text) = match event {
+        Event::Text(text) => ("text", None, Some(text)),
+        Event::Code(text) => ("code", None, Some(text)),
+        Event::Html(text) => ("html", None, Some(text)),
+        Event::InlineHtml(text) => ("inline_html", None, Some(text)),
+        Event::SoftBreak => ("softbreak", None, None),
+        Event::HardBreak => ("hardbreak", None, None),
+        Event::Start(Tag::Link { dest_url, title, .. }) => {
+            map.insert("url".into(), dest_url.to_string().into());
+            map.insert("title".into(), title.to_string().into());
+            ("start", Some("link".to_string()), None)
+        }
+        Event::Start(tag) => ("start", Some(tag_name(&format!("{:?}", tag))), None),
+        Event::End(tag) => ("end", Some(tag_name(&format!("{:?}", tag))), None),
+        other => ("other", Some(tag_name(&format!("{:?}", other))), None),
+    };
+    map.insert("kind".into(), kind.into());
+    if let Some(tag) = tag {
+        map.insert("tag".into(), tag.into());
+    }
+    if let Some(text) = text {
+        map.insert("text".into(), text.to_string().into());
+    }
+    map
+}
+
+/// `Heading { level: H1, .. }` のようなデバッグ表示から、`heading` のような種類の名前を作る
+fn tag_name(debug: &str) -> String {
+    let name: String = debug.chars().take_while(|c| c.is_alphanumeric()).collect();
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// スクリプトが返したマップをイベントに戻す。書き換えられない種類なら None
+fn map_to_event(map: &Map) -> io::Result<Option<Event<'static>>> {
+    let string = |key: &str| map.get(key).and_then(|value| value.clone().into_string().ok()).unwrap_or_default();
+    let text = || CowStr::from(string("text"));
+    let event = match (string("kind").as_str(), string("tag").as_str()) {
+        ("text", _) => Event::Text(text()),
+        ("code", _) => Event::Code(text()),
+        ("html", _) => Event::Html(text()),
+        ("inline_html", _) => Event::InlineHtml(text()),
+        ("softbreak", _) => Event::SoftBreak,
+        ("hardbreak", _) => Event::HardBreak,
+        ("start", "link") => Event::Start(Tag::Link {
+            link_type: LinkType::Inline,
+            dest_url: string("url").into(),
+            title: string("title").into(),
+            id: CowStr::Borrowed(""),
+        }),
+        ("end", "link") => Event::End(TagEnd::Link),
+        ("start", "emphasis") => Event::Start(Tag::Emphasis),
+        ("end", "emphasis") => Event::End(TagEnd::Emphasis),
+        ("start", "strong") => Event::Start(Tag::Strong),
+        ("end", "strong") => Event::End(TagEnd::Strong),
+        ("start", "strikethrough") => Event::Start(Tag::Strikethrough),
+        ("end", "strikethrough") => Event::End(TagEnd::Strikethrough),
+        ("start" | "end" | "other", _) if map.contains_key("index") => return Ok(None),
+        (kind, tag) => return Err(invalid(format!("on_render が不明なイベントを返しました: {} {}", kind, tag))),
+    };
+    Ok(Some(event))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,252 @@
+// --- 全文検索（`:search`）と似た文書（`:related`） ---
+//
+// 文書の集まりの索引（vault）が持つ文書ごとの語の出現回数から、BM25で順位を付ける。
+// 英数字は単語ごとに、日本語のように空白で区切らない文字は隣り合う2文字ずつを1語とする。
+// 似た文書は、文書の特徴的な語（tf-idfの大きい語）で検索し、リンクのつながりを加味して探す。
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::vault::{self, Vault};
+
+/// BM25の語の出現回数の効き方と、文書の長さによる補正の強さ
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// `:search` で表示する文書の数
+pub const RESULTS: usize = 50;
+/// `:related` で似た文書を探すのに使う、文書の特徴的な語の数
+const RELATED_TERMS: usize = 25;
+/// 似た文書の点数に加える、どちらかがもう一方にリンクしている場合と、同じ文書へのリンク1つごとの点数
+const LINKED_BONUS: f64 = 0.5;
+const SHARED_LINK_BONUS: f64 = 0.2;
+/// 抜粋の長さ（文字数）
+const SNIPPET_CHARS: usize = 80;
+
+/// 検索で見つかった文書
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub path: PathBuf,
+    pub score: f64,
+    /// 抜粋の元のMarkdownでの行番号（1始まり）
+    pub line: usize,
+    pub snippet: String,
+}
+
+/// 似た文書
+#[derive(Debug, Clone, PartialEq)]
+pub struct Related {
+    pub path: PathBuf,
+    pub score: f64,
+    /// どちらかがもう一方にリンクしているかどうか
+    pub linked: bool,
+    /// 共通する特徴的な語（重みの大きい順）
+    pub terms: Vec<String>,
+}
+
+/// 空白で区切らない文字（漢字・ひらがな・カタカナ・ハングル）
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{ac00}'..='\u{d7af}')
+}
+
+/// テキストを小文字の語に分ける
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut word = String::new();
+    // 続いている空白で区切らない文字
+    let mut run: Vec<char> = Vec::new();
+    // 最後の語も区切られるよう、末尾に空白を足して読む
+    for c in text.chars().chain([' ']) {
+        let cjk = is_cjk(c);
+        if !word.is_empty() && (cjk || !c.is_alphanumeric()) {
+            terms.push(std::mem::take(&mut word));
+        }
+        if !run.is_empty() && !cjk {
+            match run.as_slice() {
+                [single] => terms.push(single.to_string()),
+                _ => terms.extend(run.windows(2).map(|pair| pair.iter().collect::<String>())),
+            }
+            run.clear();
+        }
+        if cjk {
+            run.push(c);
+        } else if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+    }
+    terms
+}
+
+/// 語ごとの出現回数
+pub fn term_counts(text: &str) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    for term in terms(text) {
+        *counts.entry(term).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// `query` の語を含む文書を順位の高い順に `limit` 件まで返す。抜粋のために上位の文書だけを読み込む
+pub fn search(vault: &Vault, query: &str, limit: usize) -> Vec<Hit> {
+    let mut query_terms = terms(query);
+    query_terms.sort();
+    query_terms.dedup();
+    let scores = rank(vault, &query_terms);
+    scores
+        .into_iter()
+        .take(limit)
+        .map(|(path, score)| {
+            let (line, snippet) = fs::read_to_string(path).ok().and_then(|markdown| snippet(&markdown, &query_terms)).unwrap_or((1, String::new()));
+            Hit { path: path.clone(), score, line, snippet }
+        })
+        .collect()
+}
+
+/// 文書ごとのBM25の点数を高い順に返す
+fn rank<'a>(vault: &'a Vault, query_terms: &[String]) -> Vec<(&'a PathBuf, f64)> {
+    let count = vault.documents.len() as f64;
+    if count == 0.0 {
+        return Vec::new();
+    }
+    let average_length = vault.documents.values().map(|document| document.length as f64).sum::<f64>() / count;
+    let mut scores: HashMap<usize, f64> = HashMap::new();
+    for entries in query_terms.iter().filter_map(|term| vault.postings.get(term)) {
+        let idf = idf(count, entries.len());
+        for &(id, frequency) in entries {
+            let Some((_, document)) = vault.document(id) else {
+                continue;
+            };
+            let frequency = frequency as f64;
+            let norm = K1 * (1.0 - B + B * document.length as f64 / average_length.max(1.0));
+            *scores.entry(id).or_insert(0.0) += idf * frequency * (K1 + 1.0) / (frequency + norm);
+        }
+    }
+    let mut scores: Vec<(&PathBuf, f64)> = scores.into_iter().filter_map(|(id, score)| Some((vault.document(id)?.0, score))).collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    scores
+}
+
+/// `count` 個の文書のうち `matching` 個に含まれる語の重み
+fn idf(count: f64, matching: usize) -> f64 {
+    ((count - matching as f64 + 0.5) / (matching as f64 + 0.5) + 1.0).ln()
+}
+
+/// `path` の文書に似た文書を似ている順に `limit` 件まで返す。
+/// 本文の似方（最も似た文書を1とする）に、リンクでつながっていれば `LINKED_BONUS` を、
+/// 同じ文書へのリンク1つごとに `SHARED_LINK_BONUS` を加える
+pub fn related(vault: &Vault, path: &Path, limit: usize) -> Vec<Related> {
+    let path = vault::normalize(path);
+    let Some(document) = vault.documents.get(&path) else {
+        return Vec::new();
+    };
+    let count = vault.documents.len() as f64;
+    let mut weighted: Vec<(&String, f64)> = vault
+        .postings
+        .iter()
+        .filter_map(|(term, entries)| {
+            let (_, frequency) = entries.iter().find(|(id, _)| *id == document.id)?;
+            Some((term, *frequency as f64 * idf(count, entries.len())))
+        })
+        .collect();
+    weighted.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let terms: Vec<String> = weighted.iter().take(RELATED_TERMS).map(|(term, _)| term.to_string()).collect();
+    let text_scores: Vec<(&PathBuf, f64)> = rank(vault, &terms).into_iter().filter(|(other, _)| **other != path).collect();
+    let best = text_scores.first().map_or(1.0, |(_, score)| *score);
+    let mut scores: HashMap<&PathBuf, f64> = text_scores.into_iter().map(|(other, score)| (other, score / best)).collect();
+    let targets: HashSet<&PathBuf> = document.links.iter().map(|(target, _)| target).filter(|target| **target != path).collect();
+    let mut linked_documents = HashSet::new();
+    for (other, other_document) in &vault.documents {
+        if *other == path {
+            continue;
+        }
+        let linked = targets.contains(other) || other_document.links.iter().any(|(target, _)| *target == path);
+        if linked {
+            linked_documents.insert(other);
+        }
+        let shared: HashSet<&PathBuf> = other_document.links.iter().map(|(target, _)| target).filter(|target| targets.contains(target) && *target != other).collect();
+        let bonus = if linked { LINKED_BONUS } else { 0.0 } + SHARED_LINK_BONUS * shared.len() as f64;
+        if bonus > 0.0 {
+            *scores.entry(other).or_insert(0.0) += bonus;
+        }
+    }
+    let mut scores: Vec<(&PathBuf, f64)> = scores.into_iter().collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    scores
+        .into_iter()
+        .take(limit)
+        .map(|(other, score)| {
+            let other_document = &vault.documents[other];
+            let shared_terms = terms
+                .iter()
+                .filter(|term| vault.postings.get(*term).is_some_and(|entries| entries.iter().any(|(id, _)| *id == other_document.id)))
+                .cloned()
+                .collect();
+            Related { path: other.clone(), score, linked: linked_documents.contains(other), terms: shared_terms }
+        })
+        .collect()
+}
+
+/// 語を最も多く含む行と、その行の語の周りの抜粋。フロントマターとコードフェンスの行も対象にする
+pub fn snippet(markdown: &str, query_terms: &[String]) -> Option<(usize, String)> {
+    let (index, line) = markdown
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let line_terms = terms(line);
+            (query_terms.iter().filter(|term| line_terms.contains(term)).count(), index, line)
+        })
+        .filter(|(matched, _, _)| *matched > 0)
+        .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+        .map(|(_, index, line)| (index, line))?;
+    let chars: Vec<char> = line.trim().chars().map(|c| if c == '\t' { ' ' } else { c }).collect();
+    // 1文字ずつ小文字にして、見つけた位置がそのまま元の行の文字の位置になるようにする
+    let lower: String = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let first = query_terms.iter().filter_map(|term| lower.find(term.as_str())).min().unwrap_or(0);
+    // 最初に見つかった語が抜粋の前から1/4ほどの位置に来るようにする
+    let start = lower[..first].chars().count().saturating_sub(SNIPPET_CHARS / 4);
+    let end = (start + SNIPPET_CHARS).min(chars.len());
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    Some((index + 1, snippet))
+}
+
+/// 検索結果のMarkdown。各文書は見つかった行へのリンクにする
+pub fn report(dir: &Path, query: &str, hits: &[Hit]) -> String {
+    let mut report = format!("# Search results for \"{}\"\n\n", query);
+    if hits.is_empty() {
+        report.push_str(&format!("No documents under `{}` match.\n", dir.display()));
+        return report;
+    }
+    report.push_str(&format!(
+        "{} under `{}` (best match first).\n\n",
+        if hits.len() == 1 { "1 document".to_string() } else { format!("{} documents", hits.len()) },
+        dir.display()
+    ));
+    for hit in hits {
+        let name = hit.path.strip_prefix(dir).unwrap_or(&hit.path).to_string_lossy().to_string();
+        report.push_str(&format!("1. [{}](<{}#L{}>) (line {})\n", escape(&name), hit.path.display(), hit.line, hit.line));
+        if !hit.snippet.is_empty() {
+            report.push_str(&format!("   > {}\n", escape(&hit.snippet)));
+        }
+    }
+    report
+}
+
+/// Markdownの記法として読まれる文字をエスケープする
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,151 @@
+// --- 文書の配信（`peek serve`） ---
+//
+// ディレクトリの下の文書をHTMLにしてローカルのブラウザから読めるようにする、小さなHTTPサーバー。
+// 文書はリクエストのたびに読み込んで変換するので、保存すれば再読み込みで反映される。
+// それ以外のファイル（画像など）はそのまま返し、ディレクトリは一覧を返す。
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    export::escape_html,
+    links::{self, encode_path_segment, LinkTarget},
+};
+
+/// 遅いクライアントや途中で止まったクライアントに、接続を持たれ続けないための読み書きの時間切れ
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `127.0.0.1:port` で待ち受け、`root` の下のファイルを返し続ける。
+/// `render` は文書ならHTMLを返し、文書でなければNoneを返す
+pub fn run(root: &Path, port: u16, render: impl Fn(&Path) -> io::Result<Option<String>> + Sync) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Serving {} at http://{}/", root.display(), listener.local_addr()?);
+    // 接続ごとにスレッドで応答し、1つの遅い接続で他の接続を待たせない
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let render = &render;
+            scope.spawn(move || {
+                // 1つの接続の失敗でサーバーを止めない
+                if let Err(e) = stream.and_then(|stream| respond(stream, root, render)) {
+                    tracing::warn!("serve: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, root: &Path, render: &impl Fn(&Path) -> io::Result<Option<String>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // ヘッダーは使わないが、読み終えてから応答する
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+    tracing::info!("serve: {} {}", method, target);
+    if method != "GET" {
+        return send(&mut stream, "405 Method Not Allowed", "text/plain", b"Method not allowed");
+    }
+    let Some(path) = request_path(root, target) else {
+        return send(&mut stream, "403 Forbidden", "text/plain", b"Forbidden");
+    };
+    // 一覧に出さない `.git` や `.env` などは、パスを直接指定されても返さない
+    if is_hidden(root, &path) {
+        return send(&mut stream, "404 Not Found", "text/plain", b"Not found");
+    }
+    if path.is_dir() {
+        return send(&mut stream, "200 OK", "text/html; charset=utf-8", listing(root, &path)?.as_bytes());
+    }
+    if !path.is_file() {
+        return send(&mut stream, "404 Not Found", "text/plain", b"Not found");
+    }
+    match render(&path) {
+        Ok(Some(html)) => send(&mut stream, "200 OK", "text/html; charset=utf-8", html.as_bytes()),
+        Ok(None) => send(&mut stream, "200 OK", content_type(&path), &fs::read(&path)?),
+        Err(e) => send(&mut stream, "500 Internal Server Error", "text/plain; charset=utf-8", e.to_string().as_bytes()),
+    }
+}
+
+/// リクエストのパス（`/docs/my%20notes.md?x=1`）を `root` の下のパスにする。`root` の外を指していればNone
+pub fn request_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['?', '#']).next().unwrap_or("");
+    let LinkTarget::File(relative, _) = links::resolve(target.trim_start_matches('/'), None) else {
+        return None;
+    };
+    let inside = relative.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    inside.then(|| root.join(relative))
+}
+
+/// `root` からのパスに `.` で始まるファイルやディレクトリを含むかどうか
+pub fn is_hidden(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .any(|component| matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.')))
+}
+
+/// ディレクトリの中身の一覧のHTML
+pub fn listing(root: &Path, dir: &Path) -> io::Result<String> {
+    let mut entries: Vec<(String, bool)> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| (entry.file_name().to_string_lossy().to_string(), entry.path().is_dir()))
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let relative = dir.strip_prefix(root).unwrap_or(dir);
+    let (base, href) = relative.components().fold((String::from("/"), String::from("/")), |(base, href), component| {
+        let name = component.as_os_str().to_string_lossy();
+        (format!("{}{}/", base, name), format!("{}{}/", href, encode_path_segment(&name)))
+    });
+    let mut html = format!("<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<h1>{0}</h1>\n<ul>\n", escape_html(&base));
+    if base != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    // 表示は名前のまま、リンクは `#` や `?` や空白を含む名前でも開けるよう区切りごとに `%xx` にする
+    for (name, is_dir) in entries {
+        let suffix = if is_dir { "/" } else { "" };
+        html.push_str(&format!(
+            "<li><a href=\"{}{}{}\">{}{}</a></li>\n",
+            escape_html(&href),
+            escape_html(&encode_path_segment(&name)),
+            suffix,
+            escape_html(&name),
+            suffix
+        ));
+    }
+    html.push_str("</ul>\n");
+    Ok(html)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn send(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
@@ -0,0 +1,161 @@
+// --- 設定の階層 ---
+//
+// 設定は次の順に重ねて決まる（後のものほど優先）。
+//   1. デフォルト値
+//   2. ユーザー設定ファイル (`~/.config/md-preview/config.toml`)
+//   3. プロジェクト設定ファイル (`<gitリポジトリのルート>/.md-preview.toml`)。
+//      コマンドを実行する設定やパスを取る設定（`config::needs_trust`）は、`trusted_projects` に書いたリポジトリのものだけを使う
+//   4. 環境変数 (`MD_PREVIEW_THEME` など)
+//   5. コマンドライン引数 (`--theme=github-dark` など)
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, Config, Value};
+
+/// プロジェクト固有の設定ファイル名
+pub const PROJECT_CONFIG_FILE: &str = ".md-preview.toml";
+/// 設定を上書きする環境変数の接頭辞
+pub const ENV_PREFIX: &str = "MD_PREVIEW_";
+
+/// 起動時に読み込んだ、プロジェクト設定以外の各階層の値。
+/// `Default` はどの階層も空で、デフォルト値だけの設定になる
+#[derive(Default)]
+pub struct Settings {
+    user: Vec<(String, Value)>,
+    env: Vec<(String, Value)>,
+    cli: Vec<(String, Value)>,
+}
+
+impl Settings {
+    /// ユーザー設定ファイルと環境変数を読み込み、コマンドライン引数の値と合わせて保持する
+    pub fn load(cli: Vec<(String, Value)>) -> io::Result<Self> {
+        let user = match config_path() {
+            Some(path) => read_file(&path)?,
+            None => Vec::new(),
+        };
+        let env = env::vars()
+            .filter_map(|(name, raw)| {
+                let key = name.strip_prefix(ENV_PREFIX)?;
+                // セクション内のキーは `MD_PREVIEW_SECTION__KEY` のように書く
+                Some((key.to_ascii_lowercase().replace("__", "."), parse_override(&raw)))
+            })
+            .collect();
+        Ok(Self { user, env, cli })
+    }
+
+    /// ユーザー設定ファイルを読み込み直す。読み込めなければ前の値のまま
+    pub fn reload_user(&mut self) -> io::Result<()> {
+        self.user = match config_path() {
+            Some(path) => read_file(&path)?,
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// 各階層を重ねた設定を返す。`project_root` があればプロジェクト設定も読み込む
+    pub fn resolve(&self, project_root: Option<&Path>) -> io::Result<Config> {
+        let mut config = Config::default();
+        config.apply(&self.user)?;
+        resolve_script(&mut config, config_dir().as_deref());
+        if let Some(root) = project_root {
+            let mut entries = read_file(&root.join(PROJECT_CONFIG_FILE))?;
+            // 他人のリポジトリを開いただけでコマンドが実行されたりファイルが読み書きされたりしないよう、信頼していなければそれらの設定を使わない
+            if !self.trusts(root) {
+                let ignored: Vec<String> = entries.iter().map(|(key, _)| key.clone()).filter(|key| config::needs_trust(key)).collect();
+                if !ignored.is_empty() {
+                    tracing::warn!("ignoring {} in {} (add the project to trusted_projects to use them)", ignored.join(", "), root.display());
+                    entries.retain(|(key, _)| !config::needs_trust(key));
+                }
+            }
+            config.apply(&entries)?;
+            resolve_script(&mut config, Some(root));
+        }
+        for (key, value) in &self.env {
+            config
+                .apply(&[(key.clone(), value.clone())])
+                .map_err(|e| config::invalid(format!("環境変数 {}{}: {}", ENV_PREFIX, key.to_ascii_uppercase(), e)))?;
+        }
+        for (key, value) in &self.cli {
+            config
+                .apply(&[(key.clone(), value.clone())])
+                .map_err(|e| config::invalid(format!("--{}: {}", key.replace('_', "-"), e)))?;
+        }
+        Ok(config)
+    }
+
+    /// `root` のリポジトリを信頼しているか。プロジェクト設定以外の階層の `trusted_projects` で決める
+    pub fn trusts(&self, root: &Path) -> bool {
+        let entries: Vec<(String, Value)> =
+            self.user.iter().chain(&self.env).chain(&self.cli).filter(|(key, _)| key == "trusted_projects").cloned().collect();
+        let mut config = Config::default();
+        config.apply(&entries).is_ok() && config.trusts(root)
+    }
+}
+
+/// `script` の相対パスを、書いた設定ファイルの場所（設定ディレクトリかリポジトリのルート）からのパスにする。
+/// 環境変数とコマンドライン引数の相対パスはカレントディレクトリからのままにする
+fn resolve_script(config: &mut Config, base: Option<&Path>) {
+    if let Some(base) = base
+        && !config.script.is_empty()
+        && !config.script.starts_with('~')
+        && Path::new(&config.script).is_relative()
+    {
+        config.script = base.join(&config.script).to_string_lossy().to_string();
+    }
+}
+
+/// ユーザー設定ファイルやテンプレートを置くディレクトリ
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("md-preview"))
+}
+
+/// 索引などの作り直せるデータを置くディレクトリ
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("md-preview"))
+}
+
+/// ユーザー設定ファイルの場所
+pub fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// ユーザー設定ファイルの `key` の値を書き換える。ファイルが無ければ作る
+pub fn save_user_value(key: &str, value: &str) -> io::Result<()> {
+    let path = config_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "設定ファイルの場所が分かりません"))?;
+    let text = if path.is_file() { fs::read_to_string(&path)? } else { String::new() };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, config::set_value(&text, key, value))
+}
+
+/// 読み込む設定ファイル（無いものも含む）。変更を検知して読み込み直すのに使う
+pub fn config_files(project_root: Option<&Path>) -> Vec<PathBuf> {
+    config_path().into_iter().chain(project_root.map(|root| root.join(PROJECT_CONFIG_FILE))).collect()
+}
+
+/// 設定ファイルを読む。ファイルが無ければ空の設定として扱う
+fn read_file(path: &Path) -> io::Result<Vec<(String, Value)>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path)?;
+    let name = path.file_name().map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+    config::parse(&text, &name)
+}
+
+/// 環境変数やコマンドライン引数の値を解釈する。引用符の無い文字列もそのまま文字列として扱う
+pub fn parse_override(raw: &str) -> Value {
+    config::parse_value(raw.trim()).unwrap_or_else(|| Value::Str(raw.to_string()))
+}
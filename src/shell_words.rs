@@ -0,0 +1,56 @@
+// --- コマンドの引数の分割 ---
+//
+// `:hp "my notes.md"` のように空白を含むパスを渡せるよう、シェルに近い規則で分割する。
+// - `'...'` の中はそのまま
+// - `"..."` の中では `\"` と `\\` だけをエスケープとして扱う
+// - 引用符の外の `\` は空白・引用符・`\` の前でだけエスケープになる（Windowsのパスを壊さないため）
+
+/// コマンドラインを引数に分割する。引用符が閉じていなければNone
+pub fn split(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // 空の引用符（`""`）も1つの引数として数えるため、単語を読み始めたかを別に持つ
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"' | '\\')) => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' if chars.peek().is_some_and(|next| next.is_whitespace() || matches!(next, '"' | '\'' | '\\')) => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
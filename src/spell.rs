@@ -0,0 +1,98 @@
+// --- スペルチェック ---
+//
+// 本文の単語を `hunspell -l` に渡し、辞書に無い単語に下線を引く。
+// コードブロックとインラインコードは背景色で見分けて対象から外す。
+
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+use ratatui::prelude::*;
+
+use crate::theme::ColorScheme;
+
+/// コードとして描画されたスパンかどうか
+pub fn is_code(style: Style, theme: &ColorScheme) -> bool {
+    style.bg == Some(theme.code_bg) || style.bg == Some(theme.inline_code_bg)
+}
+
+/// スペルチェックの対象にする単語の文字（ラテン文字と語中のアポストロフィ）
+fn is_word_char(c: char) -> bool {
+    (c.is_alphabetic() && (c as u32) < 0x0250) || c == '\''
+}
+
+/// テキストを単語とそれ以外に分ける。単語なら true を付けて返す
+fn split_words(text: &str) -> Vec<(&str, bool)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        let word_char = is_word_char(c);
+        if word_char != in_word && i > start {
+            parts.push((&text[start..i], in_word));
+            start = i;
+        }
+        in_word = word_char;
+    }
+    if start < text.len() {
+        parts.push((&text[start..], in_word));
+    }
+    parts
+}
+
+/// 本文の行から辞書に無い単語を集める
+pub fn check(lines: &[Line], theme: &ColorScheme, language: &str) -> io::Result<HashSet<String>> {
+    let words: HashSet<&str> = lines
+        .iter()
+        .flat_map(|line| line.spans.iter())
+        .filter(|span| !is_code(span.style, theme))
+        .flat_map(|span| split_words(&span.content))
+        .filter(|(_, is_word)| *is_word)
+        .map(|(word, _)| word.trim_matches('\''))
+        .filter(|word| word.chars().count() > 1)
+        .collect();
+
+    let mut child = Command::new("hunspell")
+        .args(["-l", "-d", language])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("hunspell を起動できません: {}", e)))?;
+    let input: String = words.iter().map(|word| format!("{}\n", word)).collect();
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let mut output = String::new();
+    child.stdout.take().expect("stdout is piped").read_to_string(&mut output)?;
+    let status = child.wait()?;
+    let _ = writer.join();
+    if !status.success() {
+        return Err(io::Error::other(format!("hunspell が失敗しました ({})", status)));
+    }
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+/// 行の中の辞書に無い単語に下線を引く
+pub fn highlight(line: &mut Line<'static>, misspelled: &HashSet<String>, theme: &ColorScheme) {
+    let mut spans = Vec::with_capacity(line.spans.len());
+    for span in line.spans.drain(..) {
+        if is_code(span.style, theme) {
+            spans.push(span);
+            continue;
+        }
+        for (part, is_word) in split_words(&span.content) {
+            let style = if is_word && misspelled.contains(part.trim_matches('\'')) {
+                span.style
+                    .add_modifier(Modifier::UNDERLINED)
+                    .underline_color(Color::Red)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(part.to_string(), style));
+        }
+    }
+    line.spans = spans;
+}
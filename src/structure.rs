@@ -0,0 +1,230 @@
+// --- 文書の構造（`--render-json`） ---
+//
+// CIのスクリプトやエディターがTUIを介さずに使えるよう、見出し・リンク・コードブロック・語数を取り出してJSONにする。
+// 行番号は元のMarkdownでの1始まりの行番号。
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::links;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    /// `#install` のようなリンクに使うアンカー名
+    pub anchor: String,
+    pub line: usize,
+}
+
+/// リンクまたは画像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// フェンスに書かれた言語（` ```rust ` なら `rust`）。無ければNone
+    pub language: Option<String>,
+    /// フェンスの行
+    pub line: usize,
+    /// 中身の行数
+    pub lines: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Structure {
+    pub headings: Vec<Heading>,
+    pub links: Vec<Link>,
+    pub images: Vec<Link>,
+    pub code_blocks: Vec<CodeBlock>,
+    /// コードとフロントマターを除いた本文の語数と、空白以外の文字数
+    pub words: usize,
+    pub characters: usize,
+    /// 語数を数えた本文。ブロックの区切りは空白になっている（全文検索の索引に使う）
+    pub text: String,
+}
+
+/// Markdownの構造を取り出す
+pub fn analyze(markdown: &str) -> Structure {
+    let line_starts: Vec<usize> = std::iter::once(0).chain(markdown.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let source_line = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+    let mut structure = Structure::default();
+    // 読み込み中の見出し・リンク・画像・コードブロック
+    let mut heading: Option<(usize, String, usize)> = None;
+    let mut link: Option<(String, String, usize)> = None;
+    let mut image: Option<(String, String, usize)> = None;
+    let mut code: Option<(Option<String>, usize, String)> = None;
+    let mut in_metadata = false;
+    // 語数を数える本文。ブロックの終わりと改行は空白にする
+    let mut prose = String::new();
+
+    // 引用符などは元のまま取り出す
+    let options = Options::all() - Options::ENABLE_SMART_PUNCTUATION;
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => heading = Some((level as usize, String::new(), source_line(range.start))),
+            Event::End(TagEnd::Heading(_)) => {
+                prose.push(' ');
+                if let Some((level, text, line)) = heading.take() {
+                    structure.headings.push(Heading { level, text, anchor: String::new(), line });
+                }
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => link = Some((String::new(), dest_url.to_string(), source_line(range.start))),
+            Event::End(TagEnd::Link) => {
+                if let Some((text, url, line)) = link.take() {
+                    structure.links.push(Link { text, url, line });
+                }
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => image = Some((String::new(), dest_url.to_string(), source_line(range.start))),
+            Event::End(TagEnd::Image) => {
+                if let Some((text, url, line)) = image.take() {
+                    structure.images.push(Link { text, url, line });
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(str::to_string),
+                    CodeBlockKind::Indented => None,
+                };
+                code = Some((language, source_line(range.start), String::new()));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((language, line, text)) = code.take() {
+                    structure.code_blocks.push(CodeBlock { language, line, lines: text.lines().count() });
+                }
+            }
+            Event::Start(Tag::MetadataBlock(_)) => in_metadata = true,
+            Event::End(TagEnd::MetadataBlock(_)) => in_metadata = false,
+            Event::Text(_) if in_metadata => {}
+            // HTMLで書いた `<img src="...">` も画像として扱う
+            Event::Html(html) | Event::InlineHtml(html) => {
+                for (offset, url, text) in html_images(&html) {
+                    structure.images.push(Link { text, url, line: source_line(range.start + offset) });
+                }
+            }
+            Event::Text(text) if code.is_some() => {
+                if let Some((_, _, code_text)) = &mut code {
+                    code_text.push_str(&text);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, content, _)) = &mut heading {
+                    content.push_str(&text);
+                }
+                for (content, _, _) in [&mut link, &mut image].into_iter().flatten() {
+                    content.push_str(&text);
+                }
+                prose.push_str(&text);
+            }
+            Event::SoftBreak | Event::HardBreak => prose.push(' '),
+            Event::End(TagEnd::Paragraph | TagEnd::Item | TagEnd::TableCell | TagEnd::FootnoteDefinition) => prose.push(' '),
+            _ => {}
+        }
+    }
+    structure.words = prose.split_whitespace().count();
+    structure.characters = prose.chars().filter(|c| !c.is_whitespace()).count();
+    structure.text = prose;
+    let texts: Vec<String> = structure.headings.iter().map(|heading| heading.text.clone()).collect();
+    for (heading, anchor) in structure.headings.iter_mut().zip(links::heading_anchors(&texts)) {
+        heading.anchor = anchor;
+    }
+    structure
+}
+
+impl Structure {
+    /// 1行のJSONオブジェクト
+    pub fn to_json(&self) -> String {
+        let headings: Vec<String> = self
+            .headings
+            .iter()
+            .map(|heading| {
+                format!(
+                    r#"{{"level":{},"text":{},"anchor":{},"line":{}}}"#,
+                    heading.level,
+                    json_string(&heading.text),
+                    json_string(&heading.anchor),
+                    heading.line
+                )
+            })
+            .collect();
+        let links = |links: &[Link]| -> Vec<String> {
+            links
+                .iter()
+                .map(|link| format!(r#"{{"text":{},"url":{},"line":{}}}"#, json_string(&link.text), json_string(&link.url), link.line))
+                .collect()
+        };
+        let code_blocks: Vec<String> = self
+            .code_blocks
+            .iter()
+            .map(|block| {
+                let language = block.language.as_deref().map_or_else(|| "null".to_string(), json_string);
+                format!(r#"{{"language":{},"line":{},"lines":{}}}"#, language, block.line, block.lines)
+            })
+            .collect();
+        format!(
+            r#"{{"headings":[{}],"links":[{}],"images":[{}],"code_blocks":[{}],"words":{},"characters":{}}}"#,
+            headings.join(","),
+            links(&self.links).join(","),
+            links(&self.images).join(","),
+            code_blocks.join(","),
+            self.words,
+            self.characters
+        )
+    }
+}
+
+/// HTMLの中の `<img>` タグの位置と、`src` と `alt` の値
+fn html_images(html: &str) -> Vec<(usize, String, String)> {
+    let lower = html.to_ascii_lowercase();
+    let mut images = Vec::new();
+    for (start, _) in lower.match_indices("<img") {
+        let rest = &html[start + 4..];
+        if !rest.starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if let Some(src) = html_attribute(tag, "src") {
+            images.push((start, src, html_attribute(tag, "alt").unwrap_or_default()));
+        }
+    }
+    images
+}
+
+/// タグの中の属性の値。引用符で囲んでいなくてもよい
+fn html_attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    lower.match_indices(name).find_map(|(start, _)| {
+        if !tag[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            return None;
+        }
+        let value = tag[start + name.len()..].trim_start().strip_prefix('=')?.trim_start();
+        let value = match value.chars().next()? {
+            quote @ ('"' | '\'') => &value[1..][..value[1..].find(quote)?],
+            // `<img src=a.png/>` の `/` はタグの終わり
+            _ => value[..value.find(|c: char| c.is_ascii_whitespace()).unwrap_or(value.len())].trim_end_matches('/'),
+        };
+        Some(value.to_string())
+    })
+}
+
+/// JSONの文字列リテラル
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
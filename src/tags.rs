@@ -0,0 +1,107 @@
+// --- フロントマターのタグによる文書の一覧 ---
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+use ratatui::widgets::ListState;
+
+use crate::frontmatter;
+
+/// `root` 以下の文書をたどり、タグごとの文書の一覧を作る。`.gitignore` に一致するファイルは除く
+pub fn index(root: &Path, is_document: impl Fn(&Path) -> bool) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut tags: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for entry in WalkBuilder::new(root).build().flatten() {
+        let path = entry.path();
+        if !path.is_file() || !is_document(path) {
+            continue;
+        }
+        let Ok(markdown) = fs::read_to_string(path) else {
+            continue;
+        };
+        let Some(block) = frontmatter::block(&markdown) else {
+            continue;
+        };
+        for tag in frontmatter::list(block, "tags") {
+            tags.entry(tag).or_default().push(path.to_path_buf());
+        }
+    }
+    for documents in tags.values_mut() {
+        documents.sort();
+    }
+    tags
+}
+
+/// `:tags` で開くタグと文書の選択画面
+pub struct TagBrowser {
+    pub root: PathBuf,
+    pub tags: BTreeMap<String, Vec<PathBuf>>,
+    /// 文書の一覧を表示しているタグ。Noneならタグの一覧を表示する
+    pub selected_tag: Option<String>,
+    pub list_state: ListState,
+}
+
+impl TagBrowser {
+    pub fn new(root: PathBuf, tags: BTreeMap<String, Vec<PathBuf>>) -> Self {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        Self { root, tags, selected_tag: None, list_state }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.selected_tag {
+            Some(tag) => format!(" #{} ", tag),
+            None => format!(" Tags ({}) ", self.tags.len()),
+        }
+    }
+
+    /// 表示する項目
+    pub fn items(&self) -> Vec<String> {
+        match &self.selected_tag {
+            Some(tag) => self.tags[tag]
+                .iter()
+                .map(|path| path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy().to_string())
+                .collect(),
+            None => self
+                .tags
+                .iter()
+                .map(|(tag, documents)| format!("{} ({})", tag, documents.len()))
+                .collect(),
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.items().len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        self.list_state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    /// 選択中の項目を開く。タグなら文書の一覧に切り替え、文書ならそのパスを返す
+    pub fn enter(&mut self) -> Option<PathBuf> {
+        let index = self.list_state.selected()?;
+        match &self.selected_tag {
+            Some(tag) => self.tags[tag].get(index).cloned(),
+            None => {
+                self.selected_tag = self.tags.keys().nth(index).cloned();
+                self.list_state.select(Some(0));
+                None
+            }
+        }
+    }
+
+    /// 文書の一覧からタグの一覧に戻る。タグの一覧を表示していたら false を返す
+    pub fn back(&mut self) -> bool {
+        let Some(tag) = self.selected_tag.take() else {
+            return false;
+        };
+        let index = self.tags.keys().position(|name| *name == tag);
+        self.list_state.select(index.or(Some(0)));
+        true
+    }
+}
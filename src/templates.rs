@@ -0,0 +1,79 @@
+// --- ファイルのテンプレート ---
+//
+// テンプレートは設定ディレクトリの `templates/<名前>.md` に置く。
+// `{{date}}` などのプレースホルダーは作成時の値に置き換える。
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::settings;
+
+/// テンプレートを置くディレクトリ
+pub fn templates_dir() -> Option<PathBuf> {
+    Some(settings::config_dir()?.join("templates"))
+}
+
+/// 名前を指定してテンプレートを読み込む
+pub fn load(name: &str) -> io::Result<String> {
+    let dir = templates_dir().ok_or_else(|| io::Error::other("設定ディレクトリが見つかりません"))?;
+    let path = dir.join(format!("{}.md", name));
+    fs::read_to_string(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("テンプレート {} を読み込めません: {}", path.display(), e)))
+}
+
+/// `{{name}}` の形のプレースホルダーを置き換える
+pub fn render(template: &str, variables: &[(&str, &str)]) -> String {
+    variables
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| text.replace(&format!("{{{{{}}}}}", name), value))
+}
+
+/// 今日の日付（YYYY-MM-DD）。`date` コマンドがあればローカル時刻、無ければUTCで求める
+pub fn today() -> String {
+    if let Some(date) = local_date() {
+        return date;
+    }
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86_400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(unix)]
+fn local_date() -> Option<String> {
+    let output = std::process::Command::new("date").arg("+%Y-%m-%d").output().ok()?;
+    let date = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !date.is_empty()).then_some(date)
+}
+
+#[cfg(not(unix))]
+fn local_date() -> Option<String> {
+    None
+}
+
+/// 1970-01-01からの日数を年月日に変換する（Howard Hinnantのアルゴリズム）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// 先頭の `~` をホームディレクトリに置き換える
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            Path::new(&home).join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
@@ -0,0 +1,138 @@
+// --- 配色テーマ定義 ---
+
+use ratatui::style::Color;
+
+pub struct ColorScheme {
+    pub bg: Color,
+    pub fg: Color,
+    pub selection_bg: Color,
+    pub selection_fg: Color,
+    pub comment: Color,
+    pub link: Color,
+    pub heading: Color,
+    pub code_bg: Color,
+    pub inline_code_bg: Color,
+    pub quote_fg: Color,
+    pub quote_border: Color,
+    pub hr: Color,
+    // エクスプローラーでのファイル種別ごとの色
+    pub file_markdown: Color,
+    pub file_image: Color,
+    pub file_code: Color,
+    pub file_archive: Color,
+}
+
+pub const GITHUB_DARK_THEME: ColorScheme = ColorScheme {
+    bg: Color::Rgb(13, 17, 23),         // #0d1117
+    fg: Color::Rgb(201, 209, 217),      // #c9d1d9
+    selection_bg: Color::Rgb(3, 34, 82), // A selection color
+    selection_fg: Color::Rgb(201, 209, 217),
+    comment: Color::Rgb(139, 148, 158), // #8b949e
+    link: Color::Rgb(88, 166, 255),     // #58a6ff
+    heading: Color::Rgb(88, 166, 255),  // Using link color for headings
+    code_bg: Color::Rgb(22, 27, 34),    // #161b22
+    inline_code_bg: Color::Rgb(40, 45, 53),
+    quote_fg: Color::Rgb(139, 148, 158), // #8b949e
+    quote_border: Color::Rgb(48, 54, 61), // #30363d
+    hr: Color::Rgb(33, 38, 45),         // #21262d
+    file_markdown: Color::Rgb(63, 185, 80), // #3fb950
+    file_image: Color::Rgb(210, 168, 255),  // #d2a8ff
+    file_code: Color::Rgb(255, 166, 87),    // #ffa657
+    file_archive: Color::Rgb(255, 123, 114), // #ff7b72
+};
+
+/// 端末の16色だけを使うテーマ。RGBの色を表示できない旧コンソールでも崩れない
+pub const ANSI_16_THEME: ColorScheme = ColorScheme {
+    bg: Color::Reset,
+    fg: Color::Reset,
+    selection_bg: Color::Blue,
+    selection_fg: Color::White,
+    comment: Color::DarkGray,
+    link: Color::LightBlue,
+    heading: Color::LightBlue,
+    code_bg: Color::Reset,
+    inline_code_bg: Color::DarkGray,
+    quote_fg: Color::Gray,
+    quote_border: Color::DarkGray,
+    hr: Color::DarkGray,
+    file_markdown: Color::Green,
+    file_image: Color::Magenta,
+    file_code: Color::Yellow,
+    file_archive: Color::Red,
+};
+
+/// 色をほとんど使わないテーマ。選択中の行だけを灰色の背景で示す。`screen_reader` で使う
+pub const MONOCHROME_THEME: ColorScheme = ColorScheme {
+    bg: Color::Reset,
+    fg: Color::Reset,
+    selection_bg: Color::Gray,
+    selection_fg: Color::Black,
+    comment: Color::Reset,
+    link: Color::Reset,
+    heading: Color::Reset,
+    code_bg: Color::Reset,
+    inline_code_bg: Color::Reset,
+    quote_fg: Color::Reset,
+    quote_border: Color::Reset,
+    hr: Color::Reset,
+    file_markdown: Color::Reset,
+    file_image: Color::Reset,
+    file_code: Color::Reset,
+    file_archive: Color::Reset,
+};
+
+/// 背景を黒、文字を白にしたコントラストの高いテーマ。本文の色はどれも背景との
+/// コントラスト比が7:1（WCAGのAAA）以上になるようにしている
+pub const HIGH_CONTRAST_THEME: ColorScheme = ColorScheme {
+    bg: Color::Rgb(0, 0, 0),
+    fg: Color::Rgb(255, 255, 255),
+    selection_bg: Color::Rgb(255, 255, 0),
+    selection_fg: Color::Rgb(0, 0, 0),
+    comment: Color::Rgb(200, 200, 200),
+    link: Color::Rgb(0, 255, 255),
+    heading: Color::Rgb(255, 255, 0),
+    code_bg: Color::Rgb(28, 28, 28),
+    inline_code_bg: Color::Rgb(48, 48, 48),
+    quote_fg: Color::Rgb(220, 220, 220),
+    quote_border: Color::Rgb(255, 255, 255),
+    hr: Color::Rgb(255, 255, 255),
+    file_markdown: Color::Rgb(0, 255, 0),
+    file_image: Color::Rgb(255, 128, 255),
+    file_code: Color::Rgb(255, 200, 0),
+    file_archive: Color::Rgb(255, 110, 110),
+};
+
+/// 1型・2型色覚（赤と緑の区別がつきにくい）でも見分けやすいOkabe-Itoの配色を使うテーマ。
+/// 赤と緑の組み合わせを避け、青・橙・黄で区別する。本文の色は背景とのコントラスト比4.5:1（AA）以上
+pub const COLORBLIND_THEME: ColorScheme = ColorScheme {
+    bg: Color::Rgb(13, 17, 23),
+    fg: Color::Rgb(230, 230, 230),
+    selection_bg: Color::Rgb(0, 114, 178), // 青 #0072b2
+    selection_fg: Color::Rgb(255, 255, 255),
+    comment: Color::Rgb(160, 160, 160),
+    link: Color::Rgb(86, 180, 233),    // 空色 #56b4e9
+    heading: Color::Rgb(240, 228, 66), // 黄 #f0e442
+    code_bg: Color::Rgb(22, 27, 34),
+    inline_code_bg: Color::Rgb(40, 45, 53),
+    quote_fg: Color::Rgb(160, 160, 160),
+    quote_border: Color::Rgb(86, 180, 233),
+    hr: Color::Rgb(80, 80, 80),
+    file_markdown: Color::Rgb(0, 158, 115), // 青緑 #009e73
+    file_image: Color::Rgb(204, 121, 167),  // 赤紫 #cc79a7
+    file_code: Color::Rgb(230, 159, 0),     // 橙 #e69f00
+    file_archive: Color::Rgb(213, 94, 0),   // 朱色 #d55e00
+};
+
+/// 設定ファイルで指定できるテーマ名
+pub const THEME_NAMES: &[&str] = &["github-dark", "ansi-16", "monochrome", "high-contrast", "colorblind"];
+
+pub fn by_name(name: &str) -> Option<&'static ColorScheme> {
+    match name {
+        "github-dark" => Some(&GITHUB_DARK_THEME),
+        "ansi-16" => Some(&ANSI_16_THEME),
+        "monochrome" => Some(&MONOCHROME_THEME),
+        "high-contrast" => Some(&HIGH_CONTRAST_THEME),
+        "colorblind" => Some(&COLORBLIND_THEME),
+        _ => None,
+    }
+}
@@ -0,0 +1,95 @@
+// --- 翻訳の対 ---
+//
+// `doc.md` と `doc.ja.md` のような原文と翻訳を左右に並べて読むための処理。
+// 対になるファイルは設定の `translation_patterns`（`*.ja.md` の `*` が原文の拡張子を除いた名前）で探し、
+// スクロールは段落の単位で合わせる。翻訳は原文と同じ構成で書かれることが多いので、
+// 同じ順番の見出しを同じ節とみなし、節の中では同じ順番の段落を対応させる。
+
+use std::path::{Path, PathBuf};
+
+/// 表示中の文書と対になるファイルの候補。翻訳なら原文、原文ならパターンごとの翻訳を返す
+pub fn candidates(path: &Path, patterns: &[String]) -> Vec<PathBuf> {
+    let Some(directory) = path.parent() else {
+        return Vec::new();
+    };
+    // 翻訳のパターンに一致すれば原文（拡張子は翻訳と同じとする）
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("md");
+    for pattern in patterns {
+        if let Some((base, stem)) = match_pattern(pattern, path) {
+            return vec![base.join(format!("{}.{}", stem, extension))];
+        }
+    }
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return Vec::new();
+    };
+    patterns.iter().map(|pattern| directory.join(pattern.replace('*', stem))).collect()
+}
+
+/// `path` が `pattern` の翻訳なら、原文のあるディレクトリと `*` に当たる名前を返す。
+/// パターンがディレクトリを含む（`ja/*.md`）ときは、その分だけ上のディレクトリを原文の場所とする
+fn match_pattern<'a>(pattern: &str, path: &'a Path) -> Option<(&'a Path, String)> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+    let depth = pattern.matches('/').count();
+    let base = path.parent()?.ancestors().nth(depth)?;
+    let relative = path.strip_prefix(base).ok()?.to_str()?.replace('\\', "/");
+    let stem = relative.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!stem.is_empty() && !stem.contains('/')).then(|| (base, stem.to_string()))
+}
+
+/// 描画した文書の段落の構成。見出しごとの節に分けた、段落の先頭の行番号
+#[derive(Debug, Default)]
+pub struct Outline {
+    /// 最初の見出しより前を0番目とした、節ごとの段落の先頭の行
+    sections: Vec<Vec<usize>>,
+}
+
+impl Outline {
+    /// 空行で区切られたまとまりを段落とみなす。`heading_lines` の行からは新しい節を始める
+    pub fn new(lines: &[bool], heading_lines: &[usize]) -> Self {
+        let mut sections = vec![Vec::new()];
+        let mut headings = heading_lines.iter().peekable();
+        let mut previous_blank = true;
+        for (index, &blank) in lines.iter().enumerate() {
+            if headings.next_if(|&&line| line <= index).is_some() {
+                sections.push(Vec::new());
+                while headings.next_if(|&&line| line <= index).is_some() {}
+                if !blank {
+                    sections.last_mut().unwrap().push(index);
+                }
+            } else if !blank && previous_blank {
+                sections.last_mut().unwrap().push(index);
+            }
+            previous_blank = blank;
+        }
+        Self { sections }
+    }
+
+    /// `line` を含む段落の位置（節の番号、節の中の段落の番号、段落の先頭からの行数）
+    fn locate(&self, line: usize) -> (usize, usize, usize) {
+        let mut found = (0, 0, line);
+        for (section, starts) in self.sections.iter().enumerate() {
+            for (paragraph, &start) in starts.iter().enumerate() {
+                if start > line {
+                    return found;
+                }
+                found = (section, paragraph, line - start);
+            }
+        }
+        found
+    }
+
+    /// もう一方の文書で `line` に対応する行。節や段落が足りなければ、ある中で最も近いものにする
+    pub fn align(&self, line: usize, other: &Outline) -> usize {
+        let (section, paragraph, offset) = self.locate(line);
+        let section = section.min(other.sections.len().saturating_sub(1));
+        // 段落の無い節なら、前の節の最後の段落にする
+        let starts = other.sections.iter().take(section + 1).rev().find(|starts| !starts.is_empty());
+        let Some(starts) = starts else {
+            return 0;
+        };
+        let index = paragraph.min(starts.len() - 1);
+        // 段落の中では同じ行数だけ進める。次の段落を越えない
+        let limit = starts.get(index + 1).map_or(usize::MAX, |next| next.saturating_sub(1));
+        (starts[index] + offset).min(limit.max(starts[index]))
+    }
+}
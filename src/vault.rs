@@ -0,0 +1,433 @@
+// --- 文書の集まり（vault）の索引 ---
+//
+// ディレクトリの下の文書ごとに見出し・ローカルのリンク・フロントマターを覚え、語ごとにその語を含む文書の転置索引を作っておき、
+// バックリンクやタグの一覧、全文検索の答えを文書を読み直さずに返す。読み直すのは更新日時が変わった文書だけ。
+// 索引は行ごとのテキストとしてファイルに保存でき、次に起動したときに読み込んで使い回せる。
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use ignore::{gitignore::Gitignore, Match, WalkBuilder};
+
+use crate::{
+    frontmatter,
+    links::{self, LinkTarget},
+    search, settings, structure,
+};
+
+/// 保存した索引の1行目。形式を変えたら番号を上げ、古い索引は読み込まずに作り直す
+const FORMAT_HEADER: &str = "peek-vault 2";
+
+/// 索引に入れた1つの文書
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    /// 転置索引で文書を表す番号
+    pub id: usize,
+    /// 読み込んだときの更新日時
+    pub modified: Option<SystemTime>,
+    pub headings: Vec<String>,
+    /// ローカルのファイルへのリンクの行き先と、元のMarkdownの行番号
+    pub links: Vec<(PathBuf, usize)>,
+    /// フロントマターのキーごとの値
+    pub metadata: BTreeMap<String, Vec<String>>,
+    /// 本文（コードとフロントマターを除く）の語の総数
+    pub length: u32,
+    /// 転置索引に入れたこの文書の語（読み直すときに、この語の項目だけを除く）
+    pub terms: Vec<String>,
+}
+
+/// `root` の下の文書の索引
+#[derive(Debug, Clone, Default)]
+pub struct Vault {
+    pub root: PathBuf,
+    pub documents: BTreeMap<PathBuf, Document>,
+    /// 語ごとの、その語を含む文書の番号と出現回数
+    pub postings: HashMap<String, Vec<(usize, u32)>>,
+    /// 番号ごとの文書のパス。消えた文書の番号はNone
+    pub paths: Vec<Option<PathBuf>>,
+}
+
+impl Vault {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root, ..Self::default() }
+    }
+
+    /// `save` で保存した索引を読み込む。形式が違えば空の索引を返す（`refresh` で作り直される）
+    pub fn load(root: PathBuf, file: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(file)?;
+        let mut vault = Self::new(root);
+        let mut lines = text.lines();
+        if lines.next() != Some(FORMAT_HEADER) {
+            return Ok(vault);
+        }
+        let broken = || io::Error::new(io::ErrorKind::InvalidData, format!("{} の索引が壊れています", file.display()));
+        let mut current: Option<(PathBuf, Document)> = None;
+        for line in lines {
+            let (kind, rest) = line.split_once('\t').ok_or_else(broken)?;
+            // 語の行は数が多いので、エスケープを戻さずに読む（語は空白やタブを含まない）
+            if kind == "W" {
+                let (term, entries) = rest.split_once('\t').ok_or_else(broken)?;
+                let entries = entries
+                    .split(' ')
+                    .map(|entry| {
+                        let (id, count) = entry.split_once(':')?;
+                        Some((id.parse().ok()?, count.parse().ok()?))
+                    })
+                    .collect::<Option<Vec<(usize, u32)>>>()
+                    .ok_or_else(broken)?;
+                vault.postings.insert(term.to_string(), entries);
+                continue;
+            }
+            let fields: Vec<String> = rest.split('\t').map(unescape).collect();
+            match (kind, &mut current, fields.as_slice()) {
+                ("D", _, [path, modified]) => {
+                    if let Some((path, document)) = current.take() {
+                        vault.documents.insert(path, document);
+                    }
+                    // 読み直すかどうかを更新日時の一致で決めるので、ナノ秒まで正確に戻す
+                    let modified = modified
+                        .split_once('.')
+                        .and_then(|(seconds, nanos)| Some(Duration::new(seconds.parse().ok()?, nanos.parse().ok()?)))
+                        .map(|elapsed| SystemTime::UNIX_EPOCH + elapsed);
+                    let id = vault.paths.len();
+                    vault.paths.push(Some(PathBuf::from(path)));
+                    current = Some((PathBuf::from(path), Document { id, modified, ..Document::default() }));
+                }
+                ("H", Some((_, document)), [heading]) => document.headings.push(heading.clone()),
+                ("L", Some((_, document)), [target, line]) => document.links.push((PathBuf::from(target), line.parse().unwrap_or(1))),
+                ("M", Some((_, document)), [key]) => {
+                    document.metadata.entry(key.clone()).or_default();
+                }
+                ("M", Some((_, document)), [key, value]) => document.metadata.entry(key.clone()).or_default().push(value.clone()),
+                ("N", Some((_, document)), [length]) => document.length = length.parse().unwrap_or(0),
+                _ => return Err(broken()),
+            }
+        }
+        if let Some((path, document)) = current {
+            vault.documents.insert(path, document);
+        }
+        if vault.postings.values().flatten().any(|(id, _)| *id >= vault.paths.len()) {
+            return Err(broken());
+        }
+        // 文書ごとの語は保存せず、転置索引から作り直す
+        for (term, entries) in &vault.postings {
+            for (id, _) in entries {
+                if let Some(document) = vault.paths[*id].as_ref().and_then(|path| vault.documents.get_mut(path)) {
+                    document.terms.push(term.clone());
+                }
+            }
+        }
+        Ok(vault)
+    }
+
+    /// 索引をファイルに保存する。消えた文書の番号は詰める
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let mut text = format!("{}\n", FORMAT_HEADER);
+        let mut ids = vec![None; self.paths.len()];
+        for (index, (path, document)) in self.documents.iter().enumerate() {
+            ids[document.id] = Some(index);
+            let modified = document.modified.and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok()).unwrap_or_default();
+            text.push_str(&format!("D\t{}\t{}.{:09}\n", escape(&path.to_string_lossy()), modified.as_secs(), modified.subsec_nanos()));
+            text.push_str(&format!("N\t{}\n", document.length));
+            for heading in &document.headings {
+                text.push_str(&format!("H\t{}\n", escape(heading)));
+            }
+            for (target, line) in &document.links {
+                text.push_str(&format!("L\t{}\t{}\n", escape(&target.to_string_lossy()), line));
+            }
+            for (key, values) in &document.metadata {
+                if values.is_empty() {
+                    text.push_str(&format!("M\t{}\n", escape(key)));
+                }
+                for value in values {
+                    text.push_str(&format!("M\t{}\t{}\n", escape(key), escape(value)));
+                }
+            }
+        }
+        for (term, entries) in &self.postings {
+            let entries: Vec<String> =
+                entries.iter().filter_map(|(id, count)| Some(format!("{}:{}", ids.get(*id).copied().flatten()?, count))).collect();
+            if !entries.is_empty() {
+                text.push_str(&format!("W\t{}\t{}\n", term, entries.join(" ")));
+            }
+        }
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, text)
+    }
+
+    /// `path` の文書を索引に入れる。既にあれば入れ替える
+    pub fn insert(&mut self, path: PathBuf, markdown: &str, modified: Option<SystemTime>) {
+        let id = match self.documents.get(&path) {
+            Some(document) => {
+                let id = document.id;
+                self.remove_postings(&HashSet::from([id]));
+                id
+            }
+            None => self.new_id(&path),
+        };
+        self.index(id, path, markdown, modified);
+    }
+
+    /// `root` の下をたどり、新しい文書と更新日時の変わった文書を読み直し、消えた文書を除く。
+    /// `.gitignore` に一致するファイルは除く。読み直した文書の数を返す
+    pub fn refresh(&mut self, is_document: impl Fn(&Path) -> bool) -> usize {
+        let mut seen = HashSet::new();
+        let mut changed = Vec::new();
+        self.walk(&self.root, &is_document, &mut seen, &mut changed);
+        let removed: Vec<PathBuf> = self.documents.keys().filter(|path| !seen.contains(*path)).cloned().collect();
+        self.update(changed, removed)
+    }
+
+    /// 変更を知らされた `paths` だけを確かめ、新しい文書と更新日時の変わった文書を読み直し、消えた文書を除く。
+    /// ディレクトリならその下をたどり、`refresh` でたどらないパス（隠しファイルや `.gitignore` に一致するもの）は無視する。
+    /// 読み直した文書の数を返す
+    pub fn refresh_paths(&mut self, paths: &[PathBuf], is_document: impl Fn(&Path) -> bool) -> usize {
+        let mut ignores = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for path in paths {
+            if !self.documents.contains_key(path) && is_ignored(&self.root, path, &mut ignores) {
+                continue;
+            }
+            if path.is_dir() {
+                self.walk(path, &is_document, &mut seen, &mut changed);
+            } else if path.is_file() {
+                if is_document(path) && seen.insert(path.clone()) && !self.is_unchanged(path) {
+                    changed.push((path.clone(), modified_time(path)));
+                }
+            } else {
+                // 消えたファイルか、名前が変わったり消えたりしたディレクトリの下の文書
+                let under = self.documents.range(path.clone()..).map(|(document, _)| document);
+                removed.extend(under.take_while(|document| document.starts_with(path)).cloned());
+            }
+        }
+        self.update(changed, removed)
+    }
+
+    /// `dir` の下をたどり、見つけた文書を `seen` に、新しい文書や更新日時の変わった文書を `changed` に加える
+    fn walk(&self, dir: &Path, is_document: impl Fn(&Path) -> bool, seen: &mut HashSet<PathBuf>, changed: &mut Vec<(PathBuf, Option<SystemTime>)>) {
+        for entry in WalkBuilder::new(dir).build().flatten() {
+            let path = entry.path();
+            if !path.is_file() || !is_document(path) || !seen.insert(path.to_path_buf()) {
+                continue;
+            }
+            if !self.is_unchanged(path) {
+                changed.push((path.to_path_buf(), modified_time(path)));
+            }
+        }
+    }
+
+    /// 索引にある文書で、読み込んだときから更新日時が変わっていないかどうか
+    fn is_unchanged(&self, path: &Path) -> bool {
+        self.documents
+            .get(path)
+            .is_some_and(|document| document.modified.is_some() && document.modified == modified_time(path))
+    }
+
+    /// 変わった文書を読み直し、消えた文書を除く。読み直した文書の数を返す
+    fn update(&mut self, changed: Vec<(PathBuf, Option<SystemTime>)>, removed: Vec<PathBuf>) -> usize {
+        // 変わった文書と消えた文書の語をまとめて転置索引から除く
+        let stale: HashSet<usize> = changed
+            .iter()
+            .map(|(path, _)| path)
+            .chain(&removed)
+            .filter_map(|path| Some(self.documents.get(path)?.id))
+            .collect();
+        self.remove_postings(&stale);
+        for path in removed {
+            if let Some(document) = self.documents.remove(&path) {
+                self.paths[document.id] = None;
+            }
+        }
+        let mut parsed = 0;
+        for (path, modified) in changed {
+            let id = self.documents.get(&path).map(|document| document.id);
+            let Ok(markdown) = fs::read_to_string(&path) else {
+                if let Some(id) = id {
+                    self.documents.remove(&path);
+                    self.paths[id] = None;
+                }
+                continue;
+            };
+            let id = id.unwrap_or_else(|| self.new_id(&path));
+            self.index(id, path, &markdown, modified);
+            parsed += 1;
+        }
+        parsed
+    }
+
+    fn new_id(&mut self, path: &Path) -> usize {
+        self.paths.push(Some(path.to_path_buf()));
+        self.paths.len() - 1
+    }
+
+    /// 転置索引から除いてある `id` の文書として、`markdown` を読んで入れる
+    fn index(&mut self, id: usize, path: PathBuf, markdown: &str, modified: Option<SystemTime>) {
+        let structure = structure::analyze(markdown);
+        let links = structure
+            .links
+            .iter()
+            .filter_map(|link| match links::resolve(&link.url, Some(&path)) {
+                LinkTarget::File(target, _) => Some((normalize(&target), link.line)),
+                _ => None,
+            })
+            .collect();
+        let mut metadata = BTreeMap::new();
+        if let Some(block) = frontmatter::block(markdown) {
+            metadata.extend(frontmatter::fields(block));
+            // `tags: a, b` も `:tags` と同じようにリストとして読む
+            if metadata.contains_key("tags") {
+                metadata.insert("tags".to_string(), frontmatter::list(block, "tags"));
+            }
+        }
+        let counts = search::term_counts(&structure.text);
+        let length = counts.values().sum();
+        let mut terms = Vec::with_capacity(counts.len());
+        for (term, count) in counts {
+            self.postings.entry(term.clone()).or_default().push((id, count));
+            terms.push(term);
+        }
+        let headings = structure.headings.into_iter().map(|heading| heading.text).collect();
+        self.documents.insert(path, Document { id, modified, headings, links, metadata, length, terms });
+    }
+
+    /// `ids` の文書の語の項目を転置索引から除く。文書ごとに覚えた語だけを見る
+    fn remove_postings(&mut self, ids: &HashSet<usize>) {
+        for &id in ids {
+            let Some(document) = self.paths.get(id).and_then(Option::as_ref).and_then(|path| self.documents.get_mut(path)) else {
+                continue;
+            };
+            for term in std::mem::take(&mut document.terms) {
+                if let Some(entries) = self.postings.get_mut(&term) {
+                    entries.retain(|(entry, _)| *entry != id);
+                    if entries.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 番号の文書のパスと内容
+    pub fn document(&self, id: usize) -> Option<(&PathBuf, &Document)> {
+        let path = self.paths.get(id)?.as_ref()?;
+        Some((path, self.documents.get(path)?))
+    }
+
+    /// `target` にリンクしている文書と、リンクのある行番号
+    pub fn backlinks(&self, target: &Path) -> Vec<(PathBuf, usize)> {
+        let target = normalize(target);
+        self.documents
+            .iter()
+            .flat_map(|(path, document)| {
+                document.links.iter().filter(|(link, _)| *link == target).map(move |(_, line)| (path.clone(), *line))
+            })
+            .filter(|(path, _)| *path != target)
+            .collect()
+    }
+
+    /// タグごとの文書の一覧（`tags::index` と同じ形）
+    pub fn tags(&self) -> BTreeMap<String, Vec<PathBuf>> {
+        let mut tags: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for (path, document) in &self.documents {
+            for tag in document.metadata.get("tags").into_iter().flatten() {
+                tags.entry(tag.clone()).or_default().push(path.clone());
+            }
+        }
+        tags
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// `refresh` で `root` の下をたどるときに飛ばすパスかどうか。隠しファイル（`.git` の中など）と、
+/// `root` から `path` までのディレクトリの `.gitignore` や `.ignore` に一致するもの（`target` など）。
+/// 読み込んだ無視の規則はディレクトリごとに `ignores` に覚えておく
+fn is_ignored(root: &Path, path: &Path, ignores: &mut HashMap<PathBuf, Vec<Gitignore>>) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return true;
+    };
+    if relative.components().any(|component| matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.'))) {
+        return true;
+    }
+    let is_dir = path.is_dir();
+    let mut dirs: Vec<&Path> = path.ancestors().skip(1).take_while(|dir| dir.starts_with(root)).collect();
+    dirs.reverse();
+    // 深いディレクトリの規則ほど優先する
+    let mut ignored = false;
+    for dir in dirs {
+        let matchers = ignores
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| [".gitignore", ".ignore"].iter().map(|name| Gitignore::new(dir.join(name)).0).collect());
+        for matcher in matchers.iter() {
+            match matcher.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => ignored = true,
+                Match::Whitelist(_) => ignored = false,
+                Match::None => {}
+            }
+        }
+    }
+    ignored
+}
+
+/// ルートごとに分けて置くファイル（daemonのソケットや保存した索引）の名前に使う、ルートのハッシュ
+pub fn root_id(root: &Path) -> String {
+    // 標準ライブラリのハッシュはRustの版によって変わりうるので、どの版でも同じになるFNV-1aを使う
+    let hash = root.as_os_str().as_encoded_bytes().iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// `root` の索引を保存するファイル
+pub fn cache_file(root: &Path) -> Option<PathBuf> {
+    Some(settings::cache_dir()?.join(format!("vault-{}.txt", root_id(root))))
+}
+
+/// 保存する値の `\`・タブ・改行をエスケープする
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// `.` と `..` を取り除いたパス。ファイルシステムには問い合わせないので、無いファイルへのリンクも比べられる
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
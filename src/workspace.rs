@@ -0,0 +1,29 @@
+// --- ワークスペース（gitリポジトリ）の検出 ---
+
+use std::path::{Path, PathBuf};
+
+use crate::platform;
+
+/// `start` を含むgitリポジトリのルートを探す。`.git` はディレクトリでもファイル（worktree）でもよい
+pub fn find_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// タイトル表示用のパス。リポジトリ内なら「リポジトリ名/相対パス」に短縮する
+pub fn display_path(path: &Path, root: Option<&Path>) -> String {
+    let Some(root) = root else {
+        return platform::display(path);
+    };
+    let Ok(relative) = path.strip_prefix(root) else {
+        return platform::display(path);
+    };
+    let root_name = root.file_name().map_or_else(|| root.to_string_lossy(), |name| name.to_string_lossy());
+    if relative.as_os_str().is_empty() {
+        root_name.to_string()
+    } else {
+        Path::new(root_name.as_ref()).join(relative).to_string_lossy().to_string()
+    }
+}
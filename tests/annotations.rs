@@ -0,0 +1,61 @@
+// --- 文書へのコメント ---
+
+use std::path::{Path, PathBuf};
+
+use peek::annotations::{format, parse, report, sidecar_path, Annotation, Target};
+
+#[test]
+fn reads_comment_tables() {
+    let text = r##"
+# レビューのメモ
+[[comment]]
+heading = "#install"
+text = "手順が古い"
+
+[[comment]]
+line = 12
+text = "例を追加する"
+date = "2024-05-01"
+"##;
+    let annotations = parse(text, "doc.md.comments.toml").unwrap();
+    assert_eq!(
+        annotations,
+        [
+            Annotation { target: Target::Heading("install".to_string()), text: "手順が古い".to_string(), date: None },
+            Annotation { target: Target::Line(12), text: "例を追加する".to_string(), date: Some("2024-05-01".to_string()) },
+        ]
+    );
+}
+
+#[test]
+fn reports_comments_without_a_place() {
+    let error = parse("[[comment]]\ntext = \"どこ?\"\n", "doc.md.comments.toml").unwrap_err();
+    assert!(error.to_string().contains("1行目"));
+    assert!(parse("text = \"外\"\n", "doc.md.comments.toml").is_err());
+}
+
+#[test]
+fn writes_comments_that_read_back() {
+    let annotation = Annotation { target: Target::Line(3), text: "\"引用\" と\n改行 # 記号".to_string(), date: None };
+    assert_eq!(parse(&format(&annotation), "test").unwrap(), [annotation]);
+}
+
+#[test]
+fn keeps_comments_next_to_the_document() {
+    assert_eq!(sidecar_path(Path::new("docs/guide.md")), PathBuf::from("docs/guide.md.comments.toml"));
+}
+
+#[test]
+fn reports_comments_with_quoted_context() {
+    let source = "# Guide\n\n## Install\n\nRun the installer.\n\nThen restart.\n";
+    let install = Annotation { target: Target::Heading("install".to_string()), text: "手順が古い".to_string(), date: Some("2024-05-01".to_string()) };
+    let missing = Annotation { target: Target::Line(40), text: "消えた段落".to_string(), date: None };
+    let restart = Annotation { target: Target::Line(7), text: "理由を書く".to_string(), date: None };
+    assert_eq!(
+        report("guide.md", source, &[(Some(3), &install), (Some(7), &restart), (None, &missing)]),
+        "# Review notes: guide.md\n\
+         \n## Line 3 (`#install`)\n\n> ## Install\n\n手順が古い\n\n_2024-05-01_\n\
+         \n## Line 7\n\n> Then restart.\n\n理由を書く\n\
+         \n## Line 40 (not found)\n\n消えた段落\n"
+    );
+}
@@ -0,0 +1,392 @@
+// --- アプリの状態遷移（`App::update`） ---
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{backend::TestBackend, Terminal};
+use peek::{
+    app::{App, AppEvent, Effect, Options, PreviewState, Screen},
+    config::{Config, Value},
+    settings::Settings,
+};
+
+fn workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("peek-app-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("docs")).unwrap();
+    fs::write(dir.join("docs/guide.md"), "# Guide\n").unwrap();
+    fs::write(dir.join("a.md"), "# A\n\nfirst\n").unwrap();
+    fs::write(dir.join("b.md"), "# B\n").unwrap();
+    dunce::canonicalize(dir).unwrap()
+}
+
+fn key(code: KeyCode) -> AppEvent {
+    key_with(code, KeyModifiers::NONE)
+}
+
+fn key_with(code: KeyCode, modifiers: KeyModifiers) -> AppEvent {
+    AppEvent::Terminal(Ok(Event::Key(KeyEvent::new(code, modifiers))))
+}
+
+/// `:` に続けてコマンドを入力し、Enterで実行した結果を返す
+fn command(app: &mut App, text: &str) -> Vec<Effect> {
+    assert!(app.update(key(KeyCode::Char(':'))).is_empty());
+    for c in text.chars() {
+        assert!(app.update(key(KeyCode::Char(c))).is_empty());
+    }
+    app.update(key(KeyCode::Enter))
+}
+
+/// 別スレッドで読み込んだディレクトリのエントリを受け取り終えるまで待つ
+fn settle(app: &mut App, events: &Receiver<AppEvent>) {
+    while app.explorer().is_loading() {
+        let event = events.recv_timeout(Duration::from_secs(5)).expect("directory listing timed out");
+        assert!(app.update(event).is_empty());
+    }
+}
+
+fn explorer(dir: &Path) -> (App, Receiver<AppEvent>) {
+    let (sender, events) = mpsc::channel();
+    let options = Options { dir: Some(dir.to_path_buf()), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    settle(&mut app, &events);
+    (app, events)
+}
+
+/// 画面を描き、一番上の段の文字を返す
+fn top_row(terminal: &mut Terminal<TestBackend>, app: &mut App) -> String {
+    terminal.draw(|f| app.view(f)).unwrap();
+    let buffer = terminal.backend().buffer();
+    (0..buffer.area.width).map(|x| buffer.get(x, 0).symbol().to_string()).collect()
+}
+
+fn selected(app: &App) -> Option<PathBuf> {
+    app.explorer().selected_entry().map(|entry| entry.path.clone())
+}
+
+#[test]
+fn moves_through_the_explorer() {
+    let dir = workspace("explorer");
+    let (mut app, events) = explorer(&dir);
+    // ディレクトリが先に並ぶ
+    assert_eq!(selected(&app), Some(dir.join("docs")));
+    assert!(app.update(key(KeyCode::Char('j'))).is_empty());
+    assert_eq!(selected(&app), Some(dir.join("a.md")));
+
+    // Markdownを開くと、プレビューを別スレッドで開く処理を返す
+    let mut effects = app.update(key(KeyCode::Enter));
+    assert_eq!(effects.len(), 1);
+    let Some(Effect::OpenPreview { anchor: None, open }) = effects.pop() else {
+        panic!("expected a preview to open");
+    };
+    assert_eq!(open().ok().map(|state| state.title), Some(dir.join("a.md").display().to_string()));
+
+    assert!(app.update(key(KeyCode::Char('k'))).is_empty());
+    assert!(app.update(key(KeyCode::Char('l'))).is_empty());
+    assert_eq!(app.explorer().current_path, dir.join("docs"));
+    settle(&mut app, &events);
+    assert_eq!(selected(&app), Some(dir.join("docs/guide.md")));
+
+    // 親に戻ると、出てきたディレクトリを選択している
+    assert!(app.update(key(KeyCode::Char('h'))).is_empty());
+    settle(&mut app, &events);
+    assert_eq!(app.explorer().current_path, dir);
+    assert_eq!(selected(&app), Some(dir.join("docs")));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn runs_explorer_commands() {
+    let dir = workspace("commands");
+    let (mut app, _events) = explorer(&dir);
+    assert!(command(&mut app, "cat a.md").is_empty());
+    let [Screen::Explorer, Screen::Preview(state)] = app.screens() else {
+        panic!("expected the file to be shown over the explorer");
+    };
+    assert_eq!(state.title, format!("Cat: {}", dir.join("a.md").display()));
+    // プレビューを閉じるとエクスプローラーに戻る
+    assert!(app.update(key(KeyCode::Char('q'))).is_empty());
+    assert!(matches!(app.screens(), [Screen::Explorer]));
+
+    assert!(command(&mut app, "cat missing.md").is_empty());
+    assert_eq!(app.explorer().error_message.as_deref(), Some("ファイルが見つかりません: missing.md"));
+    assert!(command(&mut app, "frobnicate").is_empty());
+    assert_eq!(app.explorer().error_message.as_deref(), Some("不明なコマンドです: frobnicate"));
+    assert!(matches!(command(&mut app, "q").as_slice(), [Effect::Quit]));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reads_tags_on_a_worker_thread() {
+    let dir = workspace("tags");
+    let (mut app, _events) = explorer(&dir);
+    // 文書を読む処理を返すだけで、結果が届くまで一覧は変わらない
+    let mut effects = command(&mut app, "tags");
+    let Some(Effect::Background(work)) = effects.pop() else {
+        panic!("expected the tags to be read in the background");
+    };
+    assert_eq!(app.explorer().error_message, None);
+    assert!(app.update(AppEvent::Background(work())).is_empty());
+    assert_eq!(app.explorer().error_message.as_deref(), Some("タグの付いた文書が見つかりません"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn quits_from_a_standalone_preview() {
+    let dir = workspace("standalone");
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    assert!(matches!(app.screens(), [Screen::Preview(_)]));
+    assert!(command(&mut app, "nosuch").is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message.as_deref(), Some("Unknown command: nosuch"));
+    // 戻る画面が無ければ、閉じると終了する
+    assert!(matches!(app.update(key(KeyCode::Char('q'))).as_slice(), [Effect::Quit]));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn runs_commands_defined_in_the_script() {
+    let dir = workspace("script");
+    fs::write(dir.join("hooks.rhai"), "fn command_where(args, doc) { `${args[0]} ${doc.line}` }\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let settings = Settings::load(vec![("script".to_string(), Value::Str(dir.join("hooks.rhai").display().to_string()))]).unwrap();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(settings, &options, &sender).unwrap();
+    // スクリプトの関数は、結果をフッターに出す処理として返る
+    let mut effects = command(&mut app, "where here");
+    let Some(Effect::Background(work)) = effects.pop() else {
+        panic!("expected the script command to run in the background");
+    };
+    assert!(app.update(AppEvent::Background(work())).is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message.as_deref(), Some("here 1"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn quits_on_ctrl_c_and_fails_on_terminal_errors() {
+    let dir = workspace("quit");
+    let (mut app, _events) = explorer(&dir);
+    assert!(matches!(app.update(key_with(KeyCode::Char('c'), KeyModifiers::CONTROL)).as_slice(), [Effect::Quit]));
+    assert!(matches!(app.update(key_with(KeyCode::Char('z'), KeyModifiers::CONTROL)).as_slice(), [Effect::Suspend]));
+    let effects = app.update(AppEvent::Terminal(Err(io::Error::other("closed"))));
+    assert!(matches!(effects.as_slice(), [Effect::Fail(e)] if e.to_string() == "closed"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn expands_aliases_in_the_preview() {
+    let dir = workspace("alias");
+    fs::create_dir(dir.join(".git")).unwrap();
+    fs::write(dir.join(".md-preview.toml"), "[alias]\nopt = \"set\"\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    // プロジェクト設定の別名は、信頼したリポジトリでだけ使う
+    let settings = Settings::load(vec![("trusted_projects".to_string(), Value::List(vec![dir.display().to_string()]))]).unwrap();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(settings, &options, &sender).unwrap();
+    assert!(command(&mut app, "opt nosuch").is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message.as_deref(), Some("Unknown option: nosuch"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn splits_with_a_quoted_path() {
+    let dir = workspace("split");
+    fs::write(dir.join("my notes.md"), "# Notes\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    assert!(command(&mut app, "split \"my notes.md").is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message.as_deref(), Some("Unterminated quote: \"my notes.md"));
+    // 開いた文書の側を操作する
+    assert!(command(&mut app, "split \"my notes.md\"").is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!((state.message.as_deref(), state.title.as_str()), (None, dir.join("my notes.md").display().to_string().as_str()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exports_annotations_to_a_quoted_path() {
+    let dir = workspace("annotations");
+    fs::write(dir.join("a.md.comments.toml"), "[[comment]]\nline = 3\ntext = \"check\"\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    let target = dir.join("review notes.md");
+    assert!(command(&mut app, &format!("annotations export '{}'", target.display())).is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message, Some(format!("1 件のコメントを {} に書き出しました", target.display())));
+    assert!(fs::read_to_string(&target).unwrap().contains("check"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cuts_a_long_line_between_characters() {
+    let dir = workspace("truncate");
+    let file = dir.join("long.md");
+    // 改行の無い1行を、文字の途中にあたるバイト数で切る
+    fs::write(&file, "あ".repeat(100)).unwrap();
+    let state = PreviewState::with_limit(&file, &Config::default(), Some(100)).unwrap();
+    let text: String = state.content.lines.iter().flat_map(|line| &line.spans).map(|span| span.content.as_ref()).collect();
+    assert!(text.contains(&"あ".repeat(33)) && !text.contains(&"あ".repeat(34)));
+
+    // 切れ目より前にUTF-8でないバイトがあれば開かない
+    fs::write(&file, [b"\xff".as_slice(), "あ".repeat(100).as_bytes()].concat()).unwrap();
+    let error = PreviewState::with_limit(&file, &Config::default(), Some(100)).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn keeps_annotations_when_loading_all_of_a_large_file() {
+    let dir = workspace("load-all");
+    let file = dir.join("large.md");
+    fs::write(&file, format!("# Large\n\n{}", "text\n".repeat(1000))).unwrap();
+    fs::write(dir.join("large.md.comments.toml"), "[[comment]]\nline = 3\ntext = \"check\"\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(file.clone()), ..Options::default() };
+    let settings = Settings::load(vec![("max_file_size".to_string(), Value::Int(1))]).unwrap();
+    let mut app = App::new(settings, &options, &sender).unwrap();
+    // 全体を読み込むまでにコメントのファイルが壊れても、読めていたコメントは残す
+    fs::write(dir.join("large.md.comments.toml"), "[[comment\n").unwrap();
+    assert!(app.update(key(KeyCode::Char('L'))).is_empty());
+    let target = dir.join("notes.md");
+    assert!(command(&mut app, &format!("annotations export {}", target.display())).is_empty());
+    let [Screen::Preview(state)] = app.screens() else {
+        panic!("expected the preview to stay open");
+    };
+    assert_eq!(state.message, Some(format!("1 件のコメントを {} に書き出しました", target.display())));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn goes_back_and_forward_through_directories() {
+    let dir = workspace("history");
+    let (mut app, events) = explorer(&dir);
+    let press = |app: &mut App, code| {
+        assert!(app.update(key(code)).is_empty());
+        settle(app, &events);
+        app.explorer().current_path.clone()
+    };
+    assert_eq!(press(&mut app, KeyCode::Enter), dir.join("docs"));
+    assert_eq!(press(&mut app, KeyCode::Char('H')), dir);
+    assert_eq!(press(&mut app, KeyCode::Char('L')), dir.join("docs"));
+    // 進む先が無ければ動かない
+    assert_eq!(press(&mut app, KeyCode::Char('L')), dir.join("docs"));
+    assert_eq!(press(&mut app, KeyCode::Char('-')), dir);
+    assert_eq!(press(&mut app, KeyCode::Char('-')), dir.join("docs"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exports_and_deletes_marked_files() {
+    let dir = workspace("bulk");
+    let (mut app, events) = explorer(&dir);
+    // `Space` で印を付けると次のエントリに進む
+    for code in [KeyCode::Char('j'), KeyCode::Char(' '), KeyCode::Char(' ')] {
+        assert!(app.update(key(code)).is_empty());
+    }
+    // 書き出しは別スレッドで行い、終わったら結果を表示する
+    let mut effects = command(&mut app, "export txt out");
+    let Some(Effect::Background(work)) = effects.pop() else {
+        panic!("expected the export to run in the background");
+    };
+    assert!(!dir.join("out").exists());
+    assert!(app.update(AppEvent::Background(work())).is_empty());
+    assert_eq!(app.explorer().message, Some(format!("2 件を {} に書き出しました", dir.join("out").display())));
+    assert!(dir.join("out/a.txt").is_file() && dir.join("out/b.txt").is_file());
+    settle(&mut app, &events);
+
+    // 印が無ければ選択中のエントリを対象にし、`y` 以外のキーでは削除しない
+    assert!(app.update(key(KeyCode::End)).is_empty());
+    assert_eq!(selected(&app), Some(dir.join("b.md")));
+    assert!(command(&mut app, "delete").is_empty());
+    assert!(app.update(key(KeyCode::Char('n'))).is_empty());
+    assert!(dir.join("b.md").is_file());
+    assert!(command(&mut app, "delete").is_empty());
+    assert!(app.update(key(KeyCode::Char('y'))).is_empty());
+    assert_eq!(app.explorer().message.as_deref(), Some("1 件を削除しました"));
+    assert!(!dir.join("b.md").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn maps_lines_through_includes() {
+    let dir = workspace("include");
+    fs::write(dir.join("docs/part.md"), "one\n\ntwo\n\nthree\n").unwrap();
+    fs::write(dir.join("a.md"), "# A\n\n<!-- include: docs/part.md -->\n\nafter\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    // 取り込んだ行の後でも、開いたファイルの行番号で移動して編集する
+    assert!(command(&mut app, "goto 5").is_empty());
+    let effects = app.update(key(KeyCode::Char('v')));
+    assert!(matches!(effects.as_slice(), [Effect::Edit { path, line: Some(5) }] if *path == dir.join("a.md")));
+    // 取り込んだ行は、取り込んだファイルのその行を編集する
+    assert!(command(&mut app, "goto 3").is_empty());
+    let effects = app.update(key(KeyCode::Char('v')));
+    assert!(matches!(effects.as_slice(), [Effect::Edit { path, line: Some(1) }] if *path == dir.join("docs/part.md")));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn draws_from_the_scrolled_line_after_wrapping_quotes() {
+    let dir = workspace("quote");
+    fs::write(dir.join("a.md"), format!("{}\n\nafter the quote\n\nlast\n", "> quoted words".repeat(8))).unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(30, 8)).unwrap();
+    let mut rows = Vec::new();
+    for _ in 0..3 {
+        rows.push(top_row(&mut terminal, &mut app));
+        app.update(key(KeyCode::Char('j')));
+    }
+    fs::remove_dir_all(dir).unwrap();
+    // 引用は幅に合わせて何段にも折り返すが、1行ずつ下へ送ると次の行が一番上に来る
+    assert!(rows[0].starts_with("▎ quoted words"));
+    assert_eq!(rows[1].trim(), "");
+    assert_eq!(rows[2].trim(), "after the quote");
+}
+
+#[test]
+fn pages_by_the_rows_of_wrapped_lines() {
+    let dir = workspace("page");
+    let paragraphs: Vec<String> = (0..10).map(|n| format!("p{} {}", n, "word ".repeat(12).trim_end())).collect();
+    fs::write(dir.join("a.md"), paragraphs.join("\n\n")).unwrap();
+    // アニメーションせずにすぐ移動させる
+    fs::create_dir(dir.join(".git")).unwrap();
+    fs::write(dir.join(".md-preview.toml"), "smooth_scroll = false\n").unwrap();
+    let (sender, _events) = mpsc::channel();
+    let options = Options { file: Some(dir.join("a.md")), ..Options::default() };
+    let mut app = App::new(Settings::default(), &options, &sender).unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(30, 9)).unwrap();
+    assert!(top_row(&mut terminal, &mut app).starts_with("p0 "));
+    // 段落は3段に折り返すので、8段の画面には2つの段落と空行が収まり、次のページは3つ目の段落から始まる
+    app.update(key(KeyCode::PageDown));
+    assert!(top_row(&mut terminal, &mut app).starts_with("p2 "));
+    app.update(key(KeyCode::PageUp));
+    assert!(top_row(&mut terminal, &mut app).starts_with("p0 "));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,105 @@
+// --- アーカイブの中身の閲覧 ---
+//
+// tests/fixtures/archives のアーカイブを読む。`unzip` や `tar` が無い環境では飛ばし、飛ばしたことを表示する。
+
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use common::{fixture, installed};
+use peek::archive::{entries, escape_pattern, is_archive, read_to_string, split};
+
+/// アーカイブの `inner` ディレクトリ直下のエントリ（パスの順）
+fn listed(archive: &Path, inner: &str) -> Vec<(PathBuf, bool)> {
+    let mut listed: Vec<(PathBuf, bool)> = entries(archive, inner).unwrap().into_iter().map(|entry| (entry.path, entry.is_dir)).collect();
+    listed.sort();
+    listed
+}
+
+#[test]
+fn splits_paths_inside_archives() {
+    let zip = fixture("docs.zip");
+    assert!(is_archive(&zip) && !is_archive(&fixture("notes.md.gz")));
+    assert_eq!(split(&zip.join("guide/intro.md")), Some((zip.clone(), "guide/intro.md".to_string())));
+    assert_eq!(split(&zip), Some((zip, String::new())));
+    assert_eq!(split(&fixture("notes.md.gz")), None);
+}
+
+#[test]
+fn lists_members_as_directories() {
+    for (name, program) in [("docs.zip", "unzip"), ("docs.tar.gz", "tar")] {
+        if !installed(program) {
+            continue;
+        }
+        let archive = fixture(name);
+        let root = listed(&archive, "");
+        // zipにはディレクトリ自体のエントリが無いが、ファイルのパスから補う
+        assert!(root.contains(&(archive.join("guide"), true)), "{}", name);
+        assert!(root.contains(&(archive.join("README.md"), false)), "{}", name);
+        assert!(!root.iter().any(|(path, _)| path.ends_with("intro.md")), "{}", name);
+        assert_eq!(listed(&archive, "guide"), [(archive.join("guide/intro.md"), false), (archive.join("guide/setup.md"), false)]);
+        assert_eq!(read_to_string(&archive, "guide/setup.md").unwrap(), "# Setup\n");
+        assert!(read_to_string(&archive, "guide/missing.md").is_err());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn reads_zip_with_bsdtar_when_unzip_is_missing() {
+    const CHILD: &str = "PEEK_TEST_WITHOUT_UNZIP";
+    if std::env::var_os(CHILD).is_some() {
+        let zip = fixture("docs.zip");
+        assert_eq!(listed(&zip, "guide"), [(zip.join("guide/intro.md"), false), (zip.join("guide/setup.md"), false)]);
+        assert_eq!(read_to_string(&zip, "x*y.md").unwrap(), "# Star\n");
+        assert_eq!(read_to_string(&zip, "-x.md").unwrap(), "# Dash\n");
+        return;
+    }
+    // `unzip` の無い環境を、bsdtarだけを `tar` として置いたPATHで再現し、このテストを別のプロセスで実行する
+    let Some(bsdtar) = std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default())
+        .map(|dir| dir.join("bsdtar"))
+        .find(|path| path.is_file())
+    else {
+        common::skipped("bsdtar is not on PATH");
+        return;
+    };
+    let bin = std::env::temp_dir().join(format!("peek-archive-bin-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&bin);
+    fs::create_dir_all(&bin).unwrap();
+    std::os::unix::fs::symlink(bsdtar, bin.join("tar")).unwrap();
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "reads_zip_with_bsdtar_when_unzip_is_missing"])
+        .env("PATH", &bin)
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(bin).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+}
+
+#[test]
+fn escapes_wildcards_and_leading_dashes() {
+    assert_eq!(escape_pattern("guide/intro.md"), "guide/intro.md");
+    assert_eq!(escape_pattern("x*y[1]?.md"), "x\\*y\\[1\\]\\?.md");
+    assert_eq!(escape_pattern("-x.md"), "\\-x.md");
+    assert_eq!(escape_pattern("a-b\\c"), "a-b\\\\c");
+}
+
+#[test]
+fn reads_members_with_unusual_names() {
+    // ワイルドカードやオプションに見える名前も、その名前のファイルだけを読む
+    if installed("unzip") {
+        let zip = fixture("docs.zip");
+        assert_eq!(read_to_string(&zip, "x*y.md").unwrap(), "# Star\n");
+        assert_eq!(read_to_string(&zip, "-x.md").unwrap(), "# Dash\n");
+    }
+    if installed("tar") {
+        let tar = fixture("docs.tar.gz");
+        assert_eq!(read_to_string(&tar, "--to-command=cat.md").unwrap(), "# Option\n");
+        assert_eq!(read_to_string(&tar, "-x.md").unwrap(), "# Dash\n");
+    }
+}
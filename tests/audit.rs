@@ -0,0 +1,36 @@
+// --- 古くなった文書の検出 ---
+
+use std::path::{Path, PathBuf};
+
+use peek::{
+    audit::report,
+    git::{parse_last_changes, LastChange},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[test]
+fn keeps_the_newest_change_of_each_file() {
+    let output = "\u{1e}1714608000\t2024-05-02\n\ndocs/guide.md\n\u{1e}1700000000\t2023-11-14\n\ndocs/guide.md\nREADME.md\n";
+    assert_eq!(
+        parse_last_changes(output),
+        [
+            LastChange { path: PathBuf::from("docs/guide.md"), time: 1714608000, date: "2024-05-02".to_string() },
+            LastChange { path: PathBuf::from("README.md"), time: 1700000000, date: "2023-11-14".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn reports_stale_documents_as_a_linked_table() {
+    let now = 1000 * DAY;
+    let stale = [LastChange { path: PathBuf::from("/work/docs/old guide.md"), time: 600 * DAY, date: "1971-08-24".to_string() }];
+    assert_eq!(
+        report(Path::new("/work"), 180, now, &stale),
+        "# Stale documents\n\n\
+         1 document under `/work` has not changed in the last 180 days (oldest first).\n\n\
+         | Document | Last change | Age |\n| --- | --- | --- |\n\
+         | [docs/old guide.md](</work/docs/old guide.md>) | 1971-08-24 | 13 months ago |\n"
+    );
+    assert!(report(Path::new("/work"), 180, now, &[]).contains("No documents"));
+}
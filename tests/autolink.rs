@@ -0,0 +1,63 @@
+// --- 課題番号などの自動リンク ---
+
+use peek::{
+    autolink::{split, Autolink},
+    config::{parse, Config},
+    render::{render_markdown, RenderOptions},
+    theme::GITHUB_DARK_THEME,
+};
+
+fn rules() -> Vec<Autolink> {
+    vec![
+        Autolink::new(r"#(\d+)", "https://github.com/org/repo/issues/$1").unwrap(),
+        Autolink::new(r"([A-Z][A-Z0-9]+)-(\d+)", "https://jira.example.com/browse/$1-$2").unwrap(),
+    ]
+}
+
+#[test]
+fn splits_text_around_matches() {
+    let segments = split("Fixes #12 and PROJ-7.", &rules());
+    assert_eq!(
+        segments,
+        [
+            ("Fixes ".to_string(), None),
+            ("#12".to_string(), Some("https://github.com/org/repo/issues/12".to_string())),
+            (" and ".to_string(), None),
+            ("PROJ-7".to_string(), Some("https://jira.example.com/browse/PROJ-7".to_string())),
+            (".".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn ignores_matches_inside_words() {
+    assert_eq!(split("abc#12 xPROJ-7 #12a", &rules()), [("abc#12 xPROJ-7 #12a".to_string(), None)]);
+}
+
+#[test]
+fn rejects_unsupported_patterns() {
+    assert!(Autolink::new("(a|b)", "$0").is_err());
+    assert!(Autolink::new("#(\\d+", "$0").is_err());
+    assert!(Autolink::new("[a-z", "$0").is_err());
+    assert!(Autolink::new("", "$0").is_err());
+}
+
+#[test]
+fn links_references_outside_code_and_links() {
+    let options = RenderOptions { autolinks: rules(), ..Default::default() };
+    let markdown = "See #3, [#4](https://example.com) and `#5`.\n\n```\n#6\n```\n";
+    let rendered = render_markdown(markdown, &GITHUB_DARK_THEME, &|_| None, &options);
+    let urls: Vec<&str> = rendered.links.iter().map(|link| link.url.as_str()).collect();
+    assert_eq!(urls, ["https://github.com/org/repo/issues/3", "https://example.com"]);
+}
+
+#[test]
+fn reads_rules_from_the_config_section() {
+    let text = "[autolink]\n'#(\\d+)' = \"https://github.com/org/repo/issues/$1\"\n";
+    let mut config = Config::default();
+    config.apply(&parse(text, "config.toml").unwrap()).unwrap();
+    assert_eq!(config.autolinks, [("#(\\d+)".to_string(), "https://github.com/org/repo/issues/$1".to_string())]);
+
+    let broken = parse("[autolink]\n\"(x\" = \"https://example.com\"\n", "config.toml").unwrap();
+    assert!(Config::default().apply(&broken).is_err());
+}
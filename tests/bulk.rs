@@ -0,0 +1,25 @@
+// --- 複数のファイルへの一括操作 ---
+
+use std::{fs, path::PathBuf};
+
+use peek::{
+    bulk::{export_all, ExportFormat},
+    export::HtmlAssets,
+    theme::GITHUB_DARK_THEME,
+};
+
+#[test]
+fn numbers_exports_that_would_share_a_name() {
+    let dir = std::env::temp_dir().join(format!("peek-bulk-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let documents: Vec<(PathBuf, String)> = [("a.md", "# A\n"), ("a.org", "# Org\n"), ("docs/A.md", "# Docs\n"), ("b.md", "# B\n")]
+        .into_iter()
+        .map(|(path, markdown)| (PathBuf::from(path), markdown.to_string()))
+        .collect();
+    let written = export_all(&documents, &dir, ExportFormat::Text, &HtmlAssets::default(), &GITHUB_DARK_THEME).unwrap();
+    assert_eq!(written, [dir.join("a.txt"), dir.join("a-2.txt"), dir.join("A-3.txt"), dir.join("b.txt")]);
+    // どの文書も別のファイルに書き出され、先に書いたものを上書きしない
+    let contents: Vec<String> = written.iter().map(|path| fs::read_to_string(path).unwrap()).collect();
+    assert!(contents[0].contains('A') && contents[1].contains("Org") && contents[2].contains("Docs"));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,39 @@
+// --- 変更履歴のリリースの見出し ---
+
+use peek::changelog::{find, is_changelog, releases, version_of};
+
+fn headings(list: &[(usize, &str)]) -> Vec<(usize, usize, String)> {
+    list.iter().enumerate().map(|(line, &(level, heading))| (line, level, heading.to_string())).collect()
+}
+
+#[test]
+fn keep_a_changelog_headings_are_releases() {
+    let headings = headings(&[
+        (1, "Changelog"),
+        (2, "[Unreleased]"),
+        (2, "[1.2.0] - 2024-05-01"),
+        (3, "Added"),
+        (2, "v1.1.0-rc.1"),
+    ]);
+    let found = releases(&headings);
+    let versions: Vec<&str> = found.iter().map(|release| release.version.as_str()).collect();
+    assert_eq!(versions, ["Unreleased", "1.2.0", "v1.1.0-rc.1"]);
+    assert_eq!(find(&found, "v1.2.0").map(|release| release.line), Some(2));
+    assert_eq!(find(&found, "1.1.0-rc.1").map(|release| release.line), Some(4));
+}
+
+#[test]
+fn numbered_headings_in_other_documents_are_not_releases() {
+    let headings = headings(&[(1, "Guide"), (2, "3 ways to install"), (2, "2024 roadmap"), (2, "1.2 Setup")]);
+    assert!(!is_changelog(headings.iter().map(|(_, level, heading)| (*level, heading.as_str()))));
+    assert!(releases(&headings).is_empty());
+}
+
+#[test]
+fn versions_need_major_and_minor() {
+    assert_eq!(version_of("[2.0.1] - 2024-01-01").as_deref(), Some("2.0.1"));
+    assert_eq!(version_of("1.0").as_deref(), Some("1.0"));
+    assert_eq!(version_of("3 ways to install"), None);
+    assert_eq!(version_of("2024 roadmap"), None);
+    assert_eq!(version_of("1.2.3.4"), None);
+}
@@ -0,0 +1,125 @@
+// --- 文書の検査と配信 ---
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use peek::{
+    check::{assets_report, lint, missing_assets, LinkChecker, Problem, Report},
+    serve::{is_hidden, listing, request_path},
+    structure::analyze,
+};
+
+fn messages(problems: Vec<Problem>) -> Vec<String> {
+    problems.into_iter().map(|problem| format!("{}: {}", problem.line, problem.message)).collect()
+}
+
+#[test]
+fn lints_heading_structure_and_images() {
+    let markdown = "# Title\n\n### Skipped\n\n![](shot.png) [empty]()\n\n## Skipped\n\n# Title\n";
+    assert_eq!(
+        messages(lint(Path::new("doc.md"), &analyze(markdown))),
+        [
+            "3: heading level jumps from h1 to h3",
+            "5: image without alt text: shot.png",
+            "5: link without a destination: empty",
+            "7: duplicate heading \"Skipped\" (first on line 3)",
+            "9: more than one h1 (first on line 1)",
+            "9: duplicate heading \"Title\" (first on line 1)",
+        ]
+    );
+    assert!(lint(Path::new("doc.md"), &analyze("# Title\n\n## Usage\n\n![logo](logo.png)\n")).is_empty());
+}
+
+#[test]
+fn reports_broken_files_and_anchors() {
+    let dir = std::env::temp_dir().join(format!("peek-check-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("other.md"), "# Intro\n").unwrap();
+    let path = dir.join("doc.md");
+    let markdown = "# Usage\n\n[a](other.md#intro) [b](other.md#nope) [c](missing.md)\n\n[d](#usage) [e](#none) [f](#L3) [g](https://example.com/x)\n";
+    let mut loaded = Vec::new();
+    let mut checker = LinkChecker::new(|path: &Path| {
+        loaded.push(path.to_path_buf());
+        fs::read_to_string(path).ok()
+    });
+    let problems = messages(checker.check(&path, &analyze(markdown)));
+    // 同じ文書のアンカーは1回だけ読み込んで確かめる
+    assert_eq!(loaded, [dir.join("other.md")]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(problems, ["3: missing anchor in other.md#nope", "3: broken link to missing.md", "5: missing anchor #none"]);
+}
+
+#[test]
+fn reports_missing_images_and_files() {
+    let dir = std::env::temp_dir().join(format!("peek-assets-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("logo.png"), "").unwrap();
+    let path = dir.join("doc.md");
+    let markdown = "![logo](logo.png) ![shot](img/shot.png)\n\n[spec](spec.pdf) [gone](gone.md) [site](https://example.com/a.pdf)\n\n<img src=\"diagram.svg\">\n";
+    let structure = analyze(markdown);
+    let is_document = |path: &Path| path.extension().is_some_and(|extension| extension == "md");
+    let assets = messages(missing_assets(&path, &structure, is_document));
+    let links = messages(LinkChecker::new(|path: &Path| fs::read_to_string(path).ok()).check(&path, &structure));
+    fs::remove_dir_all(&dir).unwrap();
+    // 文書へのリンク切れは `check-links` だけが報告する
+    assert_eq!(assets, ["1: missing image img/shot.png", "3: broken link to spec.pdf", "5: missing image diagram.svg"]);
+    assert_eq!(links, ["3: broken link to spec.pdf", "3: broken link to gone.md", "1: missing image img/shot.png", "5: missing image diagram.svg"]);
+}
+
+#[test]
+fn groups_missing_assets_by_document() {
+    let problem = |path: &str, line, message: &str| Problem { path: PathBuf::from(path), line, message: message.to_string() };
+    let problems = [
+        problem("/docs/guide.md", 3, "missing image img/shot_1.png"),
+        problem("/docs/guide.md", 9, "broken link to spec.pdf"),
+        problem("/docs/api/index.md", 2, "missing image logo.png"),
+    ];
+    assert_eq!(
+        assets_report(Path::new("/docs"), 10, &problems),
+        "# Missing assets\n\n3 missing assets in 2 of 10 documents under `/docs`.\n\n\
+         ## [guide.md](</docs/guide.md>)\n\n\
+         - [line 3](</docs/guide.md#L3>): missing image img/shot\\_1.png\n\
+         - [line 9](</docs/guide.md#L9>): broken link to spec.pdf\n\n\
+         ## [api/index.md](</docs/api/index.md>)\n\n\
+         - [line 2](</docs/api/index.md#L2>): missing image logo.png\n"
+    );
+    assert!(assets_report(Path::new("/docs"), 10, &[]).contains("All images and files"));
+}
+
+#[test]
+fn summarizes_problems_with_an_exit_code() {
+    let problem = |path: &str, line| Problem { path: PathBuf::from(path), line, message: "broken link to x.md".to_string() };
+    let mut report = Report { documents: 5, ..Report::default() };
+    assert_eq!((report.exit_code(), report.summary().as_str()), (0, "5個の文書に問題はありません"));
+    report.problems = vec![problem("a.md", 1), problem("a.md", 4), problem("b.md", 2)];
+    assert_eq!((report.exit_code(), report.summary().as_str()), (1, "5個の文書のうち2個に3件の問題があります"));
+    report.unreadable = vec![PathBuf::from("c.md")];
+    assert_eq!(report.exit_code(), 2);
+    assert!(report.summary().ends_with("（1個の文書は読めませんでした）"));
+}
+
+#[test]
+fn serves_only_paths_inside_the_root() {
+    let root = Path::new("/docs");
+    assert_eq!(request_path(root, "/guide/my%20notes.md?raw=1"), Some(PathBuf::from("/docs/guide/my notes.md")));
+    assert_eq!(request_path(root, "/"), Some(PathBuf::from("/docs/")));
+    assert_eq!(request_path(root, "/../etc/passwd"), None);
+    assert_eq!(request_path(root, "/%2e%2e/etc/passwd"), None);
+}
+
+#[test]
+fn hides_dotfiles_and_encodes_listing_links() {
+    let root = std::env::temp_dir().join(format!("peek-serve-{}", std::process::id()));
+    fs::create_dir_all(root.join("my docs")).unwrap();
+    fs::write(root.join("my docs/a#b?.md"), "").unwrap();
+    fs::write(root.join("my docs/.env"), "").unwrap();
+    let html = listing(&root, &root.join("my docs")).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+    assert!(html.contains("<a href=\"/my%20docs/a%23b%3F.md\">a#b?.md</a>"), "{}", html);
+    assert!(!html.contains(".env"));
+    assert!(is_hidden(Path::new("/docs"), Path::new("/docs/.git/config")));
+    assert!(is_hidden(Path::new("/docs"), Path::new("/docs/app/.env")));
+    assert!(!is_hidden(Path::new("/docs"), Path::new("/docs/./guide.md")));
+}
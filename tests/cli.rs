@@ -0,0 +1,69 @@
+// --- コマンドライン引数 ---
+
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+fn workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("peek-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn peek(dir: &PathBuf, args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_peek")).args(args).current_dir(dir).env("NO_COLOR", "1").output().unwrap();
+    (output.status.code().unwrap_or(-1), String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[test]
+fn shows_usage() {
+    let dir = workspace("help");
+    let (code, help) = peek(&dir, &["--help"]);
+    assert_eq!(code, 0);
+    assert!(help.contains("Usage: peek [OPTIONS] [FILE]"));
+    assert!(help.contains("--log-level <VALUE>"));
+    assert_eq!(peek(&dir, &["--no-such-option"]).0, 2);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reads_subcommands_before_files_with_the_same_name() {
+    let dir = workspace("subcommand");
+    // `lint` という名前のファイルがあっても `lint` はサブコマンドで、ファイルは `./lint` と書く
+    fs::write(dir.join("lint"), "# Lint file\n").unwrap();
+    fs::write(dir.join("a.md"), "# A\n").unwrap();
+    let (code, problems) = peek(&dir, &["lint"]);
+    assert_eq!((code, problems.as_str()), (0, ""));
+    // 設定項目は `--key value` と `--key=value` のどちらでも書ける
+    let (code, text) = peek(&dir, &["--plain", "--log-level", "debug", "--theme=monochrome", "./lint"]);
+    assert_eq!(code, 0);
+    assert!(text.contains("Lint file"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn strips_colors_from_json_input() {
+    let dir = workspace("render-json");
+    let colored = "# \x1b[1;31mTitle\x1b[0m\n\nSee [\x1b[4mdocs\x1b[0m](docs.md)\n";
+    fs::write(dir.join("a.md"), colored).unwrap();
+    let (code, json) = peek(&dir, &["--render-json", "a.md"]);
+    assert_eq!(code, 0);
+    assert!(json.contains(r#""text":"Title""#) && !json.contains("\\u001b"), "{}", json);
+    // 標準入力から読んだ文書も同じように取り除く
+    let mut child = Command::new(env!("CARGO_BIN_EXE_peek"))
+        .args(["--render-json", "-"])
+        .current_dir(&dir)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(colored.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), json);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,50 @@
+// --- 結合テストで共通に使う文書の索引とフィクスチャ ---
+//
+// テストごとに使う関数が違うので、使わない関数の警告は出さない。
+#![allow(dead_code)]
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use peek::vault::Vault;
+
+/// `root` の下に `documents`（パスとMarkdown）だけを入れた索引
+pub fn vault(root: &str, documents: &[(&str, &str)]) -> Vault {
+    let mut vault = Vault::new(PathBuf::from(root));
+    for (path, markdown) in documents {
+        vault.insert(PathBuf::from(path), markdown, None);
+    }
+    vault
+}
+
+/// 索引を一時ファイルに保存して読み込み直す。`name` はテストごとに一時ファイルを分けるためのもの
+pub fn save_and_load(vault: &Vault, name: &str) -> Vault {
+    let file = std::env::temp_dir().join(format!("peek-{}-{}.txt", name, std::process::id()));
+    vault.save(&file).unwrap();
+    let loaded = Vault::load(vault.root.clone(), &file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    loaded
+}
+
+/// tests/fixtures/archives の中のファイル
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/archives").join(name)
+}
+
+/// 外部のコマンドが使えるか。使えなければ、そのコマンドを使う確認を飛ばしたことを表示する
+pub fn installed(program: &str) -> bool {
+    let found = Command::new(program).arg("--help").output().is_ok();
+    if !found {
+        skipped(&format!("{} is not installed", program));
+    }
+    found
+}
+
+/// 確認を飛ばした理由を表示する。通ったテストの出力は取り込まれて見えないので、標準エラー出力に直接書く
+pub fn skipped(reason: &str) {
+    let test = std::thread::current().name().unwrap_or("test").to_string();
+    let _ = writeln!(io::stderr(), "skipped part of {}: {}", test, reason);
+}
@@ -0,0 +1,35 @@
+// --- 圧縮されたファイルの読み込み ---
+//
+// tests/fixtures/archives の圧縮ファイルを読む。`gzip` や `zstd` が無い環境では飛ばし、飛ばしたことを表示する。
+
+mod common;
+
+use std::path::Path;
+
+use common::{fixture, installed};
+use peek::compressed::{codec, read_to_string, strip_extension, Codec};
+
+#[test]
+fn recognizes_compressed_files_by_extension() {
+    assert_eq!(codec(Path::new("notes.md.gz")), Some(Codec::Gzip));
+    assert_eq!(codec(Path::new("NOTES.MD.ZST")), Some(Codec::Zstd));
+    // `.tar.gz` はアーカイブとして扱う
+    assert_eq!(codec(Path::new("docs.tar.gz")), None);
+    assert_eq!(codec(Path::new("notes.md")), None);
+    assert_eq!(strip_extension(Path::new("dir/notes.md.zst")), Path::new("dir/notes.md"));
+    assert_eq!(strip_extension(Path::new("docs.tar.gz")), Path::new("docs.tar.gz"));
+}
+
+#[test]
+fn decompresses_with_the_external_tool() {
+    for (name, codec) in [("notes.md.gz", Codec::Gzip), ("notes.md.zst", Codec::Zstd)] {
+        let program = if codec == Codec::Gzip { "gzip" } else { "zstd" };
+        if !installed(program) {
+            continue;
+        }
+        assert_eq!(read_to_string(&fixture(name), codec).unwrap(), "# Notes\n\n圧縮されたメモ\n");
+        // 圧縮されていないファイルは展開に失敗する
+        let error = read_to_string(&fixture("docs.zip"), codec).unwrap_err();
+        assert!(error.to_string().starts_with(&format!("{} が失敗しました", program)), "{}", error);
+    }
+}
@@ -0,0 +1,18 @@
+// --- 設定ファイルの書き換え ---
+
+use peek::config::set_value;
+
+#[test]
+fn replaces_an_existing_top_level_value() {
+    let text = "# 設定\ntheme = \"ansi-16\"\nminimal_chrome = false # 後で変える\n\n[filters]\nminimal_chrome = false\n";
+    assert_eq!(
+        set_value(text, "minimal_chrome", "true"),
+        "# 設定\ntheme = \"ansi-16\"\nminimal_chrome = true\n\n[filters]\nminimal_chrome = false\n"
+    );
+}
+
+#[test]
+fn appends_before_the_first_section() {
+    assert_eq!(set_value("theme = \"ansi-16\"\n\n[filters]\n", "ascii", "true"), "theme = \"ansi-16\"\nascii = true\n\n[filters]\n");
+    assert_eq!(set_value("", "ascii", "true"), "ascii = true\n");
+}
@@ -0,0 +1,38 @@
+// --- 再読み込みの前後の差分 ---
+
+use std::ops::Range;
+
+use peek::diff::{changed_words, changes, highlight, Change};
+use ratatui::prelude::*;
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn marks_changed_words_and_added_lines() {
+    let old = lines("Title\n\nThe quick fox jumps.\nEnd");
+    let new = lines("Title\n\nThe slow brown fox leaps.\nA new line\nEnd");
+    assert_eq!(changes(&old, &new), [Change::Words(2, vec![4..14, 19..24]), Change::Line(3)]);
+}
+
+#[test]
+fn ignores_unchanged_documents_and_removed_lines() {
+    let old = lines("a\nb\nc");
+    assert_eq!(changes(&old, &old), Vec::<Change>::new());
+    assert_eq!(changes(&old, &lines("a\nc")), Vec::<Change>::new());
+}
+
+#[test]
+fn ignores_whitespace_only_changes() {
+    assert_eq!(changed_words("one two", "one  two"), Vec::<Range<usize>>::new());
+    assert_eq!(changed_words("one two three", "1 two 3"), [0..1, 6..7]);
+}
+
+#[test]
+fn highlights_ranges_across_spans() {
+    let mut line = Line::from(vec![Span::raw("abc"), Span::styled("def", Style::default().fg(Color::Red))]);
+    highlight(&mut line, &[0..1, 2..4], Style::default().bg(Color::Blue));
+    let parts: Vec<(&str, Option<Color>)> = line.spans.iter().map(|span| (span.content.as_ref(), span.style.bg)).collect();
+    assert_eq!(parts, [("a", Some(Color::Blue)), ("b", None), ("c", Some(Color::Blue)), ("d", Some(Color::Blue)), ("ef", None)]);
+}
@@ -0,0 +1,22 @@
+// --- 文書のタイトル ---
+
+use peek::{
+    render::{render_markdown, RenderOptions},
+    theme::GITHUB_DARK_THEME,
+};
+
+fn title(markdown: &str) -> Option<String> {
+    render_markdown(markdown, &GITHUB_DARK_THEME, &|_| None, &RenderOptions::default()).title
+}
+
+#[test]
+fn uses_the_first_h1() {
+    assert_eq!(title("## Intro\n\n# Guide to `peek`\n\n# Later\n").as_deref(), Some("Guide to peek"));
+    assert_eq!(title("## Only a section\n"), None);
+}
+
+#[test]
+fn prefers_the_frontmatter_title() {
+    let markdown = "---\ntitle: \"Release notes\"\ntags: [a]\n---\n\n# Heading\n";
+    assert_eq!(title(markdown).as_deref(), Some("Release notes"));
+}
@@ -0,0 +1,46 @@
+// --- gitの履歴 ---
+
+use peek::git::{age, parse_blame, parse_log, BlameLine, Commit};
+
+#[test]
+fn reads_commits_with_their_paths() {
+    let output = "\u{1e}c19d771\t2024-05-02\tRename guide, fix\ttabs\n\ndocs/guide.md\n\u{1e}0a1b2c3\t2024-05-01\tFirst draft\n\nguide.md\n";
+    assert_eq!(
+        parse_log(output),
+        [
+            Commit {
+                hash: "c19d771".to_string(),
+                date: "2024-05-02".to_string(),
+                summary: "Rename guide, fix\ttabs".to_string(),
+                path: "docs/guide.md".to_string(),
+            },
+            Commit { hash: "0a1b2c3".to_string(), date: "2024-05-01".to_string(), summary: "First draft".to_string(), path: "guide.md".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn skips_records_without_a_path() {
+    assert_eq!(parse_log(""), []);
+    assert_eq!(parse_log("\u{1e}c19d771\t2024-05-02\tMerge\n"), []);
+}
+
+#[test]
+fn reads_blame_per_line() {
+    let output = "c19d771662e89da70b568a0796f4acdd0971b007 1 1 2\nauthor Ken\nauthor-time 1714521600\nsummary Draft\nfilename guide.md\n\t# Guide\n\
+                  0000000000000000000000000000000000000000 2 2\nauthor Not Committed Yet\nauthor-time 1714608000\nfilename guide.md\n\tNew line\n";
+    assert_eq!(
+        parse_blame(output),
+        [BlameLine { author: Some("Ken".to_string()), time: 1714521600 }, BlameLine { author: None, time: 1714608000 }]
+    );
+}
+
+#[test]
+fn describes_ages_roughly() {
+    const DAY: u64 = 24 * 60 * 60;
+    assert_eq!(age(3600), "today");
+    assert_eq!(age(DAY), "1 day ago");
+    assert_eq!(age(20 * DAY), "2 weeks ago");
+    assert_eq!(age(100 * DAY), "3 months ago");
+    assert_eq!(age(800 * DAY), "2 years ago");
+}
@@ -0,0 +1,73 @@
+// --- 文書のリンクのグラフ ---
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use peek::{
+    daemon::{answer, parse_links},
+    graph::{Column, Graph, GraphBrowser},
+    vault::Vault,
+};
+
+const INDEX: (&str, &str) = ("/vault/index.md", "# Index\n\n[a](a.md) [b](sub/b.md) [a again](./a.md#top) [missing](gone.md) [pdf](spec.pdf)\n");
+const A: (&str, &str) = ("/vault/a.md", "# A\n\n[index](index.md) [self](a.md)\n");
+const B: (&str, &str) = ("/vault/sub/b.md", "# B\n\n[a](../a.md)\n");
+const LONELY: (&str, &str) = ("/vault/lonely.md", "# Lonely\n");
+
+fn vault() -> Vault {
+    common::vault("/vault", &[INDEX, A, B, LONELY])
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn links_documents_to_each_other() {
+    let graph = Graph::from_vault(&vault());
+    // 重複・自分へのリンク・索引に無いファイルへのリンクは除く
+    assert_eq!(graph.outgoing(Path::new("/vault/index.md")), paths(&["/vault/a.md", "/vault/sub/b.md"]));
+    assert_eq!(graph.outgoing(Path::new("/vault/a.md")), paths(&["/vault/index.md"]));
+    assert_eq!(graph.incoming(Path::new("/vault/a.md")), paths(&["/vault/index.md", "/vault/sub/b.md"]));
+    assert_eq!(graph.edges(), 4);
+    assert_eq!(graph.orphans(), [&PathBuf::from("/vault/lonely.md")]);
+    assert_eq!(graph.hub(Path::new("/vault")), Some(&PathBuf::from("/vault/a.md")));
+    assert_eq!(graph.hub(Path::new("/vault/sub")), Some(&PathBuf::from("/vault/sub/b.md")));
+}
+
+#[test]
+fn walks_the_graph_and_back() {
+    let graph = Graph::from_vault(&vault());
+    assert!(GraphBrowser::new(PathBuf::from("/vault"), graph.clone(), Path::new("/vault/gone.md")).is_none());
+    let mut browser = GraphBrowser::new(PathBuf::from("/vault"), graph, Path::new("/vault/index.md")).unwrap();
+    assert_eq!(browser.column, Column::Outgoing);
+    browser.move_selection(5);
+    assert_eq!(browser.selected(), Some(&PathBuf::from("/vault/sub/b.md")));
+    browser.select_column(Column::Incoming);
+    assert_eq!(browser.selected(), Some(&PathBuf::from("/vault/a.md")));
+    browser.switch_column();
+    assert!(browser.enter());
+    assert_eq!(browser.current, PathBuf::from("/vault/sub/b.md"));
+    assert_eq!(browser.name(&browser.current), "sub/b.md");
+    // 戻ると、たどってきた文書が選ばれている
+    assert!(browser.back());
+    assert_eq!(browser.current, PathBuf::from("/vault/index.md"));
+    assert_eq!((browser.column, browser.selected().cloned()), (Column::Outgoing, Some(PathBuf::from("/vault/sub/b.md"))));
+    assert!(!browser.back());
+
+    let mut lonely = GraphBrowser::new(PathBuf::from("/vault"), Graph::from_vault(&vault()), Path::new("/vault/lonely.md")).unwrap();
+    assert_eq!(lonely.selected(), None);
+    // リンクの無い列には移らない
+    lonely.select_column(Column::Incoming);
+    assert_eq!(lonely.column, Column::Outgoing);
+    assert!(!lonely.enter());
+}
+
+#[test]
+fn answers_links_through_the_protocol() {
+    let vault = common::vault("/vault", &[INDEX, A, B]);
+    let lines = answer(&vault, "links").unwrap();
+    assert_eq!(lines[1], "/vault/index.md\t/vault/a.md\t/vault/sub/b.md");
+    assert_eq!(parse_links(&lines), Graph::from_vault(&vault));
+}
@@ -0,0 +1,41 @@
+// --- インクルード指令 ---
+
+use std::fs;
+
+use peek::include::{expand, SourceLine};
+
+#[test]
+fn rebases_links_of_included_files_and_stays_in_the_workspace() {
+    let base = std::env::temp_dir().join(format!("peek-include-{}", std::process::id()));
+    let root = base.join("repo");
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::create_dir_all(root.join("shared")).unwrap();
+    fs::write(base.join("secret.md"), "secret\n").unwrap();
+    fs::write(
+        root.join("shared/part.md"),
+        "![logo](img/logo.png) [site](https://example.com) [top](#top) [ref][r]\n\n```\n[code](x.md)\n```\n\n[r]: <my notes.md>\n",
+    )
+    .unwrap();
+    let markdown = "<!-- include: shared/part.md -->\n<!-- include: ../secret.md -->\n";
+    let (expanded, _) = expand(markdown, &root.join("doc.md"));
+    fs::remove_dir_all(&base).unwrap();
+    assert_eq!(
+        expanded,
+        "![logo](shared/img/logo.png) [site](https://example.com) [top](#top) [ref][r]\n\n```\n[code](x.md)\n```\n\n[r]: <shared/my notes.md>\n\
+         > include: `../secret.md` はワークスペースの外にあるため展開しません\n"
+    );
+}
+
+#[test]
+fn records_where_each_expanded_line_came_from() {
+    let root = std::env::temp_dir().join(format!("peek-include-lines-{}", std::process::id()));
+    fs::create_dir_all(root.join("parts")).unwrap();
+    fs::write(root.join("parts/a.md"), "a1\n{{#include b.md}}\n").unwrap();
+    fs::write(root.join("parts/b.md"), "b1\nb2\n").unwrap();
+    let (expanded, lines) = expand("top\n<!-- include: parts/a.md -->\nend\n", &root.join("doc.md"));
+    let root = dunce::canonicalize(&root).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(expanded, "top\na1\nb1\nb2\nend\n");
+    let included = |file: &str, line| SourceLine { line: 2, included: Some((root.join(file), line)) };
+    assert_eq!(lines, [SourceLine::new(1), included("parts/a.md", 1), included("parts/b.md", 1), included("parts/b.md", 2), SourceLine::new(3)]);
+}
@@ -0,0 +1,64 @@
+// --- キー割り当ての表 ---
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use peek::{
+    config::KeymapProfile,
+    keymap::{Action, Key, Keymap, Lookup, Mode},
+};
+
+fn key(c: char) -> Key {
+    Key::plain(KeyCode::Char(c))
+}
+
+#[test]
+fn looks_up_single_keys_per_mode() {
+    let keymap = Keymap::new(KeymapProfile::Default);
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('q')]), Lookup::Action(Action::Close));
+    assert_eq!(keymap.lookup(Mode::Explorer, &[key('l')]), Lookup::Action(Action::Open));
+    assert_eq!(keymap.lookup(Mode::Explorer, &[key('q')]), Lookup::Unbound);
+}
+
+#[test]
+fn waits_for_the_rest_of_a_sequence() {
+    let keymap = Keymap::new(KeymapProfile::Default);
+    assert_eq!(keymap.lookup(Mode::Explorer, &[key('g')]), Lookup::Pending);
+    assert_eq!(keymap.lookup(Mode::Explorer, &[key('g'), key('r')]), Lookup::Action(Action::WorkspaceRoot));
+    assert_eq!(keymap.lookup(Mode::Explorer, &[key('g'), key('x')]), Lookup::Unbound);
+}
+
+#[test]
+fn prefers_bindings_with_modifiers() {
+    let keymap = Keymap::new(KeymapProfile::Default);
+    let alt_left = KeyEvent::new(KeyCode::Left, KeyModifiers::ALT).into();
+    assert_eq!(keymap.lookup(Mode::Explorer, &[alt_left]), Lookup::Action(Action::Back));
+    // 割り当てが無ければ修飾キーを無視する
+    let ctrl_j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL).into();
+    assert_eq!(keymap.lookup(Mode::Explorer, &[ctrl_j]), Lookup::Action(Action::MoveDown));
+    // 大文字のShiftは文字で区別する
+    let shift_j = KeyEvent::new(KeyCode::Char('J'), KeyModifiers::SHIFT).into();
+    assert_eq!(keymap.lookup(Mode::Preview, &[shift_j]), Lookup::Action(Action::FastScrollDown));
+}
+
+#[test]
+fn less_profile_moves_follow_link_to_capital_f() {
+    let keymap = Keymap::new(KeymapProfile::Less);
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('f')]), Lookup::Action(Action::PageDown));
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('F')]), Lookup::Action(Action::FollowLink));
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('g')]), Lookup::Action(Action::Top));
+    assert_eq!(Keymap::new(KeymapProfile::Default).lookup(Mode::Preview, &[key('g')]), Lookup::Unbound);
+}
+
+#[test]
+fn rebinding_replaces_the_previous_action() {
+    let mut keymap = Keymap::new(KeymapProfile::Default);
+    keymap.bind(Mode::Preview, &[key('x')], Action::Close);
+    keymap.bind(Mode::Preview, &[key('x')], Action::ToggleWrap);
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('x')]), Lookup::Action(Action::ToggleWrap));
+}
+
+#[test]
+fn tab_switches_split_panes_in_preview() {
+    let keymap = Keymap::new(KeymapProfile::Less);
+    assert_eq!(keymap.lookup(Mode::Preview, &[key('s')]), Lookup::Action(Action::ToggleSplit));
+    assert_eq!(keymap.lookup(Mode::Preview, &[Key::plain(KeyCode::Tab)]), Lookup::Action(Action::NextPane));
+}
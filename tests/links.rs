@@ -0,0 +1,58 @@
+// --- リンクの解決 ---
+
+use std::path::Path;
+
+use peek::links::{heading_anchors, line_anchor, resolve, split_location, web_url, LinkTarget};
+
+const BASE: &str = "https://github.com/org/repo/blob/main/";
+
+#[test]
+fn builds_web_urls_from_the_repository_root() {
+    let root = Path::new("/work/repo");
+    assert_eq!(
+        web_url(BASE, Path::new("/work/repo/docs/../guide/getting started.md"), root, Some("install")).as_deref(),
+        Some("https://github.com/org/repo/blob/main/guide/getting%20started.md#install")
+    );
+    assert_eq!(web_url(BASE, Path::new("/work/repo/./README.md"), root, None).as_deref(), Some("https://github.com/org/repo/blob/main/README.md"));
+}
+
+#[test]
+fn rejects_paths_outside_the_repository() {
+    let root = Path::new("/work/repo");
+    assert_eq!(web_url(BASE, Path::new("/work/repo/docs/../../other.md"), root, None), None);
+    assert_eq!(web_url(BASE, Path::new("/elsewhere/notes.md"), root, None), None);
+}
+
+#[test]
+fn numbers_duplicate_heading_anchors() {
+    let headings = ["Usage", "Install", "Usage", "Usage"].map(String::from);
+    assert_eq!(heading_anchors(&headings), ["usage", "install", "usage-1", "usage-2"]);
+}
+
+#[test]
+fn decodes_anchors_of_links_to_other_files() {
+    assert_eq!(
+        resolve("guide.md#%E6%A6%82%E8%A6%81", Some(Path::new("docs/index.md"))),
+        LinkTarget::File(Path::new("docs/guide.md").to_path_buf(), Some("概要".to_string()))
+    );
+}
+
+#[test]
+fn reads_github_line_anchors() {
+    assert_eq!(line_anchor("L120"), Some(120));
+    assert_eq!(line_anchor("L12-L30"), Some(12));
+    assert_eq!(line_anchor("l7"), Some(7));
+    assert_eq!(line_anchor("L0"), None);
+    assert_eq!(line_anchor("Limits"), None);
+}
+
+#[test]
+fn splits_command_line_locations() {
+    assert_eq!(split_location("docs/guide.md:120"), Some(("docs/guide.md", "L120".to_string())));
+    assert_eq!(split_location("docs/guide.md:120:7"), Some(("docs/guide.md", "L120".to_string())));
+    assert_eq!(split_location("docs/guide.md#installation"), Some(("docs/guide.md", "installation".to_string())));
+    assert_eq!(split_location("C:\\docs\\guide.md:3"), Some(("C:\\docs\\guide.md", "L3".to_string())));
+    assert_eq!(split_location("docs/guide.md"), None);
+    assert_eq!(split_location("docs/guide.md:0"), None);
+    assert_eq!(split_location("docs/guide.md:"), None);
+}
@@ -0,0 +1,24 @@
+// --- ログ（`:log` の一覧とログファイル） ---
+
+use std::fs;
+
+use peek::logging;
+use tracing::level_filters::LevelFilter;
+
+#[test]
+fn records_events_with_fields_to_the_list_and_the_file() {
+    let file = std::env::temp_dir().join(format!("peek-logging-{}.log", std::process::id()));
+    let _ = fs::remove_file(&file);
+    logging::init(LevelFilter::DEBUG, Some(&file)).unwrap();
+    tracing::debug!(path = "a.md", lines = 3, "rendered");
+    let recent = logging::recent();
+    let last = recent.last().unwrap();
+    assert!(last.contains("DEBUG logging: rendered path=a.md lines=3"), "{}", last);
+    assert!(fs::read_to_string(&file).unwrap().contains("rendered path=\"a.md\" lines=3"));
+
+    // 2回目はレベルだけを変える
+    logging::init(LevelFilter::WARN, None).unwrap();
+    tracing::info!("ignored");
+    assert_eq!(logging::recent().len(), recent.len());
+    fs::remove_file(file).unwrap();
+}
@@ -0,0 +1,89 @@
+// --- フロントマターによる文書の絞り込み ---
+
+mod common;
+
+use std::{collections::BTreeMap, path::Path};
+
+use peek::{
+    daemon::{answer, parse_entries},
+    frontmatter::{block, fields},
+    metadata::{filter, parse, report, Condition},
+    vault::Vault,
+};
+
+const ADR_1: (&str, &str) = ("/docs/adr/001.md", "---\ntitle: Use Postgres\nstatus: Draft\ntags: [architecture, db]\n---\n# ADR 1\n");
+const ADR_2: (&str, &str) = ("/docs/adr/002.md", "---\nstatus: accepted\ntags:\n  - architecture\nreviewed:\n---\n# Event sourcing\n");
+const NOTES: (&str, &str) = ("/docs/notes.md", "# Notes\n");
+
+fn paths(vault: &Vault, query: &str) -> Vec<String> {
+    let conditions = parse(query).unwrap();
+    filter(vault, Path::new("/docs"), &conditions).into_iter().map(|entry| entry.path.display().to_string()).collect()
+}
+
+#[test]
+fn reads_all_frontmatter_fields() {
+    let markdown = "---\ntitle: Hello, world\ntags: [a, b]\n# comment\nauthors:\n  - ken\n  - sakura\n---\n";
+    assert_eq!(
+        fields(block(markdown).unwrap()),
+        [
+            ("title".to_string(), vec!["Hello, world".to_string()]),
+            ("tags".to_string(), vec!["a".to_string(), "b".to_string()]),
+            ("authors".to_string(), vec!["ken".to_string(), "sakura".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn parses_conditions() {
+    assert_eq!(
+        parse(r#"tag=architecture status!=done reviewed !draft title="Use Postgres""#).unwrap(),
+        [
+            Condition { key: "tag".to_string(), value: Some("architecture".to_string()), negated: false },
+            Condition { key: "status".to_string(), value: Some("done".to_string()), negated: true },
+            Condition { key: "reviewed".to_string(), value: None, negated: false },
+            Condition { key: "draft".to_string(), value: None, negated: true },
+            Condition { key: "title".to_string(), value: Some("Use Postgres".to_string()), negated: false },
+        ]
+    );
+    assert!(parse("=draft").is_err());
+    assert!(parse("status=\"draft").is_err());
+}
+
+#[test]
+fn filters_documents_by_frontmatter() {
+    let vault = common::vault("/docs", &[ADR_1, ADR_2, NOTES]);
+    assert_eq!(paths(&vault, ""), ["/docs/adr/001.md", "/docs/adr/002.md", "/docs/notes.md"]);
+    // `tag` は `tags` の値を見て、値の大文字と小文字は区別しない
+    assert_eq!(paths(&vault, "tag=architecture status=draft"), ["/docs/adr/001.md"]);
+    assert_eq!(paths(&vault, "status!=draft"), ["/docs/adr/002.md", "/docs/notes.md"]);
+    assert_eq!(paths(&vault, "reviewed"), ["/docs/adr/002.md"]);
+    assert_eq!(paths(&vault, "!status"), ["/docs/notes.md"]);
+    assert!(filter(&vault, Path::new("/docs/adr"), &[]).iter().all(|entry| entry.path.starts_with("/docs/adr")));
+}
+
+#[test]
+fn reports_titles_tags_and_queried_keys() {
+    let vault = common::vault("/docs", &[ADR_1, ADR_2]);
+    let conditions = parse("tag=architecture status!=done").unwrap();
+    let entries = filter(&vault, Path::new("/docs"), &conditions);
+    assert_eq!(
+        report(Path::new("/docs"), "tag=architecture status!=done", &conditions, &entries),
+        "# Index of `/docs`\n\n2 documents matching `tag=architecture status!=done`.\n\n\
+         | Document | Title | Tags | Status |\n| --- | --- | --- | --- |\n\
+         | [adr/001.md](</docs/adr/001.md>) | Use Postgres | architecture, db | Draft |\n\
+         | [adr/002.md](</docs/adr/002.md>) | Event sourcing | architecture | accepted |\n"
+    );
+    assert!(report(Path::new("/docs"), "status=x", &[], &[]).contains("No documents matching `status=x`."));
+}
+
+#[test]
+fn metadata_survives_the_daemon_and_the_saved_index() {
+    let vault = common::vault("/docs", &[ADR_1, ADR_2, NOTES]);
+    let lines = answer(&vault, "index tag=architecture").unwrap();
+    assert_eq!(parse_entries(&lines, Path::new("/docs")), filter(&vault, Path::new("/docs"), &parse("tag=architecture").unwrap()));
+    assert!(answer(&vault, "index =x").is_err());
+
+    let loaded = common::save_and_load(&vault, "metadata-index");
+    let metadata = |vault: &Vault| vault.documents.values().map(|document| document.metadata.clone()).collect::<Vec<BTreeMap<_, _>>>();
+    assert_eq!(metadata(&loaded), metadata(&vault));
+}
@@ -0,0 +1,47 @@
+// --- 拡張子ごとのプレビューコマンド ---
+
+use std::path::Path;
+
+use peek::{
+    config::{parse, Config},
+    filter::image_extension,
+};
+
+#[test]
+fn maps_extensions_to_commands() {
+    let entries = parse("[preview]\ndrawio = \"drawio-to-md\"\n'.CSV' = \"csv2md\"\n", "config.toml").unwrap();
+    let mut config = Config::default();
+    config.apply(&entries).unwrap();
+    assert_eq!(config.preview_command(Path::new("docs/arch.drawio")), Some("drawio-to-md"));
+    assert_eq!(config.preview_command(Path::new("data.csv")), Some("csv2md"));
+    assert_eq!(config.preview_command(Path::new("notes.txt")), None);
+    assert!(config.is_previewable(Path::new("arch.drawio")));
+    assert!(config.is_previewable(Path::new("README.md")));
+}
+
+#[test]
+fn recognizes_images_by_their_contents() {
+    assert_eq!(image_extension(b"\x89PNG\r\n\x1a\n...."), Some("png"));
+    assert_eq!(image_extension(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\">"), Some("svg"));
+    assert_eq!(image_extension(b"# Heading\n"), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn renders_command_output_as_markdown() {
+    use peek::filter::{convert, Converted};
+    let converted = convert("printf '# %s\\n' \"$(basename \"$MD_PREVIEW_FILE\")\"", Path::new("/tmp/arch.drawio")).unwrap();
+    assert_eq!(converted, Converted::Markdown("# arch.drawio\n".to_string()));
+    assert!(convert("exit 3", Path::new("/tmp/arch.drawio")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn reads_filters_that_write_a_lot_to_stderr() {
+    use peek::filter::apply;
+    // 標準エラー出力のパイプが詰まっても止まらない
+    let filters = ["head -c 200000 /dev/zero >&2; tr a-z A-Z".to_string()];
+    assert_eq!(apply("# title\n".to_string(), &filters, None).unwrap(), "# TITLE\n");
+    let error = apply(String::new(), &["echo broken >&2; exit 1".to_string()], None).unwrap_err();
+    assert!(error.to_string().ends_with(": broken"));
+}
@@ -0,0 +1,74 @@
+// --- レンダリングのスナップショットテスト ---
+//
+// tests/fixtures/*.md をレンダリングし、TestBackend に描画したバッファ（文字と装飾）を
+// insta で tests/snapshots/<名前>.snap と比較する。差分が出たら `cargo insta review` で確かめて受け入れる
+// （cargo-insta が無ければ `INSTA_UPDATE=always cargo test` で更新する）。
+// 名前が `screen_reader` で始まるものはスクリーンリーダー向けの表示で、`ascii` で始まるものはASCII文字の枠で描画する。
+
+use std::{fs, path::Path};
+
+use peek::{
+    render::{clip_code_lines, render_markdown_document, wrap_quote_lines, Decorations, RenderOptions},
+    theme::{GITHUB_DARK_THEME, MONOCHROME_THEME},
+};
+use ratatui::{
+    backend::TestBackend,
+    prelude::*,
+    widgets::{Paragraph, Wrap},
+};
+
+/// 描画する端末の幅
+const WIDTH: u16 = 60;
+
+/// Markdownをプレビューと同じ設定で描画し、バッファのデバッグ表示を返す
+fn render(markdown: &str, options: &RenderOptions) -> String {
+    let theme = match options.decorations {
+        Decorations::Unicode | Decorations::Ascii => &GITHUB_DARK_THEME,
+        Decorations::ScreenReader => &MONOCHROME_THEME,
+    };
+    let (mut rendered, _) = render_markdown_document(markdown, theme, &|_| None, options);
+    clip_code_lines(&mut rendered.text, &rendered.code_lines, WIDTH as usize, theme);
+    wrap_quote_lines(&mut rendered.text, WIDTH as usize);
+    let height = rendered.text.height().max(1) as u16;
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, height)).unwrap();
+    terminal
+        .draw(|f| {
+            let paragraph = Paragraph::new(rendered.text.clone())
+                .style(Style::default().fg(theme.fg).bg(theme.bg))
+                .wrap(Wrap { trim: false });
+            f.render_widget(paragraph, f.size());
+        })
+        .unwrap();
+    format!("{:?}\n", terminal.backend().buffer())
+}
+
+#[test]
+fn fixtures_match_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut fixtures: Vec<_> = fs::read_dir(root.join("fixtures"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "tests/fixtures にMarkdownがありません");
+
+    let mut settings = insta::Settings::clone_current();
+    settings.set_snapshot_path(root.join("snapshots"));
+    settings.set_prepend_module_to_snapshot(false);
+    settings.set_omit_expression(true);
+    for fixture in fixtures {
+        let name = fixture.file_stem().unwrap().to_string_lossy().to_string();
+        let decorations = if name.starts_with("screen_reader") {
+            Decorations::ScreenReader
+        } else if name.starts_with("ascii") {
+            Decorations::Ascii
+        } else {
+            Decorations::Unicode
+        };
+        let options = RenderOptions { decorations, number_headings: name.starts_with("numbered"), ..Default::default() };
+        let actual = render(&fs::read_to_string(&fixture).unwrap(), &options);
+        settings.set_input_file(&fixture);
+        settings.bind(|| insta::assert_snapshot!(name, actual));
+    }
+}
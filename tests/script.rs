@@ -0,0 +1,96 @@
+// --- Rhaiのスクリプトによるフック ---
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use peek::{
+    render::{render_markdown, RenderOptions},
+    script::{self, CommandResult},
+    theme::GITHUB_DARK_THEME,
+};
+
+const HOOKS: &str = r#"
+// 本文の `ABC-<数字>` を課題管理のリンクにする
+fn on_render(events) {
+    let out = [];
+    for e in events {
+        let at = if e.kind == "text" { e.text.index_of("ABC-") } else { -1 };
+        if at < 0 {
+            out.push(e);
+            continue;
+        }
+        let id = e.text.sub_string(at, 6);
+        out.push(#{kind: "text", text: e.text.sub_string(0, at)});
+        out.push(#{kind: "start", tag: "link", url: "https://tracker.example/" + id});
+        out.push(#{kind: "text", text: id});
+        out.push(#{kind: "end", tag: "link"});
+        out.push(#{kind: "text", text: e.text.sub_string(at + 6)});
+    }
+    out
+}
+
+fn on_open(path, markdown) {
+    if path.ends_with("draft.md") { "> 下書き\n\n" + markdown } else { () }
+}
+
+fn command_jump(args, doc) {
+    #{message: "jumped from " + doc.line, line: parse_int(args[0]), open: args[1]}
+}
+"#;
+
+fn write_script(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("peek-script-{}-{}.rhai", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn transforms_the_event_stream_before_rendering() {
+    let path = write_script("render", HOOKS);
+    let options = RenderOptions { script: Some(script::load(&path).unwrap()), ..Default::default() };
+    let rendered = render_markdown("# Title\n\nFixes ABC-12 today.\n", &GITHUB_DARK_THEME, &|_| None, &options);
+    let urls: Vec<&str> = rendered.links.iter().map(|link| link.url.as_str()).collect();
+    assert_eq!(urls, ["https://tracker.example/ABC-12"]);
+    // 書き換えられないイベント（見出し）は元のまま描く
+    assert_eq!(rendered.headings, ["Title"]);
+    assert_eq!(rendered.script_error, None);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rewrites_opened_documents_and_runs_commands() {
+    let path = write_script("open", HOOKS);
+    let script = script::load(&path).unwrap();
+    assert_eq!(script.on_open(Path::new("notes/draft.md"), "# A\n".to_string()).unwrap(), "> 下書き\n\n# A\n");
+    assert_eq!(script.on_open(Path::new("notes/final.md"), "# A\n".to_string()).unwrap(), "# A\n");
+
+    assert!(script.has_command("jump") && !script.has_command("missing"));
+    let result = script.command("jump", vec!["3".to_string(), "other.md".to_string()], Some(Path::new("a.md")), 7).unwrap();
+    assert_eq!(
+        result,
+        CommandResult { message: Some("jumped from 7".to_string()), open: Some(PathBuf::from("other.md")), line: Some(3) }
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn reports_broken_scripts() {
+    let path = write_script("syntax", "fn on_render(events) {");
+    assert!(script::load(&path).is_err());
+    fs::remove_file(path).unwrap();
+
+    // on_render が失敗したら、フックを使わずに描画してエラーを知らせる
+    let path = write_script("runtime", "fn on_render(events) { 42 }");
+    let options = RenderOptions { script: Some(script::load(&path).unwrap()), ..Default::default() };
+    let rendered = render_markdown("Fixes ABC-12.\n", &GITHUB_DARK_THEME, &|_| None, &options);
+    assert!(rendered.script_error.unwrap().contains("on_render"));
+    assert!(rendered.links.is_empty());
+    fs::remove_file(path).unwrap();
+
+    // 止まらないスクリプトは打ち切る
+    let path = write_script("loop", "fn on_open(path, markdown) { loop {} }");
+    assert!(script::load(&path).unwrap().on_open(Path::new("a.md"), String::new()).is_err());
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,95 @@
+// --- 全文検索 ---
+
+mod common;
+
+use std::path::PathBuf;
+
+use peek::{
+    daemon::{answer, parse_hits, parse_related},
+    search::{related, search, snippet, terms},
+    vault::Vault,
+};
+
+#[test]
+fn splits_words_and_cjk_bigrams() {
+    assert_eq!(terms("Deploy the API-v2 (BETA)"), ["deploy", "the", "api", "v2", "beta"]);
+    assert_eq!(terms("設定ファイルを書く"), ["設定", "定フ", "ファ", "ァイ", "イル", "ルを", "を書", "書く"]);
+    assert_eq!(terms("k8sの設定"), ["k8s", "の設", "設定"]);
+    assert_eq!(terms("a 字 b"), ["a", "字", "b"]);
+}
+
+#[test]
+fn picks_the_line_with_the_most_terms() {
+    let markdown = "# Deploy\n\nWe deploy with kubernetes.\n\nSee also kubernetes.\n";
+    let query = terms("kubernetes deploy");
+    assert_eq!(snippet(markdown, &query), Some((3, "We deploy with kubernetes.".to_string())));
+    assert_eq!(snippet(markdown, &terms("helm")), None);
+    let long = format!("{} kubernetes {}", "x".repeat(100), "y".repeat(100));
+    let (_, text) = snippet(&long, &terms("kubernetes")).unwrap();
+    assert!(text.starts_with('…') && text.ends_with('…') && text.contains("kubernetes"));
+}
+
+const DEPLOY: (&str, &str) = ("/vault/deploy.md", "# Deploy\n\nDeploy with kubernetes. Kubernetes runs everything.\n");
+const NOTES: (&str, &str) = ("/vault/notes.md", "# Notes\n\nKubernetes came up once among many other unrelated words here.\n");
+const OTHER: (&str, &str) = ("/vault/other.md", "# Other\n\nNothing relevant.\n");
+
+#[test]
+fn ranks_documents_with_more_matches_first() {
+    let vault = common::vault("/vault", &[DEPLOY, NOTES, OTHER]);
+    let hits = search(&vault, "kubernetes", 10);
+    let paths: Vec<_> = hits.iter().map(|hit| hit.path.to_str().unwrap()).collect();
+    assert_eq!(paths, ["/vault/deploy.md", "/vault/notes.md"]);
+    assert!(hits[0].score > hits[1].score);
+    assert!(search(&vault, "helm", 10).is_empty());
+}
+
+#[test]
+fn replacing_a_document_updates_the_index() {
+    let mut vault = common::vault("/vault", &[DEPLOY, NOTES]);
+    vault.insert(PathBuf::from("/vault/deploy.md"), "# Deploy\n\nNow with helm.\n", None);
+    assert_eq!(search(&vault, "kubernetes", 10).len(), 1);
+    assert_eq!(search(&vault, "helm", 10)[0].path, PathBuf::from("/vault/deploy.md"));
+}
+
+#[test]
+fn saved_index_loads_back() {
+    let vault = common::vault("/vault", &[DEPLOY, NOTES]);
+    let loaded = common::save_and_load(&vault, "search-index");
+    assert_eq!(loaded.documents.keys().collect::<Vec<_>>(), vault.documents.keys().collect::<Vec<_>>());
+    let scores = |vault: &Vault| search(vault, "kubernetes deploy", 10).into_iter().map(|hit| (hit.path, hit.score)).collect::<Vec<_>>();
+    assert_eq!(scores(&loaded), scores(&vault));
+    assert_eq!(loaded.documents[&PathBuf::from("/vault/deploy.md")].headings, ["Deploy"]);
+}
+
+#[test]
+fn daemon_answers_search_requests() {
+    let vault = common::vault("/vault", &[DEPLOY, NOTES, OTHER]);
+    let lines = answer(&vault, "search kubernetes").unwrap();
+    let hits = parse_hits(&lines, &PathBuf::from("/vault"));
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].path, PathBuf::from("/vault/deploy.md"));
+    assert!(parse_hits(&lines, &PathBuf::from("/elsewhere")).is_empty());
+    assert!(answer(&vault, "search").is_err());
+}
+
+#[test]
+fn finds_documents_sharing_terms_and_links() {
+    let vault = common::vault(
+        "/rel",
+        &[
+            ("/rel/deploy.md", "# Deploy\n\nPods with helm charts and kubectl rollout.\n\nSee [ops](ops.md).\n"),
+            ("/rel/helm.md", "# Helm\n\nWriting helm charts for kubectl rollout.\n"),
+            ("/rel/ops.md", "# Ops\n\nOn-call rotation.\n"),
+            ("/rel/cook.md", "# Cooking\n\nPasta recipes.\n\n[ops](ops.md)\n"),
+        ],
+    );
+    let found = related(&vault, &PathBuf::from("/rel/deploy.md"), 10);
+    let names: Vec<_> = found.iter().map(|related| related.path.to_str().unwrap()).collect();
+    // 本文の似た文書、リンク先、同じ文書にリンクしている文書の順
+    assert_eq!(names, ["/rel/helm.md", "/rel/ops.md", "/rel/cook.md"]);
+    assert!(found[0].terms.contains(&"helm".to_string()) && !found[0].linked);
+    assert!(found[1].linked);
+    assert!(related(&vault, &PathBuf::from("/rel/missing.md"), 10).is_empty());
+    let lines = answer(&vault, "related /rel/deploy.md").unwrap();
+    assert_eq!(parse_related(&lines), found);
+}
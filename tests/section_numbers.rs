@@ -0,0 +1,31 @@
+// --- 見出しの節番号 ---
+
+use peek::{
+    render::{render_markdown, RenderOptions, SectionNumbers},
+    theme::GITHUB_DARK_THEME,
+};
+
+fn numbers(levels: &[usize]) -> Vec<String> {
+    let mut counter = SectionNumbers::default();
+    levels.iter().map(|&level| counter.next(level)).collect()
+}
+
+#[test]
+fn counts_nested_sections() {
+    assert_eq!(numbers(&[1, 2, 3, 3, 2, 1, 2]), ["1.", "1.1.", "1.1.1.", "1.1.2.", "1.2.", "2.", "2.1."]);
+}
+
+#[test]
+fn omits_unused_top_levels() {
+    assert_eq!(numbers(&[2, 3, 2]), ["1.", "1.1.", "2."]);
+    assert_eq!(numbers(&[1, 3]), ["1.", "1.0.1."]);
+}
+
+#[test]
+fn keeps_numbers_out_of_heading_texts() {
+    let options = RenderOptions { number_headings: true, ..Default::default() };
+    let rendered = render_markdown("# Intro\n\n## Usage\n", &GITHUB_DARK_THEME, &|_| None, &options);
+    assert_eq!(rendered.headings, ["Intro", "Usage"]);
+    assert_eq!(rendered.heading_numbers, ["1.", "1.1."]);
+    assert_eq!(rendered.title.as_deref(), Some("Intro"));
+}
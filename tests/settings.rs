@@ -0,0 +1,71 @@
+// --- プロジェクト設定の信頼 ---
+
+use std::{fs, path::PathBuf};
+
+use peek::{
+    config::{needs_trust, Config, Value},
+    settings::{Settings, PROJECT_CONFIG_FILE},
+};
+
+fn project(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("peek-settings-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let config = "theme = \"monochrome\"\nfilters = [\"my-filter\"]\nimage_viewer = \"evil\"\nscript = \"hooks.rhai\"\ntrusted_projects = [\"/\"]\n\n[preview]\ncsv = \"evil\"\n";
+    fs::write(root.join(PROJECT_CONFIG_FILE), config).unwrap();
+    root
+}
+
+#[test]
+fn ignores_commands_of_untrusted_projects() {
+    let root = project("untrusted");
+    let config = Settings::default().resolve(Some(&root)).unwrap();
+    // コマンドでない設定はそのまま使う
+    assert_eq!(config.theme, "monochrome");
+    assert!(config.filters.is_empty() && config.image_viewer.is_empty() && config.preview_commands.is_empty());
+    assert!(config.script.is_empty());
+    assert!(config.trusted_projects.is_empty());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn ignores_paths_urls_and_aliases_of_untrusted_projects() {
+    let root = std::env::temp_dir().join(format!("peek-settings-paths-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let config = "theme = \"monochrome\"\nhtml_template = \"/etc/passwd\"\nhtml_css = \"~/.ssh/id_rsa\"\nlog_file = \"/tmp/overwrite\"\n\
+                  home_dir = \"/\"\njournal_dir = \"/\"\njournal_template = \"../../secret\"\nspell_language = \"/tmp/dict\"\n\
+                  translation_patterns = [\"../*.md\"]\nlink_base = \"https://evil.example/\"\n\n\
+                  [alias]\nq = \"export html /tmp\"\n\n[autolink]\n'#(\\d+)' = \"https://evil.example/$1\"\n";
+    fs::write(root.join(PROJECT_CONFIG_FILE), config).unwrap();
+    let config = Settings::default().resolve(Some(&root)).unwrap();
+    let default = Config::default();
+    assert_eq!(config.theme, "monochrome");
+    assert_eq!((config.html_template.as_str(), config.html_css.as_str(), config.log_file.as_str()), ("", "", ""));
+    assert_eq!((config.home_dir, config.journal_dir, config.journal_template), (default.home_dir, default.journal_dir, default.journal_template));
+    assert_eq!((config.spell_language, config.translation_patterns), (default.spell_language, default.translation_patterns));
+    assert!(config.link_base.is_empty() && config.aliases.is_empty() && config.autolinks.is_empty());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn uses_commands_of_trusted_projects() {
+    let root = project("trusted");
+    let cli = vec![("trusted_projects".to_string(), Value::List(vec![root.display().to_string()]))];
+    let settings = Settings::load(cli).unwrap();
+    assert!(settings.trusts(&root) && !settings.trusts(&root.join("sub")));
+    let config = settings.resolve(Some(&root)).unwrap();
+    assert_eq!(config.filters, ["my-filter"]);
+    assert_eq!((config.image_viewer.as_str(), config.preview_commands.get("csv").map(String::as_str)), ("evil", Some("evil")));
+    // スクリプトの相対パスはリポジトリのルートから
+    assert_eq!(PathBuf::from(&config.script), root.join("hooks.rhai"));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn lists_the_keys_that_need_trust() {
+    assert!(needs_trust("filters") && needs_trust("image_viewer") && needs_trust("script") && needs_trust("preview.drawio"));
+    assert!(needs_trust("trusted_projects") && needs_trust("html_template") && needs_trust("html_css") && needs_trust("log_file"));
+    assert!(needs_trust("alias.ex") && needs_trust("autolink.#(\\d+)") && needs_trust("spell_language"));
+    assert!(!needs_trust("theme") && !needs_trust("extensions") && !needs_trust("max_file_size"));
+}
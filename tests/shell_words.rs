@@ -0,0 +1,36 @@
+// --- コマンドの引数の分割 ---
+
+use peek::shell_words::split;
+
+fn words(line: &str) -> Vec<String> {
+    split(line).expect("引用符は閉じているはず")
+}
+
+#[test]
+fn splits_on_whitespace() {
+    assert_eq!(words("  hp  notes.md "), ["hp", "notes.md"]);
+    assert_eq!(words(""), Vec::<String>::new());
+}
+
+#[test]
+fn keeps_spaces_inside_quotes() {
+    assert_eq!(words(r#"hp "my notes.md""#), ["hp", "my notes.md"]);
+    assert_eq!(words("hp 'my notes.md'"), ["hp", "my notes.md"]);
+    assert_eq!(words(r#"new "a b"'c d'.md"#), ["new", "a bc d.md"]);
+    assert_eq!(words(r#"x """#), ["x", ""]);
+}
+
+#[test]
+fn handles_escapes() {
+    assert_eq!(words(r"hp my\ notes.md"), ["hp", "my notes.md"]);
+    assert_eq!(words(r#"hp "say \"hi\".md""#), ["hp", r#"say "hi".md"#]);
+    // Windowsのパスの `\` はそのまま残す
+    assert_eq!(words(r"cat C:\Users\me\notes.md"), ["cat", r"C:\Users\me\notes.md"]);
+    assert_eq!(words(r"hp 'C:\my notes\a.md'"), ["hp", r"C:\my notes\a.md"]);
+}
+
+#[test]
+fn rejects_unterminated_quotes() {
+    assert_eq!(split(r#"hp "my notes.md"#), None);
+    assert_eq!(split("hp 'a"), None);
+}
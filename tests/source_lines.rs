@@ -0,0 +1,39 @@
+// --- 描画した行と元のMarkdownの行の対応 ---
+
+use peek::{
+    render::{render_markdown, Decorations, RenderOptions},
+    theme::GITHUB_DARK_THEME,
+};
+
+const MARKDOWN: &str = "# Title\n\nFirst paragraph\ncontinues here.\n\n- one\n- two\n\n```rust\nlet a = 1;\nlet b = 2;\n```\n\n> quoted\n";
+
+/// 空行以外の描画した行のテキストと、元の行番号
+fn mapping(decorations: Decorations) -> Vec<(String, usize)> {
+    let options = RenderOptions { decorations, ..Default::default() };
+    let rendered = render_markdown(MARKDOWN, &GITHUB_DARK_THEME, &|_| None, &options);
+    assert_eq!(rendered.source_lines.len(), rendered.text.lines.len());
+    rendered
+        .text
+        .lines
+        .iter()
+        .zip(rendered.source_lines)
+        .map(|(line, source)| (line.spans.iter().map(|span| span.content.as_ref()).collect::<String>(), source))
+        .filter(|(text, _)| !text.is_empty())
+        .collect()
+}
+
+#[test]
+fn maps_blocks_to_their_first_source_line() {
+    let lines: Vec<usize> = mapping(Decorations::Unicode).into_iter().map(|(_, source)| source).collect();
+    // 見出し、段落、リストの項目、コードブロックの枠と中身、引用
+    assert_eq!(lines, [1, 3, 6, 7, 9, 10, 11, 12, 14]);
+}
+
+#[test]
+fn keeps_spoken_markers_in_step() {
+    let mapping = mapping(Decorations::ScreenReader);
+    let source = |text: &str| mapping.iter().find(|(line, _)| line == text).map(|(_, source)| *source);
+    assert_eq!(source("End of code block"), Some(12));
+    assert_eq!(source("quoted"), Some(14));
+    assert_eq!(source("End of quote"), Some(14));
+}
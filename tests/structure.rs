@@ -0,0 +1,46 @@
+// --- 文書の構造（`--render-json`） ---
+
+use peek::structure::{analyze, CodeBlock, Heading, Link};
+
+const DOCUMENT: &str = "---\ntitle: Guide\n---\n# Guide\n\nSee [the `API`](api.md) and ![logo](logo.png).\n\n## Install\n\n```rust ignore\nfn main() {}\n\n```\n\n    indented\n";
+
+#[test]
+fn collects_headings_links_and_code_blocks() {
+    let structure = analyze(DOCUMENT);
+    assert_eq!(
+        structure.headings,
+        [
+            Heading { level: 1, text: "Guide".to_string(), anchor: "guide".to_string(), line: 4 },
+            Heading { level: 2, text: "Install".to_string(), anchor: "install".to_string(), line: 8 },
+        ]
+    );
+    assert_eq!(structure.links, [Link { text: "the API".to_string(), url: "api.md".to_string(), line: 6 }]);
+    assert_eq!(structure.images, [Link { text: "logo".to_string(), url: "logo.png".to_string(), line: 6 }]);
+    assert_eq!(
+        structure.code_blocks,
+        [CodeBlock { language: Some("rust".to_string()), line: 10, lines: 2 }, CodeBlock { language: None, line: 15, lines: 1 }]
+    );
+    // フロントマターとコードは数えない
+    assert_eq!(structure.words, 7);
+}
+
+#[test]
+fn collects_html_images() {
+    let markdown = "# Shots\n\n<p align=\"center\">\n  <IMG data-src=\"x.png\" SRC='img/a b.png' alt=\"A\">\n</p>\n\nInline <img src=img/c.png/> and <img srcset=\"d.png 2x\">.\n";
+    assert_eq!(
+        analyze(markdown).images,
+        [
+            Link { text: "A".to_string(), url: "img/a b.png".to_string(), line: 4 },
+            Link { text: String::new(), url: "img/c.png".to_string(), line: 7 },
+        ]
+    );
+}
+
+#[test]
+fn writes_escaped_json() {
+    let json = analyze("# Say \"hi\"\\\n\n```\nx\n```\n").to_json();
+    assert_eq!(
+        json,
+        r#"{"headings":[{"level":1,"text":"Say \"hi\"\\","anchor":"say-hi","line":1}],"links":[],"images":[],"code_blocks":[{"language":null,"line":3,"lines":1}],"words":2,"characters":8}"#
+    );
+}
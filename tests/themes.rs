@@ -0,0 +1,64 @@
+// --- 配色テーマのコントラスト ---
+
+use peek::theme::{self, ColorScheme};
+use ratatui::style::Color;
+
+/// WCAGの相対輝度
+fn luminance(color: Color) -> f64 {
+    let Color::Rgb(r, g, b) = color else {
+        panic!("RGBでない色: {:?}", color);
+    };
+    let channel = |value: u8| {
+        let value = value as f64 / 255.0;
+        if value <= 0.03928 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+fn contrast(a: Color, b: Color) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// 文字として表示する色と、その背景の組み合わせ
+fn text_pairs(theme: &ColorScheme) -> Vec<(&'static str, Color, Color)> {
+    vec![
+        ("fg", theme.fg, theme.bg),
+        ("selection", theme.selection_fg, theme.selection_bg),
+        ("comment", theme.comment, theme.bg),
+        ("link", theme.link, theme.bg),
+        ("heading", theme.heading, theme.bg),
+        ("code", theme.fg, theme.code_bg),
+        ("inline_code", theme.fg, theme.inline_code_bg),
+        ("quote", theme.quote_fg, theme.bg),
+        ("file_markdown", theme.file_markdown, theme.bg),
+        ("file_image", theme.file_image, theme.bg),
+        ("file_code", theme.file_code, theme.bg),
+        ("file_archive", theme.file_archive, theme.bg),
+    ]
+}
+
+fn assert_contrast(name: &str, minimum: f64) {
+    let theme = theme::by_name(name).unwrap();
+    for (role, fg, bg) in text_pairs(theme) {
+        let ratio = contrast(fg, bg);
+        assert!(ratio >= minimum, "{} の {} のコントラスト比が {:.2} しかない", name, role, ratio);
+    }
+}
+
+#[test]
+fn high_contrast_theme_meets_aaa() {
+    assert_contrast("high-contrast", 7.0);
+}
+
+#[test]
+fn colorblind_theme_meets_aa() {
+    assert_contrast("colorblind", 4.5);
+}
+
+#[test]
+fn every_theme_name_resolves() {
+    for name in theme::THEME_NAMES {
+        assert!(theme::by_name(name).is_some(), "{}", name);
+    }
+}
@@ -0,0 +1,43 @@
+// --- 翻訳の対 ---
+
+use std::path::{Path, PathBuf};
+
+use peek::translation::{candidates, Outline};
+
+fn patterns(patterns: &[&str]) -> Vec<String> {
+    patterns.iter().map(|pattern| pattern.to_string()).collect()
+}
+
+#[test]
+fn finds_translations_of_an_original() {
+    let found = candidates(Path::new("/docs/guide.md"), &patterns(&["*.ja.md", "ja/*.md"]));
+    assert_eq!(found, [PathBuf::from("/docs/guide.ja.md"), PathBuf::from("/docs/ja/guide.md")]);
+}
+
+#[test]
+fn finds_the_original_of_a_translation() {
+    let patterns = patterns(&["*.ja.md", "ja/*.md"]);
+    assert_eq!(candidates(Path::new("/docs/guide.ja.md"), &patterns), [PathBuf::from("/docs/guide.md")]);
+    assert_eq!(candidates(Path::new("/docs/ja/guide.md"), &patterns), [PathBuf::from("/docs/guide.md")]);
+}
+
+#[test]
+fn aligns_paragraphs_by_section_and_order() {
+    // 見出し、段落（2行）、段落、見出し、段落
+    let original = Outline::new(&[false, true, false, false, true, false, true, false, true, false], &[0, 7]);
+    // 翻訳は段落の行数が違う
+    let translated = Outline::new(&[false, true, false, true, false, false, false, true, false, true, false], &[0, 8]);
+    assert_eq!(original.align(0, &translated), 0);
+    assert_eq!(original.align(3, &translated), 3);
+    assert_eq!(original.align(5, &translated), 4);
+    assert_eq!(original.align(9, &translated), 10);
+}
+
+#[test]
+fn falls_back_to_the_nearest_paragraph() {
+    let original = Outline::new(&[false, true, false, true, false, true, false], &[0]);
+    let translated = Outline::new(&[false, true, false], &[0]);
+    // 翻訳に無い段落は最後の段落に、段落の中では次の段落を越えない
+    assert_eq!(original.align(6, &translated), 2);
+    assert_eq!(translated.align(2, &Outline::default()), 0);
+}
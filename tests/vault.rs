@@ -0,0 +1,86 @@
+// --- 文書の集まりの索引とdaemonの答え ---
+
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use peek::{
+    daemon::{answer, parse_backlinks, parse_tags},
+    vault::{Vault, normalize},
+};
+
+const A: (&str, &str) = ("/vault/a.md", "---\ntags: [arch, draft]\n---\n# A\n\nsee [b](sub/b.md) and [web](https://example.com)\n");
+const B: (&str, &str) = ("/vault/sub/b.md", "---\ntags: [arch]\n---\n# B\n\n[up](../a.md#a) [self](b.md)\n\n[again](./../a.md)\n");
+
+#[test]
+fn normalizes_relative_components() {
+    assert_eq!(normalize(Path::new("/vault/sub/./../a.md")), PathBuf::from("/vault/a.md"));
+    assert_eq!(normalize(Path::new("../x/../y.md")), PathBuf::from("../y.md"));
+}
+
+#[test]
+fn finds_backlinks_and_tags() {
+    let vault = common::vault("/vault", &[A, B]);
+    assert_eq!(vault.documents[Path::new("/vault/a.md")].links, [(PathBuf::from("/vault/sub/b.md"), 6)]);
+    assert_eq!(vault.backlinks(Path::new("/vault/a.md")), [(PathBuf::from("/vault/sub/b.md"), 6), (PathBuf::from("/vault/sub/b.md"), 8)]);
+    // 自分へのリンクはバックリンクに含めない
+    assert_eq!(vault.backlinks(Path::new("/vault/sub/b.md")), [(PathBuf::from("/vault/a.md"), 6)]);
+    let tags = vault.tags();
+    assert_eq!(tags["arch"], [PathBuf::from("/vault/a.md"), PathBuf::from("/vault/sub/b.md")]);
+    assert_eq!(tags["draft"], [PathBuf::from("/vault/a.md")]);
+}
+
+#[test]
+fn answers_round_trip_through_the_protocol() {
+    let vault = common::vault("/vault", &[A, B]);
+    let tags = answer(&vault, "tags").unwrap();
+    assert_eq!(parse_tags(&tags, Path::new("/vault")), vault.tags());
+    // 開いているディレクトリの下の文書だけを残す
+    assert_eq!(parse_tags(&tags, Path::new("/vault/sub")).keys().collect::<Vec<_>>(), ["arch"]);
+    let backlinks = answer(&vault, "backlinks /vault/a.md").unwrap();
+    assert_eq!(parse_backlinks(&backlinks), vault.backlinks(Path::new("/vault/a.md")));
+    assert!(answer(&vault, "backlinks").is_err());
+    assert!(answer(&vault, "bogus x").is_err());
+}
+
+#[test]
+fn refreshes_only_the_changed_paths() {
+    let root = std::env::temp_dir().join(format!("peek-refresh-paths-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("a.md"), "# A\n").unwrap();
+    fs::write(root.join("sub/b.md"), "# B\n").unwrap();
+    let is_document = |path: &Path| path.extension().is_some_and(|extension| extension == "md");
+    let mut vault = Vault::new(root.clone());
+    assert_eq!(vault.refresh(is_document), 2);
+
+    // 変わっていない文書は読み直さない
+    assert_eq!(vault.refresh_paths(&[root.join("a.md")], is_document), 0);
+    fs::write(root.join("a.md"), "# A\n\nzebra\n").unwrap();
+    assert_eq!(vault.refresh_paths(&[root.join("a.md")], is_document), 1);
+    assert!(vault.postings.contains_key("zebra"));
+    fs::remove_file(root.join("a.md")).unwrap();
+    assert_eq!(vault.refresh_paths(&[root.join("a.md")], is_document), 0);
+    assert!(!vault.documents.contains_key(&root.join("a.md")));
+    assert!(!vault.postings.contains_key("zebra"));
+    // 新しい文書や、名前の変わったディレクトリの下の文書を読む
+    fs::write(root.join("c.md"), "# C\n").unwrap();
+    assert_eq!(vault.refresh_paths(&[root.join("c.md")], is_document), 1);
+    fs::rename(root.join("sub"), root.join("moved")).unwrap();
+    assert_eq!(vault.refresh_paths(&[root.join("sub"), root.join("moved")], is_document), 1);
+    assert_eq!(vault.documents.keys().cloned().collect::<Vec<PathBuf>>(), [root.join("c.md"), root.join("moved/b.md")]);
+    // 文書でないファイルや、たどらないディレクトリの中の文書は無視する
+    fs::write(root.join("notes.txt"), "x").unwrap();
+    fs::write(root.join(".ignore"), "target/\n").unwrap();
+    fs::create_dir_all(root.join("target/doc")).unwrap();
+    fs::write(root.join("target/doc/x.md"), "# X\n").unwrap();
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::write(root.join(".git/notes.md"), "# N\n").unwrap();
+    let ignored = [root.join("notes.txt"), root.join("target"), root.join("target/doc/x.md"), root.join(".git/notes.md")];
+    assert_eq!(vault.refresh_paths(&ignored, is_document), 0);
+    assert_eq!(vault.refresh(is_document), 0);
+    fs::remove_dir_all(&root).unwrap();
+}